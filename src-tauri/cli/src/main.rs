@@ -0,0 +1,112 @@
+use std::path::Path;
+use std::process::ExitCode;
+
+use haybox_core::error::HayboxError;
+use haybox_core::events::{EventSink, Events};
+use haybox_core::flashing::FlashProgress;
+use haybox_core::{config_file, config_proto, flash_target, get_current_device_status, run_install_winusb};
+use serde::Serialize;
+
+const USAGE: &str = "\
+Usage: haybox-cli <command>
+
+Commands:
+  status                  Show the connected controller and its driver state
+  install-winusb          Install WinUSB for the controller (run from an elevated prompt)
+  flash <firmware>        Flash a .uf2 or .hex file to the controller in BOOTSEL or DFU mode
+  export-config <path>    Save the config of the controller in config mode to a file
+
+Results are printed to stdout as JSON; progress and errors go to stderr.";
+
+enum Command {
+  Status,
+  InstallWinusb,
+  Flash(String),
+  ExportConfig(String),
+}
+
+impl Command {
+  fn parse(args: &[String]) -> Option<Self> {
+    match args {
+      [command] if command == "status" => Some(Command::Status),
+      [command] if command == "install-winusb" => Some(Command::InstallWinusb),
+      [command, firmware] if command == "flash" => Some(Command::Flash(firmware.clone())),
+      [command, path] if command == "export-config" => Some(Command::ExportConfig(path.clone())),
+      _ => None,
+    }
+  }
+}
+
+/// Prints flash progress to stderr so stdout only ever holds the result.
+struct ProgressPrinter;
+
+impl EventSink for ProgressPrinter {
+  fn emit(&self, event: &str, payload: serde_json::Value) {
+    if event != "flash_progress" {
+      return;
+    }
+    if let Ok(progress) = serde_json::from_value::<FlashProgress>(payload) {
+      eprintln!(
+        "{:?}: {}/{} bytes",
+        progress.stage, progress.bytes_written, progress.total_bytes
+      );
+    }
+  }
+}
+
+fn print_json<T: Serialize>(value: &T) -> Result<(), HayboxError> {
+  let json = serde_json::to_string_pretty(value)
+    .map_err(|e| HayboxError::Other(format!("Failed to serialize result: {}", e)))?;
+  println!("{}", json);
+  Ok(())
+}
+
+fn run(command: Command) -> Result<(), HayboxError> {
+  match command {
+    Command::Status => {
+      let status =
+        get_current_device_status().map_err(|e| HayboxError::Other(format!("Failed to get device status: {}", e)))?;
+      print_json(&status)
+    }
+    Command::InstallWinusb => {
+      let result = run_install_winusb();
+      print_json(&result)?;
+      if result.success {
+        Ok(())
+      } else {
+        Err(HayboxError::Driver(result.message))
+      }
+    }
+    Command::Flash(firmware) => {
+      let result = flash_target::flash_firmware(&Events::new(ProgressPrinter), Path::new(&firmware))?;
+      print_json(&result)
+    }
+    Command::ExportConfig(path) => {
+      config_proto::connect_config_mode(None)?;
+      let file = config_file::export_config(Path::new(&path));
+      config_proto::disconnect_config_mode();
+      print_json(&file?)
+    }
+  }
+}
+
+fn main() -> ExitCode {
+  let args: Vec<String> = std::env::args().skip(1).collect();
+  if matches!(args.as_slice(), [flag] if flag == "-h" || flag == "--help") {
+    println!("{}", USAGE);
+    return ExitCode::SUCCESS;
+  }
+
+  let Some(command) = Command::parse(&args) else {
+    eprintln!("{}", USAGE);
+    return ExitCode::from(2);
+  };
+
+  match run(command) {
+    Ok(()) => ExitCode::SUCCESS,
+    Err(e) => {
+      eprintln!("error: {}", e);
+      ExitCode::FAILURE
+    }
+  }
+}
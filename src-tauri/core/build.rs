@@ -0,0 +1,79 @@
+use std::path::Path;
+
+use sha2::{Digest, Sha256};
+
+/// Certificate subject names a binary resource must be signed by. Microsoft
+/// signs WinUSB, the WDF coinstallers and devcon; libusbK comes from libwdi,
+/// whose DLL Akeo Consulting signs and whose driver is attestation-signed
+/// through Microsoft's hardware program.
+fn trusted_signers(name: &str) -> &'static [&'static str] {
+  let file_name = name.rsplit('/').next().unwrap_or(name).to_lowercase();
+  if ![".dll", ".sys", ".exe"].iter().any(|ext| file_name.ends_with(ext)) {
+    &[]
+  } else if file_name.starts_with("libusbk") {
+    &["Akeo Consulting", "Microsoft Windows Hardware Compatibility Publisher"]
+  } else {
+    &["Microsoft Corporation", "Microsoft Windows"]
+  }
+}
+
+/// Generates a table of every file in `src-tauri/driver_resources/` so the
+/// driver templates and coinstallers are compiled into the binary, including
+/// the per-architecture subdirectories (`amd64/`, `arm64/`, `x86/`), each with
+/// its SHA-256 taken here and the signers it must carry. Windows builds fail without the directory; other
+/// targets never install drivers and get an empty table.
+fn embed_driver_resources() {
+  let resource_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../driver_resources");
+  println!("cargo:rerun-if-changed={}", resource_dir.display());
+
+  let mut entries = Vec::new();
+  if let Ok(dir) = std::fs::read_dir(&resource_dir) {
+    for entry in dir.flatten() {
+      let path = entry.path();
+      let name = entry.file_name().to_string_lossy().to_string();
+      if path.is_file() {
+        println!("cargo:rerun-if-changed={}", path.display());
+        entries.push((name, path));
+      } else if path.is_dir() {
+        println!("cargo:rerun-if-changed={}", path.display());
+        for file in std::fs::read_dir(&path).into_iter().flatten().flatten() {
+          if file.path().is_file() {
+            println!("cargo:rerun-if-changed={}", file.path().display());
+            entries.push((format!("{}/{}", name, file.file_name().to_string_lossy()), file.path()));
+          }
+        }
+      }
+    }
+  }
+  entries.sort();
+
+  let windows = std::env::var("CARGO_CFG_TARGET_OS").is_ok_and(|os| os == "windows");
+  if windows && entries.is_empty() {
+    panic!(
+      "No driver resources in {}; add the INF templates, coinstallers and devcon builds before building for Windows",
+      resource_dir.display()
+    );
+  }
+
+  let mut generated = String::from("pub static EMBEDDED_RESOURCES: &[(&str, &[u8], &str, &[&str])] = &[\n");
+  for (name, path) in &entries {
+    let bytes = std::fs::read(path).unwrap_or_else(|e| panic!("Failed to read {}: {}", path.display(), e));
+    let hash = format!("{:x}", Sha256::digest(&bytes));
+    generated.push_str(&format!(
+      "  ({:?}, include_bytes!({:?}), {:?}, &{:?}),\n",
+      name,
+      path,
+      hash,
+      trusted_signers(name)
+    ));
+  }
+  generated.push_str("];\n");
+
+  let out_dir = std::env::var("OUT_DIR").expect("OUT_DIR is set by cargo");
+  std::fs::write(Path::new(&out_dir).join("embedded_resources.rs"), generated)
+    .expect("Failed to write embedded resource table");
+}
+
+fn main() {
+  embed_driver_resources();
+}
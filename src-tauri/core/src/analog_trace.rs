@@ -0,0 +1,172 @@
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::HayboxError;
+use crate::events::Events;
+use crate::input_monitor::{AxisValue, InputState};
+
+/// A minute of reports at 1 kHz.
+const TRACE_CAPACITY: usize = 60_000;
+/// The UI gets one batch per display frame rather than every report.
+const BATCH_INTERVAL_US: u64 = 16_000;
+
+/// Range of one axis over a batch. Keeping the extremes rather than a
+/// sample means a one-report spike still shows up on screen.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AxisEnvelope {
+  pub name: String,
+  pub min: f32,
+  pub max: f32,
+  pub last: f32,
+}
+
+/// Emitted as `analog_trace` while tracing.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AnalogTraceBatch {
+  pub from_us: u64,
+  pub to_us: u64,
+  pub samples: usize,
+  pub axes: Vec<AxisEnvelope>,
+}
+
+#[derive(Default)]
+struct Trace {
+  /// Axis names, in the order of each sample's values.
+  columns: Vec<String>,
+  /// Timestamp and each axis's raw and normalized value.
+  samples: VecDeque<(u64, Vec<(i32, f32)>)>,
+  batch: Option<AnalogTraceBatch>,
+}
+
+static TRACING: AtomicBool = AtomicBool::new(false);
+
+lazy_static::lazy_static! {
+  static ref TRACE: Mutex<Trace> = Mutex::new(Trace::default());
+}
+
+/// Called by the input monitor for every report.
+pub fn observe(events: &Events, state: &InputState) {
+  if !TRACING.load(Ordering::Relaxed) || state.axes.is_empty() {
+    return;
+  }
+  let mut trace = TRACE.lock().unwrap();
+
+  let mut values = Vec::with_capacity(trace.columns.len());
+  for axis in &state.axes {
+    let column = match trace.columns.iter().position(|name| *name == axis.name) {
+      Some(column) => column,
+      None => {
+        trace.columns.push(axis.name.clone());
+        trace.columns.len() - 1
+      }
+    };
+    if values.len() <= column {
+      values.resize(column + 1, (0, 0.5));
+    }
+    values[column] = (axis.value, axis.normalized);
+  }
+  if trace.samples.len() == TRACE_CAPACITY {
+    trace.samples.pop_front();
+  }
+  trace.samples.push_back((state.timestamp_us, values));
+
+  let batch = trace.batch.get_or_insert_with(|| AnalogTraceBatch {
+    from_us: state.timestamp_us,
+    to_us: state.timestamp_us,
+    samples: 0,
+    axes: Vec::new(),
+  });
+  batch.to_us = state.timestamp_us;
+  batch.samples += 1;
+  for axis in &state.axes {
+    match batch.axes.iter_mut().find(|envelope| envelope.name == axis.name) {
+      Some(envelope) => {
+        envelope.min = envelope.min.min(axis.normalized);
+        envelope.max = envelope.max.max(axis.normalized);
+        envelope.last = axis.normalized;
+      }
+      None => batch.axes.push(AxisEnvelope {
+        name: axis.name.clone(),
+        min: axis.normalized,
+        max: axis.normalized,
+        last: axis.normalized,
+      }),
+    }
+  }
+  if batch.to_us - batch.from_us >= BATCH_INTERVAL_US {
+    if let Some(batch) = trace.batch.take() {
+      events.emit("analog_trace", batch);
+    }
+  }
+}
+
+/// Clears the buffer and starts capturing every report's axis values from
+/// the input monitor.
+pub fn start_analog_trace() {
+  *TRACE.lock().unwrap() = Trace::default();
+  TRACING.store(true, Ordering::Relaxed);
+}
+
+/// Stops capturing. The buffer is kept for `export_trace`.
+pub fn stop_analog_trace() {
+  TRACING.store(false, Ordering::Relaxed);
+}
+
+/// The buffered samples as input states, oldest first.
+pub fn trace_frames() -> Vec<InputState> {
+  let trace = TRACE.lock().unwrap();
+  trace
+    .samples
+    .iter()
+    .map(|(timestamp_us, values)| InputState {
+      timestamp_us: *timestamp_us,
+      report_id: 0,
+      pressed: Vec::new(),
+      axes: trace
+        .columns
+        .iter()
+        .zip(values)
+        .map(|(name, &(value, normalized))| AxisValue {
+          name: name.clone(),
+          value,
+          normalized,
+        })
+        .collect(),
+      hat: None,
+    })
+    .collect()
+}
+
+/// Writes the buffered samples as CSV: a timestamp column in microseconds,
+/// then one column of raw values per axis.
+pub fn export_trace(path: &Path) -> Result<usize, HayboxError> {
+  let trace = TRACE.lock().unwrap();
+  if trace.samples.is_empty() {
+    return Err(HayboxError::Other("The analog trace is empty".to_string()));
+  }
+
+  let mut csv = String::from("timestamp_us");
+  for name in &trace.columns {
+    csv.push(',');
+    csv.push_str(name);
+  }
+  csv.push('\n');
+  for (timestamp, values) in &trace.samples {
+    let _ = write!(csv, "{}", timestamp);
+    for column in 0..trace.columns.len() {
+      csv.push(',');
+      if let Some((value, _)) = values.get(column) {
+        let _ = write!(csv, "{}", value);
+      }
+    }
+    csv.push('\n');
+  }
+
+  std::fs::write(path, csv).map_err(|e| HayboxError::Io(format!("Failed to write {}: {}", path.display(), e)))?;
+  Ok(trace.samples.len())
+}
@@ -0,0 +1,155 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+#[cfg(target_os = "windows")]
+use windows::Win32::System::SystemInformation::{
+  IMAGE_FILE_MACHINE, IMAGE_FILE_MACHINE_AMD64, IMAGE_FILE_MACHINE_ARM64, IMAGE_FILE_MACHINE_I386,
+};
+#[cfg(target_os = "windows")]
+use windows::Win32::System::Threading::{GetCurrentProcess, IsWow64Process2};
+
+#[cfg(target_os = "windows")]
+use crate::registry::{self, HKEY_LOCAL_MACHINE};
+
+#[cfg(target_os = "windows")]
+const CURRENT_VERSION_KEY: &str = "SOFTWARE\\Microsoft\\Windows NT\\CurrentVersion";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Architecture {
+  X86,
+  X64,
+  Arm64,
+  Unknown,
+}
+
+impl Architecture {
+  /// Subdirectory of the driver resources holding binaries for this
+  /// architecture, named as in the WDK redistributables.
+  pub fn resource_dir_name(&self) -> Option<&'static str> {
+    match self {
+      Architecture::X86 => Some("x86"),
+      Architecture::X64 => Some("amd64"),
+      Architecture::Arm64 => Some("arm64"),
+      Architecture::Unknown => None,
+    }
+  }
+
+  #[cfg(target_os = "windows")]
+  fn from_machine(machine: IMAGE_FILE_MACHINE) -> Self {
+    match machine {
+      IMAGE_FILE_MACHINE_I386 => Architecture::X86,
+      IMAGE_FILE_MACHINE_AMD64 => Architecture::X64,
+      IMAGE_FILE_MACHINE_ARM64 => Architecture::Arm64,
+      _ => Architecture::Unknown,
+    }
+  }
+}
+
+impl std::fmt::Display for Architecture {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    match self {
+      Architecture::X86 => write!(f, "x86"),
+      Architecture::X64 => write!(f, "x64"),
+      Architecture::Arm64 => write!(f, "ARM64"),
+      Architecture::Unknown => write!(f, "an unknown architecture"),
+    }
+  }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ArchitectureInfo {
+  pub os_architecture: Architecture,
+  pub process_architecture: Architecture,
+  /// The app runs under WOW64 or x64 emulation rather than natively.
+  pub emulated: bool,
+  pub windows_build: Option<u32>,
+  /// Before Windows 10 the WDF coinstallers must ship with the driver.
+  pub coinstallers_required: bool,
+}
+
+/// The architecture this binary was built for.
+pub fn process_architecture() -> Architecture {
+  match std::env::consts::ARCH {
+    "x86" => Architecture::X86,
+    "x86_64" => Architecture::X64,
+    "aarch64" => Architecture::Arm64,
+    _ => Architecture::Unknown,
+  }
+}
+
+/// The architecture of Windows itself, which drivers and devcon must match
+/// even when the app runs emulated.
+#[cfg(target_os = "windows")]
+pub fn os_architecture() -> Architecture {
+  let mut process_machine = IMAGE_FILE_MACHINE::default();
+  let mut native_machine = IMAGE_FILE_MACHINE::default();
+  if unsafe { IsWow64Process2(GetCurrentProcess(), &mut process_machine, Some(&mut native_machine)) }.is_ok() {
+    return Architecture::from_machine(native_machine);
+  }
+  // IsWow64Process2 is missing before Windows 10 1511, where there is no
+  // ARM64 and WOW64 sets this variable for 32-bit processes.
+  match std::env::var("PROCESSOR_ARCHITEW6432").as_deref() {
+    Ok("AMD64") => Architecture::X64,
+    _ => process_architecture(),
+  }
+}
+
+/// Drivers are only installed on Windows, so elsewhere the process
+/// architecture is all that matters.
+#[cfg(not(target_os = "windows"))]
+pub fn os_architecture() -> Architecture {
+  process_architecture()
+}
+
+#[cfg(target_os = "windows")]
+pub fn windows_build() -> Option<u32> {
+  registry::read_string(HKEY_LOCAL_MACHINE, CURRENT_VERSION_KEY, "CurrentBuildNumber")?
+    .trim()
+    .parse()
+    .ok()
+}
+
+/// Windows 10 and later ship KMDF and WinUSB inbox. Only they have
+/// `CurrentMajorVersionNumber`; 8.1 reports itself as 6.3.
+#[cfg(target_os = "windows")]
+pub fn is_windows_10_or_later() -> bool {
+  registry::read_dword(HKEY_LOCAL_MACHINE, CURRENT_VERSION_KEY, "CurrentMajorVersionNumber")
+    .is_some_and(|major| major >= 10)
+}
+
+#[cfg(not(target_os = "windows"))]
+pub fn windows_build() -> Option<u32> {
+  None
+}
+
+#[cfg(not(target_os = "windows"))]
+pub fn is_windows_10_or_later() -> bool {
+  false
+}
+
+/// A driver binary for the OS architecture, as a path relative to the
+/// resource directory: `<arch>/<file>`, or the file itself in the flat
+/// layout, which only ever held x64 builds.
+pub fn resource_file(resource_dir: &Path, file_name: &str) -> Option<String> {
+  let architecture = os_architecture();
+  if let Some(dir_name) = architecture.resource_dir_name() {
+    let relative = format!("{}/{}", dir_name, file_name);
+    if resource_dir.join(&relative).is_file() {
+      return Some(relative);
+    }
+  }
+  (architecture == Architecture::X64 && resource_dir.join(file_name).is_file()).then(|| file_name.to_string())
+}
+
+pub fn architecture_info() -> ArchitectureInfo {
+  let os_architecture = os_architecture();
+  let process_architecture = process_architecture();
+  ArchitectureInfo {
+    os_architecture,
+    process_architecture,
+    emulated: os_architecture != process_architecture,
+    windows_build: windows_build(),
+    coinstallers_required: cfg!(target_os = "windows") && !is_windows_10_or_later(),
+  }
+}
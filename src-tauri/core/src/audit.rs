@@ -0,0 +1,137 @@
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::HayboxError;
+use crate::paths::app_data_dir;
+use crate::telemetry;
+
+const AUDIT_LOG_FILE: &str = "driver_audit.jsonl";
+const MAX_LOG_BYTES: u64 = 1024 * 1024;
+const MAX_ROTATED_LOGS: usize = 3;
+
+/// One change the app made (or tried to make) to the system's driver setup.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct AuditEntry {
+  pub timestamp: u64,
+  pub operation: String,
+  pub vendor_id: Option<u16>,
+  pub product_id: Option<u16>,
+  pub inf: Option<String>,
+  pub tool_output: Option<String>,
+  pub success: bool,
+  pub message: String,
+  /// The `HayboxError` code of a failure.
+  pub error_code: Option<String>,
+}
+
+impl AuditEntry {
+  pub fn new(operation: &str) -> Self {
+    Self {
+      timestamp: SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default(),
+      operation: operation.to_string(),
+      ..Default::default()
+    }
+  }
+
+  pub fn target(mut self, vendor_id: u16, product_id: u16) -> Self {
+    self.vendor_id = Some(vendor_id);
+    self.product_id = Some(product_id);
+    self
+  }
+
+  pub fn inf(mut self, inf: &str) -> Self {
+    self.inf = Some(inf.to_string());
+    self
+  }
+
+  pub fn tool_output(mut self, output: &str) -> Self {
+    self.tool_output = Some(output.trim().to_string());
+    self
+  }
+
+  pub fn outcome<T, E: Clone + Into<HayboxError>>(mut self, result: &Result<T, E>) -> Self {
+    match result {
+      Ok(_) => self.success = true,
+      Err(e) => {
+        let error: HayboxError = e.clone().into();
+        self.message = error.to_string();
+        self.error_code = Some(error.code().to_string());
+      }
+    }
+    self
+  }
+
+  pub fn record(self) {
+    telemetry::count_outcome(&self);
+    if let Err(e) = append(&self) {
+      tracing::warn!("failed to write driver audit log: {}", e);
+    }
+  }
+}
+
+fn log_path(generation: usize) -> PathBuf {
+  match generation {
+    0 => app_data_dir().join(AUDIT_LOG_FILE),
+    n => app_data_dir().join(format!("driver_audit.{}.jsonl", n)),
+  }
+}
+
+fn rotate_if_needed() -> std::io::Result<()> {
+  let current = log_path(0);
+  let size = std::fs::metadata(&current).map(|m| m.len()).unwrap_or(0);
+  if size < MAX_LOG_BYTES {
+    return Ok(());
+  }
+
+  for generation in (1..MAX_ROTATED_LOGS).rev() {
+    let from = log_path(generation);
+    if from.exists() {
+      std::fs::rename(&from, log_path(generation + 1))?;
+    }
+  }
+  std::fs::rename(&current, log_path(1))
+}
+
+fn append(entry: &AuditEntry) -> std::io::Result<()> {
+  rotate_if_needed()?;
+
+  let line = serde_json::to_string(entry).map_err(std::io::Error::other)?;
+  let mut file = std::fs::OpenOptions::new()
+    .create(true)
+    .append(true)
+    .open(log_path(0))?;
+  writeln!(file, "{}", line)
+}
+
+/// Returns up to `limit` entries, newest first, across the current and rotated
+/// log files.
+pub fn read_audit_log(limit: usize) -> Vec<AuditEntry> {
+  let mut entries = Vec::new();
+
+  for generation in 0..=MAX_ROTATED_LOGS {
+    let content = match std::fs::read_to_string(log_path(generation)) {
+      Ok(content) => content,
+      Err(_) => continue,
+    };
+
+    let mut file_entries: Vec<AuditEntry> = content
+      .lines()
+      .filter_map(|line| serde_json::from_str(line).ok())
+      .collect();
+    file_entries.reverse();
+    entries.extend(file_entries);
+
+    if entries.len() >= limit {
+      break;
+    }
+  }
+
+  entries.truncate(limit);
+  entries
+}
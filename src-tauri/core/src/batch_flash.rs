@@ -0,0 +1,172 @@
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+#[cfg(target_os = "windows")]
+use windows::core::PCWSTR;
+#[cfg(target_os = "windows")]
+use windows::Win32::Storage::FileSystem::GetVolumeInformationW;
+
+use crate::error::HayboxError;
+use crate::events::Events;
+use crate::flash_target::{self, FlashTarget};
+use crate::flashing::{self, copy_to_volume};
+#[cfg(target_os = "windows")]
+use crate::integrity::to_wide;
+use crate::uf2;
+
+/// How long a drive may stay mounted after the last block before the unit is
+/// considered not to have rebooted.
+const REBOOT_TIMEOUT: Duration = Duration::from_secs(10);
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum UnitStatus {
+  Queued,
+  Copying,
+  Rebooting,
+  Done,
+  Failed,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BatchUnit {
+  pub volume: String,
+  /// Volume serial of the RPI-RP2 drive, which tells units apart when drive
+  /// letters get reused.
+  pub volume_serial: Option<u32>,
+  pub status: UnitStatus,
+  pub bytes_written: u64,
+  pub total_bytes: u64,
+  pub message: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BatchFlashResult {
+  pub units: Vec<BatchUnit>,
+  pub succeeded: usize,
+  pub failed: usize,
+}
+
+#[cfg(target_os = "windows")]
+fn volume_serial(volume: &Path) -> Option<u32> {
+  let root = to_wide(&volume.display().to_string());
+  let mut serial = 0u32;
+  unsafe { GetVolumeInformationW(PCWSTR(root.as_ptr()), None, Some(&mut serial), None, None, None) }.ok()?;
+  Some(serial)
+}
+
+/// Only Windows exposes the FAT serial without reading the boot sector.
+#[cfg(not(target_os = "windows"))]
+fn volume_serial(_volume: &Path) -> Option<u32> {
+  None
+}
+
+/// The queue, emitted as a whole as `batch_flash_progress` on every change.
+struct Queue<'a> {
+  events: &'a Events,
+  units: Mutex<Vec<BatchUnit>>,
+}
+
+impl Queue<'_> {
+  fn update(&self, index: usize, change: impl FnOnce(&mut BatchUnit)) {
+    let mut units = self.units.lock().unwrap();
+    change(&mut units[index]);
+    self.events.emit("batch_flash_progress", units.clone());
+  }
+
+  fn fail(&self, index: usize, message: String) {
+    self.update(index, |unit| {
+      unit.status = UnitStatus::Failed;
+      unit.message = Some(message);
+    });
+  }
+
+  fn flash_unit(&self, index: usize, volume: &Path, data: &[u8], file_name: &std::ffi::OsStr) {
+    let target = flashing::volume_target(volume).unwrap_or(FlashTarget::Rp2040);
+    if let Err(e) = uf2::validate_for(data, target.uf2_families()) {
+      self.fail(index, e.to_string());
+      return;
+    }
+
+    self.update(index, |unit| unit.status = UnitStatus::Copying);
+    let copied = copy_to_volume(data, volume, file_name, |bytes_written| {
+      self.update(index, |unit| unit.bytes_written = bytes_written)
+    });
+    if let Err(e) = copied {
+      self.fail(index, e.to_string());
+      return;
+    }
+
+    // The boot ROM drops the drive when it reboots into the new firmware.
+    self.update(index, |unit| unit.status = UnitStatus::Rebooting);
+    let deadline = Instant::now() + REBOOT_TIMEOUT;
+    while flashing::is_bootsel_volume(volume) {
+      if Instant::now() >= deadline {
+        self.fail(index, "Drive is still mounted; the unit did not reboot".to_string());
+        return;
+      }
+      std::thread::sleep(POLL_INTERVAL);
+    }
+    self.update(index, |unit| unit.status = UnitStatus::Done);
+  }
+}
+
+/// Flashes `path` onto every mounted RPI-RP2 drive, one after another or all
+/// at once, and reports each unit's outcome. A unit counts as done once its
+/// drive disappears, since several controllers coming back in the same mode
+/// cannot be told apart.
+pub fn batch_flash(events: &Events, path: &Path, parallel: bool) -> Result<BatchFlashResult, HayboxError> {
+  let data = std::fs::read(path).map_err(|e| HayboxError::Io(format!("Failed to read {}: {}", path.display(), e)))?;
+  uf2::validate_for(&data, &flash_target::all_uf2_families())?;
+  let file_name = path
+    .file_name()
+    .ok_or_else(|| HayboxError::Other("Invalid firmware file name".to_string()))?;
+
+  let volumes: Vec<PathBuf> = flashing::find_bootsel_volumes();
+  if volumes.is_empty() {
+    return Err(HayboxError::DeviceNotConnected(
+      "No RPI-RP2 drives found; put the controllers in BOOTSEL mode first".to_string(),
+    ));
+  }
+
+  let queue = Queue {
+    events,
+    units: Mutex::new(
+      volumes
+        .iter()
+        .map(|volume| BatchUnit {
+          volume: volume.display().to_string(),
+          volume_serial: volume_serial(volume),
+          status: UnitStatus::Queued,
+          bytes_written: 0,
+          total_bytes: data.len() as u64,
+          message: None,
+        })
+        .collect(),
+    ),
+  };
+
+  if parallel {
+    std::thread::scope(|scope| {
+      for (index, volume) in volumes.iter().enumerate() {
+        let (queue, data) = (&queue, &data);
+        scope.spawn(move || queue.flash_unit(index, volume, data, file_name));
+      }
+    });
+  } else {
+    for (index, volume) in volumes.iter().enumerate() {
+      queue.flash_unit(index, volume, &data, file_name);
+    }
+  }
+
+  let units = queue.units.into_inner().unwrap();
+  let succeeded = units.iter().filter(|unit| unit.status == UnitStatus::Done).count();
+  Ok(BatchFlashResult {
+    failed: units.len() - succeeded,
+    succeeded,
+    units,
+  })
+}
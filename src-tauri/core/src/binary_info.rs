@@ -0,0 +1,232 @@
+use serde::{Deserialize, Serialize};
+
+/// Bracket the `binary_info_header` the Pico SDK places near the start of
+/// every image.
+const HEADER_MAGIC_START: u32 = 0x7188_EBF2;
+const HEADER_MAGIC_END: u32 = 0xE71A_A390;
+/// Flash images start with the 256-byte boot2 stage; RAM-only images don't.
+const SEARCH_BASES: &[u32] = &[0x1000_0100, 0x2000_0000];
+const SEARCH_WORDS: u32 = 64;
+
+const TYPE_ID_AND_STRING: u16 = 6;
+const TYPE_PINS_WITH_FUNC: u16 = 8;
+const TYPE_PINS_WITH_NAME: u16 = 9;
+/// `BINARY_INFO_MAKE_TAG('R', 'P')`.
+const TAG_RASPBERRY_PI: u16 = 0x5052;
+const ID_PROGRAM_NAME: u32 = 0x0203_1C86;
+const ID_PROGRAM_VERSION: u32 = 0x11A9_BC3A;
+const ID_PROGRAM_BUILD_DATE: u32 = 0x9DA2_2254;
+const ID_SDK_VERSION: u32 = 0x5360_B3AB;
+const ID_PICO_BOARD: u32 = 0xB63C_FFBB;
+const ID_PROGRAM_FEATURE: u32 = 0xA1F4_B453;
+
+/// `BI_PINS_ENCODING_*`: a low..=high range or up to five listed pins.
+const PINS_ENCODING_RANGE: u32 = 1;
+const PINS_ENCODING_MULTI: u32 = 2;
+/// GPIO function names, indexed by the RP2040 `GPIO_FUNC_*` value.
+const GPIO_FUNCTIONS: &[&str] = &["XIP", "SPI", "UART", "I2C", "PWM", "SIO", "PIO0", "PIO1", "GPCK", "USB"];
+
+const MAX_ENTRIES: u32 = 256;
+const MAX_STRING_LEN: u32 = 256;
+
+/// The strings `picotool info` shows, read from the image's binary info.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct BinaryInfo {
+  pub program_name: Option<String>,
+  pub version: Option<String>,
+  pub build_date: Option<String>,
+  pub sdk_version: Option<String>,
+  pub board: Option<String>,
+  pub features: Vec<String>,
+  /// Pins the firmware declared, in declaration order.
+  pub pins: Vec<PinMapping>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PinMapping {
+  pub pin: u8,
+  /// A GPIO function such as `UART` or a name such as `A Button`.
+  pub function: String,
+}
+
+/// An image accessed by target address, through a UF2 file or PICOBOOT.
+struct Image<R> {
+  read: R,
+  /// (flash source, RAM destination start, RAM destination end) for data
+  /// that crt0 copies into RAM before `main`.
+  mappings: Vec<(u32, u32, u32)>,
+}
+
+impl<R: Fn(u32, usize) -> Option<Vec<u8>>> Image<R> {
+  fn translate(&self, addr: u32) -> u32 {
+    self
+      .mappings
+      .iter()
+      .find(|(_, start, end)| addr >= *start && addr < *end)
+      .map(|(source, start, _)| source.wrapping_add(addr - start))
+      .unwrap_or(addr)
+  }
+
+  fn word(&self, addr: u32) -> Option<u32> {
+    let bytes = (self.read)(self.translate(addr), 4)?;
+    Some(u32::from_le_bytes(bytes.try_into().ok()?))
+  }
+
+  fn string(&self, addr: u32) -> Option<String> {
+    let mut bytes = Vec::new();
+    for offset in 0..MAX_STRING_LEN {
+      let byte = (self.read)(self.translate(addr.checked_add(offset)?), 1)?[0];
+      if byte == 0 {
+        return Some(String::from_utf8_lossy(&bytes).to_string());
+      }
+      bytes.push(byte);
+    }
+    None
+  }
+}
+
+fn find_header<R: Fn(u32, usize) -> Option<Vec<u8>>>(image: &Image<R>) -> Option<(u32, u32, u32)> {
+  SEARCH_BASES.iter().find_map(|base| {
+    (0..SEARCH_WORDS).find_map(|index| {
+      let addr = base + index * 4;
+      if image.word(addr)? != HEADER_MAGIC_START || image.word(addr + 16)? != HEADER_MAGIC_END {
+        return None;
+      }
+      Some((image.word(addr + 4)?, image.word(addr + 8)?, image.word(addr + 12)?))
+    })
+  })
+}
+
+fn read_mappings<R: Fn(u32, usize) -> Option<Vec<u8>>>(image: &Image<R>, table: u32) -> Vec<(u32, u32, u32)> {
+  let mut mappings = Vec::new();
+  let mut addr = table;
+  while let (Some(source), Some(start), Some(end)) = (
+    image.word(addr),
+    image.word(addr.wrapping_add(4)),
+    image.word(addr.wrapping_add(8)),
+  ) {
+    if source == 0 || mappings.len() >= MAX_ENTRIES as usize {
+      break;
+    }
+    mappings.push((source, start, end));
+    addr = addr.wrapping_add(12);
+  }
+  mappings
+}
+
+fn pins_with_func(encoding: u32) -> Vec<PinMapping> {
+  let function = GPIO_FUNCTIONS
+    .get(((encoding >> 3) & 0xF) as usize)
+    .copied()
+    .unwrap_or("Unknown")
+    .to_string();
+  let pin_at = |index: u32| ((encoding >> (7 + index * 5)) & 0x1F) as u8;
+
+  let pins: Vec<u8> = match encoding & 0x7 {
+    PINS_ENCODING_RANGE => (pin_at(0)..=pin_at(1)).collect(),
+    PINS_ENCODING_MULTI => {
+      // Unused slots repeat the last pin.
+      let mut pins = vec![pin_at(0)];
+      for index in 1..5 {
+        let pin = pin_at(index);
+        if Some(&pin) == pins.last() {
+          break;
+        }
+        pins.push(pin);
+      }
+      pins
+    }
+    _ => vec![],
+  };
+
+  pins
+    .into_iter()
+    .map(|pin| PinMapping {
+      pin,
+      function: function.clone(),
+    })
+    .collect()
+}
+
+/// `label` holds one `|`-separated name per set bit of `mask`, lowest first.
+fn pins_with_name(mask: u32, label: &str) -> Vec<PinMapping> {
+  (0..32u8)
+    .filter(|pin| mask & (1 << pin) != 0)
+    .zip(label.split('|'))
+    .map(|(pin, name)| PinMapping {
+      pin,
+      function: name.to_string(),
+    })
+    .collect()
+}
+
+/// Parses the Pico SDK binary info from an image, where `read(addr, len)`
+/// returns `len` bytes at target address `addr` or `None` if the image does
+/// not cover them. Images without binary info yield an empty result.
+pub fn parse(read: impl Fn(u32, usize) -> Option<Vec<u8>>) -> BinaryInfo {
+  let mut image = Image {
+    read,
+    mappings: Vec::new(),
+  };
+  let mut info = BinaryInfo::default();
+
+  let Some((start, end, mapping_table)) = find_header(&image) else {
+    return info;
+  };
+  image.mappings = read_mappings(&image, mapping_table);
+
+  let count = (end.saturating_sub(start) / 4).min(MAX_ENTRIES);
+  for index in 0..count {
+    let Some(entry) = image.word(start.wrapping_add(index * 4)) else {
+      continue;
+    };
+    let Some(core) = image.word(entry) else {
+      continue;
+    };
+    if (core >> 16) as u16 != TAG_RASPBERRY_PI {
+      continue;
+    }
+    match core as u16 {
+      TYPE_ID_AND_STRING => {}
+      TYPE_PINS_WITH_FUNC => {
+        if let Some(encoding) = image.word(entry.wrapping_add(4)) {
+          info.pins.extend(pins_with_func(encoding));
+        }
+        continue;
+      }
+      TYPE_PINS_WITH_NAME => {
+        if let (Some(mask), Some(label)) = (
+          image.word(entry.wrapping_add(4)),
+          image.word(entry.wrapping_add(8)).and_then(|addr| image.string(addr)),
+        ) {
+          info.pins.extend(pins_with_name(mask, &label));
+        }
+        continue;
+      }
+      _ => continue,
+    }
+
+    let (Some(id), Some(value)) = (image.word(entry.wrapping_add(4)), image.word(entry.wrapping_add(8))) else {
+      continue;
+    };
+
+    if id == ID_PROGRAM_FEATURE {
+      info.features.extend(image.string(value));
+      continue;
+    }
+
+    let slot = match id {
+      ID_PROGRAM_NAME => &mut info.program_name,
+      ID_PROGRAM_VERSION => &mut info.version,
+      ID_PROGRAM_BUILD_DATE => &mut info.build_date,
+      ID_SDK_VERSION => &mut info.sdk_version,
+      ID_PICO_BOARD => &mut info.board,
+      _ => continue,
+    };
+    if slot.is_none() {
+      *slot = image.string(value);
+    }
+  }
+
+  info
+}
@@ -0,0 +1,305 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::time::Duration;
+
+#[cfg(target_os = "windows")]
+use regex::Regex;
+use rusb::UsbContext;
+use serde::{Deserialize, Serialize};
+#[cfg(target_os = "windows")]
+use windows::core::PCWSTR;
+#[cfg(target_os = "windows")]
+use windows::Win32::Devices::Communication::{GetCommState, SetCommState, DCB};
+#[cfg(target_os = "windows")]
+use windows::Win32::Foundation::{CloseHandle, GENERIC_READ, GENERIC_WRITE};
+#[cfg(target_os = "windows")]
+use windows::Win32::Storage::FileSystem::{CreateFileW, FILE_ATTRIBUTE_NORMAL, FILE_SHARE_MODE, OPEN_EXISTING};
+
+use crate::binary_info::{self, BinaryInfo};
+use crate::device_mode::DeviceMode;
+use crate::error::HayboxError;
+use crate::hotplug::{self, HotplugKind};
+#[cfg(target_os = "windows")]
+use crate::integrity::to_wide;
+use crate::picoboot::{Picoboot, FLASH_START};
+#[cfg(target_os = "windows")]
+use crate::wmi_worker;
+use crate::{UsbDeviceInfo, DEVICES};
+
+/// Opening the CDC port at this rate and closing it is the Arduino-style
+/// "1200 baud touch" that reboots the RP2040 into BOOTSEL.
+const TOUCH_BAUD_RATE: u32 = 1200;
+/// The Pico SDK's reset interface: vendor class, subclass 0, protocol 1.
+const RESET_INTERFACE_CLASS: u8 = 0xFF;
+const RESET_INTERFACE_SUBCLASS: u8 = 0x00;
+const RESET_INTERFACE_PROTOCOL: u8 = 0x01;
+/// Vendor, interface recipient, host to device.
+const RESET_REQUEST_TYPE: u8 = 0x41;
+const RESET_REQUEST_BOOTSEL: u8 = 0x01;
+const CONTROL_TIMEOUT: Duration = Duration::from_millis(500);
+const BOOTSEL_TIMEOUT: Duration = Duration::from_secs(10);
+/// The boot ROM version byte follows the `Mu\x01` magic at 0x10.
+const ROM_VERSION_ADDR: u32 = 0x10;
+/// RP2040 supports up to 16 MB of external flash.
+const FLASH_WINDOW: u32 = 16 * 1024 * 1024;
+/// Flash is read in pages and cached, since binary info parsing reads
+/// strings a byte at a time.
+const PAGE_SIZE: u32 = 256;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RebootMethod {
+  SerialTouch,
+  ResetInterface,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BootselResult {
+  pub method: RebootMethod,
+  /// Whether the BOOTSEL device showed up afterwards.
+  pub confirmed: bool,
+  pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BootselInfo {
+  pub rom_version: Option<u8>,
+  /// What `picotool info` reports for the program in flash.
+  pub binary_info: BinaryInfo,
+}
+
+#[cfg(target_os = "windows")]
+#[derive(Debug, Deserialize)]
+struct WmiSerialPort {
+  #[serde(rename = "Name")]
+  name: Option<String>,
+}
+
+/// COM port of the device's CDC interface, e.g. `COM5`.
+#[cfg(target_os = "windows")]
+fn find_com_port(vendor_id: u16, product_id: u16) -> Result<Option<String>, HayboxError> {
+  let query = format!(
+    "SELECT Name FROM Win32_PnPEntity WHERE DeviceID LIKE '%VID\\_{0:04X}%' AND DeviceID LIKE '%PID\\_{1:04X}%' AND Name LIKE '%(COM%'",
+    vendor_id, product_id
+  );
+
+  let ports: Vec<WmiSerialPort> = wmi_worker::query(&query)?;
+
+  let re = Regex::new(r"\((COM\d+)\)").unwrap();
+  Ok(
+    ports
+      .iter()
+      .filter_map(|port| port.name.as_deref())
+      .find_map(|name| re.captures(name).map(|caps| caps[1].to_string())),
+  )
+}
+
+/// The device's CDC port, e.g. `/dev/ttyACM0`.
+#[cfg(not(target_os = "windows"))]
+fn find_com_port(vendor_id: u16, product_id: u16) -> Result<Option<String>, HayboxError> {
+  Ok(
+    crate::serial_ports::get_serial_ports_for_device(vendor_id, product_id)?
+      .into_iter()
+      .next()
+      .map(|port| port.port),
+  )
+}
+
+#[cfg(target_os = "windows")]
+fn serial_touch(port: &str) -> Result<(), HayboxError> {
+  let path = to_wide(&format!("\\\\.\\{}", port));
+  let handle = unsafe {
+    CreateFileW(
+      PCWSTR(path.as_ptr()),
+      GENERIC_READ.0 | GENERIC_WRITE.0,
+      FILE_SHARE_MODE(0),
+      None,
+      OPEN_EXISTING,
+      FILE_ATTRIBUTE_NORMAL,
+      None,
+    )
+  }
+  .map_err(|e| HayboxError::SerialPort(format!("Failed to open {}: {}", port, e)))?;
+
+  let mut dcb = DCB {
+    DCBlength: std::mem::size_of::<DCB>() as u32,
+    ..Default::default()
+  };
+  let result = unsafe { GetCommState(handle, &mut dcb) }
+    .and_then(|_| {
+      dcb.BaudRate = TOUCH_BAUD_RATE;
+      unsafe { SetCommState(handle, &dcb) }
+    })
+    .map_err(|e| HayboxError::SerialPort(format!("Failed to set {} to {} baud: {}", port, TOUCH_BAUD_RATE, e)));
+
+  // Closing the port drops DTR, which is what triggers the reboot.
+  let _ = unsafe { CloseHandle(handle) };
+  result
+}
+
+/// The termios driver drops DTR when the port is closed, as on Windows.
+#[cfg(not(target_os = "windows"))]
+fn serial_touch(port: &str) -> Result<(), HayboxError> {
+  serialport::new(port, TOUCH_BAUD_RATE)
+    .timeout(CONTROL_TIMEOUT)
+    .open()
+    .map(drop)
+    .map_err(|e| HayboxError::SerialPort(format!("Failed to open {} at {} baud: {}", port, TOUCH_BAUD_RATE, e)))
+}
+
+fn reset_interface_request(device: &UsbDeviceInfo) -> Result<(), HayboxError> {
+  let context = rusb::Context::new().map_err(|e| HayboxError::Usb(format!("Failed to create USB context: {}", e)))?;
+  let handle = context
+    .open_device_with_vid_pid(device.vid, device.pid)
+    .ok_or_else(|| HayboxError::Usb(format!("Could not open {}", device.name)))?;
+
+  let config = handle
+    .device()
+    .active_config_descriptor()
+    .map_err(|e| HayboxError::Usb(format!("Failed to read configuration descriptor: {}", e)))?;
+  let interface = config
+    .interfaces()
+    .flat_map(|interface| interface.descriptors())
+    .find(|descriptor| {
+      descriptor.class_code() == RESET_INTERFACE_CLASS
+        && descriptor.sub_class_code() == RESET_INTERFACE_SUBCLASS
+        && descriptor.protocol_code() == RESET_INTERFACE_PROTOCOL
+    })
+    .map(|descriptor| descriptor.interface_number())
+    .ok_or_else(|| HayboxError::Usb(format!("{} has no reset interface", device.name)))?;
+
+  // The device drops off the bus before it can acknowledge, so a pipe or
+  // I/O error here still means the request arrived.
+  match handle.write_control(
+    RESET_REQUEST_TYPE,
+    RESET_REQUEST_BOOTSEL,
+    0,
+    interface as u16,
+    &[],
+    CONTROL_TIMEOUT,
+  ) {
+    Ok(_) | Err(rusb::Error::Pipe) | Err(rusb::Error::Io) | Err(rusb::Error::NoDevice) => Ok(()),
+    Err(e) => Err(HayboxError::Usb(format!("Reset request failed: {}", e))),
+  }
+}
+
+/// Sends the BOOTSEL request to whichever firmware mode answers it.
+fn reset_any_interface() -> Result<(), HayboxError> {
+  let mut errors = Vec::new();
+  for mode in DeviceMode::FIRMWARE {
+    let Some(device) = mode.device() else {
+      continue;
+    };
+    match reset_interface_request(device) {
+      Ok(()) => return Ok(()),
+      Err(e) => errors.push(e.to_string()),
+    }
+  }
+  Err(HayboxError::Usb(format!(
+    "Could not reboot the controller: {}",
+    errors.join("; ")
+  )))
+}
+
+/// Sends the reboot request with `method` without waiting for the BOOTSEL
+/// device to appear.
+pub fn request_bootsel(method: RebootMethod) -> Result<(), HayboxError> {
+  match method {
+    RebootMethod::SerialTouch => {
+      let config = &DEVICES.config_mode;
+      let port = find_com_port(config.vid, config.pid)?.ok_or_else(|| {
+        HayboxError::DeviceNotConnected("No serial port found for the controller in config mode".to_string())
+      })?;
+      serial_touch(&port)
+    }
+    RebootMethod::ResetInterface => reset_any_interface(),
+  }
+}
+
+/// Reboots the controller into BOOTSEL, through the CDC port in config mode
+/// or the Pico SDK reset interface otherwise, then waits for the hotplug
+/// watcher to see the BOOTSEL device arrive.
+pub fn enter_bootsel_mode() -> Result<BootselResult, HayboxError> {
+  let bootsel = &DEVICES.bootsel_mode;
+  let config = &DEVICES.config_mode;
+
+  let waiter = hotplug::waiter();
+  let method = match find_com_port(config.vid, config.pid)? {
+    Some(port) => {
+      serial_touch(&port)?;
+      RebootMethod::SerialTouch
+    }
+    None => {
+      reset_any_interface()?;
+      RebootMethod::ResetInterface
+    }
+  };
+
+  let confirmed = waiter
+    .wait(BOOTSEL_TIMEOUT, |event| {
+      event.kind == HotplugKind::Arrived && event.vendor_id == bootsel.vid && event.product_id == bootsel.pid
+    })
+    .is_some();
+
+  Ok(BootselResult {
+    method,
+    confirmed,
+    message: if confirmed {
+      "Controller rebooted into BOOTSEL mode".to_string()
+    } else {
+      "Reboot request sent, but no BOOTSEL device appeared; hold BOOTSEL while plugging in instead".to_string()
+    },
+  })
+}
+
+/// Reads the boot ROM version and the flashed program's binary info over
+/// PICOBOOT while the controller sits in BOOTSEL mode.
+pub fn get_bootsel_info() -> Result<BootselInfo, HayboxError> {
+  let mut picoboot = Picoboot::open()?;
+  picoboot.exclusive_access(true)?;
+
+  let mut rom_magic = [0u8; 4];
+  let rom_version = picoboot
+    .read(ROM_VERSION_ADDR, &mut rom_magic)
+    .ok()
+    .filter(|_| rom_magic[..3] == *b"Mu\x01")
+    .map(|_| rom_magic[3]);
+
+  picoboot.exit_xip()?;
+
+  let picoboot = RefCell::new(picoboot);
+  let pages: RefCell<HashMap<u32, Option<Vec<u8>>>> = RefCell::new(HashMap::new());
+  let read_page = |page: u32| -> Option<Vec<u8>> {
+    pages
+      .borrow_mut()
+      .entry(page)
+      .or_insert_with(|| {
+        let mut buffer = vec![0u8; PAGE_SIZE as usize];
+        picoboot.borrow_mut().read(page, &mut buffer).ok().map(|_| buffer)
+      })
+      .clone()
+  };
+
+  let binary_info = binary_info::parse(|addr, len| {
+    let end = addr.checked_add(len as u32)?;
+    if addr < FLASH_START || end > FLASH_START + FLASH_WINDOW {
+      return None;
+    }
+    let mut out = Vec::with_capacity(len);
+    let mut cursor = addr;
+    while cursor < end {
+      let page = cursor - cursor % PAGE_SIZE;
+      let data = read_page(page)?;
+      let take = (end.min(page + PAGE_SIZE) - cursor) as usize;
+      let offset = (cursor - page) as usize;
+      out.extend_from_slice(&data[offset..offset + take]);
+      cursor += take as u32;
+    }
+    Some(out)
+  });
+
+  Ok(BootselInfo {
+    rom_version,
+    binary_info,
+  })
+}
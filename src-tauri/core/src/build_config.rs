@@ -0,0 +1,140 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::binary_info::PinMapping;
+use crate::error::HayboxError;
+use crate::{bootsel, firmware, is_device_connected_batch, DEVICES};
+
+const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BuildConfigFormat {
+  Json,
+  /// A `config.h`-style header of `#define`s.
+  Header,
+}
+
+/// What the app can read off the connected controller, in a form that can be
+/// carried over into a custom HayBox build.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct BuildConfig {
+  pub schema_version: u32,
+  /// The USB mode the settings were read in.
+  pub source_mode: String,
+  pub program_name: Option<String>,
+  pub firmware_version: Option<String>,
+  pub board: Option<String>,
+  /// Communication backend the controller enumerates with by default.
+  pub backend: Option<String>,
+  pub pins: Vec<PinMapping>,
+}
+
+fn backend_for_mode(vid: u16, pid: u16) -> Option<&'static str> {
+  if (vid, pid) == (DEVICES.default_mode.vid, DEVICES.default_mode.pid) {
+    Some("xinput")
+  } else if (vid, pid) == (DEVICES.switch_mode.vid, DEVICES.switch_mode.pid) {
+    Some("switch")
+  } else if (vid, pid) == (DEVICES.gamecube_mode.vid, DEVICES.gamecube_mode.pid) {
+    Some("gamecube_adapter")
+  } else {
+    None
+  }
+}
+
+/// Reads the controller's settings. In BOOTSEL mode the board and pin
+/// mappings come from the firmware's binary info; otherwise only the version
+/// and backend are known.
+pub fn gather() -> Result<BuildConfig, HayboxError> {
+  let bootsel = &DEVICES.bootsel_mode;
+  if is_device_connected_batch(&[(bootsel.vid, bootsel.pid)])[0] {
+    let info = bootsel::get_bootsel_info()?.binary_info;
+    return Ok(BuildConfig {
+      schema_version: SCHEMA_VERSION,
+      source_mode: bootsel.name.clone(),
+      program_name: info.program_name,
+      firmware_version: info.version,
+      board: info.board,
+      backend: None,
+      pins: info.pins,
+    });
+  }
+
+  let (source_mode, firmware_version, _) = firmware::read_device_version()
+    .ok_or_else(|| HayboxError::DeviceNotConnected("No HayBox controller is connected".to_string()))?;
+  let modes = [
+    &DEVICES.default_mode,
+    &DEVICES.config_mode,
+    &DEVICES.switch_mode,
+    &DEVICES.gamecube_mode,
+  ];
+  let backend = modes
+    .iter()
+    .find(|mode| mode.name == source_mode)
+    .and_then(|mode| backend_for_mode(mode.vid, mode.pid))
+    .map(str::to_string);
+
+  Ok(BuildConfig {
+    schema_version: SCHEMA_VERSION,
+    source_mode,
+    firmware_version,
+    backend,
+    ..Default::default()
+  })
+}
+
+fn define_name(name: &str) -> String {
+  name
+    .chars()
+    .map(|c| {
+      if c.is_ascii_alphanumeric() {
+        c.to_ascii_uppercase()
+      } else {
+        '_'
+      }
+    })
+    .collect()
+}
+
+fn render_header(config: &BuildConfig) -> String {
+  let mut out = format!(
+    "// Generated by HayBox Debugger from a controller in {}.\n#pragma once\n\n",
+    config.source_mode
+  );
+  let strings = [
+    ("HAYBOX_PROGRAM_NAME", &config.program_name),
+    ("HAYBOX_FIRMWARE_VERSION", &config.firmware_version),
+    ("HAYBOX_BOARD", &config.board),
+    ("HAYBOX_DEFAULT_BACKEND", &config.backend),
+  ];
+  for (name, value) in strings {
+    if let Some(value) = value {
+      out.push_str(&format!("#define {} \"{}\"\n", name, value.replace('"', "\\\"")));
+    }
+  }
+
+  if !config.pins.is_empty() {
+    out.push_str("\n// GPIO assignments\n");
+    for mapping in &config.pins {
+      out.push_str(&format!(
+        "#define PIN_{} {}\n",
+        define_name(&mapping.function),
+        mapping.pin
+      ));
+    }
+  }
+  out
+}
+
+/// Writes the controller's settings to `path` as JSON or a header.
+pub fn export_build_config(path: &Path, format: BuildConfigFormat) -> Result<BuildConfig, HayboxError> {
+  let config = gather()?;
+  let content = match format {
+    BuildConfigFormat::Json => serde_json::to_string_pretty(&config)
+      .map_err(|e| HayboxError::Other(format!("Failed to serialize build config: {}", e)))?,
+    BuildConfigFormat::Header => render_header(&config),
+  };
+  std::fs::write(path, content).map_err(|e| HayboxError::Io(format!("Failed to write {}: {}", path.display(), e)))?;
+  Ok(config)
+}
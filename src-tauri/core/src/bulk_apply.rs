@@ -0,0 +1,93 @@
+use serde::{Deserialize, Serialize};
+
+use crate::config_file;
+use crate::config_proto::{self, Config, ConfigConnection};
+use crate::error::HayboxError;
+use crate::serial_ports;
+use crate::DEVICES;
+
+/// Narrows which connected controllers are written. Empty fields match
+/// everything.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct DeviceFilter {
+  #[serde(default)]
+  pub serial_numbers: Vec<String>,
+  /// Matched case-insensitively against the name the firmware reports.
+  #[serde(default)]
+  pub device_name: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DeviceApplyResult {
+  pub port: String,
+  pub serial_number: Option<String>,
+  pub device_name: Option<String>,
+  pub success: bool,
+  pub message: String,
+}
+
+fn name_matches(filter: &DeviceFilter, conn: &ConfigConnection) -> bool {
+  filter
+    .device_name
+    .as_ref()
+    .is_none_or(|name| conn.device_info.device_name.eq_ignore_ascii_case(name))
+}
+
+fn write(conn: &mut ConfigConnection, filter: &DeviceFilter, config: &Config) -> Result<Option<String>, HayboxError> {
+  if !name_matches(filter, conn) {
+    return Ok(None);
+  }
+  conn.set_config(config)?;
+  Ok(Some(conn.device_info.device_name.clone()))
+}
+
+/// Writes `config` to every controller in config mode that passes `filter`,
+/// one at a time. Controllers still in game mode are not touched; they
+/// have to be plugged in holding Start first.
+pub fn apply_config_to_all(config: &Config, filter: &DeviceFilter) -> Result<Vec<DeviceApplyResult>, HayboxError> {
+  config_file::validate_config(config)?;
+
+  let device = &DEVICES.config_mode;
+  let ports: Vec<_> = serial_ports::get_serial_ports_for_device(device.vid, device.pid)?
+    .into_iter()
+    .filter(|port| {
+      filter.serial_numbers.is_empty()
+        || port
+          .serial_number
+          .as_ref()
+          .is_some_and(|serial| filter.serial_numbers.contains(serial))
+    })
+    .collect();
+  if ports.is_empty() {
+    return Err(HayboxError::DeviceNotConnected(
+      "No matching controllers are in config mode".to_string(),
+    ));
+  }
+
+  // The app's own connection keeps its port open, so that controller is
+  // written through it rather than a second handle.
+  let connected_port = config_proto::with_connection(|conn| Ok(conn.port_name().to_string())).ok();
+
+  let mut results = Vec::new();
+  for port in ports {
+    let outcome = if connected_port.as_deref() == Some(port.port.as_str()) {
+      config_proto::with_connection(|conn| write(conn, filter, config))
+    } else {
+      ConfigConnection::open(&port.port).and_then(|mut conn| write(&mut conn, filter, config))
+    };
+
+    let (success, device_name, message) = match outcome {
+      Ok(Some(name)) => (true, Some(name), "Config written".to_string()),
+      Ok(None) => continue,
+      Err(e) => (false, None, e.to_string()),
+    };
+    results.push(DeviceApplyResult {
+      port: port.port,
+      serial_number: port.serial_number,
+      device_name,
+      success,
+      message,
+    });
+  }
+  Ok(results)
+}
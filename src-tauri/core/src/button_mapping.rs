@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config_proto::{self, Button, ButtonRemap, GameModeConfig};
+use crate::error::HayboxError;
+
+/// Buttons every profile must be able to press: Start is how the controller
+/// is put back into config mode.
+const REQUIRED_BUTTONS: &[Button] = &[Button::Mb1];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ButtonMapping {
+  /// The switch that is pressed.
+  pub physical: Button,
+  /// The button the game mode sees.
+  pub logical: Button,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProfileMappings {
+  pub profile: u32,
+  pub name: String,
+  /// One entry per physical button, including unmapped ones that activate
+  /// themselves.
+  pub mappings: Vec<ButtonMapping>,
+}
+
+fn button(value: i32) -> Button {
+  Button::try_from(value).unwrap_or(Button::Unspecified)
+}
+
+fn all_buttons() -> impl Iterator<Item = Button> {
+  (1..).map_while(|value| Button::try_from(value).ok())
+}
+
+/// The button each physical button activates. Buttons without a remap entry
+/// activate themselves.
+fn effective_mappings(mode: &GameModeConfig) -> Vec<ButtonMapping> {
+  let remaps: HashMap<Button, Button> = mode
+    .button_remapping
+    .iter()
+    .map(|remap| (button(remap.physical_button), button(remap.activates)))
+    .collect();
+  all_buttons()
+    .map(|physical| ButtonMapping {
+      physical,
+      logical: remaps.get(&physical).copied().unwrap_or(physical),
+    })
+    .collect()
+}
+
+/// Rejects two physical buttons remapped onto the same button, and profiles
+/// where a required or activation button can no longer be pressed.
+pub fn validate(mode: &GameModeConfig) -> Result<(), HayboxError> {
+  let mut seen: HashMap<Button, Button> = HashMap::new();
+  for remap in &mode.button_remapping {
+    let physical = button(remap.physical_button);
+    let logical = button(remap.activates);
+    if physical == Button::Unspecified || logical == Button::Unspecified {
+      return Err(HayboxError::Other(format!(
+        "Profile '{}' has a remap with an unknown button",
+        mode.name
+      )));
+    }
+    if let Some(other) = seen.insert(logical, physical) {
+      return Err(HayboxError::Other(format!(
+        "{:?} and {:?} are both mapped to {:?} in profile '{}'",
+        other, physical, logical, mode.name
+      )));
+    }
+  }
+
+  let reachable: Vec<Button> = effective_mappings(mode)
+    .into_iter()
+    .map(|mapping| mapping.logical)
+    .collect();
+  let activation = mode.activation_binding.iter().map(|&value| button(value));
+  for required in REQUIRED_BUTTONS.iter().copied().chain(activation) {
+    if !reachable.contains(&required) {
+      return Err(HayboxError::Other(format!(
+        "No physical button activates {:?} in profile '{}'",
+        required, mode.name
+      )));
+    }
+  }
+  Ok(())
+}
+
+fn profile_mappings(profile: u32, mode: &GameModeConfig) -> ProfileMappings {
+  ProfileMappings {
+    profile,
+    name: mode.name.clone(),
+    mappings: effective_mappings(mode),
+  }
+}
+
+pub fn get_button_mappings(profile: u32) -> Result<ProfileMappings, HayboxError> {
+  let config = config_proto::get_config()?;
+  let mode = config
+    .game_mode_configs
+    .get(profile as usize)
+    .ok_or_else(|| HayboxError::Other(format!("Profile {} does not exist", profile)))?;
+  Ok(profile_mappings(profile, mode))
+}
+
+/// Makes `physical` activate `logical` in the given game mode profile and
+/// writes the config back. Mapping a button to itself clears its remap.
+pub fn set_button_mapping(profile: u32, physical: Button, logical: Button) -> Result<ProfileMappings, HayboxError> {
+  if physical == Button::Unspecified || logical == Button::Unspecified {
+    return Err(HayboxError::Other("Both buttons must be specified".to_string()));
+  }
+
+  config_proto::with_connection(|conn| {
+    let mut config = conn.get_config()?;
+    let mode = config
+      .game_mode_configs
+      .get_mut(profile as usize)
+      .ok_or_else(|| HayboxError::Other(format!("Profile {} does not exist", profile)))?;
+
+    mode
+      .button_remapping
+      .retain(|remap| button(remap.physical_button) != physical);
+    if physical != logical {
+      mode.button_remapping.push(ButtonRemap {
+        physical_button: physical as i32,
+        activates: logical as i32,
+      });
+    }
+    validate(mode)?;
+
+    let result = profile_mappings(profile, mode);
+    conn.set_config(&config)?;
+    Ok(result)
+  })
+}
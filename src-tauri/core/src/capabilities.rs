@@ -0,0 +1,130 @@
+use serde::{Deserialize, Serialize};
+
+use crate::config_proto::{self, Button, Capabilities, CommunicationBackendId, Config, GameModeId};
+use crate::error::HayboxError;
+
+/// Something in a config the connected controller cannot do. `path` points
+/// at the offending field in the same form `diff_configs` uses.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ConfigViolation {
+  pub path: String,
+  pub message: String,
+}
+
+/// Capabilities implied by a firmware version. No HayBox release reports
+/// its board's wiring or build options, so none are known yet; firmware that
+/// does gets an entry here.
+pub fn for_firmware(_firmware_version: &str) -> Option<Capabilities> {
+  None
+}
+
+fn violation(path: String, message: String) -> ConfigViolation {
+  ConfigViolation { path, message }
+}
+
+/// Checks `config` against what the controller reports it supports. Remaps
+/// and bindings that use buttons the board has no switch for would leave
+/// those inputs unreachable.
+pub fn check_config(config: &Config, capabilities: &Capabilities) -> Vec<ConfigViolation> {
+  let mut violations = Vec::new();
+  let button_name = |value: i32| {
+    Button::try_from(value)
+      .map(|button| format!("{:?}", button))
+      .unwrap_or_else(|_| value.to_string())
+  };
+  let wired = |value: i32| capabilities.buttons.is_empty() || capabilities.buttons.contains(&value);
+
+  let mode_count = config.game_mode_configs.len() as u32;
+  if capabilities.max_game_modes > 0 && mode_count > capabilities.max_game_modes {
+    violations.push(violation(
+      "/game_mode_configs".to_string(),
+      format!(
+        "{} game modes configured, but the firmware holds at most {}",
+        mode_count, capabilities.max_game_modes
+      ),
+    ));
+  }
+
+  for (index, mode) in config.game_mode_configs.iter().enumerate() {
+    let path = format!("/game_mode_configs/{}", index);
+    if !capabilities.supported_modes.is_empty() && !capabilities.supported_modes.contains(&mode.mode_id) {
+      let id = GameModeId::try_from(mode.mode_id)
+        .map(|id| format!("{:?}", id))
+        .unwrap_or_else(|_| mode.mode_id.to_string());
+      violations.push(violation(
+        format!("{}/mode_id", path),
+        format!(
+          "Profile '{}' uses {}, which this firmware does not include",
+          mode.name, id
+        ),
+      ));
+    }
+
+    for (remap_index, remap) in mode.button_remapping.iter().enumerate() {
+      if !wired(remap.physical_button) {
+        violations.push(violation(
+          format!("{}/button_remapping/{}/physical_button", path, remap_index),
+          format!(
+            "Profile '{}' remaps {}, which this board has no switch for",
+            mode.name,
+            button_name(remap.physical_button)
+          ),
+        ));
+      }
+    }
+
+    for (binding_index, &button) in mode.activation_binding.iter().enumerate() {
+      if !wired(button) {
+        violations.push(violation(
+          format!("{}/activation_binding/{}", path, binding_index),
+          format!(
+            "Profile '{}' is selected with {}, which this board has no switch for",
+            mode.name,
+            button_name(button)
+          ),
+        ));
+      }
+    }
+  }
+
+  for (index, backend) in config.communication_backend_configs.iter().enumerate() {
+    let path = format!("/communication_backend_configs/{}", index);
+    if !capabilities.supported_backends.is_empty() && !capabilities.supported_backends.contains(&backend.backend_id) {
+      let id = CommunicationBackendId::try_from(backend.backend_id)
+        .map(|id| format!("{:?}", id))
+        .unwrap_or_else(|_| backend.backend_id.to_string());
+      violations.push(violation(
+        format!("{}/backend_id", path),
+        format!("The {} backend is not available on this controller", id),
+      ));
+    }
+    for (binding_index, &button) in backend.activation_binding.iter().enumerate() {
+      if !wired(button) {
+        violations.push(violation(
+          format!("{}/activation_binding/{}", path, binding_index),
+          format!(
+            "Backend {} is selected with {}, which this board has no switch for",
+            index,
+            button_name(button)
+          ),
+        ));
+      }
+    }
+  }
+
+  violations
+}
+
+/// Validates a pending config against the connected controller before it is
+/// written. Firmware whose capabilities are unknown yields no violations.
+pub fn validate_config_for_device(config: &Config) -> Result<Vec<ConfigViolation>, HayboxError> {
+  config_proto::with_connection(|conn| {
+    Ok(
+      conn
+        .capabilities
+        .as_ref()
+        .map(|capabilities| check_config(config, capabilities))
+        .unwrap_or_default(),
+    )
+  })
+}
@@ -0,0 +1,138 @@
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config_file::{self, ConfigFile, CONFIG_SCHEMA_VERSION};
+use crate::config_migration;
+use crate::config_proto::{self, Config, ConfigConnection};
+use crate::error::HayboxError;
+use crate::paths::app_data_dir;
+
+const BACKUP_DIR: &str = "config_backups";
+/// Backups kept per controller; the oldest are deleted past this.
+const MAX_BACKUPS: usize = 50;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ConfigBackup {
+  /// Pass to `restore_config_backup`.
+  pub id: String,
+  pub saved_at_ms: u64,
+  pub device_name: String,
+  pub firmware_version: String,
+  pub profiles: usize,
+}
+
+/// Names the connected controller's files by its USB serial number so two
+/// controllers of the same model stay apart. Without one the device name is
+/// used.
+pub fn device_key(conn: &ConfigConnection) -> String {
+  let key = conn
+    .serial_number
+    .as_deref()
+    .filter(|serial| !serial.is_empty())
+    .unwrap_or(&conn.device_info.device_name);
+  let key: String = key
+    .chars()
+    .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+    .collect();
+  if key.is_empty() {
+    "controller".to_string()
+  } else {
+    key
+  }
+}
+
+fn backup_dir(conn: &ConfigConnection) -> PathBuf {
+  app_data_dir().join(BACKUP_DIR).join(device_key(conn))
+}
+
+/// Backup ids in the directory, newest first.
+fn backup_ids(dir: &Path) -> Vec<u64> {
+  let mut ids: Vec<u64> = std::fs::read_dir(dir)
+    .map(|entries| {
+      entries
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.strip_suffix(".json")?.parse().ok())
+        .collect()
+    })
+    .unwrap_or_default();
+  ids.sort_unstable_by(|a, b| b.cmp(a));
+  ids
+}
+
+/// Stores the config a write is about to replace. Called by
+/// `ConfigConnection::set_config` before every write.
+pub fn save_backup(conn: &ConfigConnection, previous: Config) -> Result<(), HayboxError> {
+  let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+  let file = ConfigFile {
+    schema_version: CONFIG_SCHEMA_VERSION,
+    exported_at: now.as_secs(),
+    device_name: conn.device_info.device_name.clone(),
+    firmware_version: conn.device_info.firmware_version.clone(),
+    config_version: config_migration::effective_version(conn.device_info.config_version),
+    config: previous,
+  };
+
+  let dir = backup_dir(conn);
+  std::fs::create_dir_all(&dir).map_err(|e| HayboxError::Io(format!("Failed to create {}: {}", dir.display(), e)))?;
+  let mut id = now.as_millis() as u64;
+  while dir.join(format!("{}.json", id)).exists() {
+    id += 1;
+  }
+  let path = dir.join(format!("{}.json", id));
+  let content = serde_json::to_string_pretty(&file)
+    .map_err(|e| HayboxError::Other(format!("Failed to serialize config backup: {}", e)))?;
+  std::fs::write(&path, content).map_err(|e| HayboxError::Io(format!("Failed to write {}: {}", path.display(), e)))?;
+
+  for old in backup_ids(&dir).into_iter().skip(MAX_BACKUPS) {
+    let path = dir.join(format!("{}.json", old));
+    if let Err(e) = std::fs::remove_file(&path) {
+      tracing::warn!("failed to remove old config backup {}: {}", path.display(), e);
+    }
+  }
+  Ok(())
+}
+
+/// The connected controller's backups, newest first. Unreadable files are
+/// skipped.
+pub fn list_config_backups() -> Result<Vec<ConfigBackup>, HayboxError> {
+  config_proto::with_connection(|conn| {
+    let dir = backup_dir(conn);
+    Ok(
+      backup_ids(&dir)
+        .into_iter()
+        .filter_map(|id| {
+          let file = config_file::read_config_file(&dir.join(format!("{}.json", id))).ok()?;
+          Some(ConfigBackup {
+            id: id.to_string(),
+            saved_at_ms: id,
+            device_name: file.device_name,
+            firmware_version: file.firmware_version,
+            profiles: file.config.game_mode_configs.len(),
+          })
+        })
+        .collect(),
+    )
+  })
+}
+
+/// Writes a backup back to the connected controller. The config it
+/// replaces is backed up in turn, so a restore can itself be undone.
+pub fn restore_config_backup(id: &str) -> Result<ConfigFile, HayboxError> {
+  let id: u64 = id
+    .parse()
+    .map_err(|_| HayboxError::Other(format!("'{}' is not a backup id", id)))?;
+  config_proto::with_connection(|conn| {
+    let path = backup_dir(conn).join(format!("{}.json", id));
+    if !path.exists() {
+      return Err(HayboxError::Other(format!(
+        "Backup {} does not exist for this controller",
+        id
+      )));
+    }
+    let file = config_file::read_config_file(&path)?;
+    conn.set_config(&file.config)?;
+    Ok(file)
+  })
+}
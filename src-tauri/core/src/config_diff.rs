@@ -0,0 +1,172 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::config_file;
+use crate::config_proto::{self, Config};
+use crate::error::HayboxError;
+
+/// Where a config to compare comes from.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ConfigSource {
+  /// The controller connected in config mode.
+  Device,
+  /// A file written by `export_config`.
+  File { path: PathBuf },
+}
+
+/// One differing field. `path` is a JSON pointer into the config, e.g.
+/// `/game_mode_configs/0/button_remapping/2/activates`. `before` is missing
+/// for added fields and `after` for removed ones.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ConfigChange {
+  pub path: String,
+  pub before: Option<Value>,
+  pub after: Option<Value>,
+}
+
+fn load(source: &ConfigSource) -> Result<Config, HayboxError> {
+  match source {
+    ConfigSource::Device => config_proto::get_config(),
+    ConfigSource::File { path } => config_file::read_config_file(path).map(|file| file.config),
+  }
+}
+
+fn to_value(config: &Config) -> Result<Value, HayboxError> {
+  serde_json::to_value(config).map_err(|e| HayboxError::Other(format!("Failed to serialize config: {}", e)))
+}
+
+fn escape(key: &str) -> String {
+  key.replace('~', "~0").replace('/', "~1")
+}
+
+fn diff_values(path: &str, before: &Value, after: &Value, changes: &mut Vec<ConfigChange>) {
+  match (before, after) {
+    (Value::Object(a), Value::Object(b)) => {
+      for (key, value) in a {
+        let child = format!("{}/{}", path, escape(key));
+        match b.get(key) {
+          Some(other) => diff_values(&child, value, other, changes),
+          None => changes.push(ConfigChange {
+            path: child,
+            before: Some(value.clone()),
+            after: None,
+          }),
+        }
+      }
+      for (key, value) in b.iter().filter(|(key, _)| !a.contains_key(*key)) {
+        changes.push(ConfigChange {
+          path: format!("{}/{}", path, escape(key)),
+          before: None,
+          after: Some(value.clone()),
+        });
+      }
+    }
+    (Value::Array(a), Value::Array(b)) => {
+      for (index, (x, y)) in a.iter().zip(b).enumerate() {
+        diff_values(&format!("{}/{}", path, index), x, y, changes);
+      }
+      for (index, value) in b.iter().enumerate().skip(a.len()) {
+        changes.push(ConfigChange {
+          path: format!("{}/{}", path, index),
+          before: None,
+          after: Some(value.clone()),
+        });
+      }
+      // Highest index first, so applying removals in order keeps the
+      // remaining indices valid.
+      for (index, value) in a.iter().enumerate().skip(b.len()).rev() {
+        changes.push(ConfigChange {
+          path: format!("{}/{}", path, index),
+          before: Some(value.clone()),
+          after: None,
+        });
+      }
+    }
+    _ if before != after => changes.push(ConfigChange {
+      path: path.to_string(),
+      before: Some(before.clone()),
+      after: Some(after.clone()),
+    }),
+    _ => {}
+  }
+}
+
+/// Compares two configs field by field. Array elements are matched by
+/// position, so a profile inserted in the middle shows up as changes to
+/// every profile after it.
+pub fn diff_configs(a: &ConfigSource, b: &ConfigSource) -> Result<Vec<ConfigChange>, HayboxError> {
+  let before = to_value(&load(a)?)?;
+  let after = to_value(&load(b)?)?;
+  let mut changes = Vec::new();
+  diff_values("", &before, &after, &mut changes);
+  Ok(changes)
+}
+
+fn split_pointer(path: &str) -> Result<(&str, String), HayboxError> {
+  let (parent, key) = path
+    .rsplit_once('/')
+    .ok_or_else(|| HayboxError::Other(format!("'{}' is not a JSON pointer", path)))?;
+  Ok((parent, key.replace("~1", "/").replace("~0", "~")))
+}
+
+fn apply_change(root: &mut Value, change: &ConfigChange) -> Result<(), HayboxError> {
+  let (parent_path, key) = split_pointer(&change.path)?;
+  let parent = root
+    .pointer_mut(parent_path)
+    .ok_or_else(|| HayboxError::Other(format!("{} does not exist in the target config", parent_path)))?;
+
+  match (parent, &change.after) {
+    (Value::Object(map), Some(value)) => {
+      map.insert(key, value.clone());
+    }
+    (Value::Object(map), None) => {
+      map.remove(&key);
+    }
+    (Value::Array(items), after) => {
+      let index: usize = key
+        .parse()
+        .map_err(|_| HayboxError::Other(format!("{} is not an array index", change.path)))?;
+      match after {
+        Some(value) if index < items.len() => items[index] = value.clone(),
+        Some(value) if index == items.len() => items.push(value.clone()),
+        None if index < items.len() => {
+          items.remove(index);
+        }
+        _ => {
+          return Err(HayboxError::Other(format!(
+            "{} is out of range in the target config",
+            change.path
+          )))
+        }
+      }
+    }
+    _ => {
+      return Err(HayboxError::Other(format!(
+        "{} does not point into an object or array",
+        change.path
+      )))
+    }
+  }
+  Ok(())
+}
+
+/// Applies the selected changes from `diff_configs` to the controller's
+/// current config and writes it back if the result is still valid. Changes
+/// are applied in the order given.
+pub fn apply_config_patch(changes: &[ConfigChange]) -> Result<Config, HayboxError> {
+  config_proto::with_connection(|conn| {
+    let mut value = to_value(&conn.get_config()?)?;
+    for change in changes {
+      apply_change(&mut value, change)?;
+    }
+
+    let config: Config = serde_json::from_value(value)
+      .map_err(|e| HayboxError::Other(format!("Patched config is not a valid config: {}", e)))?;
+    config_file::validate_config(&config)?;
+    conn.set_config(&config)?;
+    Ok(config)
+  })
+}
@@ -0,0 +1,153 @@
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::button_mapping;
+use crate::config_migration;
+use crate::config_proto::{self, CommunicationBackendId, Config, GameModeId};
+use crate::error::HayboxError;
+
+/// Bumped whenever the file layout changes. Version 2 added
+/// `config_version`; version 1 files always hold version 1 configs.
+pub const CONFIG_SCHEMA_VERSION: u32 = 2;
+
+/// A controller config as written to disk, with enough context to tell
+/// where it came from.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ConfigFile {
+  pub schema_version: u32,
+  pub exported_at: u64,
+  pub device_name: String,
+  pub firmware_version: String,
+  /// The controller's config version when exported. `config` is upgraded
+  /// to the current version when the file is read.
+  pub config_version: u32,
+  pub config: Config,
+}
+
+/// Checks indices and enum values so a hand-edited or corrupted file cannot
+/// leave the controller pointing at modes that do not exist.
+pub fn validate_config(config: &Config) -> Result<(), HayboxError> {
+  if config.game_mode_configs.is_empty() {
+    return Err(HayboxError::Other("Config has no game modes".to_string()));
+  }
+  if config.communication_backend_configs.is_empty() {
+    return Err(HayboxError::Other("Config has no communication backends".to_string()));
+  }
+
+  for mode in &config.game_mode_configs {
+    if GameModeId::try_from(mode.mode_id).is_err() {
+      return Err(HayboxError::Other(format!(
+        "Profile '{}' has unknown mode id {}",
+        mode.name, mode.mode_id
+      )));
+    }
+    button_mapping::validate(mode)?;
+  }
+
+  let mode_count = config.game_mode_configs.len() as u32;
+  for backend in &config.communication_backend_configs {
+    if CommunicationBackendId::try_from(backend.backend_id).is_err() {
+      return Err(HayboxError::Other(format!(
+        "Unknown communication backend id {}",
+        backend.backend_id
+      )));
+    }
+    if backend.default_mode_config >= mode_count {
+      return Err(HayboxError::Other(format!(
+        "Backend {} starts in game mode {}, but only {} exist",
+        backend.backend_id, backend.default_mode_config, mode_count
+      )));
+    }
+  }
+
+  let backend_count = config.communication_backend_configs.len() as u32;
+  for (field, index) in [
+    ("default_backend_config", config.default_backend_config),
+    ("default_usb_backend_config", config.default_usb_backend_config),
+  ] {
+    if index >= backend_count {
+      return Err(HayboxError::Other(format!(
+        "{} is {}, but only {} backends exist",
+        field, index, backend_count
+      )));
+    }
+  }
+  Ok(())
+}
+
+/// Parses, upgrades and validates a config file's contents. `source` names
+/// where it came from in errors.
+pub fn parse_config_file(content: &str, source: &str) -> Result<ConfigFile, HayboxError> {
+  let mut value: Value =
+    serde_json::from_str(content).map_err(|e| HayboxError::Other(format!("{} is not a config file: {}", source, e)))?;
+
+  let field = |value: &Value, name: &str| value.get(name).and_then(Value::as_u64).map(|v| v as u32);
+  let schema_version =
+    field(&value, "schema_version").ok_or_else(|| HayboxError::Other(format!("{} has no schema version", source)))?;
+  if schema_version == 0 || schema_version > CONFIG_SCHEMA_VERSION {
+    return Err(HayboxError::Other(format!(
+      "{} uses file schema {}, this version of the app reads up to schema {}",
+      source, schema_version, CONFIG_SCHEMA_VERSION
+    )));
+  }
+  let config_version = field(&value, "config_version").unwrap_or(1);
+
+  let config = value
+    .get_mut("config")
+    .map(Value::take)
+    .ok_or_else(|| HayboxError::Other(format!("{} has no config", source)))?;
+  let config = config_migration::upgrade(config, config_version)?;
+  validate_config(&config)?;
+
+  Ok(ConfigFile {
+    schema_version: CONFIG_SCHEMA_VERSION,
+    exported_at: field(&value, "exported_at").unwrap_or_default() as u64,
+    device_name: value["device_name"].as_str().unwrap_or_default().to_string(),
+    firmware_version: value["firmware_version"].as_str().unwrap_or_default().to_string(),
+    config_version: config_migration::CURRENT_CONFIG_VERSION,
+    config,
+  })
+}
+
+pub fn read_config_file(path: &Path) -> Result<ConfigFile, HayboxError> {
+  let content =
+    std::fs::read_to_string(path).map_err(|e| HayboxError::Io(format!("Failed to read {}: {}", path.display(), e)))?;
+  parse_config_file(&content, &path.display().to_string())
+}
+
+/// The connected controller's config in file form.
+pub fn device_config_file() -> Result<ConfigFile, HayboxError> {
+  config_proto::with_connection(|conn| {
+    Ok(ConfigFile {
+      schema_version: CONFIG_SCHEMA_VERSION,
+      exported_at: SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default(),
+      device_name: conn.device_info.device_name.clone(),
+      firmware_version: conn.device_info.firmware_version.clone(),
+      config_version: config_migration::effective_version(conn.device_info.config_version),
+      config: conn.get_config()?,
+    })
+  })
+}
+
+/// Reads the config from the connected controller and writes it to `path`.
+pub fn export_config(path: &Path) -> Result<ConfigFile, HayboxError> {
+  let file = device_config_file()?;
+
+  let content = serde_json::to_string_pretty(&file)
+    .map_err(|e| HayboxError::Other(format!("Failed to serialize config: {}", e)))?;
+  std::fs::write(path, content).map_err(|e| HayboxError::Io(format!("Failed to write {}: {}", path.display(), e)))?;
+  Ok(file)
+}
+
+/// Validates the file at `path` and writes its config to the controller.
+pub fn import_config(path: &Path) -> Result<ConfigFile, HayboxError> {
+  let file = read_config_file(path)?;
+  config_proto::set_config(&file.config)?;
+  Ok(file)
+}
@@ -0,0 +1,48 @@
+use serde_json::Value;
+
+use crate::config_proto::Config;
+use crate::error::HayboxError;
+
+/// Version of the firmware's config protobuf that `config_proto::Config`
+/// models. Every HayBox release so far uses the first version; one that
+/// changes the schema needs up/down conversions of the stored JSON here.
+pub const CURRENT_CONFIG_VERSION: u32 = 1;
+/// Firmware from before the device info reported a config version.
+const LEGACY_CONFIG_VERSION: u32 = 1;
+
+/// The config version a controller uses; firmware that does not report one
+/// predates versioning.
+pub fn effective_version(reported: u32) -> u32 {
+  if reported == 0 {
+    LEGACY_CONFIG_VERSION
+  } else {
+    reported
+  }
+}
+
+fn check_supported(version: u32) -> Result<(), HayboxError> {
+  if !(LEGACY_CONFIG_VERSION..=CURRENT_CONFIG_VERSION).contains(&version) {
+    return Err(HayboxError::Other(format!(
+      "Config version {} is not supported; this app handles versions {} to {}",
+      version, LEGACY_CONFIG_VERSION, CURRENT_CONFIG_VERSION
+    )));
+  }
+  Ok(())
+}
+
+/// Parses a config stored at version `from` into the current model.
+pub fn upgrade(config: Value, from: u32) -> Result<Config, HayboxError> {
+  check_supported(from)?;
+  serde_json::from_value(config).map_err(|e| {
+    HayboxError::Other(format!(
+      "Config does not match version {}: {}",
+      CURRENT_CONFIG_VERSION, e
+    ))
+  })
+}
+
+/// Checks that a controller using `device_version` has a config this app
+/// can write without losing settings.
+pub fn check_writable(device_version: u32) -> Result<(), HayboxError> {
+  check_supported(effective_version(device_version))
+}
@@ -0,0 +1,786 @@
+use std::io::{Read, Write};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use serialport::SerialPort;
+
+use crate::error::HayboxError;
+use crate::protocol_trace::{self, TraceDirection};
+use crate::{capabilities, config_backup, config_migration, serial_ports, DEVICES};
+
+/// Requests and responses are `[command, payload...]` packets, COBS-encoded
+/// and terminated by a zero byte. Payloads are protobuf messages.
+const CMD_GET_DEVICE_INFO: u8 = 0x01;
+const CMD_GET_CONFIG: u8 = 0x02;
+const CMD_SET_CONFIG: u8 = 0x03;
+const CMD_REBOOT_FIRMWARE: u8 = 0x04;
+/// Sent back instead of the echoed command, followed by a UTF-8 message.
+const CMD_ERROR: u8 = 0xFF;
+const PACKET_DELIMITER: u8 = 0x00;
+
+/// Name of a command byte, for the protocol trace.
+pub fn command_name(command: u8) -> &'static str {
+  match command {
+    CMD_GET_DEVICE_INFO => "GetDeviceInfo",
+    CMD_GET_CONFIG => "GetConfig",
+    CMD_SET_CONFIG => "SetConfig",
+    CMD_REBOOT_FIRMWARE => "RebootFirmware",
+    CMD_ERROR => "Error",
+    _ => "Unknown",
+  }
+}
+
+/// Decodes a packet's payload into JSON where its message type is known
+/// from the command and direction.
+pub fn decode_payload(command: u8, sent: bool, payload: &[u8]) -> Option<serde_json::Value> {
+  fn json<M: Message + Default + Serialize>(payload: &[u8]) -> Option<serde_json::Value> {
+    M::decode(payload)
+      .ok()
+      .and_then(|message| serde_json::to_value(message).ok())
+  }
+
+  match (command, sent) {
+    (CMD_SET_CONFIG, true) | (CMD_GET_CONFIG, false) => json::<Config>(payload),
+    (CMD_GET_DEVICE_INFO, false) => json::<DeviceInfo>(payload),
+    (CMD_ERROR, false) => Some(serde_json::Value::String(String::from_utf8_lossy(payload).to_string())),
+    _ => None,
+  }
+}
+
+/// CDC ignores the baud rate, but the port must be opened with one.
+const BAUD_RATE: u32 = 115_200;
+const READ_TIMEOUT: Duration = Duration::from_millis(100);
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(3);
+/// A full config is a few KB; anything far larger is line noise.
+const MAX_PACKET: usize = 64 * 1024;
+
+lazy_static::lazy_static! {
+  static ref CONNECTION: Mutex<Option<ConfigConnection>> = Mutex::new(None);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration, Serialize, Deserialize)]
+#[repr(i32)]
+pub enum GameModeId {
+  Unspecified = 0,
+  Melee = 1,
+  ProjectM = 2,
+  Ultimate = 3,
+  Fgc = 4,
+  RivalsOfAether = 5,
+  Keyboard = 6,
+  Custom = 7,
+  Rivals2 = 8,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration, Serialize, Deserialize)]
+#[repr(i32)]
+pub enum CommunicationBackendId {
+  Unspecified = 0,
+  Dinput = 1,
+  Xinput = 2,
+  Gamecube = 3,
+  N64 = 4,
+  NintendoSwitch = 5,
+  Nes = 6,
+  Snes = 7,
+  Configurator = 8,
+}
+
+/// Physical button positions, named by cluster: left fingers, left thumb,
+/// middle, right thumb and right fingers. Game modes give each a meaning.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+#[repr(i32)]
+pub enum Button {
+  Unspecified = 0,
+  Lf1 = 1,
+  Lf2 = 2,
+  Lf3 = 3,
+  Lf4 = 4,
+  Lf5 = 5,
+  Lf6 = 6,
+  Lf7 = 7,
+  Lf8 = 8,
+  Lf9 = 9,
+  Lf10 = 10,
+  Lf11 = 11,
+  Lf12 = 12,
+  Lf13 = 13,
+  Lf14 = 14,
+  Lf15 = 15,
+  Lf16 = 16,
+  Lt1 = 17,
+  Lt2 = 18,
+  Lt3 = 19,
+  Lt4 = 20,
+  Lt5 = 21,
+  Lt6 = 22,
+  Lt7 = 23,
+  Lt8 = 24,
+  Mb1 = 25,
+  Mb2 = 26,
+  Mb3 = 27,
+  Mb4 = 28,
+  Mb5 = 29,
+  Mb6 = 30,
+  Mb7 = 31,
+  Mb8 = 32,
+  Mb9 = 33,
+  Mb10 = 34,
+  Mb11 = 35,
+  Mb12 = 36,
+  Rt1 = 37,
+  Rt2 = 38,
+  Rt3 = 39,
+  Rt4 = 40,
+  Rt5 = 41,
+  Rt6 = 42,
+  Rt7 = 43,
+  Rt8 = 44,
+  Rt9 = 45,
+  Rt10 = 46,
+  Rt11 = 47,
+  Rt12 = 48,
+  Rt13 = 49,
+  Rt14 = 50,
+  Rt15 = 51,
+  Rt16 = 52,
+  Rf1 = 53,
+  Rf2 = 54,
+  Rf3 = 55,
+  Rf4 = 56,
+  Rf5 = 57,
+  Rf6 = 58,
+  Rf7 = 59,
+  Rf8 = 60,
+  Rf9 = 61,
+  Rf10 = 62,
+  Rf11 = 63,
+  Rf12 = 64,
+  Rf13 = 65,
+  Rf14 = 66,
+  Rf15 = 67,
+  Rf16 = 68,
+}
+
+/// How a pair of opposing directions resolves when both are held.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration, Serialize, Deserialize)]
+#[repr(i32)]
+pub enum SocdType {
+  Unspecified = 0,
+  /// Both cancel out.
+  Neutral = 1,
+  /// Second input priority: the newer direction wins and the older one
+  /// comes back when the newer is released.
+  SecondInputPriority = 2,
+  /// As above, but the older direction stays off until pressed again.
+  SecondInputPriorityNoReactivation = 3,
+  Dir1Priority = 4,
+  Dir2Priority = 5,
+}
+
+/// The firmware's `Config` message. Fields this app does not model are
+/// dropped on decode, so `set_config` refuses to replace a config that has
+/// any; see `CONFIG_SCHEMA`.
+#[derive(Clone, PartialEq, Message, Serialize, Deserialize)]
+pub struct Config {
+  #[prost(message, repeated, tag = "1")]
+  pub game_mode_configs: Vec<GameModeConfig>,
+  #[prost(message, repeated, tag = "2")]
+  pub communication_backend_configs: Vec<CommunicationBackendConfig>,
+  #[prost(message, repeated, tag = "3")]
+  pub keyboard_modes: Vec<KeyboardModeConfig>,
+  #[prost(message, repeated, tag = "4")]
+  pub rgb_configs: Vec<RgbConfig>,
+  /// Index into `communication_backend_configs` used when no button is held.
+  #[prost(uint32, tag = "5")]
+  pub default_backend_config: u32,
+  #[prost(uint32, tag = "6")]
+  pub default_usb_backend_config: u32,
+  #[prost(uint32, tag = "7")]
+  pub rgb_brightness: u32,
+}
+
+#[derive(Clone, PartialEq, Message, Serialize, Deserialize)]
+pub struct GameModeConfig {
+  #[prost(enumeration = "GameModeId", tag = "1")]
+  pub mode_id: i32,
+  #[prost(string, tag = "2")]
+  pub name: String,
+  #[prost(message, repeated, tag = "3")]
+  pub button_remapping: Vec<ButtonRemap>,
+  /// Buttons held at plug-in to select this mode.
+  #[prost(enumeration = "Button", repeated, tag = "4")]
+  pub activation_binding: Vec<i32>,
+  #[prost(message, repeated, tag = "5")]
+  pub socd_pairs: Vec<SocdPair>,
+  #[prost(uint32, tag = "6")]
+  pub keyboard_mode_config: u32,
+  #[prost(uint32, tag = "7")]
+  pub rgb_config: u32,
+}
+
+#[derive(Clone, PartialEq, Message, Serialize, Deserialize)]
+pub struct ButtonRemap {
+  #[prost(enumeration = "Button", tag = "1")]
+  pub physical_button: i32,
+  #[prost(enumeration = "Button", tag = "2")]
+  pub activates: i32,
+}
+
+#[derive(Clone, PartialEq, Message, Serialize, Deserialize)]
+pub struct SocdPair {
+  #[prost(enumeration = "Button", tag = "1")]
+  pub button_dir1: i32,
+  #[prost(enumeration = "Button", tag = "2")]
+  pub button_dir2: i32,
+  #[prost(enumeration = "SocdType", tag = "3")]
+  pub socd_type: i32,
+}
+
+#[derive(Clone, PartialEq, Message, Serialize, Deserialize)]
+pub struct CommunicationBackendConfig {
+  #[prost(enumeration = "CommunicationBackendId", tag = "1")]
+  pub backend_id: i32,
+  #[prost(enumeration = "Button", repeated, tag = "2")]
+  pub activation_binding: Vec<i32>,
+  /// Index into `game_mode_configs` this backend starts in.
+  #[prost(uint32, tag = "3")]
+  pub default_mode_config: u32,
+}
+
+#[derive(Clone, PartialEq, Message, Serialize, Deserialize)]
+pub struct KeyboardModeConfig {
+  #[prost(uint32, tag = "1")]
+  pub id: u32,
+  #[prost(message, repeated, tag = "2")]
+  pub buttons_to_keycodes: Vec<ButtonToKeycodeMapping>,
+}
+
+#[derive(Clone, PartialEq, Message, Serialize, Deserialize)]
+pub struct ButtonToKeycodeMapping {
+  #[prost(enumeration = "Button", tag = "1")]
+  pub button: i32,
+  #[prost(uint32, tag = "2")]
+  pub keycode: u32,
+}
+
+#[derive(Clone, PartialEq, Message, Serialize, Deserialize)]
+pub struct RgbConfig {
+  #[prost(message, repeated, tag = "1")]
+  pub button_colors: Vec<ButtonToColorMapping>,
+  /// 0xRRGGBB.
+  #[prost(uint32, tag = "2")]
+  pub default_color: u32,
+}
+
+#[derive(Clone, PartialEq, Message, Serialize, Deserialize)]
+pub struct ButtonToColorMapping {
+  #[prost(enumeration = "Button", tag = "1")]
+  pub button: i32,
+  #[prost(uint32, tag = "2")]
+  pub color: u32,
+}
+
+#[derive(Clone, PartialEq, Message, Serialize, Deserialize)]
+pub struct DeviceInfo {
+  #[prost(string, tag = "1")]
+  pub firmware_name: String,
+  #[prost(string, tag = "2")]
+  pub firmware_version: String,
+  #[prost(string, tag = "3")]
+  pub device_name: String,
+  /// Version of the config message the firmware speaks; 0 on firmware that
+  /// predates it.
+  #[prost(uint32, tag = "4")]
+  pub config_version: u32,
+}
+
+/// What the board and firmware build support. The config protocol has no
+/// request for these, so they are only known where listed by firmware
+/// version; elsewhere a config is not checked against the board.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Capabilities {
+  /// Number of GPIO pins wired to buttons.
+  pub pin_count: u32,
+  /// `Button` values the board has a switch for.
+  pub buttons: Vec<i32>,
+  /// `GameModeId` values.
+  pub supported_modes: Vec<i32>,
+  /// `CommunicationBackendId` values.
+  pub supported_backends: Vec<i32>,
+  /// 0 when the firmware has no fixed limit.
+  pub max_game_modes: u32,
+}
+
+/// Field numbers of a message above, with the schema of those that are
+/// messages themselves.
+struct Schema {
+  name: &'static str,
+  fields: &'static [(u32, Option<&'static Schema>)],
+}
+
+static BUTTON_REMAP_SCHEMA: Schema = Schema {
+  name: "ButtonRemap",
+  fields: &[(1, None), (2, None)],
+};
+static SOCD_PAIR_SCHEMA: Schema = Schema {
+  name: "SocdPair",
+  fields: &[(1, None), (2, None), (3, None)],
+};
+static GAME_MODE_CONFIG_SCHEMA: Schema = Schema {
+  name: "GameModeConfig",
+  fields: &[
+    (1, None),
+    (2, None),
+    (3, Some(&BUTTON_REMAP_SCHEMA)),
+    (4, None),
+    (5, Some(&SOCD_PAIR_SCHEMA)),
+    (6, None),
+    (7, None),
+  ],
+};
+static COMMUNICATION_BACKEND_CONFIG_SCHEMA: Schema = Schema {
+  name: "CommunicationBackendConfig",
+  fields: &[(1, None), (2, None), (3, None)],
+};
+static BUTTON_TO_KEYCODE_MAPPING_SCHEMA: Schema = Schema {
+  name: "ButtonToKeycodeMapping",
+  fields: &[(1, None), (2, None)],
+};
+static KEYBOARD_MODE_CONFIG_SCHEMA: Schema = Schema {
+  name: "KeyboardModeConfig",
+  fields: &[(1, None), (2, Some(&BUTTON_TO_KEYCODE_MAPPING_SCHEMA))],
+};
+static BUTTON_TO_COLOR_MAPPING_SCHEMA: Schema = Schema {
+  name: "ButtonToColorMapping",
+  fields: &[(1, None), (2, None)],
+};
+static RGB_CONFIG_SCHEMA: Schema = Schema {
+  name: "RgbConfig",
+  fields: &[(1, Some(&BUTTON_TO_COLOR_MAPPING_SCHEMA)), (2, None)],
+};
+/// Everything `Config` models. Keep in step with the structs above.
+static CONFIG_SCHEMA: Schema = Schema {
+  name: "Config",
+  fields: &[
+    (1, Some(&GAME_MODE_CONFIG_SCHEMA)),
+    (2, Some(&COMMUNICATION_BACKEND_CONFIG_SCHEMA)),
+    (3, Some(&KEYBOARD_MODE_CONFIG_SCHEMA)),
+    (4, Some(&RGB_CONFIG_SCHEMA)),
+    (5, None),
+    (6, None),
+    (7, None),
+  ],
+};
+
+fn read_varint(buf: &mut &[u8]) -> Option<u64> {
+  let mut value = 0u64;
+  for shift in (0..64).step_by(7) {
+    let (&byte, rest) = buf.split_first()?;
+    *buf = rest;
+    value |= u64::from(byte & 0x7F) << shift;
+    if byte & 0x80 == 0 {
+      return Some(value);
+    }
+  }
+  None
+}
+
+/// Walks the encoded message in `buf` and adds every field `schema` does not
+/// list to `unknown`, as "Message field N". `None` if `buf` is malformed.
+fn find_unknown_fields(mut buf: &[u8], schema: &Schema, unknown: &mut Vec<String>) -> Option<()> {
+  while !buf.is_empty() {
+    let key = read_varint(&mut buf)?;
+    let tag = u32::try_from(key >> 3).ok()?;
+    let known = schema.fields.iter().find(|(field, _)| *field == tag);
+    match key & 0x7 {
+      0 => {
+        read_varint(&mut buf)?;
+      }
+      1 => buf = buf.get(8..)?,
+      2 => {
+        let len = usize::try_from(read_varint(&mut buf)?).ok()?;
+        let body = buf.get(..len)?;
+        buf = &buf[len..];
+        if let Some((_, Some(nested))) = known {
+          find_unknown_fields(body, nested, unknown)?;
+        }
+      }
+      5 => buf = buf.get(4..)?,
+      _ => return None,
+    }
+    let field = format!("{} field {}", schema.name, tag);
+    if known.is_none() && !unknown.contains(&field) {
+      unknown.push(field);
+    }
+  }
+  Some(())
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ConfigModeConnection {
+  pub port: String,
+  pub device_info: DeviceInfo,
+  pub capabilities: Option<Capabilities>,
+}
+
+fn cobs_encode(data: &[u8]) -> Vec<u8> {
+  let mut out = vec![0u8];
+  let mut code_index = 0;
+  let mut code = 1u8;
+
+  for &byte in data {
+    if byte == 0 {
+      out[code_index] = code;
+      code_index = out.len();
+      out.push(0);
+      code = 1;
+      continue;
+    }
+    out.push(byte);
+    code += 1;
+    if code == 0xFF {
+      out[code_index] = code;
+      code_index = out.len();
+      out.push(0);
+      code = 1;
+    }
+  }
+  out[code_index] = code;
+  out
+}
+
+fn cobs_decode(data: &[u8]) -> Result<Vec<u8>, HayboxError> {
+  let mut out = Vec::with_capacity(data.len());
+  let mut index = 0;
+
+  while index < data.len() {
+    let code = data[index] as usize;
+    if code == 0 || index + code > data.len() + 1 {
+      return Err(HayboxError::Other("Malformed packet from controller".to_string()));
+    }
+    let end = (index + code).min(data.len());
+    out.extend_from_slice(&data[index + 1..end]);
+    index += code;
+    if code < 0xFF && index < data.len() {
+      out.push(0);
+    }
+  }
+  Ok(out)
+}
+
+pub struct ConfigConnection {
+  port: Box<dyn SerialPort>,
+  port_name: String,
+  /// Bytes read past the end of the last packet.
+  pending: Vec<u8>,
+  /// Read once when the port is opened.
+  pub device_info: DeviceInfo,
+  pub capabilities: Option<Capabilities>,
+  pub serial_number: Option<String>,
+}
+
+impl ConfigConnection {
+  /// Opens the port and reads the device info to confirm the firmware
+  /// speaks the protocol.
+  pub fn open(port_name: &str) -> Result<Self, HayboxError> {
+    let mut port = serialport::new(port_name, BAUD_RATE)
+      .timeout(READ_TIMEOUT)
+      .open()
+      .map_err(|e| HayboxError::SerialPort(format!("Failed to open {}: {}", port_name, e)))?;
+    // TinyUSB only sends once the host asserts DTR.
+    port
+      .write_data_terminal_ready(true)
+      .map_err(|e| HayboxError::SerialPort(format!("Failed to set DTR on {}: {}", port_name, e)))?;
+
+    let mut conn = ConfigConnection {
+      port,
+      port_name: port_name.to_string(),
+      pending: Vec::new(),
+      device_info: DeviceInfo::default(),
+      capabilities: None,
+      serial_number: serial_ports::serial_number_for_port(port_name),
+    };
+    conn.device_info = conn.read_device_info()?;
+    conn.capabilities = capabilities::for_firmware(&conn.device_info.firmware_version);
+    Ok(conn)
+  }
+
+  pub fn port_name(&self) -> &str {
+    &self.port_name
+  }
+
+  fn read_packet(&mut self) -> Result<Vec<u8>, HayboxError> {
+    let deadline = Instant::now() + RESPONSE_TIMEOUT;
+    let mut buffer = [0u8; 512];
+
+    loop {
+      if let Some(end) = self.pending.iter().position(|&byte| byte == PACKET_DELIMITER) {
+        let frame: Vec<u8> = self.pending.drain(..=end).take(end).collect();
+        if frame.is_empty() {
+          continue;
+        }
+        return cobs_decode(&frame);
+      }
+      if self.pending.len() > MAX_PACKET {
+        self.pending.clear();
+        return Err(HayboxError::Other("Controller sent an oversized packet".to_string()));
+      }
+      if Instant::now() >= deadline {
+        return Err(HayboxError::Timeout(format!(
+          "Timed out waiting for a response on {}",
+          self.port_name
+        )));
+      }
+
+      match self.port.read(&mut buffer) {
+        Ok(count) => self.pending.extend_from_slice(&buffer[..count]),
+        Err(e) if e.kind() == std::io::ErrorKind::TimedOut => {}
+        Err(e) => {
+          return Err(HayboxError::SerialPort(format!(
+            "Failed to read from {}: {}",
+            self.port_name, e
+          )))
+        }
+      }
+    }
+  }
+
+  /// Sends a command and returns the response payload.
+  fn send_packet(&mut self, packet: &[u8]) -> Result<(), HayboxError> {
+    protocol_trace::record(TraceDirection::Sent, packet);
+    let mut frame = cobs_encode(packet);
+    frame.push(PACKET_DELIMITER);
+    self
+      .port
+      .write_all(&frame)
+      .and_then(|_| self.port.flush())
+      .map_err(|e| HayboxError::SerialPort(format!("Failed to write to {}: {}", self.port_name, e)))
+  }
+
+  pub fn request(&mut self, command: u8, payload: &[u8]) -> Result<Vec<u8>, HayboxError> {
+    let mut packet = Vec::with_capacity(payload.len() + 1);
+    packet.push(command);
+    packet.extend_from_slice(payload);
+
+    self.pending.clear();
+    self.send_packet(&packet)?;
+
+    let response = self.read_packet()?;
+    protocol_trace::record(TraceDirection::Received, &response);
+    match response.split_first() {
+      Some((&CMD_ERROR, message)) => Err(HayboxError::Other(format!(
+        "Controller rejected the request: {}",
+        String::from_utf8_lossy(message)
+      ))),
+      Some((&echoed, body)) if echoed == command => Ok(body.to_vec()),
+      Some((&other, _)) => Err(HayboxError::Other(format!(
+        "Controller answered command 0x{:02X} with 0x{:02X}",
+        command, other
+      ))),
+      None => Err(HayboxError::Other("Controller sent an empty response".to_string())),
+    }
+  }
+
+  fn read_device_info(&mut self) -> Result<DeviceInfo, HayboxError> {
+    let payload = self.request(CMD_GET_DEVICE_INFO, &[])?;
+    DeviceInfo::decode(payload.as_slice())
+      .map_err(|e| HayboxError::Other(format!("Failed to decode device info: {}", e)))
+  }
+
+  pub fn get_config(&mut self) -> Result<Config, HayboxError> {
+    let (config, unknown) = self.read_config()?;
+    if !unknown.is_empty() {
+      tracing::warn!("config has fields this app does not model: {}", unknown.join(", "));
+    }
+    Ok(config)
+  }
+
+  /// Reads the config along with the fields decoding it dropped.
+  fn read_config(&mut self) -> Result<(Config, Vec<String>), HayboxError> {
+    let payload = self.request(CMD_GET_CONFIG, &[])?;
+    let config =
+      Config::decode(payload.as_slice()).map_err(|e| HayboxError::Other(format!("Failed to decode config: {}", e)))?;
+    let mut unknown = Vec::new();
+    find_unknown_fields(&payload, &CONFIG_SCHEMA, &mut unknown)
+      .ok_or_else(|| HayboxError::Other("Failed to decode config: malformed field".to_string()))?;
+    Ok((config, unknown))
+  }
+
+  /// Writes `config`, first backing up the config it replaces. Refuses when
+  /// the controller's config has fields this app does not model, since
+  /// writing would erase them.
+  pub fn set_config(&mut self, config: &Config) -> Result<(), HayboxError> {
+    config_migration::check_writable(self.device_info.config_version)?;
+    if let Some(capabilities) = &self.capabilities {
+      let violations = capabilities::check_config(config, capabilities);
+      if !violations.is_empty() {
+        let messages: Vec<String> = violations.into_iter().map(|violation| violation.message).collect();
+        return Err(HayboxError::Other(format!(
+          "Config does not fit this controller: {}",
+          messages.join("; ")
+        )));
+      }
+    }
+
+    let (previous, unknown) = self.read_config()?;
+    if !unknown.is_empty() {
+      return Err(HayboxError::Unsupported(format!(
+        "The controller's config has settings this version of the app does not know ({}); writing would erase \
+         them, so update the app first",
+        unknown.join(", ")
+      )));
+    }
+    if previous == *config {
+      return Ok(());
+    }
+    config_backup::save_backup(self, previous)?;
+    self.request(CMD_SET_CONFIG, &config.encode_to_vec()).map(|_| ())
+  }
+
+  /// Restarts into the firmware. The port disappears with the reboot, so no
+  /// response is read.
+  pub fn reboot(&mut self) -> Result<(), HayboxError> {
+    self.send_packet(&[CMD_REBOOT_FIRMWARE])
+  }
+}
+
+/// Runs `op` on the open config mode connection, dropping the connection if
+/// the port has gone away.
+pub fn with_connection<T>(op: impl FnOnce(&mut ConfigConnection) -> Result<T, HayboxError>) -> Result<T, HayboxError> {
+  let mut connection = CONNECTION.lock().unwrap();
+  let conn = connection
+    .as_mut()
+    .ok_or_else(|| HayboxError::DeviceNotConnected("Not connected to a controller in config mode".to_string()))?;
+  let result = op(conn);
+  if result.is_err()
+    && serialport::available_ports().is_ok_and(|ports| !ports.iter().any(|p| p.port_name == conn.port_name))
+  {
+    *connection = None;
+  }
+  result
+}
+
+/// Opens the config mode CDC port, found automatically unless `port` is
+/// given.
+pub fn connect_config_mode(port: Option<&str>) -> Result<ConfigModeConnection, HayboxError> {
+  let port_name = match port {
+    Some(port) => port.to_string(),
+    None => {
+      let config = &DEVICES.config_mode;
+      serial_ports::single_port_for_device(config.vid, config.pid)?.ok_or_else(|| {
+        HayboxError::DeviceNotConnected(
+          "No controller in config mode found; hold Start while plugging it in".to_string(),
+        )
+      })?
+    }
+  };
+
+  let mut connection = CONNECTION.lock().unwrap();
+  *connection = None;
+
+  let conn = ConfigConnection::open(&port_name)?;
+  let result = ConfigModeConnection {
+    port: port_name,
+    device_info: conn.device_info.clone(),
+    capabilities: conn.capabilities.clone(),
+  };
+  *connection = Some(conn);
+  Ok(result)
+}
+
+pub fn disconnect_config_mode() {
+  *CONNECTION.lock().unwrap() = None;
+}
+
+/// Restarts a controller in config mode into its firmware, connecting first
+/// if needed. The connection is dropped since the port goes away.
+pub fn reboot_firmware() -> Result<(), HayboxError> {
+  if CONNECTION.lock().unwrap().is_none() {
+    connect_config_mode(None)?;
+  }
+  let mut connection = CONNECTION.lock().unwrap();
+  let result = connection
+    .as_mut()
+    .ok_or_else(|| HayboxError::DeviceNotConnected("Not connected to a controller in config mode".to_string()))
+    .and_then(|conn| conn.reboot());
+  *connection = None;
+  result
+}
+
+pub fn get_config() -> Result<Config, HayboxError> {
+  with_connection(|conn| conn.get_config())
+}
+
+pub fn set_config(config: &Config) -> Result<(), HayboxError> {
+  with_connection(|conn| conn.set_config(config))
+}
+
+impl CommunicationBackendId {
+  /// Console backends run when the controller is plugged into a console
+  /// rather than a USB host.
+  pub fn is_console(self) -> bool {
+    matches!(
+      self,
+      CommunicationBackendId::Gamecube
+        | CommunicationBackendId::N64
+        | CommunicationBackendId::Nes
+        | CommunicationBackendId::Snes
+    )
+  }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DefaultModeResult {
+  pub mode: GameModeId,
+  pub backend: CommunicationBackendId,
+}
+
+/// Makes `mode` on `backend` what the controller boots into with no buttons
+/// held, then reboots it. Without `backend`, the current default backend is
+/// kept.
+pub fn set_default_mode(
+  mode: GameModeId,
+  backend: Option<CommunicationBackendId>,
+) -> Result<DefaultModeResult, HayboxError> {
+  let mut connection = CONNECTION.lock().unwrap();
+  let conn = connection
+    .as_mut()
+    .ok_or_else(|| HayboxError::DeviceNotConnected("Not connected to a controller in config mode".to_string()))?;
+  let mut config = conn.get_config()?;
+
+  let mode_index = config
+    .game_mode_configs
+    .iter()
+    .position(|mode_config| mode_config.mode_id == mode as i32)
+    .ok_or_else(|| HayboxError::Other(format!("The controller has no {:?} mode configured", mode)))?;
+  let backend_index = match backend {
+    Some(backend) => config
+      .communication_backend_configs
+      .iter()
+      .position(|backend_config| backend_config.backend_id == backend as i32)
+      .ok_or_else(|| HayboxError::Other(format!("The controller has no {:?} backend configured", backend)))?,
+    None => config.default_usb_backend_config as usize,
+  };
+  let backend_config = config
+    .communication_backend_configs
+    .get_mut(backend_index)
+    .ok_or_else(|| HayboxError::Other(format!("Default backend {} does not exist", backend_index)))?;
+  let backend =
+    CommunicationBackendId::try_from(backend_config.backend_id).unwrap_or(CommunicationBackendId::Unspecified);
+
+  backend_config.default_mode_config = mode_index as u32;
+  if backend.is_console() {
+    config.default_backend_config = backend_index as u32;
+  } else {
+    config.default_usb_backend_config = backend_index as u32;
+  }
+  conn.set_config(&config)?;
+  conn.reboot()?;
+  *connection = None;
+
+  Ok(DefaultModeResult { mode, backend })
+}
@@ -0,0 +1,187 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config_proto::{self, Config, ConfigConnection};
+use crate::error::HayboxError;
+use crate::events::Events;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(15);
+const TICK: Duration = Duration::from_secs(1);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TrialState {
+  Running,
+  Confirmed,
+  Reverted,
+  /// The previous config could not be written back; `message` says why.
+  RevertFailed,
+}
+
+/// Emitted as `config_trial` once a second while a trial runs and once when
+/// it ends.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ConfigTrialEvent {
+  pub state: TrialState,
+  pub seconds_left: u64,
+  pub message: Option<String>,
+}
+
+/// The controller a trial runs on, so the revert never lands on another one
+/// plugged in meanwhile.
+#[derive(Clone, PartialEq, Eq)]
+struct Target {
+  port: String,
+  serial_number: Option<String>,
+}
+
+impl Target {
+  fn of(conn: &ConfigConnection) -> Self {
+    Target {
+      port: conn.port_name().to_string(),
+      serial_number: conn.serial_number.clone(),
+    }
+  }
+}
+
+struct Trial {
+  id: u64,
+  target: Target,
+  previous: Config,
+  deadline: Instant,
+}
+
+lazy_static::lazy_static! {
+  static ref TRIAL: Mutex<Option<Trial>> = Mutex::new(None);
+}
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+fn emit(events: &Events, state: TrialState, seconds_left: u64, message: Option<String>) {
+  events.emit(
+    "config_trial",
+    ConfigTrialEvent {
+      state,
+      seconds_left,
+      message,
+    },
+  );
+}
+
+/// Writes `trial.previous` back, but only to the controller the trial
+/// started on.
+fn revert(trial: Trial) -> Result<(), HayboxError> {
+  config_proto::with_connection(|conn| {
+    if Target::of(conn) != trial.target {
+      return Err(HayboxError::DeviceNotConnected(format!(
+        "The controller on {} that ran the trial is no longer connected; its previous config was not written to \
+         the one connected now",
+        trial.target.port
+      )));
+    }
+    conn.set_config(&trial.previous)
+  })
+}
+
+/// Writes `config` and restores the one it replaced unless
+/// `confirm_config_trial` is called within `timeout`. Starting a new trial
+/// while one runs keeps the original config as the one to restore.
+pub fn try_config(events: &Events, config: &Config, timeout: Option<Duration>) -> Result<(), HayboxError> {
+  let timeout = timeout.unwrap_or(DEFAULT_TIMEOUT);
+  let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+
+  let running = TRIAL
+    .lock()
+    .unwrap()
+    .as_ref()
+    .map(|trial| (trial.target.clone(), trial.previous.clone()));
+  let (target, previous) = config_proto::with_connection(|conn| {
+    let target = Target::of(conn);
+    let previous = match running {
+      Some((running, previous)) if running == target => previous,
+      Some(_) => {
+        return Err(HayboxError::DeviceBusy(
+          "A config trial is running on another controller; confirm or cancel it first".to_string(),
+        ))
+      }
+      None => conn.get_config()?,
+    };
+    conn
+      .set_config(config)
+      .map_err(|e| HayboxError::Other(format!("Failed to write the trial config: {}", e)))?;
+    Ok((target, previous))
+  })?;
+  *TRIAL.lock().unwrap() = Some(Trial {
+    id,
+    target,
+    previous,
+    deadline: Instant::now() + timeout,
+  });
+
+  let events = events.clone();
+  std::thread::spawn(move || loop {
+    let remaining = {
+      let mut trial = TRIAL.lock().unwrap();
+      match trial.as_ref() {
+        // Confirmed, or replaced by a newer trial with its own countdown.
+        Some(current) if current.id != id => return,
+        None => return,
+        Some(current) => match current.deadline.checked_duration_since(Instant::now()) {
+          Some(remaining) => remaining,
+          None => {
+            let expired = trial.take();
+            drop(trial);
+            match expired.map(revert) {
+              Some(Err(e)) => emit(&events, TrialState::RevertFailed, 0, Some(e.to_string())),
+              _ => emit(&events, TrialState::Reverted, 0, None),
+            }
+            return;
+          }
+        },
+      }
+    };
+    emit(
+      &events,
+      TrialState::Running,
+      remaining.as_secs_f64().ceil() as u64,
+      None,
+    );
+    std::thread::sleep(remaining.min(TICK));
+  });
+
+  Ok(())
+}
+
+/// Keeps the trial config. The UI calls this when the user confirms; the
+/// firmware has no serial heartbeat to confirm with.
+pub fn confirm_config_trial(events: &Events) -> Result<(), HayboxError> {
+  TRIAL
+    .lock()
+    .unwrap()
+    .take()
+    .ok_or_else(|| HayboxError::Other("No config trial is running".to_string()))?;
+  emit(events, TrialState::Confirmed, 0, None);
+  Ok(())
+}
+
+/// Ends the trial early and restores the previous config.
+pub fn cancel_config_trial(events: &Events) -> Result<(), HayboxError> {
+  let trial = TRIAL
+    .lock()
+    .unwrap()
+    .take()
+    .ok_or_else(|| HayboxError::Other("No config trial is running".to_string()))?;
+  match revert(trial) {
+    Ok(()) => {
+      emit(events, TrialState::Reverted, 0, None);
+      Ok(())
+    }
+    Err(e) => {
+      emit(events, TrialState::RevertFailed, 0, Some(e.to_string()));
+      Err(e)
+    }
+  }
+}
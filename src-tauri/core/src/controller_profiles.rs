@@ -0,0 +1,235 @@
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config_backup;
+use crate::config_proto::{self, Button, Config, ConfigConnection, GameModeConfig, GameModeId};
+use crate::error::HayboxError;
+use crate::paths::app_data_dir;
+
+const SNAPSHOT_DIR: &str = "profile_snapshots";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ControllerProfile {
+  pub index: u32,
+  pub name: String,
+  pub mode: GameModeId,
+  /// Buttons held at plug-in to select this profile.
+  pub activation_binding: Vec<Button>,
+  pub remapped_buttons: usize,
+  /// Whether the controller boots into this profile over USB.
+  pub active: bool,
+}
+
+/// The controller's profiles as last saved through the app, kept so they can
+/// be written back after a reflash resets the config.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProfileSnapshot {
+  pub device_name: String,
+  pub firmware_version: String,
+  pub saved_at: u64,
+  pub profiles: Vec<GameModeConfig>,
+}
+
+fn snapshot_path(conn: &ConfigConnection) -> PathBuf {
+  app_data_dir()
+    .join(SNAPSHOT_DIR)
+    .join(format!("{}.json", config_backup::device_key(conn)))
+}
+
+fn save_snapshot(conn: &ConfigConnection, config: &Config) -> Result<(), HayboxError> {
+  let snapshot = ProfileSnapshot {
+    device_name: conn.device_info.device_name.clone(),
+    firmware_version: conn.device_info.firmware_version.clone(),
+    saved_at: SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .map(|d| d.as_secs())
+      .unwrap_or_default(),
+    profiles: config.game_mode_configs.clone(),
+  };
+
+  let path = snapshot_path(conn);
+  if let Some(parent) = path.parent() {
+    std::fs::create_dir_all(parent)
+      .map_err(|e| HayboxError::Io(format!("Failed to create {}: {}", parent.display(), e)))?;
+  }
+  let content = serde_json::to_string_pretty(&snapshot)
+    .map_err(|e| HayboxError::Other(format!("Failed to serialize profile snapshot: {}", e)))?;
+  std::fs::write(&path, content).map_err(|e| HayboxError::Io(format!("Failed to write {}: {}", path.display(), e)))
+}
+
+fn load_snapshot(conn: &ConfigConnection) -> Option<ProfileSnapshot> {
+  std::fs::read_to_string(snapshot_path(conn))
+    .ok()
+    .and_then(|content| serde_json::from_str(&content).ok())
+}
+
+fn button(value: i32) -> Button {
+  Button::try_from(value).unwrap_or(Button::Unspecified)
+}
+
+/// Index of the profile the default USB backend boots into.
+fn active_profile(config: &Config) -> Option<u32> {
+  config
+    .communication_backend_configs
+    .get(config.default_usb_backend_config as usize)
+    .map(|backend| backend.default_mode_config)
+}
+
+fn summarize(config: &Config) -> Vec<ControllerProfile> {
+  let active = active_profile(config);
+  config
+    .game_mode_configs
+    .iter()
+    .enumerate()
+    .map(|(index, mode)| ControllerProfile {
+      index: index as u32,
+      name: mode.name.clone(),
+      mode: GameModeId::try_from(mode.mode_id).unwrap_or(GameModeId::Unspecified),
+      activation_binding: mode.activation_binding.iter().map(|&value| button(value)).collect(),
+      remapped_buttons: mode.button_remapping.len(),
+      active: active == Some(index as u32),
+    })
+    .collect()
+}
+
+fn check_binding_unique(config: &Config, binding: &[i32]) -> Result<(), HayboxError> {
+  if binding.is_empty() {
+    return Ok(());
+  }
+  let wanted: BTreeSet<i32> = binding.iter().copied().collect();
+  for mode in &config.game_mode_configs {
+    if mode.activation_binding.iter().copied().collect::<BTreeSet<i32>>() == wanted {
+      return Err(HayboxError::Other(format!(
+        "Profile '{}' already uses that activation binding",
+        mode.name
+      )));
+    }
+  }
+  Ok(())
+}
+
+/// Reads the config, applies `edit`, writes it back and snapshots the
+/// result.
+fn modify(edit: impl FnOnce(&mut Config) -> Result<(), HayboxError>) -> Result<Vec<ControllerProfile>, HayboxError> {
+  config_proto::with_connection(|conn| {
+    let mut config = conn.get_config()?;
+    edit(&mut config)?;
+    conn.set_config(&config)?;
+    if let Err(e) = save_snapshot(conn, &config) {
+      tracing::warn!("{}", e);
+    }
+    Ok(summarize(&config))
+  })
+}
+
+fn profile_mut(config: &mut Config, profile: u32) -> Result<&mut GameModeConfig, HayboxError> {
+  config
+    .game_mode_configs
+    .get_mut(profile as usize)
+    .ok_or_else(|| HayboxError::Other(format!("Profile {} does not exist", profile)))
+}
+
+pub fn list_profiles() -> Result<Vec<ControllerProfile>, HayboxError> {
+  config_proto::with_connection(|conn| Ok(summarize(&conn.get_config()?)))
+}
+
+pub fn create_profile(
+  name: &str,
+  mode: GameModeId,
+  activation_binding: &[Button],
+) -> Result<Vec<ControllerProfile>, HayboxError> {
+  if name.trim().is_empty() {
+    return Err(HayboxError::Other("Profile name cannot be empty".to_string()));
+  }
+  let binding: Vec<i32> = activation_binding.iter().map(|&button| button as i32).collect();
+
+  modify(|config| {
+    check_binding_unique(config, &binding)?;
+    config.game_mode_configs.push(GameModeConfig {
+      mode_id: mode as i32,
+      name: name.trim().to_string(),
+      activation_binding: binding,
+      ..Default::default()
+    });
+    Ok(())
+  })
+}
+
+pub fn rename_profile(profile: u32, name: &str) -> Result<Vec<ControllerProfile>, HayboxError> {
+  if name.trim().is_empty() {
+    return Err(HayboxError::Other("Profile name cannot be empty".to_string()));
+  }
+  modify(|config| {
+    profile_mut(config, profile)?.name = name.trim().to_string();
+    Ok(())
+  })
+}
+
+/// Removes a profile and shifts backend defaults that pointed past it.
+/// Backends that booted into the deleted profile fall back to the first.
+pub fn delete_profile(profile: u32) -> Result<Vec<ControllerProfile>, HayboxError> {
+  modify(|config| {
+    if config.game_mode_configs.len() <= 1 {
+      return Err(HayboxError::Other(
+        "The controller needs at least one profile".to_string(),
+      ));
+    }
+    profile_mut(config, profile)?;
+    config.game_mode_configs.remove(profile as usize);
+
+    for backend in &mut config.communication_backend_configs {
+      if backend.default_mode_config == profile {
+        backend.default_mode_config = 0;
+      } else if backend.default_mode_config > profile {
+        backend.default_mode_config -= 1;
+      }
+    }
+    Ok(())
+  })
+}
+
+/// Makes the default USB backend boot into `profile`.
+pub fn set_active_profile(profile: u32) -> Result<Vec<ControllerProfile>, HayboxError> {
+  modify(|config| {
+    profile_mut(config, profile)?;
+    let backend_index = config.default_usb_backend_config as usize;
+    let backend = config
+      .communication_backend_configs
+      .get_mut(backend_index)
+      .ok_or_else(|| HayboxError::Other(format!("Default backend {} does not exist", backend_index)))?;
+    backend.default_mode_config = profile;
+    Ok(())
+  })
+}
+
+/// The saved snapshot for the connected controller, if any.
+pub fn get_profile_snapshot() -> Result<Option<ProfileSnapshot>, HayboxError> {
+  config_proto::with_connection(|conn| Ok(load_snapshot(conn)))
+}
+
+/// Writes the saved profiles back, e.g. after a reflash reset them to the
+/// firmware defaults. Backend defaults past the end of the restored list are
+/// reset to the first profile.
+pub fn restore_profile_snapshot() -> Result<Vec<ControllerProfile>, HayboxError> {
+  config_proto::with_connection(|conn| {
+    let snapshot = load_snapshot(conn)
+      .ok_or_else(|| HayboxError::Other(format!("No saved profiles for {}", conn.device_info.device_name)))?;
+    if snapshot.profiles.is_empty() {
+      return Err(HayboxError::Other("The saved snapshot has no profiles".to_string()));
+    }
+
+    let mut config = conn.get_config()?;
+    config.game_mode_configs = snapshot.profiles;
+    let count = config.game_mode_configs.len() as u32;
+    for backend in &mut config.communication_backend_configs {
+      if backend.default_mode_config >= count {
+        backend.default_mode_config = 0;
+      }
+    }
+    conn.set_config(&config)?;
+    Ok(summarize(&config))
+  })
+}
@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::analog_trace;
+use crate::error::HayboxError;
+use crate::input_monitor::InputState;
+use crate::input_recording;
+
+/// Sticks checked for circle limits, as pairs of HID axis names.
+const STICKS: [(&str, &str); 3] = [("X", "Y"), ("Rx", "Ry"), ("Z", "Rz")];
+/// An axis further than this from center counts as pushed fully for the
+/// SOCD reversal check.
+const FULL_PUSH: f64 = 0.8;
+/// Violations listed per rule; the rest are only counted.
+const MAX_LISTED: usize = 20;
+
+/// Limits a ruleset places on analog output. Offsets are measured from
+/// center on an 8-bit scale, which for HayBox's Melee modes is Melee's
+/// 0.0125 step.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Ruleset {
+  pub name: String,
+  /// Largest offset on any single axis.
+  pub max_axis: Option<u32>,
+  /// Largest distance from center for each stick.
+  pub max_radius: Option<f64>,
+  /// Opposing directions must cancel out, so an axis may not go from one
+  /// extreme to the other without passing through center.
+  #[serde(default)]
+  pub require_neutral_socd: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CaptureSource {
+  /// The analog trace buffer.
+  Trace,
+  Recording {
+    path: PathBuf,
+  },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LegalityRule {
+  MaxAxis,
+  MaxRadius,
+  NeutralSocd,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LegalityViolation {
+  pub rule: LegalityRule,
+  pub at_us: u64,
+  pub axis: String,
+  pub value: f64,
+  pub limit: f64,
+  pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ComplianceReport {
+  pub ruleset: Ruleset,
+  pub samples: usize,
+  pub compliant: bool,
+  pub violation_count: usize,
+  /// The first few violations of each rule.
+  pub violations: Vec<LegalityViolation>,
+  /// Largest offset seen per axis.
+  pub max_seen: Vec<(String, u32)>,
+}
+
+/// Rulesets shipped with the app. Anything else can be passed in full.
+pub fn get_rulesets() -> Vec<Ruleset> {
+  vec![
+    Ruleset {
+      name: "Melee (coordinates only)".to_string(),
+      max_axis: Some(80),
+      max_radius: Some(80.0),
+      require_neutral_socd: false,
+    },
+    Ruleset {
+      name: "Melee with neutral SOCD".to_string(),
+      max_axis: Some(80),
+      max_radius: Some(80.0),
+      require_neutral_socd: true,
+    },
+  ]
+}
+
+fn offset(normalized: f32) -> f64 {
+  (normalized as f64 * 255.0).round() - 128.0
+}
+
+fn axis_offset(frame: &InputState, name: &str) -> Option<f64> {
+  frame
+    .axes
+    .iter()
+    .find(|axis| axis.name == name)
+    .map(|axis| offset(axis.normalized))
+}
+
+fn check_frames(frames: &[InputState], ruleset: &Ruleset) -> ComplianceReport {
+  let mut violations: Vec<LegalityViolation> = Vec::new();
+  let mut counts = [0usize; 3];
+  let mut max_seen: Vec<(String, u32)> = Vec::new();
+  let mut push = |violation: LegalityViolation| {
+    let count = &mut counts[violation.rule as usize];
+    *count += 1;
+    if *count <= MAX_LISTED {
+      violations.push(violation);
+    }
+  };
+
+  // Last side of center each axis was fully pushed to, cleared at center.
+  let mut last_extreme: HashMap<String, i8> = HashMap::new();
+
+  for frame in frames {
+    for axis in &frame.axes {
+      let value = offset(axis.normalized);
+      let magnitude = value.abs() as u32;
+      match max_seen.iter_mut().find(|(name, _)| *name == axis.name) {
+        Some((_, max)) => *max = (*max).max(magnitude),
+        None => max_seen.push((axis.name.clone(), magnitude)),
+      }
+
+      if let Some(limit) = ruleset.max_axis {
+        if magnitude > limit {
+          push(LegalityViolation {
+            rule: LegalityRule::MaxAxis,
+            at_us: frame.timestamp_us,
+            axis: axis.name.clone(),
+            value,
+            limit: limit as f64,
+            message: format!("{} reached {} where the limit is {}", axis.name, value, limit),
+          });
+        }
+      }
+
+      if ruleset.require_neutral_socd {
+        let full = ruleset.max_axis.unwrap_or(127) as f64 * FULL_PUSH;
+        let side: i8 = if value >= full {
+          1
+        } else if value <= -full {
+          -1
+        } else {
+          0
+        };
+        let last = last_extreme.entry(axis.name.clone()).or_default();
+        if side != 0 && *last == -side {
+          push(LegalityViolation {
+            rule: LegalityRule::NeutralSocd,
+            at_us: frame.timestamp_us,
+            axis: axis.name.clone(),
+            value,
+            limit: 0.0,
+            message: format!(
+              "{} went from one extreme to the other without passing through center",
+              axis.name
+            ),
+          });
+        }
+        if side != 0 || value.abs() < full / 2.0 {
+          *last = side;
+        }
+      }
+    }
+
+    if let Some(limit) = ruleset.max_radius {
+      for (x, y) in STICKS {
+        let (Some(x_value), Some(y_value)) = (axis_offset(frame, x), axis_offset(frame, y)) else {
+          continue;
+        };
+        let radius = x_value.hypot(y_value);
+        // Allow for rounding on the 8-bit scale.
+        if radius > limit + 0.5 {
+          push(LegalityViolation {
+            rule: LegalityRule::MaxRadius,
+            at_us: frame.timestamp_us,
+            axis: format!("{}/{}", x, y),
+            value: radius,
+            limit,
+            message: format!(
+              "{}/{} at ({}, {}) is {:.1} from center where the limit is {}",
+              x, y, x_value, y_value, radius, limit
+            ),
+          });
+        }
+      }
+    }
+  }
+
+  let violation_count = counts.iter().sum();
+  violations.sort_by_key(|violation| violation.at_us);
+  ComplianceReport {
+    ruleset: ruleset.clone(),
+    samples: frames.len(),
+    compliant: violation_count == 0,
+    violation_count,
+    violations,
+    max_seen,
+  }
+}
+
+/// Checks captured analog output against a ruleset, for controller checks
+/// at tournament check-in.
+pub fn check_coordinate_legality(source: &CaptureSource, ruleset: &Ruleset) -> Result<ComplianceReport, HayboxError> {
+  let frames = match source {
+    CaptureSource::Trace => analog_trace::trace_frames(),
+    CaptureSource::Recording { path } => input_recording::load_recording(path)?.frames,
+  };
+  if frames.is_empty() {
+    return Err(HayboxError::Other("The capture has no analog samples".to_string()));
+  }
+  Ok(check_frames(&frames, ruleset))
+}
@@ -0,0 +1,261 @@
+use std::backtrace::Backtrace;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+#[cfg(target_os = "windows")]
+use windows::core::PCWSTR;
+#[cfg(target_os = "windows")]
+use windows::Win32::Foundation::{CloseHandle, FALSE};
+#[cfg(target_os = "windows")]
+use windows::Win32::Storage::FileSystem::{
+  CreateFileW, CREATE_ALWAYS, FILE_ATTRIBUTE_NORMAL, FILE_GENERIC_WRITE, FILE_SHARE_MODE,
+};
+#[cfg(target_os = "windows")]
+use windows::Win32::System::Diagnostics::Debug::{
+  MiniDumpWithThreadInfo, MiniDumpWriteDump, SetUnhandledExceptionFilter, EXCEPTION_CONTINUE_SEARCH,
+  EXCEPTION_POINTERS, MINIDUMP_EXCEPTION_INFORMATION,
+};
+#[cfg(target_os = "windows")]
+use windows::Win32::System::Threading::{GetCurrentProcess, GetCurrentProcessId, GetCurrentThreadId};
+
+use crate::error::HayboxError;
+#[cfg(target_os = "windows")]
+use crate::integrity::to_wide;
+use crate::logging::{self, LogEntry};
+use crate::paths::app_data_dir;
+
+const CRASH_DIR: &str = "crashes";
+/// Reports kept before the oldest, and its minidump, are deleted.
+const MAX_CRASH_REPORTS: usize = 10;
+/// Log entries copied into each report.
+const CRASH_LOG_ENTRIES: usize = 200;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CrashKind {
+  Panic,
+  /// An unhandled structured exception, e.g. an access violation in a
+  /// driver library. Windows only.
+  Exception,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CrashReport {
+  /// Seconds since the Unix epoch.
+  pub timestamp: u64,
+  pub app_version: String,
+  pub os: String,
+  pub kind: CrashKind,
+  pub message: String,
+  /// Source location of a panic, or the faulting address of an exception.
+  pub location: Option<String>,
+  pub thread: Option<String>,
+  pub backtrace: String,
+  pub recent_logs: Vec<LogEntry>,
+  pub minidump_path: Option<String>,
+  /// Whether `get_last_crash_report` has returned this report before, so
+  /// the UI only offers it once.
+  #[serde(default)]
+  pub seen: bool,
+}
+
+fn crash_dir() -> PathBuf {
+  app_data_dir().join(CRASH_DIR)
+}
+
+fn now() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_secs())
+    .unwrap_or_default()
+}
+
+/// Report files, oldest first.
+fn report_files() -> Vec<PathBuf> {
+  let Ok(entries) = std::fs::read_dir(crash_dir()) else {
+    return Vec::new();
+  };
+  let mut files: Vec<PathBuf> = entries
+    .flatten()
+    .map(|entry| entry.path())
+    .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+    .collect();
+  // Names carry the timestamp, so they sort by age.
+  files.sort();
+  files
+}
+
+fn prune_reports() {
+  let files = report_files();
+  for report in files.iter().take(files.len().saturating_sub(MAX_CRASH_REPORTS)) {
+    let _ = std::fs::remove_file(report);
+    let _ = std::fs::remove_file(report.with_extension("dmp"));
+  }
+}
+
+fn write_report(report: &CrashReport, path: &Path) -> Result<(), HayboxError> {
+  let json = serde_json::to_string_pretty(report)
+    .map_err(|e| HayboxError::Other(format!("Failed to serialize crash report: {}", e)))?;
+  std::fs::write(path, json).map_err(|e| HayboxError::Io(format!("Failed to write {}: {}", path.display(), e)))
+}
+
+/// Writes a report for the crash in progress. Runs inside the panic hook or
+/// the exception filter, so failures are only printed.
+fn save_crash(timestamp: u64, kind: CrashKind, message: String, location: Option<String>, minidump: Option<&Path>) {
+  let dir = crash_dir();
+  if let Err(e) = std::fs::create_dir_all(&dir) {
+    eprintln!("Failed to create {}: {}", dir.display(), e);
+    return;
+  }
+
+  let report = CrashReport {
+    timestamp,
+    app_version: env!("CARGO_PKG_VERSION").to_string(),
+    os: std::env::consts::OS.to_string(),
+    kind,
+    message,
+    location,
+    thread: std::thread::current().name().map(str::to_string),
+    backtrace: Backtrace::force_capture().to_string(),
+    recent_logs: logging::try_recent_logs(CRASH_LOG_ENTRIES),
+    minidump_path: minidump.map(|path| path.display().to_string()),
+    seen: false,
+  };
+
+  let path = dir.join(format!("crash-{}.json", timestamp));
+  match write_report(&report, &path) {
+    Ok(()) => {
+      eprintln!("Crash report written to {}", path.display());
+      prune_reports();
+    }
+    Err(e) => eprintln!("{}", e),
+  }
+}
+
+fn panic_message(info: &std::panic::PanicHookInfo) -> String {
+  if let Some(message) = info.payload().downcast_ref::<&str>() {
+    message.to_string()
+  } else if let Some(message) = info.payload().downcast_ref::<String>() {
+    message.clone()
+  } else {
+    "Box<dyn Any>".to_string()
+  }
+}
+
+/// Writes a minidump of the whole process with the faulting thread's
+/// context.
+#[cfg(target_os = "windows")]
+fn write_minidump(path: &Path, exception: *const EXCEPTION_POINTERS) -> Result<(), HayboxError> {
+  let wide_path = to_wide(&path.to_string_lossy());
+  let file = unsafe {
+    CreateFileW(
+      PCWSTR(wide_path.as_ptr()),
+      FILE_GENERIC_WRITE.0,
+      FILE_SHARE_MODE(0),
+      None,
+      CREATE_ALWAYS,
+      FILE_ATTRIBUTE_NORMAL,
+      None,
+    )
+  }
+  .map_err(|e| HayboxError::Io(format!("Failed to create {}: {}", path.display(), e)))?;
+
+  let exception_info = MINIDUMP_EXCEPTION_INFORMATION {
+    ThreadId: unsafe { GetCurrentThreadId() },
+    ExceptionPointers: exception as *mut _,
+    ClientPointers: FALSE,
+  };
+  let result = unsafe {
+    MiniDumpWriteDump(
+      GetCurrentProcess(),
+      GetCurrentProcessId(),
+      file,
+      MiniDumpWithThreadInfo,
+      Some(&exception_info),
+      None,
+      None,
+    )
+  };
+  let _ = unsafe { CloseHandle(file) };
+  result.map_err(|e| HayboxError::Io(format!("Failed to write minidump: {}", e)))
+}
+
+#[cfg(target_os = "windows")]
+unsafe extern "system" fn exception_filter(exception: *const EXCEPTION_POINTERS) -> i32 {
+  let record = unsafe {
+    exception
+      .as_ref()
+      .and_then(|pointers| pointers.ExceptionRecord.as_ref())
+  };
+  let (message, location) = match record {
+    Some(record) => (
+      format!("Unhandled exception 0x{:08X}", record.ExceptionCode.0 as u32),
+      Some(format!("{:p}", record.ExceptionAddress)),
+    ),
+    None => ("Unhandled exception".to_string(), None),
+  };
+
+  let timestamp = now();
+  let dump_path = crash_dir().join(format!("crash-{}.dmp", timestamp));
+  let minidump = match std::fs::create_dir_all(crash_dir()).map_err(HayboxError::from) {
+    Ok(()) => write_minidump(&dump_path, exception),
+    Err(e) => Err(e),
+  };
+  if let Err(e) = &minidump {
+    eprintln!("{}", e);
+  }
+
+  save_crash(
+    timestamp,
+    CrashKind::Exception,
+    message,
+    location,
+    minidump.is_ok().then_some(dump_path.as_path()),
+  );
+  // Let Windows Error Reporting handle the crash as it would have.
+  EXCEPTION_CONTINUE_SEARCH
+}
+
+/// Installs the panic hook and, on Windows, the unhandled exception filter.
+/// The previous panic hook still runs, so panics are printed as before.
+pub fn install() {
+  let previous = std::panic::take_hook();
+  std::panic::set_hook(Box::new(move |info| {
+    let message = panic_message(info);
+    let location = info
+      .location()
+      .map(|location| format!("{}:{}:{}", location.file(), location.line(), location.column()));
+    save_crash(now(), CrashKind::Panic, message.clone(), location, None);
+    tracing::error!("panic: {}", message);
+    previous(info);
+  }));
+
+  #[cfg(target_os = "windows")]
+  unsafe {
+    SetUnhandledExceptionFilter(Some(exception_filter));
+  }
+}
+
+/// The newest crash report, if any. It is marked as seen, so `seen` is only
+/// false the first time it is returned.
+pub fn get_last_crash_report() -> Result<Option<CrashReport>, HayboxError> {
+  let Some(path) = report_files().pop() else {
+    return Ok(None);
+  };
+  let content =
+    std::fs::read_to_string(&path).map_err(|e| HayboxError::Io(format!("Failed to read {}: {}", path.display(), e)))?;
+  let report: CrashReport = serde_json::from_str(&content)
+    .map_err(|e| HayboxError::Other(format!("Failed to parse {}: {}", path.display(), e)))?;
+
+  if !report.seen {
+    let seen = CrashReport {
+      seen: true,
+      ..report.clone()
+    };
+    if let Err(e) = write_report(&seen, &path) {
+      tracing::warn!("{}", e);
+    }
+  }
+  Ok(Some(report))
+}
@@ -0,0 +1,203 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::bootsel::{self, RebootMethod};
+use crate::config_proto;
+use crate::error::HayboxError;
+use crate::hotplug::{self, HotplugKind};
+use crate::picoboot::Picoboot;
+use crate::{platform, UsbDeviceInfo, DEVICES};
+
+/// How long to wait for the controller to come back after a reboot.
+const TRANSITION_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where the controller is in its lifecycle, judged by the USB IDs it
+/// enumerates with.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceMode {
+  Disconnected,
+  Default,
+  Config,
+  Bootsel,
+  Switch,
+}
+
+impl DeviceMode {
+  /// Modes in which the controller runs its firmware rather than the boot
+  /// ROM.
+  pub const FIRMWARE: [DeviceMode; 3] = [DeviceMode::Default, DeviceMode::Config, DeviceMode::Switch];
+  /// Connected modes, furthest from normal use first, for when several
+  /// controllers are attached.
+  const PRIORITY: [DeviceMode; 4] = [
+    DeviceMode::Bootsel,
+    DeviceMode::Config,
+    DeviceMode::Switch,
+    DeviceMode::Default,
+  ];
+
+  pub fn device(self) -> Option<&'static UsbDeviceInfo> {
+    match self {
+      DeviceMode::Disconnected => None,
+      DeviceMode::Default => Some(&DEVICES.default_mode),
+      DeviceMode::Config => Some(&DEVICES.config_mode),
+      DeviceMode::Bootsel => Some(&DEVICES.bootsel_mode),
+      DeviceMode::Switch => Some(&DEVICES.switch_mode),
+    }
+  }
+
+  pub fn from_ids(vendor_id: u16, product_id: u16) -> Option<DeviceMode> {
+    DeviceMode::PRIORITY.into_iter().find(|mode| {
+      mode
+        .device()
+        .is_some_and(|device| device.vid == vendor_id && device.pid == product_id)
+    })
+  }
+
+  /// The mode of the connected controller.
+  pub fn current() -> DeviceMode {
+    let connected = platform::current().connected_devices();
+    DeviceMode::PRIORITY
+      .into_iter()
+      .find(|mode| {
+        mode
+          .device()
+          .is_some_and(|device| connected.contains(&(device.vid, device.pid)))
+      })
+      .unwrap_or(DeviceMode::Disconnected)
+  }
+
+  fn name(self) -> &'static str {
+    match self.device() {
+      Some(device) => &device.name,
+      None => "Disconnected",
+    }
+  }
+
+  /// What to hold while plugging the controller in to start in this mode.
+  fn plug_in_instruction(self) -> &'static str {
+    match self {
+      DeviceMode::Disconnected => "Unplug the controller",
+      DeviceMode::Default => "Plug the controller in without holding any buttons",
+      DeviceMode::Config => "Plug the controller in while holding Start",
+      DeviceMode::Bootsel => "Plug the controller in while holding the BOOTSEL button",
+      DeviceMode::Switch => "Plug the controller into the Switch, or hold its Switch mode button while plugging it in",
+    }
+  }
+}
+
+/// How one mode is left for another.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ModeAction {
+  /// Already in the target mode.
+  None,
+  /// The Pico SDK reset interface's BOOTSEL vendor request.
+  ResetInterface,
+  /// Opening the config mode serial port at 1200 baud and closing it.
+  SerialTouch,
+  /// The config protocol's reboot command, which starts the firmware in the
+  /// mode it boots into with no buttons held.
+  ConfigReboot,
+  /// The boot ROM's PICOBOOT reboot command.
+  PicobootReboot,
+  /// A step only the user can do, such as replugging while holding a button.
+  UserInstruction { instruction: String },
+}
+
+/// The action for the edge from `from` to `to`. Edges the backend cannot
+/// drive itself fall back to instructions for the user.
+pub fn transition(from: DeviceMode, to: DeviceMode) -> ModeAction {
+  match (from, to) {
+    _ if from == to => ModeAction::None,
+    (DeviceMode::Default | DeviceMode::Switch, DeviceMode::Bootsel) => ModeAction::ResetInterface,
+    (DeviceMode::Config, DeviceMode::Bootsel) => ModeAction::SerialTouch,
+    (DeviceMode::Config, DeviceMode::Default) => ModeAction::ConfigReboot,
+    (DeviceMode::Bootsel, DeviceMode::Default) => ModeAction::PicobootReboot,
+    (DeviceMode::Disconnected, _) | (_, DeviceMode::Disconnected) => ModeAction::UserInstruction {
+      instruction: to.plug_in_instruction().to_string(),
+    },
+    _ => ModeAction::UserInstruction {
+      instruction: format!("Unplug the controller. {}", to.plug_in_instruction()),
+    },
+  }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ModeTransition {
+  pub from: DeviceMode,
+  pub target: DeviceMode,
+  pub action: ModeAction,
+  /// The mode the controller is in afterwards.
+  pub mode: DeviceMode,
+  pub reached: bool,
+  pub message: String,
+}
+
+fn perform(action: &ModeAction) -> Result<(), HayboxError> {
+  match action {
+    ModeAction::None | ModeAction::UserInstruction { .. } => Ok(()),
+    ModeAction::ResetInterface => bootsel::request_bootsel(RebootMethod::ResetInterface),
+    ModeAction::SerialTouch => bootsel::request_bootsel(RebootMethod::SerialTouch),
+    ModeAction::ConfigReboot => config_proto::reboot_firmware(),
+    ModeAction::PicobootReboot => Picoboot::open()?.reboot(),
+  }
+}
+
+/// Moves the controller towards `target`. Actions the backend can perform
+/// are sent and confirmed by waiting for the controller to re-enumerate;
+/// otherwise the result carries the instruction to show the user.
+pub fn request_mode(target: DeviceMode) -> Result<ModeTransition, HayboxError> {
+  let from = DeviceMode::current();
+  let action = transition(from, target);
+
+  let unperformed = match &action {
+    ModeAction::None => Some(format!("The controller is already in {}", target.name())),
+    ModeAction::UserInstruction { instruction } => Some(instruction.clone()),
+    _ => None,
+  };
+  if let Some(message) = unperformed {
+    return Ok(ModeTransition {
+      from,
+      target,
+      mode: from,
+      reached: from == target,
+      message,
+      action,
+    });
+  }
+
+  let waiter = hotplug::waiter();
+  perform(&action)?;
+  let arrived = waiter.wait(TRANSITION_TIMEOUT, |event| {
+    event.kind == HotplugKind::Arrived && DeviceMode::from_ids(event.vendor_id, event.product_id).is_some()
+  });
+  let mode = arrived
+    .and_then(|event| DeviceMode::from_ids(event.vendor_id, event.product_id))
+    .unwrap_or_else(DeviceMode::current);
+
+  let message = if mode == target {
+    format!("The controller is now in {}", target.name())
+  } else if arrived.is_some() {
+    format!(
+      "The controller came back in {} instead of {}",
+      mode.name(),
+      target.name()
+    )
+  } else {
+    format!(
+      "The request was sent, but the controller did not come back in {}. {}",
+      target.name(),
+      target.plug_in_instruction()
+    )
+  };
+  Ok(ModeTransition {
+    from,
+    target,
+    action,
+    mode,
+    reached: mode == target,
+    message,
+  })
+}
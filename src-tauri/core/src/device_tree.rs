@@ -0,0 +1,165 @@
+use serde::{Deserialize, Serialize};
+#[cfg(target_os = "windows")]
+use windows::core::PCWSTR;
+#[cfg(target_os = "windows")]
+use windows::Win32::Devices::DeviceAndDriverInstallation::{
+  CM_Get_Child, CM_Get_DevNode_Registry_PropertyW, CM_Get_DevNode_Status, CM_Get_Device_IDW, CM_Get_Sibling,
+  CM_Locate_DevNodeW, CM_DEVNODE_STATUS_FLAGS, CM_DRP_DEVICEDESC, CM_DRP_DEVICE_POWER_DATA, CM_DRP_FRIENDLYNAME,
+  CM_DRP_SERVICE, CM_LOCATE_DEVNODE_NORMAL, CM_PROB, CR_SUCCESS, DN_HAS_PROBLEM, DN_STARTED, MAX_DEVICE_ID_LEN,
+};
+
+use crate::error::HayboxError;
+#[cfg(target_os = "windows")]
+use crate::integrity::to_wide;
+#[cfg(target_os = "windows")]
+use crate::wmi_worker;
+
+/// Guards against cycles or absurdly deep hub chains.
+#[cfg(target_os = "windows")]
+const MAX_DEPTH: usize = 16;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PnpDeviceNode {
+  pub instance_id: String,
+  pub name: Option<String>,
+  /// Driver service, e.g. `WinUSB`, `HidUsb` or `usbhub3`.
+  pub service: Option<String>,
+  pub started: bool,
+  /// Device Manager's problem code ("Code 10", "Code 28", ...), if any.
+  pub problem_code: Option<u32>,
+  /// Most recent device power state, `D0` (on) to `D3` (off).
+  pub power_state: Option<String>,
+  pub children: Vec<PnpDeviceNode>,
+}
+
+#[cfg(target_os = "windows")]
+#[derive(Debug, Deserialize)]
+struct WmiUsbController {
+  #[serde(rename = "DeviceID")]
+  device_id: String,
+}
+
+#[cfg(target_os = "windows")]
+pub fn locate_devnode(instance_id: &str) -> Option<u32> {
+  let wide_id = to_wide(instance_id);
+  let mut devinst = 0u32;
+  let result = unsafe { CM_Locate_DevNodeW(&mut devinst, PCWSTR(wide_id.as_ptr()), CM_LOCATE_DEVNODE_NORMAL) };
+  (result == CR_SUCCESS).then_some(devinst)
+}
+
+#[cfg(target_os = "windows")]
+fn raw_property(devinst: u32, property: u32) -> Option<Vec<u8>> {
+  let mut buffer = vec![0u8; 1024];
+  let mut length = buffer.len() as u32;
+  let result = unsafe {
+    CM_Get_DevNode_Registry_PropertyW(
+      devinst,
+      property,
+      None,
+      Some(buffer.as_mut_ptr() as *mut _),
+      &mut length,
+      0,
+    )
+  };
+  if result != CR_SUCCESS {
+    return None;
+  }
+  buffer.truncate(length as usize);
+  Some(buffer)
+}
+
+#[cfg(target_os = "windows")]
+pub fn string_property(devinst: u32, property: u32) -> Option<String> {
+  let buffer = raw_property(devinst, property)?;
+  let units: Vec<u16> = buffer
+    .chunks_exact(2)
+    .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+    .take_while(|&c| c != 0)
+    .collect();
+  (!units.is_empty()).then(|| String::from_utf16_lossy(&units))
+}
+
+/// Reads `PD_MostRecentPowerState`, the second field of `CM_POWER_DATA`.
+#[cfg(target_os = "windows")]
+fn power_state(devinst: u32) -> Option<String> {
+  let data = raw_property(devinst, CM_DRP_DEVICE_POWER_DATA)?;
+  let state = i32::from_le_bytes(data.get(4..8)?.try_into().ok()?);
+  // DEVICE_POWER_STATE: PowerDeviceD0 = 1 ... PowerDeviceD3 = 4.
+  (1..=4).contains(&state).then(|| format!("D{}", state - 1))
+}
+
+#[cfg(target_os = "windows")]
+fn device_id(devinst: u32) -> Option<String> {
+  let mut buffer = [0u16; MAX_DEVICE_ID_LEN as usize + 1];
+  if unsafe { CM_Get_Device_IDW(devinst, &mut buffer, 0) } != CR_SUCCESS {
+    return None;
+  }
+  let end = buffer.iter().position(|&c| c == 0).unwrap_or(buffer.len());
+  Some(String::from_utf16_lossy(&buffer[..end]))
+}
+
+#[cfg(target_os = "windows")]
+fn children(devinst: u32) -> Vec<u32> {
+  let mut children = Vec::new();
+  let mut child = 0u32;
+  if unsafe { CM_Get_Child(&mut child, devinst, 0) } != CR_SUCCESS {
+    return children;
+  }
+
+  loop {
+    children.push(child);
+    let mut sibling = 0u32;
+    if unsafe { CM_Get_Sibling(&mut sibling, child, 0) } != CR_SUCCESS {
+      return children;
+    }
+    child = sibling;
+  }
+}
+
+#[cfg(target_os = "windows")]
+fn build_node(devinst: u32, depth: usize) -> Option<PnpDeviceNode> {
+  let instance_id = device_id(devinst)?;
+
+  let mut status = CM_DEVNODE_STATUS_FLAGS(0);
+  let mut problem = CM_PROB(0);
+  let has_status = unsafe { CM_Get_DevNode_Status(&mut status, &mut problem, devinst, 0) } == CR_SUCCESS;
+
+  Some(PnpDeviceNode {
+    instance_id,
+    name: string_property(devinst, CM_DRP_FRIENDLYNAME).or_else(|| string_property(devinst, CM_DRP_DEVICEDESC)),
+    service: string_property(devinst, CM_DRP_SERVICE),
+    started: has_status && status.contains(DN_STARTED),
+    problem_code: (has_status && status.contains(DN_HAS_PROBLEM)).then_some(problem.0),
+    power_state: power_state(devinst),
+    children: if depth < MAX_DEPTH {
+      children(devinst)
+        .into_iter()
+        .filter_map(|child| build_node(child, depth + 1))
+        .collect()
+    } else {
+      vec![]
+    },
+  })
+}
+
+/// The PnP tree below every USB host controller: root hubs, hubs, devices and
+/// their interfaces, with the details Device Manager shows for each.
+#[cfg(target_os = "windows")]
+pub fn get_pnp_device_tree() -> Result<Vec<PnpDeviceNode>, HayboxError> {
+  let controllers: Vec<WmiUsbController> = wmi_worker::query("SELECT DeviceID FROM Win32_USBController")?;
+
+  Ok(
+    controllers
+      .iter()
+      .filter_map(|controller| locate_devnode(&controller.device_id))
+      .filter_map(|devinst| build_node(devinst, 0))
+      .collect(),
+  )
+}
+
+#[cfg(not(target_os = "windows"))]
+pub fn get_pnp_device_tree() -> Result<Vec<PnpDeviceNode>, HayboxError> {
+  Err(HayboxError::Unsupported(
+    "The PnP device tree is only available on Windows".to_string(),
+  ))
+}
@@ -0,0 +1,294 @@
+#[cfg(target_os = "windows")]
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+#[cfg(target_os = "windows")]
+use windows::core::{PCWSTR, PWSTR};
+#[cfg(target_os = "windows")]
+use windows::Wdk::Foundation::{NtQueryObject, OBJECT_INFORMATION_CLASS, OBJECT_NAME_INFORMATION};
+#[cfg(target_os = "windows")]
+use windows::Wdk::System::SystemInformation::{NtQuerySystemInformation, SYSTEM_INFORMATION_CLASS};
+#[cfg(target_os = "windows")]
+use windows::Win32::Devices::DeviceAndDriverInstallation::CM_DRP_PHYSICAL_DEVICE_OBJECT_NAME;
+#[cfg(target_os = "windows")]
+use windows::Win32::Foundation::{
+  CloseHandle, DuplicateHandle, DUPLICATE_SAME_ACCESS, HANDLE, STATUS_INFO_LENGTH_MISMATCH,
+};
+#[cfg(target_os = "windows")]
+use windows::Win32::Storage::FileSystem::{
+  CreateFileW, GetFileType, FILE_ATTRIBUTE_NORMAL, FILE_GENERIC_READ, FILE_SHARE_READ, FILE_TYPE_PIPE, OPEN_EXISTING,
+};
+#[cfg(target_os = "windows")]
+use windows::Win32::System::Threading::{
+  GetCurrentProcess, GetCurrentProcessId, OpenProcess, QueryFullProcessImageNameW, PROCESS_DUP_HANDLE,
+  PROCESS_NAME_WIN32, PROCESS_QUERY_LIMITED_INFORMATION,
+};
+
+#[cfg(target_os = "windows")]
+use crate::device_tree::{locate_devnode, string_property};
+use crate::error::HayboxError;
+#[cfg(target_os = "windows")]
+use crate::integrity::to_wide;
+#[cfg(target_os = "windows")]
+use crate::pnp::list_replaceable_devices;
+
+/// Not exposed by the `windows` crate's enums.
+#[cfg(target_os = "windows")]
+const SYSTEM_EXTENDED_HANDLE_INFORMATION: SYSTEM_INFORMATION_CLASS = SYSTEM_INFORMATION_CLASS(64);
+#[cfg(target_os = "windows")]
+const OBJECT_NAME_INFORMATION_CLASS: OBJECT_INFORMATION_CLASS = OBJECT_INFORMATION_CLASS(1);
+/// The handle table grows while we read it, so give up after a few retries.
+#[cfg(target_os = "windows")]
+const MAX_QUERY_ATTEMPTS: usize = 8;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DeviceProcess {
+  pub pid: u32,
+  pub name: String,
+  pub exe_path: Option<String>,
+  /// The device instance (USB or HID interface) the process has open.
+  pub device_instance_id: String,
+}
+
+#[cfg(target_os = "windows")]
+#[repr(C)]
+struct SystemHandleInformationEx {
+  number_of_handles: usize,
+  reserved: usize,
+}
+
+#[cfg(target_os = "windows")]
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct SystemHandleEntry {
+  object: *mut std::ffi::c_void,
+  unique_process_id: usize,
+  handle_value: usize,
+  granted_access: u32,
+  creator_back_trace_index: u16,
+  object_type_index: u16,
+  handle_attributes: u32,
+  reserved: u32,
+}
+
+/// `\Device\...` name of the device's physical device object, which is what
+/// open handles to it resolve to.
+#[cfg(target_os = "windows")]
+fn physical_device_object_name(instance_id: &str) -> Option<String> {
+  string_property(locate_devnode(instance_id)?, CM_DRP_PHYSICAL_DEVICE_OBJECT_NAME)
+}
+
+/// Snapshot of every open handle in the system.
+#[cfg(target_os = "windows")]
+fn system_handles() -> Result<Vec<SystemHandleEntry>, HayboxError> {
+  let mut size = 1 << 20;
+
+  for _ in 0..MAX_QUERY_ATTEMPTS {
+    // u64 storage keeps the buffer aligned for the pointer-sized fields.
+    let mut buffer = vec![0u64; size / 8];
+    let mut returned = 0u32;
+    let status = unsafe {
+      NtQuerySystemInformation(
+        SYSTEM_EXTENDED_HANDLE_INFORMATION,
+        buffer.as_mut_ptr() as *mut _,
+        size as u32,
+        &mut returned,
+      )
+    };
+
+    if status == STATUS_INFO_LENGTH_MISMATCH {
+      size = (returned as usize).max(size * 2);
+      continue;
+    }
+    if status.is_err() {
+      return Err(HayboxError::Other(format!(
+        "Failed to query system handles: 0x{:08X}",
+        status.0
+      )));
+    }
+
+    let header = unsafe { &*(buffer.as_ptr() as *const SystemHandleInformationEx) };
+    let entries = unsafe {
+      let first = (buffer.as_ptr() as *const u8).add(std::mem::size_of::<SystemHandleInformationEx>());
+      std::slice::from_raw_parts(first as *const SystemHandleEntry, header.number_of_handles)
+    };
+    return Ok(entries.to_vec());
+  }
+
+  Err(HayboxError::Other(
+    "Failed to query system handles: the handle table kept growing".to_string(),
+  ))
+}
+
+/// Object type index the kernel uses for file handles, found by looking up a
+/// handle we opened ourselves.
+#[cfg(target_os = "windows")]
+fn file_type_index(handles: &[SystemHandleEntry], own_file: HANDLE) -> Option<u16> {
+  let own_pid = unsafe { GetCurrentProcessId() } as usize;
+  let own_handle = own_file.0 as usize;
+  handles
+    .iter()
+    .find(|entry| entry.unique_process_id == own_pid && entry.handle_value == own_handle)
+    .map(|entry| entry.object_type_index)
+}
+
+#[cfg(target_os = "windows")]
+fn object_name(handle: HANDLE) -> Option<String> {
+  // Synchronous pipes can block NtQueryObject forever; devices never are pipes.
+  if unsafe { GetFileType(handle) } == FILE_TYPE_PIPE {
+    return None;
+  }
+
+  let mut buffer = vec![0u64; 512];
+  let mut returned = 0u32;
+  let status = unsafe {
+    NtQueryObject(
+      Some(handle),
+      OBJECT_NAME_INFORMATION_CLASS,
+      Some(buffer.as_mut_ptr() as *mut _),
+      (buffer.len() * 8) as u32,
+      Some(&mut returned),
+    )
+  };
+  if status.is_err() {
+    return None;
+  }
+
+  let info = unsafe { &*(buffer.as_ptr() as *const OBJECT_NAME_INFORMATION) };
+  if info.Name.Buffer.is_null() {
+    return None;
+  }
+  let name = unsafe { std::slice::from_raw_parts(info.Name.Buffer.0, info.Name.Length as usize / 2) };
+  Some(String::from_utf16_lossy(name))
+}
+
+#[cfg(target_os = "windows")]
+fn process_image_path(process: HANDLE) -> Option<String> {
+  let mut buffer = [0u16; 1024];
+  let mut length = buffer.len() as u32;
+  unsafe { QueryFullProcessImageNameW(process, PROCESS_NAME_WIN32, PWSTR(buffer.as_mut_ptr()), &mut length) }.ok()?;
+  Some(String::from_utf16_lossy(&buffer[..length as usize]))
+}
+
+/// Lists the processes that have one of the device's USB or HID interfaces
+/// open, so "device busy" errors can name the application holding it.
+/// Processes we are not allowed to open are skipped, so running elevated
+/// finds more.
+#[cfg(target_os = "windows")]
+pub fn find_processes_using_device(vendor_id: u16, product_id: u16) -> Result<Vec<DeviceProcess>, HayboxError> {
+  let pdo_names: HashMap<String, String> = list_replaceable_devices()?
+    .into_iter()
+    .filter(|device| {
+      device
+        .ids
+        .map(|ids| ids.vendor_id == vendor_id && ids.product_id == product_id)
+        .unwrap_or(false)
+    })
+    .filter_map(|device| Some((physical_device_object_name(&device.instance_id)?, device.instance_id)))
+    .collect();
+
+  if pdo_names.is_empty() {
+    return Err(HayboxError::DeviceNotConnected(format!(
+      "No device instances found for {:04X}:{:04X}; is the controller connected?",
+      vendor_id, product_id
+    )));
+  }
+
+  let exe_path =
+    std::env::current_exe().map_err(|e| HayboxError::Io(format!("Could not find executable path: {}", e)))?;
+  let wide_exe_path = to_wide(&exe_path.to_string_lossy());
+  let own_file = unsafe {
+    CreateFileW(
+      PCWSTR(wide_exe_path.as_ptr()),
+      FILE_GENERIC_READ.0,
+      FILE_SHARE_READ,
+      None,
+      OPEN_EXISTING,
+      FILE_ATTRIBUTE_NORMAL,
+      None,
+    )
+  }
+  .map_err(|e| HayboxError::Io(format!("Failed to open executable: {}", e)))?;
+
+  let handles = system_handles();
+  let file_type = handles
+    .as_ref()
+    .ok()
+    .and_then(|handles| file_type_index(handles, own_file));
+  let _ = unsafe { CloseHandle(own_file) };
+  let handles = handles?;
+  let file_type =
+    file_type.ok_or_else(|| HayboxError::Other("Failed to determine the file object type".to_string()))?;
+
+  let own_pid = unsafe { GetCurrentProcessId() };
+  let mut processes: HashMap<u32, Option<HANDLE>> = HashMap::new();
+  let mut found: Vec<DeviceProcess> = Vec::new();
+
+  for entry in handles.iter().filter(|entry| entry.object_type_index == file_type) {
+    let pid = entry.unique_process_id as u32;
+    if pid == own_pid || pid <= 4 || found.iter().any(|process| process.pid == pid) {
+      continue;
+    }
+
+    let process = *processes.entry(pid).or_insert_with(|| {
+      unsafe { OpenProcess(PROCESS_DUP_HANDLE | PROCESS_QUERY_LIMITED_INFORMATION, false, pid) }.ok()
+    });
+    let process = match process {
+      Some(process) => process,
+      None => continue,
+    };
+
+    let mut duplicate = HANDLE::default();
+    let duplicated = unsafe {
+      DuplicateHandle(
+        process,
+        HANDLE(entry.handle_value as *mut _),
+        GetCurrentProcess(),
+        &mut duplicate,
+        0,
+        false,
+        DUPLICATE_SAME_ACCESS,
+      )
+    };
+    if duplicated.is_err() {
+      continue;
+    }
+
+    let name = object_name(duplicate);
+    let _ = unsafe { CloseHandle(duplicate) };
+
+    let instance_id = name.and_then(|name| {
+      pdo_names
+        .iter()
+        .find(|(pdo, _)| name == **pdo || name.starts_with(&format!("{}\\", pdo)))
+        .map(|(_, instance_id)| instance_id.clone())
+    });
+
+    if let Some(device_instance_id) = instance_id {
+      let exe_path = process_image_path(process);
+      found.push(DeviceProcess {
+        pid,
+        name: exe_path
+          .as_deref()
+          .and_then(|path| std::path::Path::new(path).file_name())
+          .map(|name| name.to_string_lossy().to_string())
+          .unwrap_or_else(|| format!("PID {}", pid)),
+        exe_path,
+        device_instance_id,
+      });
+    }
+  }
+
+  for process in processes.into_values().flatten() {
+    let _ = unsafe { CloseHandle(process) };
+  }
+
+  Ok(found)
+}
+
+#[cfg(not(target_os = "windows"))]
+pub fn find_processes_using_device(_vendor_id: u16, _product_id: u16) -> Result<Vec<DeviceProcess>, HayboxError> {
+  Err(HayboxError::Unsupported(
+    "Finding the process holding a device is only available on Windows".to_string(),
+  ))
+}
@@ -0,0 +1,293 @@
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use rusb::{Context, DeviceHandle, UsbContext};
+
+use crate::error::HayboxError;
+use crate::events::Events;
+use crate::flash_target::FlashTarget;
+use crate::flashing::{self, FlashResult, FlashStage, UsbPort};
+
+/// Application-specific class, DFU subclass.
+const DFU_CLASS: u8 = 0xFE;
+const DFU_SUBCLASS: u8 = 0x01;
+/// Class requests to the interface.
+const REQUEST_TYPE_OUT: u8 = 0x21;
+const REQUEST_TYPE_IN: u8 = 0xA1;
+const DFU_DNLOAD: u8 = 1;
+const DFU_GETSTATUS: u8 = 3;
+const DFU_CLRSTATUS: u8 = 4;
+const DFU_ABORT: u8 = 6;
+
+const STATE_IDLE: u8 = 2;
+const STATE_DNLOAD_IDLE: u8 = 5;
+const STATE_ERROR: u8 = 10;
+const STATUS_OK: u8 = 0;
+
+/// Atmel FLIP commands carried in DNLOAD requests, as dfu-programmer sends
+/// them.
+const CMD_CHIP_ERASE: &[u8] = &[0x04, 0x00, 0xFF];
+const CMD_START_APP: &[u8] = &[0x04, 0x03, 0x00];
+const PROGRAM_HEADER_LEN: usize = 32;
+const PROGRAM_FOOTER_LEN: usize = 16;
+const PROGRAM_CHUNK: usize = 0x400;
+/// 32 KB of flash minus the 4 KB bootloader.
+const APPLICATION_SIZE: usize = 0x7000;
+/// Far beyond any AVR, so a stray extended address record cannot make the
+/// image balloon.
+const MAX_HEX_IMAGE: usize = 0x10_0000;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+const ERASE_TIMEOUT: Duration = Duration::from_secs(10);
+const REENUMERATION_TIMEOUT: Duration = Duration::from_secs(15);
+
+struct DfuStatus {
+  status: u8,
+  poll_timeout: Duration,
+  state: u8,
+}
+
+struct Dfu {
+  handle: DeviceHandle<Context>,
+  interface: u8,
+  transaction: u16,
+}
+
+impl Dfu {
+  fn open(vendor_id: u16, product_id: u16) -> Result<Self, HayboxError> {
+    let context = Context::new().map_err(|e| HayboxError::Usb(format!("Failed to create USB context: {}", e)))?;
+    let handle = context.open_device_with_vid_pid(vendor_id, product_id).ok_or_else(|| {
+      HayboxError::Usb("Could not open the DFU bootloader; is WinUSB or libusbK bound to it?".to_string())
+    })?;
+
+    let config = handle
+      .device()
+      .active_config_descriptor()
+      .map_err(|e| HayboxError::Usb(format!("Failed to read configuration descriptor: {}", e)))?;
+    let interface = config
+      .interfaces()
+      .flat_map(|interface| interface.descriptors())
+      .find(|descriptor| descriptor.class_code() == DFU_CLASS && descriptor.sub_class_code() == DFU_SUBCLASS)
+      .map(|descriptor| descriptor.interface_number())
+      .ok_or_else(|| HayboxError::Usb("Device has no DFU interface".to_string()))?;
+
+    handle
+      .claim_interface(interface)
+      .map_err(|e| HayboxError::DeviceBusy(format!("Failed to claim the DFU interface: {}", e)))?;
+
+    Ok(Dfu {
+      handle,
+      interface,
+      transaction: 0,
+    })
+  }
+
+  fn get_status(&self) -> Result<DfuStatus, HayboxError> {
+    let mut buffer = [0u8; 6];
+    let read = self
+      .handle
+      .read_control(
+        REQUEST_TYPE_IN,
+        DFU_GETSTATUS,
+        0,
+        self.interface as u16,
+        &mut buffer,
+        TIMEOUT,
+      )
+      .map_err(|e| HayboxError::Usb(format!("DFU_GETSTATUS failed: {}", e)))?;
+    if read != buffer.len() {
+      return Err(HayboxError::Usb("Short DFU_GETSTATUS response".to_string()));
+    }
+    Ok(DfuStatus {
+      status: buffer[0],
+      poll_timeout: Duration::from_millis(u32::from_le_bytes([buffer[1], buffer[2], buffer[3], 0]) as u64),
+      state: buffer[4],
+    })
+  }
+
+  fn control_out(&self, request: u8, value: u16, data: &[u8]) -> Result<(), HayboxError> {
+    self
+      .handle
+      .write_control(REQUEST_TYPE_OUT, request, value, self.interface as u16, data, TIMEOUT)
+      .map(|_| ())
+      .map_err(|e| HayboxError::Usb(format!("DFU request {} failed: {}", request, e)))
+  }
+
+  /// Brings the bootloader back to dfuIDLE from an error or an interrupted
+  /// download, like dfu-util does before starting.
+  fn make_idle(&self) -> Result<(), HayboxError> {
+    let status = self.get_status()?;
+    match status.state {
+      STATE_IDLE => Ok(()),
+      STATE_ERROR => self.control_out(DFU_CLRSTATUS, 0, &[]),
+      _ => self.control_out(DFU_ABORT, 0, &[]),
+    }
+  }
+
+  fn download(&mut self, data: &[u8]) -> Result<(), HayboxError> {
+    self.control_out(DFU_DNLOAD, self.transaction, data)?;
+    self.transaction = self.transaction.wrapping_add(1);
+    Ok(())
+  }
+
+  /// Polls DFU_GETSTATUS, honouring `bwPollTimeout`, until the last request
+  /// has been processed.
+  fn wait_ready(&self, timeout: Duration) -> Result<(), HayboxError> {
+    let deadline = Instant::now() + timeout;
+    loop {
+      let status = self.get_status()?;
+      if status.status != STATUS_OK {
+        let _ = self.control_out(DFU_CLRSTATUS, 0, &[]);
+        return Err(HayboxError::Usb(format!(
+          "Bootloader reported DFU error status {}",
+          status.status
+        )));
+      }
+      if status.state == STATE_IDLE || status.state == STATE_DNLOAD_IDLE {
+        return Ok(());
+      }
+      if Instant::now() >= deadline {
+        return Err(HayboxError::Usb(format!(
+          "Bootloader stayed busy in DFU state {}",
+          status.state
+        )));
+      }
+      std::thread::sleep(status.poll_timeout.max(Duration::from_millis(5)));
+    }
+  }
+
+  /// Programs `data` at `start` with one FLIP program command. The payload
+  /// sits at the same offset within its 32-byte line as the address does.
+  fn program(&mut self, start: usize, data: &[u8]) -> Result<(), HayboxError> {
+    let end = start + data.len() - 1;
+    let offset = start % PROGRAM_HEADER_LEN;
+    let mut message = vec![0u8; PROGRAM_HEADER_LEN + offset + data.len() + PROGRAM_FOOTER_LEN];
+    message[0] = 0x01;
+    message[2..4].copy_from_slice(&(start as u16).to_be_bytes());
+    message[4..6].copy_from_slice(&(end as u16).to_be_bytes());
+    message[PROGRAM_HEADER_LEN + offset..PROGRAM_HEADER_LEN + offset + data.len()].copy_from_slice(data);
+    self.download(&message)?;
+    self.wait_ready(TIMEOUT)
+  }
+}
+
+impl Drop for Dfu {
+  fn drop(&mut self) {
+    let _ = self.handle.release_interface(self.interface);
+  }
+}
+
+/// Parses Intel HEX into a flat image starting at address 0, padding gaps
+/// with erased flash (0xFF).
+pub fn parse_intel_hex(content: &str) -> Result<Vec<u8>, HayboxError> {
+  let mut image = Vec::new();
+  let mut base = 0usize;
+
+  for (number, line) in content
+    .lines()
+    .enumerate()
+    .map(|(index, line)| (index + 1, line.trim()))
+  {
+    if line.is_empty() {
+      continue;
+    }
+    let hex = line
+      .strip_prefix(':')
+      .ok_or_else(|| HayboxError::Other(format!("Line {} is not an Intel HEX record", number)))?;
+    let bytes = (0..hex.len())
+      .step_by(2)
+      .map(|i| hex.get(i..i + 2).and_then(|pair| u8::from_str_radix(pair, 16).ok()))
+      .collect::<Option<Vec<u8>>>()
+      .ok_or_else(|| HayboxError::Other(format!("Line {} contains invalid hex", number)))?;
+    if bytes.len() < 5 || bytes.len() != bytes[0] as usize + 5 {
+      return Err(HayboxError::Other(format!("Line {} has the wrong length", number)));
+    }
+    if bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) != 0 {
+      return Err(HayboxError::Other(format!("Line {} has a bad checksum", number)));
+    }
+
+    let address = u16::from_be_bytes([bytes[1], bytes[2]]) as usize;
+    let data = &bytes[4..bytes.len() - 1];
+    match bytes[3] {
+      0x00 => {
+        let start = base + address;
+        if start + data.len() > MAX_HEX_IMAGE {
+          return Err(HayboxError::Other(format!(
+            "Line {} writes outside the AVR address space",
+            number
+          )));
+        }
+        if image.len() < start + data.len() {
+          image.resize(start + data.len(), 0xFF);
+        }
+        image[start..start + data.len()].copy_from_slice(data);
+      }
+      0x01 => break,
+      0x02 if data.len() == 2 => base = (u16::from_be_bytes([data[0], data[1]]) as usize) << 4,
+      0x04 if data.len() == 2 => base = (u16::from_be_bytes([data[0], data[1]]) as usize) << 16,
+      0x03 | 0x05 => {}
+      kind => {
+        return Err(HayboxError::Other(format!(
+          "Line {} has unsupported record type {:02X}",
+          number, kind
+        )))
+      }
+    }
+  }
+
+  if image.is_empty() {
+    return Err(HayboxError::Other("HEX file contains no data".to_string()));
+  }
+  Ok(image)
+}
+
+/// Erases and programs an ATmega32U4 through its Atmel DFU bootloader, then
+/// starts the application and waits for the controller to come back.
+pub fn flash_hex(events: &Events, path: &Path) -> Result<FlashResult, HayboxError> {
+  flashing::emit_progress(events, FlashStage::Validating, 0, 0);
+  let content =
+    std::fs::read_to_string(path).map_err(|e| HayboxError::Io(format!("Failed to read {}: {}", path.display(), e)))?;
+  let image = parse_intel_hex(&content)?;
+  if image.len() > APPLICATION_SIZE {
+    return Err(HayboxError::Other(format!(
+      "Firmware is {} bytes but only {} fit below the bootloader",
+      image.len(),
+      APPLICATION_SIZE
+    )));
+  }
+  let total_bytes = image.len() as u64;
+
+  let (vendor_id, product_id) = FlashTarget::Atmega32u4.bootloader_id();
+  let mut dfu = Dfu::open(vendor_id, product_id)?;
+  let port = UsbPort::of_device(&dfu.handle.device())
+    .ok_or_else(|| HayboxError::Usb("Could not read the bootloader's USB port".to_string()))?;
+  dfu.make_idle()?;
+
+  dfu.download(CMD_CHIP_ERASE)?;
+  dfu.wait_ready(ERASE_TIMEOUT)?;
+
+  let mut bytes_written = 0u64;
+  for (index, chunk) in image.chunks(PROGRAM_CHUNK).enumerate() {
+    dfu.program(index * PROGRAM_CHUNK, chunk)?;
+    bytes_written += chunk.len() as u64;
+    flashing::emit_progress(events, FlashStage::Copying, bytes_written, total_bytes);
+  }
+
+  // The bootloader resets while handling the empty request, so errors here
+  // are expected.
+  dfu.download(CMD_START_APP)?;
+  let _ = dfu.download(&[]);
+  drop(dfu);
+
+  flashing::emit_progress(events, FlashStage::WaitingForDevice, bytes_written, total_bytes);
+  let device_mode = flashing::wait_for_reenumeration(&port, REENUMERATION_TIMEOUT);
+  let verification = flashing::verify_flash(None, device_mode.is_some());
+  flashing::emit_progress(events, FlashStage::Done, bytes_written, total_bytes);
+
+  Ok(FlashResult {
+    volume: format!("DFU {:04X}:{:04X}", vendor_id, product_id),
+    bytes_written,
+    device_mode,
+    verification,
+  })
+}
@@ -0,0 +1,302 @@
+use std::fmt::Write as _;
+use std::io::Write;
+use std::path::Path;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rusb::UsbContext;
+use serde::{Deserialize, Serialize};
+use zip::write::SimpleFileOptions;
+use zip::ZipWriter;
+
+use crate::architecture::{architecture_info, ArchitectureInfo};
+use crate::error::HayboxError;
+use crate::platform::{self, PlatformCapabilities};
+#[cfg(target_os = "windows")]
+use crate::registry::{self, HKEY_LOCAL_MACHINE};
+use crate::{
+  audit, environment, get_current_device_status, hotplug, logging, paths, report_descriptor, UsbDeviceInfo, DEVICES,
+};
+
+#[cfg(target_os = "windows")]
+const CURRENT_VERSION_KEY: &str = "SOFTWARE\\Microsoft\\Windows NT\\CurrentVersion";
+/// Audit entries included, newest first.
+const AUDIT_ENTRIES: usize = 500;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DiagnosticsExport {
+  pub path: String,
+  /// Files written to the archive.
+  pub files: Vec<String>,
+  /// Sections that could not be collected, with the reason. The archive is
+  /// still written without them.
+  pub errors: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct Summary {
+  app_version: String,
+  os: String,
+  os_version: Option<String>,
+  architecture: ArchitectureInfo,
+  capabilities: PlatformCapabilities,
+  portable: bool,
+  created_at: u64,
+}
+
+fn supported_devices() -> [&'static UsbDeviceInfo; 5] {
+  [
+    &DEVICES.default_mode,
+    &DEVICES.config_mode,
+    &DEVICES.bootsel_mode,
+    &DEVICES.switch_mode,
+    &DEVICES.gamecube_mode,
+  ]
+}
+
+/// Product name and release, e.g. "Windows 10 Pro 22H2".
+#[cfg(target_os = "windows")]
+fn os_version() -> Option<String> {
+  let product = registry::read_string(HKEY_LOCAL_MACHINE, CURRENT_VERSION_KEY, "ProductName")?;
+  let release = registry::read_string(HKEY_LOCAL_MACHINE, CURRENT_VERSION_KEY, "DisplayVersion");
+  Some(match release {
+    Some(release) => format!("{} {}", product, release),
+    None => product,
+  })
+}
+
+#[cfg(target_os = "macos")]
+fn os_version() -> Option<String> {
+  let output = Command::new("sw_vers").arg("-productVersion").output().ok()?;
+  Some(format!("macOS {}", String::from_utf8_lossy(&output.stdout).trim()))
+}
+
+/// The distribution's name and release, e.g. "SteamOS 3.6.19".
+#[cfg(target_os = "linux")]
+fn os_version() -> Option<String> {
+  crate::steamos::os_release_value("PRETTY_NAME")
+}
+
+/// Tool output worth attaching, as (file name, program, arguments).
+fn system_commands() -> &'static [(&'static str, &'static str, &'static [&'static str])] {
+  if cfg!(target_os = "windows") {
+    &[
+      (
+        "pnputil_devices.txt",
+        "pnputil",
+        &["/enum-devices", "/connected", "/drivers"],
+      ),
+      ("pnputil_drivers.txt", "pnputil", &["/enum-drivers"]),
+    ]
+  } else if cfg!(target_os = "macos") {
+    &[("ioreg_usb.txt", "ioreg", &["-p", "IOUSB", "-l", "-w0"])]
+  } else {
+    &[("lsusb.txt", "lsusb", &[]), ("lsusb_tree.txt", "lsusb", &["-t"])]
+  }
+}
+
+fn run_command(program: &str, args: &[&str]) -> Result<String, HayboxError> {
+  let output = Command::new(program)
+    .args(args)
+    .output()
+    .map_err(|e| HayboxError::Io(format!("Failed to run {}: {}", program, e)))?;
+  let mut text = String::from_utf8_lossy(&output.stdout).to_string();
+  if !output.status.success() {
+    let _ = write!(
+      text,
+      "\n{} exited with {}: {}",
+      program,
+      output.status,
+      String::from_utf8_lossy(&output.stderr).trim()
+    );
+  }
+  Ok(text)
+}
+
+/// Device and configuration descriptors of every connected HayBox device,
+/// as libusb reads them.
+fn usb_descriptors() -> Result<String, HayboxError> {
+  let devices = rusb::Context::new()
+    .and_then(|context| context.devices())
+    .map_err(|e| HayboxError::Usb(format!("Failed to list USB devices: {}", e)))?;
+  let supported = supported_devices();
+
+  let mut text = String::new();
+  for device in devices.iter() {
+    let Ok(descriptor) = device.device_descriptor() else {
+      continue;
+    };
+    let Some(info) = supported
+      .iter()
+      .find(|info| info.vid == descriptor.vendor_id() && info.pid == descriptor.product_id())
+    else {
+      continue;
+    };
+
+    let _ = writeln!(
+      text,
+      "{} ({:04X}:{:04X}) on bus {} address {}",
+      info.name,
+      info.vid,
+      info.pid,
+      device.bus_number(),
+      device.address()
+    );
+    let _ = writeln!(text, "{:#?}", descriptor);
+    for index in 0..descriptor.num_configurations() {
+      let config = match device.config_descriptor(index) {
+        Ok(config) => config,
+        Err(e) => {
+          let _ = writeln!(text, "  configuration {}: {}", index, e);
+          continue;
+        }
+      };
+      let _ = writeln!(
+        text,
+        "  configuration {} ({} interfaces, max power {} mA)",
+        config.number(),
+        config.num_interfaces(),
+        config.max_power()
+      );
+      for interface in config.interfaces() {
+        for setting in interface.descriptors() {
+          let _ = writeln!(
+            text,
+            "    interface {} alt {}: class {:02X} subclass {:02X} protocol {:02X}",
+            setting.interface_number(),
+            setting.setting_number(),
+            setting.class_code(),
+            setting.sub_class_code(),
+            setting.protocol_code()
+          );
+          for endpoint in setting.endpoint_descriptors() {
+            let _ = writeln!(
+              text,
+              "      endpoint {:02X} {:?} {:?}, max packet {}, interval {}",
+              endpoint.address(),
+              endpoint.direction(),
+              endpoint.transfer_type(),
+              endpoint.max_packet_size(),
+              endpoint.interval()
+            );
+          }
+        }
+      }
+    }
+    text.push('\n');
+  }
+
+  if text.is_empty() {
+    text.push_str("No HayBox devices connected\n");
+  }
+  Ok(text)
+}
+
+/// HID report descriptors of the connected HID modes, decoded.
+fn hid_report_descriptors() -> Result<String, HayboxError> {
+  let connected = platform::current().connected_devices();
+  let descriptors: Vec<_> = supported_devices()
+    .iter()
+    .filter(|device| connected.contains(&(device.vid, device.pid)))
+    .filter_map(|device| report_descriptor::read_report_descriptor(device.vid, device.pid).ok())
+    .collect();
+  to_json(&descriptors)
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<String, HayboxError> {
+  serde_json::to_string_pretty(value).map_err(|e| HayboxError::Other(format!("Failed to serialize: {}", e)))
+}
+
+struct Bundle {
+  zip: ZipWriter<std::fs::File>,
+  files: Vec<String>,
+  errors: Vec<String>,
+}
+
+impl Bundle {
+  fn add(&mut self, name: &str, content: Result<String, HayboxError>) -> Result<(), HayboxError> {
+    let content = match content {
+      Ok(content) => content,
+      Err(e) => {
+        self.errors.push(format!("{}: {}", name, e));
+        return Ok(());
+      }
+    };
+    self
+      .zip
+      .start_file(name, SimpleFileOptions::default())
+      .and_then(|_| Ok(self.zip.write_all(content.as_bytes())?))
+      .map_err(|e| HayboxError::Io(format!("Failed to write {} to the archive: {}", name, e)))?;
+    self.files.push(name.to_string());
+    Ok(())
+  }
+}
+
+/// Writes everything useful for a bug report into one zip at `path`: device
+/// state and hotplug history, drivers, descriptors, the driver audit log,
+/// logs and the OS and app versions. Sections that fail are listed in the
+/// result instead of aborting the export.
+pub fn export_diagnostics(path: &Path, app_version: &str) -> Result<DiagnosticsExport, HayboxError> {
+  let file =
+    std::fs::File::create(path).map_err(|e| HayboxError::Io(format!("Failed to create {}: {}", path.display(), e)))?;
+  let mut bundle = Bundle {
+    zip: ZipWriter::new(file),
+    files: Vec::new(),
+    errors: Vec::new(),
+  };
+
+  let summary = Summary {
+    app_version: app_version.to_string(),
+    os: std::env::consts::OS.to_string(),
+    os_version: os_version(),
+    architecture: architecture_info(),
+    capabilities: platform::current().capabilities(),
+    portable: paths::is_portable(),
+    created_at: SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .map(|d| d.as_secs())
+      .unwrap_or_default(),
+  };
+  bundle.add("summary.json", to_json(&summary))?;
+  bundle.add(
+    "device_status.json",
+    get_current_device_status()
+      .map_err(|e| HayboxError::Other(e.to_string()))
+      .and_then(|status| to_json(&status)),
+  )?;
+  bundle.add("device_history.json", to_json(&hotplug::history()))?;
+  bundle.add(
+    "driver_info.json",
+    platform::current()
+      .driver_info(None, None)
+      .and_then(|drivers| to_json(&drivers)),
+  )?;
+  bundle.add("usb_descriptors.txt", usb_descriptors())?;
+  bundle.add("hid_report_descriptors.json", hid_report_descriptors())?;
+  bundle.add(
+    "environment_warnings.json",
+    to_json(&environment::collect_environment_warnings()),
+  )?;
+  bundle.add("driver_audit.json", to_json(&audit::read_audit_log(AUDIT_ENTRIES)))?;
+  for (name, program, args) in system_commands() {
+    bundle.add(name, run_command(program, args))?;
+  }
+  for log_file in logging::log_files() {
+    let name = format!("logs/{}", log_file.file_name().unwrap_or_default().to_string_lossy());
+    let content = std::fs::read(&log_file)
+      .map(|bytes| String::from_utf8_lossy(&bytes).to_string())
+      .map_err(|e| HayboxError::Io(format!("Failed to read {}: {}", log_file.display(), e)));
+    bundle.add(&name, content)?;
+  }
+
+  bundle
+    .zip
+    .finish()
+    .map_err(|e| HayboxError::Io(format!("Failed to finish {}: {}", path.display(), e)))?;
+
+  Ok(DiagnosticsExport {
+    path: path.display().to_string(),
+    files: bundle.files,
+    errors: bundle.errors,
+  })
+}
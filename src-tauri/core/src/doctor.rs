@@ -0,0 +1,338 @@
+use serde::{Deserialize, Serialize};
+
+use crate::driver::{ConfigBuilder, DriverKind};
+use crate::environment::collect_environment_warnings;
+#[cfg(target_os = "windows")]
+use crate::error::HayboxError;
+use crate::privileges::{get_privilege_status, PrivilegeLevel};
+use crate::resources::driver_resource_dir;
+use crate::udev::udev_status;
+#[cfg(target_os = "windows")]
+use crate::wmi_worker;
+
+/// Ordered from best to worst, so the report's status is the maximum.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+  /// Not relevant on this OS.
+  Skipped,
+  Pass,
+  Warn,
+  Fail,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DoctorCheck {
+  /// Stable identifier, e.g. `libusb`, for the UI to attach help to.
+  pub id: String,
+  pub name: String,
+  pub status: CheckStatus,
+  pub message: String,
+  pub fix: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DoctorReport {
+  /// The worst status of all checks.
+  pub status: CheckStatus,
+  pub checks: Vec<DoctorCheck>,
+}
+
+#[cfg(target_os = "windows")]
+#[derive(Debug, Deserialize)]
+struct WmiOperatingSystem {
+  #[serde(rename = "Caption")]
+  _caption: Option<String>,
+}
+
+#[cfg(target_os = "windows")]
+#[derive(Debug, Deserialize)]
+struct WmiControllerDriver {
+  #[serde(rename = "DeviceName")]
+  device_name: Option<String>,
+  #[serde(rename = "DriverProviderName")]
+  driver_provider_name: Option<String>,
+  #[serde(rename = "DriverVersion")]
+  driver_version: Option<String>,
+}
+
+impl DoctorCheck {
+  fn new(id: &str, name: &str, status: CheckStatus, message: impl Into<String>) -> Self {
+    DoctorCheck {
+      id: id.to_string(),
+      name: name.to_string(),
+      status,
+      message: message.into(),
+      fix: None,
+    }
+  }
+
+  fn fix(mut self, fix: &str) -> Self {
+    self.fix = Some(fix.to_string());
+    self
+  }
+}
+
+fn windows_only(id: &str, name: &str) -> DoctorCheck {
+  DoctorCheck::new(id, name, CheckStatus::Skipped, "Only checked on Windows")
+}
+
+fn check_libusb() -> DoctorCheck {
+  let version = rusb::version();
+  let version = format!("{}.{}.{}", version.major(), version.minor(), version.micro());
+
+  match rusb::Context::new().and_then(|context| rusb::UsbContext::devices(&context)) {
+    Ok(devices) => DoctorCheck::new(
+      "libusb",
+      "libusb",
+      CheckStatus::Pass,
+      format!("libusb {} lists {} USB devices", version, devices.len()),
+    ),
+    Err(e) => DoctorCheck::new(
+      "libusb",
+      "libusb",
+      CheckStatus::Fail,
+      format!("libusb {} could not list USB devices: {}", version, e),
+    )
+    .fix("Reinstall the app to restore the libusb runtime. Device lists fall back to the OS until then."),
+  }
+}
+
+#[cfg(not(target_os = "windows"))]
+fn check_wmi() -> DoctorCheck {
+  windows_only("wmi", "WMI")
+}
+
+#[cfg(target_os = "windows")]
+fn check_wmi() -> DoctorCheck {
+  match wmi_worker::query::<WmiOperatingSystem>("SELECT Caption FROM Win32_OperatingSystem") {
+    Ok(_) => DoctorCheck::new("wmi", "WMI", CheckStatus::Pass, "WMI is reachable"),
+    Err(e) => DoctorCheck::new("wmi", "WMI", CheckStatus::Fail, e.to_string())
+      .fix("Restart the Windows Management Instrumentation service or reboot."),
+  }
+}
+
+/// Administrator status on Windows; on Linux, whether the udev rules give the
+/// user access to the controller.
+fn check_permissions() -> DoctorCheck {
+  if cfg!(target_os = "windows") {
+    return match get_privilege_status().level {
+      PrivilegeLevel::Elevated => DoctorCheck::new(
+        "permissions",
+        "Administrator",
+        CheckStatus::Pass,
+        "Running as administrator",
+      ),
+      PrivilegeLevel::AdminNotElevated => DoctorCheck::new(
+        "permissions",
+        "Administrator",
+        CheckStatus::Pass,
+        "Not elevated; driver actions will show a UAC prompt",
+      ),
+      PrivilegeLevel::StandardUser => DoctorCheck::new(
+        "permissions",
+        "Administrator",
+        CheckStatus::Warn,
+        "Running as a standard user; driver actions need an administrator's password",
+      )
+      .fix("Run the app from an administrator account to install drivers."),
+    };
+  }
+
+  if !cfg!(target_os = "linux") {
+    return DoctorCheck::new(
+      "permissions",
+      "Device access",
+      CheckStatus::Skipped,
+      "No setup needed on this OS",
+    );
+  }
+
+  let status = udev_status();
+  if !status.problems.is_empty() {
+    DoctorCheck::new(
+      "permissions",
+      "Device access",
+      CheckStatus::Fail,
+      status.problems.join("; "),
+    )
+    .fix("Install the udev rules from the app, then unplug and replug the controller.")
+  } else if !status.rules_installed {
+    DoctorCheck::new(
+      "permissions",
+      "Device access",
+      CheckStatus::Warn,
+      "The udev rules are not installed",
+    )
+    .fix("Install the udev rules so config mode and BOOTSEL work without root.")
+  } else if !status.rules_current {
+    DoctorCheck::new(
+      "permissions",
+      "Device access",
+      CheckStatus::Warn,
+      "The installed udev rules are from another version of the app",
+    )
+    .fix("Reinstall the udev rules from the app.")
+  } else {
+    DoctorCheck::new(
+      "permissions",
+      "Device access",
+      CheckStatus::Pass,
+      "The udev rules are installed and current",
+    )
+  }
+}
+
+fn check_driver_resources() -> DoctorCheck {
+  if !cfg!(target_os = "windows") {
+    return windows_only("driver_resources", "Driver resources");
+  }
+
+  let Some(resource_dir) = driver_resource_dir() else {
+    return DoctorCheck::new(
+      "driver_resources",
+      "Driver resources",
+      CheckStatus::Fail,
+      "Driver resources not found",
+    )
+    .fix("Reinstall the app.");
+  };
+
+  let config = ConfigBuilder::new()
+    .kind(DriverKind::WinUsb)
+    .allow_unverified_resources(true)
+    .build();
+  match config.verify_resources(&resource_dir) {
+    Ok(results) => {
+      let failures: Vec<String> = results.iter().filter_map(|result| result.failure_reason()).collect();
+      if failures.is_empty() {
+        DoctorCheck::new(
+          "driver_resources",
+          "Driver resources",
+          CheckStatus::Pass,
+          format!("{} files match their expected hashes and signatures", results.len()),
+        )
+      } else {
+        DoctorCheck::new(
+          "driver_resources",
+          "Driver resources",
+          CheckStatus::Fail,
+          failures.join("; "),
+        )
+        .fix("Reinstall the app; antivirus software may have modified or quarantined these files.")
+      }
+    }
+    Err(e) => DoctorCheck::new("driver_resources", "Driver resources", CheckStatus::Fail, e.to_string())
+      .fix("Reinstall the app."),
+  }
+}
+
+fn check_conflicting_software() -> DoctorCheck {
+  let warnings = collect_environment_warnings();
+  if warnings.is_empty() {
+    return DoctorCheck::new(
+      "conflicting_software",
+      "Conflicting software",
+      CheckStatus::Pass,
+      "No conflicting drivers or software found",
+    );
+  }
+
+  DoctorCheck::new(
+    "conflicting_software",
+    "Conflicting software",
+    CheckStatus::Warn,
+    warnings
+      .iter()
+      .map(|warning| warning.message.as_str())
+      .collect::<Vec<_>>()
+      .join("; "),
+  )
+  .fix("See the environment warnings for what each one affects and how to remove it.")
+}
+
+#[cfg(not(target_os = "windows"))]
+fn check_usb_controllers() -> DoctorCheck {
+  windows_only("usb_controllers", "USB controllers")
+}
+
+/// Host controllers on third-party drivers (older ASMedia, Renesas or Etron
+/// packages) are a common cause of disconnects and missed polls.
+#[cfg(target_os = "windows")]
+fn check_usb_controllers() -> DoctorCheck {
+  let drivers: Result<Vec<WmiControllerDriver>, HayboxError> = wmi_worker::query(
+    "SELECT DeviceName, DriverProviderName, DriverVersion FROM Win32_PnPSignedDriver WHERE DeviceClass = 'USB' AND DeviceID LIKE 'PCI\\\\%'",
+  );
+  let drivers = match drivers {
+    Ok(drivers) => drivers,
+    Err(e) => return DoctorCheck::new("usb_controllers", "USB controllers", CheckStatus::Fail, e.to_string()),
+  };
+
+  let describe = |driver: &WmiControllerDriver| {
+    format!(
+      "{} ({} {})",
+      driver.device_name.as_deref().unwrap_or("Unknown controller"),
+      driver.driver_provider_name.as_deref().unwrap_or("unknown provider"),
+      driver.driver_version.as_deref().unwrap_or("unknown version")
+    )
+  };
+  let third_party: Vec<String> = drivers
+    .iter()
+    .filter(|driver| {
+      !driver
+        .driver_provider_name
+        .as_deref()
+        .is_some_and(|provider| provider.eq_ignore_ascii_case("Microsoft"))
+    })
+    .map(describe)
+    .collect();
+
+  if drivers.is_empty() {
+    DoctorCheck::new(
+      "usb_controllers",
+      "USB controllers",
+      CheckStatus::Warn,
+      "No USB host controllers found",
+    )
+  } else if third_party.is_empty() {
+    DoctorCheck::new(
+      "usb_controllers",
+      "USB controllers",
+      CheckStatus::Pass,
+      drivers.iter().map(describe).collect::<Vec<_>>().join("; "),
+    )
+  } else {
+    DoctorCheck::new(
+      "usb_controllers",
+      "USB controllers",
+      CheckStatus::Warn,
+      format!("Third-party controller drivers: {}", third_party.join("; ")),
+    )
+    .fix("Plug the controller into a port on a controller using the Microsoft driver, or update the chipset driver.")
+  }
+}
+
+type Check = fn() -> DoctorCheck;
+
+const CHECKS: &[Check] = &[
+  check_libusb,
+  check_wmi,
+  check_permissions,
+  check_driver_resources,
+  check_conflicting_software,
+  check_usb_controllers,
+];
+
+/// Runs every check in order. Checks never abort the run; a check that
+/// cannot complete reports itself as failed.
+pub fn run_doctor() -> DoctorReport {
+  let checks: Vec<DoctorCheck> = CHECKS.iter().map(|check| check()).collect();
+  DoctorReport {
+    status: checks
+      .iter()
+      .map(|check| check.status)
+      .max()
+      .unwrap_or(CheckStatus::Skipped),
+    checks,
+  }
+}
@@ -0,0 +1,871 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use serde::{Deserialize, Serialize};
+
+use crate::architecture::{is_windows_10_or_later, os_architecture, resource_file};
+use crate::audit::AuditEntry;
+use crate::check_admin_rights;
+use crate::driver_cache;
+use crate::error::HayboxError;
+use crate::inf_template::{load_template, validate_template};
+use crate::integrity::{load_expected_hashes, verify_resource, ResourceVerification};
+#[cfg(target_os = "macos")]
+use crate::iokit;
+use crate::operations;
+use crate::pending::{record_pending_action, PendingReason};
+use crate::resources::driver_resource_dir;
+use crate::staging::{create_staging_dir, remove_staging_dir, unique_staging_dir};
+#[cfg(target_os = "linux")]
+use crate::udev;
+#[cfg(target_os = "windows")]
+use crate::wmi_worker;
+
+/// pnputil exit code when the package was installed but a reboot is needed
+/// before the device picks it up.
+const ERROR_SUCCESS_REBOOT_REQUIRED: i32 = 3010;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DriverKind {
+  WinUsb,
+  LibUsbK,
+  HidUsb,
+}
+
+/// How a driver package is put together for the running Windows version.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PackageFlow {
+  /// Windows 7 and 8.1: the WDF coinstallers ship with the package and
+  /// install the framework version the driver needs.
+  LegacyCoinstaller,
+  /// Windows 10 and later: KMDF and WinUSB are inbox, so the INF names no
+  /// coinstallers and none are staged, which also keeps the DLLs SmartScreen
+  /// flags out of the package.
+  InfOnly,
+}
+
+impl PackageFlow {
+  pub fn current() -> Self {
+    if is_windows_10_or_later() {
+      PackageFlow::InfOnly
+    } else {
+      PackageFlow::LegacyCoinstaller
+    }
+  }
+}
+
+impl DriverKind {
+  /// INF template shipped in the driver resources for this driver.
+  pub fn template_name(&self) -> &'static str {
+    match self {
+      DriverKind::WinUsb => "winusb_template.inf",
+      DriverKind::LibUsbK => "libusbk_template.inf",
+      DriverKind::HidUsb => "hidusb_template.inf",
+    }
+  }
+
+  /// Name of the generated INF inside the staging directory.
+  pub fn inf_name(&self) -> &'static str {
+    match self {
+      DriverKind::WinUsb => "winusb_driver.inf",
+      DriverKind::LibUsbK => "libusbk_driver.inf",
+      DriverKind::HidUsb => "hidusb_driver.inf",
+    }
+  }
+
+  /// Extra files the INF references and which must be staged next to it.
+  pub fn support_files(&self) -> &'static [&'static str] {
+    match self {
+      DriverKind::WinUsb => &["WinUSBCoInstaller2.dll", "WdfCoInstaller01011.dll"],
+      DriverKind::LibUsbK => &["libusbK.sys", "libusbK.dll", "WdfCoInstaller01011.dll"],
+      DriverKind::HidUsb => &[],
+    }
+  }
+
+  /// The WDF coinstallers among `support_files`, which Windows 10 and later
+  /// do not need.
+  pub fn coinstallers(&self) -> &'static [&'static str] {
+    match self {
+      DriverKind::WinUsb => &["WinUSBCoInstaller2.dll", "WdfCoInstaller01011.dll"],
+      DriverKind::LibUsbK => &["WdfCoInstaller01011.dll"],
+      DriverKind::HidUsb => &[],
+    }
+  }
+
+  /// Service name Windows reports once the driver is bound.
+  pub fn service_name(&self) -> &'static str {
+    match self {
+      DriverKind::WinUsb => "WinUSB",
+      DriverKind::LibUsbK => "libusbK",
+      DriverKind::HidUsb => "HidUsb",
+    }
+  }
+}
+
+impl std::fmt::Display for DriverKind {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    match self {
+      DriverKind::WinUsb => write!(f, "WinUSB"),
+      DriverKind::LibUsbK => write!(f, "libusbK"),
+      DriverKind::HidUsb => write!(f, "HID (HidUsb)"),
+    }
+  }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct InstallOutcome {
+  pub reboot_required: bool,
+  /// The device reported the expected service and no ConfigManager error
+  /// after installation.
+  pub verified: bool,
+  pub binding: Option<DeviceBinding>,
+  /// The package was only added to the driver store because the device is
+  /// not connected; it binds when the device is next plugged in.
+  pub bind_on_plug: bool,
+}
+
+/// What Windows actually has bound to a device node.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct DeviceBinding {
+  pub device_id: String,
+  pub service: Option<String>,
+  pub provider: Option<String>,
+  pub config_manager_error_code: Option<u32>,
+}
+
+impl DeviceBinding {
+  pub fn is_bound_to(&self, kind: DriverKind) -> bool {
+    let service_matches = self
+      .service
+      .as_deref()
+      .map(|service| service.eq_ignore_ascii_case(kind.service_name()))
+      .unwrap_or(false);
+    service_matches && self.config_manager_error_code.unwrap_or(0) == 0
+  }
+}
+
+/// Attempts made to read back the binding; PnP can take a moment to restart
+/// the device after pnputil returns.
+const BINDING_CHECK_ATTEMPTS: u32 = 5;
+
+#[derive(Debug)]
+pub struct Config {
+  pub vendor_id: u16,
+  pub product_id: u16,
+  pub description: String,
+  pub manufacturer: String,
+  pub kind: DriverKind,
+  pub interface: Option<u8>,
+  pub allow_unverified_resources: bool,
+  /// Per-install directory the INF and support files are staged into.
+  pub staging_dir: PathBuf,
+  /// Only add the package to the driver store, for devices that are not
+  /// connected yet.
+  pub preinstall: bool,
+}
+
+impl Config {
+  /// Hardware ID the INF matches. Composite devices get an `MI_xx` suffix so
+  /// only the selected interface is rebound.
+  pub fn hardware_id(&self) -> String {
+    match self.interface {
+      Some(interface) => format!(
+        "USB\\VID_{:04X}&PID_{:04X}&MI_{:02X}",
+        self.vendor_id, self.product_id, interface
+      ),
+      None => format!("USB\\VID_{:04X}&PID_{:04X}", self.vendor_id, self.product_id),
+    }
+  }
+
+  /// Resolves each support file to its build for the OS architecture, as a
+  /// path relative to the resource directory. Coinstallers are left out
+  /// (`None`) in the INF-only flow; any other missing build is reported here
+  /// rather than as a pnputil failure later.
+  fn resolve_support_files(
+    &self,
+    resource_dir: &Path,
+  ) -> Result<Vec<(&'static str, Option<String>)>, PrepareDriverError> {
+    let flow = PackageFlow::current();
+    let mut resolved = Vec::new();
+    for &file_name in self.kind.support_files() {
+      if flow == PackageFlow::InfOnly && self.kind.coinstallers().contains(&file_name) {
+        resolved.push((file_name, None));
+        continue;
+      }
+      let path = resource_file(resource_dir, file_name).ok_or_else(|| {
+        PrepareDriverError::UnsupportedArchitecture(format!(
+          "No {} build of {} in the driver resources",
+          os_architecture(),
+          file_name
+        ))
+      })?;
+      resolved.push((file_name, Some(path)));
+    }
+    Ok(resolved)
+  }
+
+  /// Checks every binary this driver kind stages (plus devcon.exe, if shipped)
+  /// against the hashes compiled into the build and an Authenticode
+  /// signature from the publisher recorded for that file.
+  pub fn verify_resources(&self, resource_dir: &Path) -> Result<Vec<ResourceVerification>, PrepareDriverError> {
+    let expected = load_expected_hashes();
+    let mut results = Vec::new();
+
+    let mut files: Vec<String> = self
+      .resolve_support_files(resource_dir)?
+      .into_iter()
+      .filter_map(|(_, path)| path)
+      .collect();
+    files.extend(resource_file(resource_dir, "devcon.exe"));
+
+    for file_name in &files {
+      let verification = verify_resource(resource_dir, file_name, &expected);
+      if let Some(reason) = verification.failure_reason() {
+        if self.allow_unverified_resources {
+          tracing::warn!("{}", reason);
+        } else {
+          return Err(PrepareDriverError::TamperedResource(reason));
+        }
+      }
+      results.push(verification);
+    }
+
+    Ok(results)
+  }
+
+  pub fn prepare_driver(&self) -> Result<(), PrepareDriverError> {
+    if !check_admin_rights() {
+      return Err(PrepareDriverError::PermissionDenied);
+    }
+
+    create_staging_dir(&self.staging_dir).map_err(|e| PrepareDriverError::UnknownError(e.to_string()))?;
+
+    let driver_resource_path = driver_resource_dir().ok_or(PrepareDriverError::DriverNotFound)?;
+
+    let support_files = self.resolve_support_files(&driver_resource_path)?;
+    self.verify_resources(&driver_resource_path)?;
+
+    let template = load_template(self.kind)
+      .map_err(|e| PrepareDriverError::UnknownError(e.to_string()))?
+      .ok_or(PrepareDriverError::DriverNotFound)?;
+    if template.customized {
+      validate_template(&template.content)
+        .map_err(|e| PrepareDriverError::UnknownError(format!("Custom INF template is invalid: {}", e)))?;
+    }
+    let template_content = template.content;
+
+    // Older templates have no {{MI}} placeholder; append it to the PID in
+    // hardware IDs so interface selection works with them too.
+    let template_content = if template_content.contains("{{MI}}") {
+      template_content
+    } else {
+      template_content.replace("PID_{{PID}}", "PID_{{PID}}{{MI}}")
+    };
+
+    let interface_suffix = self
+      .interface
+      .map(|interface| format!("&MI_{:02X}", interface))
+      .unwrap_or_default();
+
+    let inf_content = template_content
+      .replace("{{MI}}", &interface_suffix)
+      .replace("{{VID}}", &format!("{:04X}", self.vendor_id))
+      .replace("{{PID}}", &format!("{:04X}", self.product_id))
+      .replace("{{DESCRIPTION}}", &self.description)
+      .replace("{{MANUFACTURER}}", &self.manufacturer);
+
+    let skipped: Vec<&str> = support_files
+      .iter()
+      .filter(|(_, path)| path.is_none())
+      .map(|(file_name, _)| *file_name)
+      .collect();
+    let inf_content = match PackageFlow::current() {
+      PackageFlow::InfOnly => strip_coinstaller_sections(&inf_content),
+      PackageFlow::LegacyCoinstaller => inf_content,
+    };
+    let inf_content = strip_file_references(&inf_content, &skipped);
+
+    let inf_path = self.staging_dir.join(self.kind.inf_name());
+    std::fs::write(&inf_path, inf_content)
+      .map_err(|e| PrepareDriverError::UnknownError(format!("Failed to write INF file: {}", e)))?;
+
+    for (file_name, path) in &support_files {
+      let Some(path) = path else {
+        continue;
+      };
+      let target_path = self.staging_dir.join(file_name);
+      std::fs::copy(driver_resource_path.join(path), &target_path)
+        .map_err(|e| PrepareDriverError::UnknownError(format!("Failed to copy {}: {}", file_name, e)))?;
+    }
+
+    Ok(())
+  }
+
+  pub fn install_driver(&self) -> Result<InstallOutcome, HayboxError> {
+    if !check_admin_rights() {
+      return Err(HayboxError::elevation_required());
+    }
+
+    let inf_path = self.staging_dir.join(self.kind.inf_name());
+
+    if !inf_path.exists() {
+      return Err(HayboxError::Driver(
+        "Driver INF file not found. Did you call prepare_driver first?".to_string(),
+      ));
+    }
+
+    let inf_path_str = inf_path.to_string_lossy().to_string();
+
+    let mut pnputil_args = vec!["/add-driver", inf_path_str.as_str()];
+    if !self.preinstall {
+      pnputil_args.push("/install");
+    }
+
+    let output = operations::output(Command::new("pnputil").args(&pnputil_args))
+      .map_err(|e| HayboxError::Driver(format!("Failed to execute pnputil: {}", e)))?;
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    // Only the exit code is reliable; pnputil's failure messages can mention
+    // rebooting too.
+    let mut reboot_required = output.status.code() == Some(ERROR_SUCCESS_REBOOT_REQUIRED);
+
+    let pnputil_result = if !output.status.success() && !reboot_required {
+      let error_message = String::from_utf8_lossy(&output.stderr);
+      Err(HayboxError::Driver(format!(
+        "pnputil failed (0x{:08X}): {}",
+        output.status.code().unwrap_or_default() as u32,
+        error_message
+      )))
+    } else {
+      Ok(())
+    };
+
+    AuditEntry::new("install_driver")
+      .target(self.vendor_id, self.product_id)
+      .inf(self.kind.inf_name())
+      .tool_output(&stdout)
+      .outcome(&pnputil_result)
+      .record();
+
+    pnputil_result?;
+
+    // devcon can only update devices that are present.
+    if self.preinstall {
+      record_pending_action(
+        self.kind,
+        self.vendor_id,
+        self.product_id,
+        self.interface,
+        PendingReason::NextPlug,
+      );
+      return Ok(InstallOutcome {
+        bind_on_plug: true,
+        ..Default::default()
+      });
+    }
+
+    let resource_dir =
+      driver_resource_dir().ok_or_else(|| HayboxError::Driver("Driver resources not found".to_string()))?;
+    // devcon has to match the OS; an x64 build cannot update drivers on
+    // ARM64 even though it runs there.
+    if let Some(devcon) = resource_file(&resource_dir, "devcon.exe") {
+      let devcon_path = resource_dir.join(&devcon);
+      let expected = load_expected_hashes();
+      let verification = verify_resource(&resource_dir, &devcon, &expected);
+      if !verification.is_trusted() && !self.allow_unverified_resources {
+        return Err(HayboxError::Driver(
+          verification
+            .failure_reason()
+            .unwrap_or_else(|| "devcon.exe failed verification".to_string()),
+        ));
+      }
+
+      let devcon_result =
+        operations::output(Command::new(&devcon_path).args(["update", &inf_path_str, &self.hardware_id()]));
+
+      match devcon_result {
+        // devcon exits with 1 when the update succeeded but needs a reboot.
+        Ok(output) if output.status.code() == Some(1) => reboot_required = true,
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {
+          return Err(HayboxError::Driver(format!("devcon was stopped: {}", e)));
+        }
+        Err(e) => tracing::warn!("devcon failed: {}", e),
+      }
+    }
+
+    if reboot_required {
+      record_pending_action(
+        self.kind,
+        self.vendor_id,
+        self.product_id,
+        self.interface,
+        PendingReason::Reboot,
+      );
+    }
+
+    Ok(InstallOutcome {
+      reboot_required,
+      ..Default::default()
+    })
+  }
+}
+
+/// Drops the CoInstallers sections and every directive pointing at them, so
+/// the INF installs with the inbox KMDF and WinUSB alone.
+fn strip_coinstaller_sections(inf: &str) -> String {
+  let mut in_coinstaller_section = false;
+  inf
+    .split_inclusive('\n')
+    .filter(|line| {
+      let line = line.trim().to_lowercase();
+      if line.starts_with('[') {
+        in_coinstaller_section = line.contains("coinstaller");
+      }
+      !in_coinstaller_section && !line.contains("coinstaller")
+    })
+    .collect()
+}
+
+/// Drops every line naming one of `file_names`, which takes a skipped
+/// coinstaller out of CopyFiles, SourceDisksFiles and the CoInstallers32
+/// registry entry.
+fn strip_file_references(inf: &str, file_names: &[&str]) -> String {
+  let file_names: Vec<String> = file_names.iter().map(|name| name.to_lowercase()).collect();
+  inf
+    .split_inclusive('\n')
+    .filter(|line| {
+      let line = line.to_lowercase();
+      !file_names.iter().any(|name| line.contains(name.as_str()))
+    })
+    .collect()
+}
+
+pub struct ConfigBuilder {
+  vendor_id: u16,
+  product_id: u16,
+  description: String,
+  manufacturer: String,
+  kind: DriverKind,
+  interface: Option<u8>,
+  allow_unverified_resources: bool,
+  preinstall: bool,
+}
+
+impl ConfigBuilder {
+  pub fn new() -> Self {
+    Self {
+      vendor_id: 0,
+      product_id: 0,
+      description: String::new(),
+      manufacturer: String::new(),
+      kind: DriverKind::WinUsb,
+      interface: None,
+      allow_unverified_resources: false,
+      preinstall: false,
+    }
+  }
+}
+
+impl Default for ConfigBuilder {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl ConfigBuilder {
+  pub fn vendor_id(mut self, vendor_id: u16) -> Self {
+    self.vendor_id = vendor_id;
+    self
+  }
+
+  pub fn product_id(mut self, product_id: u16) -> Self {
+    self.product_id = product_id;
+    self
+  }
+
+  pub fn description(mut self, description: &str) -> Self {
+    self.description = description.to_string();
+    self
+  }
+
+  pub fn manufacturer(mut self, manufacturer: &str) -> Self {
+    self.manufacturer = manufacturer.to_string();
+    self
+  }
+
+  pub fn kind(mut self, kind: DriverKind) -> Self {
+    self.kind = kind;
+    self
+  }
+
+  pub fn interface(mut self, interface: u8) -> Self {
+    self.interface = Some(interface);
+    self
+  }
+
+  /// Downgrades resource verification failures to warnings instead of
+  /// refusing to stage the driver.
+  pub fn allow_unverified_resources(mut self, allow: bool) -> Self {
+    self.allow_unverified_resources = allow;
+    self
+  }
+
+  /// Adds the package to the driver store without binding it, so it applies
+  /// when the device is next plugged in.
+  pub fn preinstall(mut self, preinstall: bool) -> Self {
+    self.preinstall = preinstall;
+    self
+  }
+
+  pub fn build(self) -> Config {
+    Config {
+      vendor_id: self.vendor_id,
+      product_id: self.product_id,
+      description: self.description,
+      manufacturer: self.manufacturer,
+      kind: self.kind,
+      interface: self.interface,
+      allow_unverified_resources: self.allow_unverified_resources,
+      staging_dir: unique_staging_dir(),
+      preinstall: self.preinstall,
+    }
+  }
+}
+
+#[derive(Debug, Clone)]
+pub enum PrepareDriverError {
+  DriverNotFound,
+  PermissionDenied,
+  TamperedResource(String),
+  /// A required binary has no build for the OS architecture.
+  UnsupportedArchitecture(String),
+  UnknownError(String),
+}
+
+impl std::fmt::Display for PrepareDriverError {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    match self {
+      PrepareDriverError::DriverNotFound => write!(f, "Driver files not found"),
+      PrepareDriverError::PermissionDenied => write!(f, "Permission denied"),
+      PrepareDriverError::TamperedResource(e) => write!(f, "Driver resource failed verification: {}", e),
+      PrepareDriverError::UnsupportedArchitecture(e) => write!(f, "{}", e),
+      PrepareDriverError::UnknownError(e) => write!(f, "Unknown error: {}", e),
+    }
+  }
+}
+
+impl std::error::Error for PrepareDriverError {}
+
+pub fn install_driver_package(config: &Config) -> Result<InstallOutcome, HayboxError> {
+  let prepare_result = config.prepare_driver();
+
+  AuditEntry::new("prepare_driver")
+    .target(config.vendor_id, config.product_id)
+    .inf(config.kind.inf_name())
+    .outcome(&prepare_result)
+    .record();
+
+  let result = match prepare_result {
+    Ok(_) => config
+      .install_driver()
+      .map_err(|e| HayboxError::Driver(format!("Failed to install driver: {}", e))),
+    Err(PrepareDriverError::DriverNotFound) => {
+      Err(HayboxError::Driver(format!("{} driver files not found", config.kind)))
+    }
+    Err(e) => Err(HayboxError::Driver(format!("Failed to prepare driver: {}", e))),
+  };
+
+  // pnputil has copied the package into the driver store by now.
+  remove_staging_dir(&config.staging_dir);
+  driver_cache::invalidate(config.vendor_id, config.product_id);
+
+  match result {
+    Ok(outcome) if outcome.reboot_required || outcome.bind_on_plug => Ok(outcome),
+    Ok(outcome) => Ok(verify_binding(config, outcome)),
+    Err(e) => Err(e),
+  }
+}
+
+/// Re-reads the device after installation instead of trusting pnputil's exit
+/// code, retrying while PnP restarts the device.
+fn verify_binding(config: &Config, mut outcome: InstallOutcome) -> InstallOutcome {
+  for attempt in 0..BINDING_CHECK_ATTEMPTS {
+    if attempt > 0 {
+      std::thread::sleep(std::time::Duration::from_secs(1));
+    }
+
+    match query_binding(config.vendor_id, config.product_id, config.interface) {
+      Ok(binding) => {
+        outcome.verified = binding.as_ref().map(|b| b.is_bound_to(config.kind)).unwrap_or(false);
+        outcome.binding = binding;
+      }
+      Err(e) => tracing::warn!("failed to verify driver binding: {}", e),
+    }
+
+    if outcome.verified {
+      break;
+    }
+  }
+
+  let verification = if outcome.verified {
+    Ok(())
+  } else {
+    let service = outcome.binding.as_ref().and_then(|b| b.service.as_deref());
+    Err(HayboxError::Driver(format!(
+      "Device is bound to {}",
+      service.unwrap_or("no driver")
+    )))
+  };
+
+  AuditEntry::new("verify_binding")
+    .target(config.vendor_id, config.product_id)
+    .inf(config.kind.inf_name())
+    .outcome(&verification)
+    .record();
+
+  outcome
+}
+
+#[cfg(target_os = "windows")]
+#[derive(Debug, Deserialize)]
+struct WmiPnPService {
+  #[serde(rename = "DeviceID")]
+  device_id: String,
+  #[serde(rename = "Service")]
+  service: Option<String>,
+  #[serde(rename = "ConfigManagerErrorCode")]
+  config_manager_error_code: Option<u32>,
+}
+
+#[cfg(target_os = "windows")]
+#[derive(Debug, Deserialize)]
+struct WmiDriverProvider {
+  #[serde(rename = "DeviceID")]
+  device_id: Option<String>,
+  #[serde(rename = "DriverProviderName")]
+  driver_provider_name: Option<String>,
+}
+
+/// Returns the service, driver provider and ConfigManager error code of the
+/// device node, or `None` if the device is not present.
+#[cfg(target_os = "windows")]
+pub fn query_binding(
+  vendor_id: u16,
+  product_id: u16,
+  interface: Option<u8>,
+) -> Result<Option<DeviceBinding>, HayboxError> {
+  let id_filter = format!(
+    "DeviceID LIKE '%VID\\_{0:04X}%' AND DeviceID LIKE '%PID\\_{1:04X}%'",
+    vendor_id, product_id
+  );
+
+  let devices: Vec<WmiPnPService> = wmi_worker::query(&format!(
+    "SELECT DeviceID, Service, ConfigManagerErrorCode FROM Win32_PnPEntity WHERE {}",
+    id_filter
+  ))?;
+
+  let interface_tag = interface.map(|interface| format!("&MI_{:02X}", interface));
+  let device = devices.into_iter().find(|device| match &interface_tag {
+    Some(tag) => device.device_id.to_uppercase().contains(tag),
+    // The parent node of a composite device carries no MI_ suffix.
+    None => !device.device_id.to_uppercase().contains("&MI_"),
+  });
+
+  let device = match device {
+    Some(device) => device,
+    None => return Ok(None),
+  };
+
+  let providers: Vec<WmiDriverProvider> = wmi_worker::query(&format!(
+    "SELECT DeviceID, DriverProviderName FROM Win32_PnPSignedDriver WHERE {}",
+    id_filter
+  ))?;
+
+  let provider = providers
+    .into_iter()
+    .find(|p| {
+      p.device_id
+        .as_deref()
+        .map(|id| id.eq_ignore_ascii_case(&device.device_id))
+        .unwrap_or(false)
+    })
+    .and_then(|p| p.driver_provider_name);
+
+  Ok(Some(DeviceBinding {
+    device_id: device.device_id,
+    service: device.service,
+    provider,
+    config_manager_error_code: device.config_manager_error_code,
+  }))
+}
+
+/// Returns the kernel driver sysfs lists for the interface. Without an
+/// interface the first one stands in for the device, which has no driver of
+/// its own beyond the `usb` core.
+#[cfg(target_os = "linux")]
+pub fn query_binding(
+  vendor_id: u16,
+  product_id: u16,
+  interface: Option<u8>,
+) -> Result<Option<DeviceBinding>, HayboxError> {
+  let binding = udev::driver_bindings(Some(vendor_id), Some(product_id))
+    .into_iter()
+    .find(|binding| match interface {
+      // Interface directories are named `<device>:<config>.<interface>`.
+      Some(interface) => {
+        binding
+          .interface
+          .rsplit('.')
+          .next()
+          .and_then(|number| number.parse::<u8>().ok())
+          == Some(interface)
+      }
+      None => true,
+    });
+
+  Ok(binding.map(|binding| DeviceBinding {
+    device_id: binding.interface,
+    service: binding.driver,
+    provider: None,
+    config_manager_error_code: None,
+  }))
+}
+
+/// Returns the driver IOKit matched to the interface, or to the first one
+/// when no interface is given.
+#[cfg(target_os = "macos")]
+pub fn query_binding(
+  vendor_id: u16,
+  product_id: u16,
+  interface: Option<u8>,
+) -> Result<Option<DeviceBinding>, HayboxError> {
+  let Some(device) = iokit::usb_devices(Some(vendor_id), Some(product_id))?
+    .into_iter()
+    .next()
+  else {
+    return Ok(None);
+  };
+  let matched = device.interfaces.into_iter().find(|candidate| match interface {
+    Some(interface) => candidate.number == Some(u16::from(interface)),
+    None => true,
+  });
+
+  Ok(Some(DeviceBinding {
+    device_id: match interface {
+      Some(interface) => format!("USB\\VID_{:04X}&PID_{:04X}&MI_{:02X}", vendor_id, product_id, interface),
+      None => format!("USB\\VID_{:04X}&PID_{:04X}", vendor_id, product_id),
+    },
+    service: matched.and_then(|interface| interface.driver),
+    provider: None,
+    config_manager_error_code: None,
+  }))
+}
+
+/// Returns the service (WinUSB, HidUsb, libusbK, ...) Windows currently has
+/// bound to the device, or `None` if the device is not present.
+pub fn query_bound_service(
+  vendor_id: u16,
+  product_id: u16,
+  interface: Option<u8>,
+) -> Result<Option<String>, HayboxError> {
+  Ok(query_binding(vendor_id, product_id, interface)?.and_then(|binding| binding.service))
+}
+
+#[cfg(target_os = "windows")]
+#[derive(Debug, Deserialize)]
+struct WmiSignedDriver {
+  #[serde(rename = "DeviceID")]
+  device_id: Option<String>,
+  #[serde(rename = "InfName")]
+  inf_name: Option<String>,
+}
+
+#[cfg(not(target_os = "windows"))]
+pub fn restore_default_driver(_vendor_id: u16, _product_id: u16) -> Result<(), HayboxError> {
+  Err(HayboxError::Unsupported(
+    "Only Windows binds replacement drivers; nothing needs restoring".to_string(),
+  ))
+}
+
+/// Removes whatever third-party driver package is bound to the device and lets
+/// Windows rebind the inbox HidUsb driver on the following rescan.
+#[cfg(target_os = "windows")]
+pub fn restore_default_driver(vendor_id: u16, product_id: u16) -> Result<(), HayboxError> {
+  if !check_admin_rights() {
+    return Err(HayboxError::elevation_required());
+  }
+
+  let query = format!(
+    "SELECT DeviceID, InfName FROM Win32_PnPSignedDriver WHERE DeviceID LIKE '%VID\\_{0:04X}%' AND DeviceID LIKE '%PID\\_{1:04X}%'",
+    vendor_id, product_id
+  );
+
+  let drivers: Vec<WmiSignedDriver> = wmi_worker::query(&query)?;
+
+  if drivers.is_empty() {
+    return Err(HayboxError::DeviceNotConnected(format!(
+      "No device found for {:04X}:{:04X}",
+      vendor_id, product_id
+    )));
+  }
+
+  for driver in &drivers {
+    // Only oemNN.inf packages were added by us (or another tool); inbox INFs
+    // such as input.inf must never be deleted from the driver store.
+    if let Some(inf_name) = driver
+      .inf_name
+      .as_ref()
+      .filter(|inf| inf.to_lowercase().starts_with("oem"))
+    {
+      let output =
+        operations::output(Command::new("pnputil").args(["/delete-driver", inf_name, "/uninstall", "/force"]))
+          .map_err(|e| HayboxError::Driver(format!("Failed to execute pnputil: {}", e)))?;
+
+      let stdout = String::from_utf8_lossy(&output.stdout);
+      let delete_result = if output.status.success() {
+        Ok(())
+      } else {
+        Err(HayboxError::Driver(format!(
+          "pnputil failed to remove {}: {}",
+          inf_name,
+          stdout.trim()
+        )))
+      };
+
+      AuditEntry::new("restore_default_driver")
+        .target(vendor_id, product_id)
+        .inf(inf_name)
+        .tool_output(&stdout)
+        .outcome(&delete_result)
+        .record();
+
+      delete_result?;
+    }
+
+    if let Some(device_id) = &driver.device_id {
+      let output = operations::output(Command::new("pnputil").args(["/remove-device", device_id]))
+        .map_err(|e| HayboxError::Driver(format!("Failed to execute pnputil: {}", e)))?;
+
+      if !output.status.success() {
+        tracing::warn!("failed to remove device {}", device_id);
+      }
+    }
+  }
+
+  let output = operations::output(Command::new("pnputil").args(["/scan-devices"]))
+    .map_err(|e| HayboxError::Driver(format!("Failed to execute pnputil: {}", e)))?;
+
+  if !output.status.success() {
+    let error_message = String::from_utf8_lossy(&output.stdout);
+    return Err(HayboxError::Driver(format!(
+      "Device rescan failed: {}",
+      error_message.trim()
+    )));
+  }
+
+  driver_cache::invalidate(vendor_id, product_id);
+  Ok(())
+}
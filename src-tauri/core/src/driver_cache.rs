@@ -0,0 +1,121 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::error::HayboxError;
+use crate::hotplug;
+use crate::{platform, DriverInfo};
+
+/// How long a lookup is trusted without a hotplug event. Driver changes made
+/// outside the app (Device Manager, Zadig) are picked up after this.
+const CACHE_TTL: Duration = Duration::from_secs(30);
+
+/// The `vendor_id`/`product_id` filter a lookup was made with.
+type QueryKey = (Option<u16>, Option<u16>);
+
+struct CachedQuery {
+  /// Instance IDs of the devices the lookup returned, in order.
+  device_ids: Vec<String>,
+  fetched_at: Instant,
+}
+
+#[derive(Default)]
+struct DriverCache {
+  /// Driver details by device instance ID, shared by every lookup that
+  /// returned the device.
+  drivers: HashMap<String, DriverInfo>,
+  queries: HashMap<QueryKey, CachedQuery>,
+}
+
+lazy_static::lazy_static! {
+  static ref CACHE: Mutex<DriverCache> = Mutex::new(DriverCache::default());
+}
+
+impl DriverCache {
+  fn get(&self, key: QueryKey) -> Option<Vec<DriverInfo>> {
+    let query = self.queries.get(&key)?;
+    if query.fetched_at.elapsed() >= CACHE_TTL {
+      return None;
+    }
+    query
+      .device_ids
+      .iter()
+      .map(|device_id| self.drivers.get(device_id).cloned())
+      .collect()
+  }
+
+  fn insert(&mut self, key: QueryKey, drivers: &[DriverInfo]) {
+    for driver in drivers {
+      self.drivers.insert(driver.device_id.clone(), driver.clone());
+    }
+    self.queries.insert(
+      key,
+      CachedQuery {
+        device_ids: drivers.iter().map(|driver| driver.device_id.clone()).collect(),
+        fetched_at: Instant::now(),
+      },
+    );
+  }
+
+  /// Drops lookups that could have returned the device, then any drivers no
+  /// remaining lookup refers to.
+  fn invalidate(&mut self, vendor_id: u16, product_id: u16) {
+    self
+      .queries
+      .retain(|(vid, pid), _| vid.is_some_and(|vid| vid != vendor_id) || pid.is_some_and(|pid| pid != product_id));
+    let referenced: HashSet<&String> = self.queries.values().flat_map(|query| &query.device_ids).collect();
+    self.drivers.retain(|device_id, _| referenced.contains(device_id));
+  }
+}
+
+fn fetch(key: QueryKey) -> Result<Vec<DriverInfo>, HayboxError> {
+  // Query without holding the lock; a slow WMI call should not block
+  // lookups that are already cached.
+  let drivers = platform::current().driver_info(key.0, key.1)?;
+  CACHE.lock().unwrap().insert(key, &drivers);
+  Ok(drivers)
+}
+
+/// Like `Platform::driver_info`, but answered from the cache while the
+/// previous lookup with the same filter is fresh and no matching device was
+/// plugged, removed or reinstalled since.
+pub fn driver_info(vendor_id: Option<u16>, product_id: Option<u16>) -> Result<Vec<DriverInfo>, HayboxError> {
+  let key = (vendor_id, product_id);
+  if let Some(drivers) = CACHE.lock().unwrap().get(key) {
+    return Ok(drivers);
+  }
+  fetch(key)
+}
+
+/// Re-queries every lookup made so far and returns the drivers of all USB
+/// devices. Without `force`, lookups that are still fresh are kept.
+pub fn refresh(force: bool) -> Result<Vec<DriverInfo>, HayboxError> {
+  let keys: Vec<QueryKey> = {
+    let mut cache = CACHE.lock().unwrap();
+    if force {
+      cache.queries.clear();
+      cache.drivers.clear();
+    }
+    cache.queries.keys().copied().collect()
+  };
+
+  for key in keys.into_iter().filter(|key| *key != (None, None)) {
+    if CACHE.lock().unwrap().get(key).is_none() {
+      if let Err(e) = fetch(key) {
+        tracing::warn!("{}", e);
+      }
+    }
+  }
+  driver_info(None, None)
+}
+
+/// Forgets cached drivers for a device whose driver or presence changed.
+pub fn invalidate(vendor_id: u16, product_id: u16) {
+  CACHE.lock().unwrap().invalidate(vendor_id, product_id);
+}
+
+/// Invalidates a device's entries whenever the hotplug watcher sees it
+/// arrive or leave, which includes the re-enumeration after a driver swap.
+pub fn watch_hotplug() {
+  hotplug::subscribe(|_, event| invalidate(event.vendor_id, event.product_id));
+}
@@ -0,0 +1,222 @@
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::SystemTime;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::audit::AuditEntry;
+use crate::check_admin_rights;
+use crate::driver::DriverKind;
+use crate::error::HayboxError;
+use crate::operations;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DriverStoreEntry {
+  pub published_name: String,
+  pub original_name: String,
+  pub provider: String,
+  pub class_name: String,
+  pub version: String,
+  pub hardware_ids: Vec<String>,
+}
+
+impl DriverStoreEntry {
+  /// How recently the package was published: its DriverVer date and
+  /// version, then when Windows copied its INF into `%SystemRoot%\INF`,
+  /// which tells apart packages built from the same template. The `oemNN`
+  /// number says nothing here because Windows reuses freed numbers.
+  fn publish_order(&self) -> ((u32, u32, u32), Vec<u32>, Option<SystemTime>) {
+    let mut parts = self.version.split_whitespace();
+    let date = parts
+      .next()
+      .and_then(|date| {
+        let fields: Vec<u32> = date.split('/').filter_map(|field| field.parse().ok()).collect();
+        match fields.as_slice() {
+          [month, day, year] => Some((*year, *month, *day)),
+          _ => None,
+        }
+      })
+      .unwrap_or_default();
+    let version = parts
+      .next()
+      .map(|version| version.split('.').filter_map(|part| part.parse().ok()).collect())
+      .unwrap_or_default();
+    let published = std::fs::metadata(published_inf_path(&self.published_name))
+      .and_then(|metadata| metadata.modified())
+      .ok();
+    (date, version, published)
+  }
+
+  /// Only packages built from our own templates count. Matching on the
+  /// hardware IDs as well would also pick up packages Zadig or a vendor
+  /// installed for the same controller, which are not ours to delete.
+  fn is_haybox_driver(&self) -> bool {
+    [DriverKind::WinUsb, DriverKind::LibUsbK, DriverKind::HidUsb]
+      .iter()
+      .any(|kind| self.original_name.eq_ignore_ascii_case(kind.inf_name()))
+  }
+}
+
+/// Parses the `Key: Value` blocks printed by `pnputil /enum-drivers`, one block
+/// per published driver package separated by blank lines.
+fn parse_enum_drivers(output: &str) -> Vec<DriverStoreEntry> {
+  let mut entries = Vec::new();
+  let mut current: Option<DriverStoreEntry> = None;
+
+  for line in output.lines() {
+    let Some((key, value)) = line.split_once(':') else {
+      continue;
+    };
+    let value = value.trim().to_string();
+
+    match key.trim() {
+      "Published Name" => {
+        if let Some(entry) = current.take() {
+          entries.push(entry);
+        }
+        current = Some(DriverStoreEntry {
+          published_name: value,
+          original_name: String::new(),
+          provider: String::new(),
+          class_name: String::new(),
+          version: String::new(),
+          hardware_ids: Vec::new(),
+        });
+      }
+      "Original Name" => current.iter_mut().for_each(|entry| entry.original_name = value.clone()),
+      "Provider Name" => current.iter_mut().for_each(|entry| entry.provider = value.clone()),
+      "Class Name" => current.iter_mut().for_each(|entry| entry.class_name = value.clone()),
+      "Driver Version" => current.iter_mut().for_each(|entry| entry.version = value.clone()),
+      _ => {}
+    }
+  }
+
+  if let Some(entry) = current {
+    entries.push(entry);
+  }
+
+  entries
+}
+
+/// The copy of a published INF that Windows keeps in `%SystemRoot%\INF`.
+fn published_inf_path(published_name: &str) -> PathBuf {
+  std::env::var("SystemRoot")
+    .map(|root| PathBuf::from(root).join("INF"))
+    .unwrap_or_else(|_| PathBuf::from("C:\\Windows\\INF"))
+    .join(published_name)
+}
+
+/// pnputil does not list hardware IDs, so read them from the published copy of
+/// the INF.
+fn read_hardware_ids(published_name: &str) -> Vec<String> {
+  let inf_path = published_inf_path(published_name);
+
+  let content = match std::fs::read(&inf_path) {
+    Ok(bytes) => decode_inf(&bytes),
+    Err(_) => return vec![],
+  };
+
+  let re = Regex::new(r"(?i)USB\\VID_[0-9A-F]{4}&PID_[0-9A-F]{4}(&MI_[0-9A-F]{2})?").unwrap();
+  let mut ids: Vec<String> = re.find_iter(&content).map(|m| m.as_str().to_uppercase()).collect();
+  ids.sort();
+  ids.dedup();
+  ids
+}
+
+/// INF files are either ANSI or UTF-16LE with a BOM.
+fn decode_inf(bytes: &[u8]) -> String {
+  if bytes.starts_with(&[0xFF, 0xFE]) {
+    let units: Vec<u16> = bytes[2..]
+      .chunks_exact(2)
+      .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+      .collect();
+    String::from_utf16_lossy(&units)
+  } else {
+    String::from_utf8_lossy(bytes).to_string()
+  }
+}
+
+pub fn list_driver_store() -> Result<Vec<DriverStoreEntry>, HayboxError> {
+  let output = operations::output(Command::new("pnputil").args(["/enum-drivers"]))
+    .map_err(|e| HayboxError::Driver(format!("Failed to execute pnputil: {}", e)))?;
+
+  if !output.status.success() {
+    let error_message = String::from_utf8_lossy(&output.stdout);
+    return Err(HayboxError::Driver(format!("pnputil failed: {}", error_message.trim())));
+  }
+
+  let mut entries = parse_enum_drivers(&String::from_utf8_lossy(&output.stdout));
+  for entry in entries.iter_mut() {
+    entry.hardware_ids = read_hardware_ids(&entry.published_name);
+  }
+
+  Ok(entries)
+}
+
+pub fn list_haybox_drivers() -> Result<Vec<DriverStoreEntry>, HayboxError> {
+  Ok(
+    list_driver_store()?
+      .into_iter()
+      .filter(|entry| entry.is_haybox_driver())
+      .collect(),
+  )
+}
+
+/// Deletes every HayBox-generated package except the most recently published
+/// one for each (INF name, hardware ID set) pair. Returns the packages removed.
+pub fn remove_stale_drivers() -> Result<Vec<String>, HayboxError> {
+  if !check_admin_rights() {
+    return Err(HayboxError::elevation_required());
+  }
+
+  let mut drivers = list_haybox_drivers()?;
+  drivers.sort_by_cached_key(|entry| std::cmp::Reverse(entry.publish_order()));
+
+  let mut seen: Vec<(String, Vec<String>)> = Vec::new();
+  let mut removed = Vec::new();
+  let mut errors = Vec::new();
+
+  for entry in drivers {
+    let key = (entry.original_name.to_lowercase(), entry.hardware_ids.clone());
+    if !seen.contains(&key) {
+      seen.push(key);
+      continue;
+    }
+
+    let output = operations::output(Command::new("pnputil").args(["/delete-driver", &entry.published_name]))
+      .map_err(|e| HayboxError::Driver(format!("Failed to execute pnputil: {}", e)))?;
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let delete_result = if output.status.success() {
+      Ok(())
+    } else {
+      Err(HayboxError::Driver(format!(
+        "{}: {}",
+        entry.published_name,
+        stdout.trim()
+      )))
+    };
+
+    AuditEntry::new("remove_stale_driver")
+      .inf(&entry.published_name)
+      .tool_output(&stdout)
+      .outcome(&delete_result)
+      .record();
+
+    match delete_result {
+      Ok(()) => removed.push(entry.published_name),
+      Err(e) => errors.push(e.to_string()),
+    }
+  }
+
+  if !errors.is_empty() {
+    return Err(HayboxError::Driver(format!(
+      "Removed {} package(s), failed to remove: {}",
+      removed.len(),
+      errors.join("; ")
+    )));
+  }
+
+  Ok(removed)
+}
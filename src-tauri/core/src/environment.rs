@@ -0,0 +1,174 @@
+use serde::{Deserialize, Serialize};
+
+use crate::architecture::architecture_info;
+use crate::driver_store::list_driver_store;
+use crate::error::HayboxError;
+use crate::steam::detect_steam_input;
+use crate::steamos::detect_steam_claim;
+use crate::virtualization::{detect_usb_passthrough, detect_virtual_machine};
+#[cfg(target_os = "windows")]
+use crate::wmi_worker;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EnvironmentIssue {
+  /// Driver packages generated by Zadig/libwdi are in the driver store.
+  ZadigDriver,
+  /// The libusb-win32 filter driver, usually installed through Zadig.
+  LibusbFilter,
+  UsbDk,
+  HidHide,
+  HidGuardian,
+  /// Steam is running with Steam Input enabled for the controller type.
+  SteamInput,
+  /// The app is not native to the OS, e.g. an x64 build on ARM64.
+  EmulatedProcess,
+  /// The app runs in WSL or a virtual machine.
+  VirtualMachine,
+  /// The controller reaches the app through usbipd, VirtualHere or a VM's
+  /// USB capture.
+  UsbPassthrough,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EnvironmentWarning {
+  pub issue: EnvironmentIssue,
+  pub message: String,
+  pub detail: Option<String>,
+}
+
+#[cfg(target_os = "windows")]
+#[derive(Debug, Deserialize)]
+struct WmiSystemDriver {
+  #[serde(rename = "Name")]
+  name: String,
+  #[serde(rename = "State")]
+  state: Option<String>,
+}
+
+/// Kernel services that sit between Windows and USB/HID devices and are known
+/// to break WinUSB binding or hide controllers from applications.
+#[cfg(target_os = "windows")]
+const CONFLICTING_SERVICES: &[(&str, EnvironmentIssue, &str)] = &[
+  (
+    "libusb0",
+    EnvironmentIssue::LibusbFilter,
+    "The libusb-win32 filter driver is installed and can stop WinUSB from binding",
+  ),
+  (
+    "UsbDk",
+    EnvironmentIssue::UsbDk,
+    "UsbDk is installed and can take USB devices away from their drivers",
+  ),
+  (
+    "HidHide",
+    EnvironmentIssue::HidHide,
+    "HidHide is installed and may be hiding the controller from games",
+  ),
+  (
+    "HidGuardian",
+    EnvironmentIssue::HidGuardian,
+    "HidGuardian is installed and may be hiding the controller from games",
+  ),
+];
+
+#[cfg(target_os = "windows")]
+fn detect_conflicting_services() -> Result<Vec<EnvironmentWarning>, HayboxError> {
+  let filter = CONFLICTING_SERVICES
+    .iter()
+    .map(|(name, _, _)| format!("Name = '{}'", name))
+    .collect::<Vec<_>>()
+    .join(" OR ");
+  let query = format!("SELECT Name, State FROM Win32_SystemDriver WHERE {}", filter);
+
+  let services: Vec<WmiSystemDriver> = wmi_worker::query(&query)?;
+
+  Ok(
+    services
+      .into_iter()
+      .filter_map(|service| {
+        let (_, issue, message) = CONFLICTING_SERVICES
+          .iter()
+          .find(|(name, _, _)| name.eq_ignore_ascii_case(&service.name))?;
+        Some(EnvironmentWarning {
+          issue: *issue,
+          message: message.to_string(),
+          detail: Some(format!(
+            "Service {} is {}",
+            service.name,
+            service.state.as_deref().unwrap_or("in an unknown state").to_lowercase()
+          )),
+        })
+      })
+      .collect(),
+  )
+}
+
+/// These are all Windows kernel services.
+#[cfg(not(target_os = "windows"))]
+fn detect_conflicting_services() -> Result<Vec<EnvironmentWarning>, HayboxError> {
+  Ok(vec![])
+}
+
+/// Zadig writes its packages through libwdi, which sets itself as provider.
+fn detect_zadig_drivers() -> Result<Vec<EnvironmentWarning>, HayboxError> {
+  let zadig_packages: Vec<String> = list_driver_store()?
+    .into_iter()
+    .filter(|entry| entry.provider.to_lowercase().contains("libwdi"))
+    .map(|entry| entry.published_name)
+    .collect();
+
+  if zadig_packages.is_empty() {
+    return Ok(vec![]);
+  }
+
+  Ok(vec![EnvironmentWarning {
+    issue: EnvironmentIssue::ZadigDriver,
+    message: "Drivers installed with Zadig are present and may override the WinUSB binding".to_string(),
+    detail: Some(format!("Packages: {}", zadig_packages.join(", "))),
+  }])
+}
+
+/// Emulated builds work, but it explains driver failures on ARM64, where
+/// the drivers must be native and older packages have no ARM64 build.
+fn detect_emulation() -> Result<Vec<EnvironmentWarning>, HayboxError> {
+  let info = architecture_info();
+  if !info.emulated {
+    return Ok(vec![]);
+  }
+
+  Ok(vec![EnvironmentWarning {
+    issue: EnvironmentIssue::EmulatedProcess,
+    message: format!(
+      "The app is a {} build running on {} Windows",
+      info.process_architecture, info.os_architecture
+    ),
+    detail: info.windows_build.map(|build| format!("Windows build {}", build)),
+  }])
+}
+
+type Detector = fn() -> Result<Vec<EnvironmentWarning>, HayboxError>;
+
+const DETECTORS: &[Detector] = &[
+  detect_conflicting_services,
+  detect_zadig_drivers,
+  detect_steam_input,
+  detect_steam_claim,
+  detect_emulation,
+  detect_virtual_machine,
+  detect_usb_passthrough,
+];
+
+/// Runs every detector. A detector that fails only logs a warning so one
+/// broken check does not hide the others.
+pub fn collect_environment_warnings() -> Vec<EnvironmentWarning> {
+  DETECTORS
+    .iter()
+    .flat_map(|detect| {
+      detect().unwrap_or_else(|e| {
+        tracing::warn!("environment check failed: {}", e);
+        vec![]
+      })
+    })
+    .collect()
+}
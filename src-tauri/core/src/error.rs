@@ -0,0 +1,164 @@
+use serde::{Serialize, Serializer};
+
+use crate::driver::PrepareDriverError;
+
+/// The error every command returns. The frontend receives it as
+/// `{ code, message, hint }` and can map `code` to localized help text,
+/// falling back to `message`.
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum HayboxError {
+  /// The operation needs Administrator, or root, and the app runs without.
+  #[error("{0}")]
+  ElevationRequired(String),
+  #[error("{0}")]
+  Unsupported(String),
+  #[error("{0}")]
+  DeviceNotConnected(String),
+  /// Another application or driver holds the device.
+  #[error("{0}")]
+  DeviceBusy(String),
+  #[error("{0}")]
+  Timeout(String),
+  /// A driver package could not be prepared, installed or removed.
+  #[error("{0}")]
+  Driver(String),
+  #[error("{0}")]
+  Wmi(String),
+  #[error("{0}")]
+  Hid(String),
+  #[error("{0}")]
+  Usb(String),
+  #[error("{0}")]
+  SerialPort(String),
+  /// GitHub or gist requests for firmware and shared layouts.
+  #[error("{0}")]
+  Network(String),
+  #[error("{0}")]
+  Io(String),
+  #[error("{0}")]
+  Other(String),
+}
+
+impl HayboxError {
+  /// The message every command that needs Administrator fails with.
+  pub fn elevation_required() -> Self {
+    HayboxError::ElevationRequired("Administrator privileges required".to_string())
+  }
+
+  /// For actions the platform the app was built for cannot do.
+  pub fn unsupported(action: &str) -> Self {
+    HayboxError::Unsupported(format!("{} is not supported on {}", action, std::env::consts::OS))
+  }
+
+  pub fn code(&self) -> &'static str {
+    match self {
+      HayboxError::ElevationRequired(_) => "elevation_required",
+      HayboxError::Unsupported(_) => "unsupported",
+      HayboxError::DeviceNotConnected(_) => "device_not_connected",
+      HayboxError::DeviceBusy(_) => "device_busy",
+      HayboxError::Timeout(_) => "timeout",
+      HayboxError::Driver(_) => "driver",
+      HayboxError::Wmi(_) => "wmi",
+      HayboxError::Hid(_) => "hid",
+      HayboxError::Usb(_) => "usb",
+      HayboxError::SerialPort(_) => "serial_port",
+      HayboxError::Network(_) => "network",
+      HayboxError::Io(_) => "io",
+      HayboxError::Other(_) => "other",
+    }
+  }
+
+  /// What the user can try next, in English. The UI prefers its own text
+  /// for the code when it has one.
+  pub fn hint(&self) -> Option<&'static str> {
+    match self {
+      HayboxError::ElevationRequired(_) => {
+        Some("Run the action again and accept the administrator prompt, or restart the app as administrator.")
+      }
+      HayboxError::DeviceNotConnected(_) => {
+        Some("Check the USB cable and that the controller is in the right mode, then refresh.")
+      }
+      HayboxError::DeviceBusy(_) => Some(
+        "Close Dolphin, Steam and other apps that may have the controller open, or check which driver is bound to it.",
+      ),
+      HayboxError::Timeout(_) => Some("Replug the controller and try again."),
+      HayboxError::Driver(_) => Some("Check the driver audit log, or restore the default driver and try again."),
+      HayboxError::Wmi(_) => Some("Restart the Windows Management Instrumentation service or reboot."),
+      HayboxError::Usb(_) => Some("Make sure the WinUSB driver is installed for this device and replug it."),
+      HayboxError::SerialPort(_) => Some("Close other serial consoles that may have the port open."),
+      HayboxError::Network(_) => Some("Check the internet connection, or download the file manually."),
+      HayboxError::Unsupported(_) | HayboxError::Hid(_) | HayboxError::Io(_) | HayboxError::Other(_) => None,
+    }
+  }
+}
+
+impl From<std::io::Error> for HayboxError {
+  fn from(error: std::io::Error) -> Self {
+    match error.kind() {
+      std::io::ErrorKind::TimedOut => HayboxError::Timeout(error.to_string()),
+      _ => HayboxError::Io(error.to_string()),
+    }
+  }
+}
+
+impl From<rusb::Error> for HayboxError {
+  fn from(error: rusb::Error) -> Self {
+    match error {
+      rusb::Error::NoDevice | rusb::Error::NotFound => HayboxError::DeviceNotConnected(error.to_string()),
+      rusb::Error::Busy | rusb::Error::Access => HayboxError::DeviceBusy(error.to_string()),
+      rusb::Error::Timeout => HayboxError::Timeout(error.to_string()),
+      rusb::Error::NotSupported => HayboxError::Unsupported(error.to_string()),
+      _ => HayboxError::Usb(error.to_string()),
+    }
+  }
+}
+
+impl From<hidapi::HidError> for HayboxError {
+  fn from(error: hidapi::HidError) -> Self {
+    HayboxError::Hid(error.to_string())
+  }
+}
+
+impl From<serialport::Error> for HayboxError {
+  fn from(error: serialport::Error) -> Self {
+    match error.kind() {
+      serialport::ErrorKind::NoDevice => HayboxError::DeviceNotConnected(error.to_string()),
+      serialport::ErrorKind::Io(std::io::ErrorKind::TimedOut) => HayboxError::Timeout(error.to_string()),
+      _ => HayboxError::SerialPort(error.to_string()),
+    }
+  }
+}
+
+impl From<ureq::Error> for HayboxError {
+  fn from(error: ureq::Error) -> Self {
+    HayboxError::Network(error.to_string())
+  }
+}
+
+impl From<PrepareDriverError> for HayboxError {
+  fn from(error: PrepareDriverError) -> Self {
+    match error {
+      PrepareDriverError::PermissionDenied => HayboxError::ElevationRequired(error.to_string()),
+      PrepareDriverError::UnsupportedArchitecture(_) => HayboxError::Unsupported(error.to_string()),
+      _ => HayboxError::Driver(error.to_string()),
+    }
+  }
+}
+
+impl Serialize for HayboxError {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    #[derive(Serialize)]
+    struct Payload<'a> {
+      code: &'a str,
+      message: String,
+      hint: Option<&'a str>,
+    }
+
+    Payload {
+      code: self.code(),
+      message: self.to_string(),
+      hint: self.hint(),
+    }
+    .serialize(serializer)
+  }
+}
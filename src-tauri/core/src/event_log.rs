@@ -0,0 +1,333 @@
+#[cfg(target_os = "windows")]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[cfg(target_os = "windows")]
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+#[cfg(target_os = "windows")]
+use windows::core::PCWSTR;
+#[cfg(target_os = "windows")]
+use windows::Win32::Foundation::{ERROR_INSUFFICIENT_BUFFER, ERROR_NO_MORE_ITEMS};
+#[cfg(target_os = "windows")]
+use windows::Win32::System::EventLog::{
+  EvtClose, EvtFormatMessage, EvtFormatMessageEvent, EvtNext, EvtOpenPublisherMetadata, EvtQuery, EvtQueryChannelPath,
+  EvtQueryReverseDirection, EvtQueryTolerateQueryErrors, EvtRender, EvtRenderEventXml, EVT_HANDLE,
+};
+
+use crate::error::HayboxError;
+#[cfg(target_os = "windows")]
+use crate::integrity::to_wide;
+#[cfg(target_os = "windows")]
+use crate::{UsbDeviceInfo, DEVICES};
+
+/// Channels where PnP and the UMDF host log device configuration, driver
+/// load failures and surprise removals.
+#[cfg(target_os = "windows")]
+const CHANNELS: &[&str] = &[
+  "Microsoft-Windows-Kernel-PnP/Configuration",
+  "Microsoft-Windows-DriverFrameworks-UserMode/Operational",
+];
+/// How far back to look when the caller gives no start time.
+#[cfg(target_os = "windows")]
+const DEFAULT_LOOKBACK_SECS: u64 = 24 * 60 * 60;
+/// Events read per channel, newest first. Kernel-PnP logs every device on
+/// the system, so this bounds the work on busy machines.
+#[cfg(target_os = "windows")]
+const MAX_EVENTS_PER_CHANNEL: usize = 2000;
+#[cfg(target_os = "windows")]
+const BATCH_SIZE: usize = 64;
+
+#[cfg(target_os = "windows")]
+lazy_static::lazy_static! {
+  static ref HARDWARE_ID_RE: Regex = Regex::new(r"(?i)VID_([0-9A-F]{4})&(?:amp;)?PID_([0-9A-F]{4})").unwrap();
+  static ref INSTANCE_ID_RE: Regex =
+    Regex::new(r"(?i)(?:USB|HID)\\VID_[0-9A-F]{4}&(?:amp;)?PID_[0-9A-F]{4}[^<'\x22\s]*").unwrap();
+  static ref PROVIDER_RE: Regex = Regex::new(r#"<Provider Name=['"]([^'"]+)['"]"#).unwrap();
+  static ref EVENT_ID_RE: Regex = Regex::new(r"<EventID[^>]*>(\d+)</EventID>").unwrap();
+  static ref LEVEL_RE: Regex = Regex::new(r"<Level>(\d+)</Level>").unwrap();
+  static ref TIME_CREATED_RE: Regex = Regex::new(r#"<TimeCreated SystemTime=['"]([^'"]+)['"]"#).unwrap();
+  static ref SYSTEM_TIME_RE: Regex = Regex::new(r"^(\d{4})-(\d{2})-(\d{2})T(\d{2}):(\d{2}):(\d{2})").unwrap();
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EventLevel {
+  Critical,
+  Error,
+  Warning,
+  Information,
+  Verbose,
+}
+
+#[cfg(target_os = "windows")]
+impl EventLevel {
+  fn from_level(level: u32) -> Self {
+    match level {
+      1 => EventLevel::Critical,
+      2 => EventLevel::Error,
+      3 => EventLevel::Warning,
+      5 => EventLevel::Verbose,
+      _ => EventLevel::Information,
+    }
+  }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SystemUsbEvent {
+  /// Seconds since the Unix epoch, like hotplug history timestamps.
+  pub timestamp: u64,
+  pub channel: String,
+  pub provider: String,
+  pub event_id: u32,
+  pub level: EventLevel,
+  /// Name of the HayBox mode whose hardware ID the event mentions.
+  pub device: String,
+  pub device_instance_id: Option<String>,
+  /// The event's text as Event Viewer shows it, when the provider's
+  /// message resources could be loaded.
+  pub message: Option<String>,
+}
+
+#[cfg(target_os = "windows")]
+fn supported_devices() -> [&'static UsbDeviceInfo; 5] {
+  [
+    &DEVICES.default_mode,
+    &DEVICES.config_mode,
+    &DEVICES.bootsel_mode,
+    &DEVICES.switch_mode,
+    &DEVICES.gamecube_mode,
+  ]
+}
+
+#[cfg(target_os = "windows")]
+struct EventHandle(EVT_HANDLE);
+
+#[cfg(target_os = "windows")]
+impl Drop for EventHandle {
+  fn drop(&mut self) {
+    unsafe {
+      let _ = EvtClose(self.0);
+    }
+  }
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date.
+#[cfg(target_os = "windows")]
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+  let year = if month <= 2 { year - 1 } else { year };
+  let era = year.div_euclid(400);
+  let year_of_era = year - era * 400;
+  let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+  let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+  era * 146097 + day_of_era - 719468
+}
+
+/// Parses the UTC `SystemTime` attribute of an event, e.g.
+/// `2024-05-01T12:34:56.1234567Z`, to seconds since the Unix epoch.
+#[cfg(target_os = "windows")]
+fn parse_system_time(value: &str) -> Option<u64> {
+  let captures = SYSTEM_TIME_RE.captures(value)?;
+  let field = |index: usize| captures[index].parse::<i64>().ok();
+  let days = days_from_civil(field(1)?, field(2)?, field(3)?);
+  let seconds = days * 86400 + field(4)? * 3600 + field(5)? * 60 + field(6)?;
+  u64::try_from(seconds).ok()
+}
+
+#[cfg(target_os = "windows")]
+fn render_xml(event: &EventHandle) -> Result<String, HayboxError> {
+  let mut used = 0u32;
+  let mut count = 0u32;
+  let size_query = unsafe { EvtRender(None, event.0, EvtRenderEventXml.0, 0, None, &mut used, &mut count) };
+  if let Err(e) = size_query {
+    if e.code() != ERROR_INSUFFICIENT_BUFFER.to_hresult() {
+      return Err(HayboxError::Other(format!("Failed to render event: {}", e)));
+    }
+  }
+
+  let mut buffer = vec![0u16; (used as usize).div_ceil(2)];
+  unsafe {
+    EvtRender(
+      None,
+      event.0,
+      EvtRenderEventXml.0,
+      (buffer.len() * 2) as u32,
+      Some(buffer.as_mut_ptr() as *mut _),
+      &mut used,
+      &mut count,
+    )
+  }
+  .map_err(|e| HayboxError::Other(format!("Failed to render event: {}", e)))?;
+
+  let end = buffer.iter().position(|&c| c == 0).unwrap_or(buffer.len());
+  Ok(String::from_utf16_lossy(&buffer[..end]))
+}
+
+/// The event's description from the provider's message table.
+#[cfg(target_os = "windows")]
+fn format_message(provider: &str, event: &EventHandle) -> Option<String> {
+  let provider_wide = to_wide(provider);
+  let metadata =
+    unsafe { EvtOpenPublisherMetadata(None, PCWSTR(provider_wide.as_ptr()), PCWSTR::null(), 0, 0) }.ok()?;
+  let metadata = EventHandle(metadata);
+
+  let mut used = 0u32;
+  let _ = unsafe {
+    EvtFormatMessage(
+      Some(metadata.0),
+      Some(event.0),
+      0,
+      None,
+      EvtFormatMessageEvent.0,
+      None,
+      &mut used,
+    )
+  };
+  if used == 0 {
+    return None;
+  }
+  let mut buffer = vec![0u16; used as usize];
+  unsafe {
+    EvtFormatMessage(
+      Some(metadata.0),
+      Some(event.0),
+      0,
+      None,
+      EvtFormatMessageEvent.0,
+      Some(&mut buffer),
+      &mut used,
+    )
+  }
+  .ok()?;
+
+  let end = buffer.iter().position(|&c| c == 0).unwrap_or(buffer.len());
+  let message = String::from_utf16_lossy(&buffer[..end]).trim().to_string();
+  (!message.is_empty()).then_some(message)
+}
+
+/// Turns one rendered event into a `SystemUsbEvent` if it mentions a HayBox
+/// hardware ID.
+#[cfg(target_os = "windows")]
+fn parse_event(channel: &str, xml: &str) -> Option<SystemUsbEvent> {
+  let device = HARDWARE_ID_RE.captures_iter(xml).find_map(|captures| {
+    let vid = u16::from_str_radix(&captures[1], 16).ok()?;
+    let pid = u16::from_str_radix(&captures[2], 16).ok()?;
+    supported_devices()
+      .into_iter()
+      .find(|device| device.vid == vid && device.pid == pid)
+  })?;
+
+  Some(SystemUsbEvent {
+    timestamp: TIME_CREATED_RE
+      .captures(xml)
+      .and_then(|captures| parse_system_time(&captures[1]))
+      .unwrap_or_default(),
+    channel: channel.to_string(),
+    provider: PROVIDER_RE
+      .captures(xml)
+      .map(|captures| captures[1].to_string())
+      .unwrap_or_default(),
+    event_id: EVENT_ID_RE
+      .captures(xml)
+      .and_then(|captures| captures[1].parse().ok())
+      .unwrap_or_default(),
+    level: EventLevel::from_level(
+      LEVEL_RE
+        .captures(xml)
+        .and_then(|captures| captures[1].parse().ok())
+        .unwrap_or_default(),
+    ),
+    device: device.name.clone(),
+    device_instance_id: INSTANCE_ID_RE
+      .find(xml)
+      .map(|instance| instance.as_str().replace("&amp;", "&")),
+    message: None,
+  })
+}
+
+#[cfg(target_os = "windows")]
+fn query_channel(channel: &str, lookback_ms: u64) -> Result<Vec<SystemUsbEvent>, HayboxError> {
+  let channel_wide = to_wide(channel);
+  let query = to_wide(&format!(
+    "*[System[TimeCreated[timediff(@SystemTime) <= {}]]]",
+    lookback_ms
+  ));
+  let results = unsafe {
+    EvtQuery(
+      None,
+      PCWSTR(channel_wide.as_ptr()),
+      PCWSTR(query.as_ptr()),
+      EvtQueryChannelPath.0 | EvtQueryReverseDirection.0 | EvtQueryTolerateQueryErrors.0,
+    )
+  }
+  .map_err(|e| HayboxError::Other(format!("Failed to query {}: {}", channel, e)))?;
+  let results = EventHandle(results);
+
+  let mut events = Vec::new();
+  let mut read = 0;
+  while read < MAX_EVENTS_PER_CHANNEL {
+    let mut handles = [0isize; BATCH_SIZE];
+    let mut returned = 0u32;
+    if let Err(e) = unsafe { EvtNext(results.0, &mut handles, 0, 0, &mut returned) } {
+      if e.code() == ERROR_NO_MORE_ITEMS.to_hresult() {
+        break;
+      }
+      return Err(HayboxError::Other(format!("Failed to read {}: {}", channel, e)));
+    }
+
+    for handle in &handles[..returned as usize] {
+      let event = EventHandle(EVT_HANDLE(*handle));
+      let xml = match render_xml(&event) {
+        Ok(xml) => xml,
+        Err(e) => {
+          tracing::debug!("{}: {}", channel, e);
+          continue;
+        }
+      };
+      if let Some(mut parsed) = parse_event(channel, &xml) {
+        parsed.message = format_message(&parsed.provider, &event);
+        events.push(parsed);
+      }
+    }
+    read += returned as usize;
+  }
+
+  Ok(events)
+}
+
+/// Events the OS logged about HayBox devices since `since` (seconds since the
+/// Unix epoch, default the last 24 hours), newest first. Channels that are
+/// missing or unreadable are skipped so one disabled log does not hide the
+/// others.
+#[cfg(target_os = "windows")]
+pub fn get_system_usb_events(since: Option<u64>) -> Result<Vec<SystemUsbEvent>, HayboxError> {
+  let now = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_secs())
+    .unwrap_or_default();
+  let lookback_secs = since.map_or(DEFAULT_LOOKBACK_SECS, |since| now.saturating_sub(since));
+
+  let mut events = Vec::new();
+  let mut errors = Vec::new();
+  for channel in CHANNELS {
+    match query_channel(channel, lookback_secs * 1000) {
+      Ok(channel_events) => events.extend(channel_events),
+      Err(e) => {
+        tracing::warn!("{}", e);
+        errors.push(e.to_string());
+      }
+    }
+  }
+  if errors.len() == CHANNELS.len() {
+    return Err(HayboxError::Other(errors.join("; ")));
+  }
+
+  events.sort_by_key(|event| std::cmp::Reverse(event.timestamp));
+  Ok(events)
+}
+
+#[cfg(not(target_os = "windows"))]
+pub fn get_system_usb_events(_since: Option<u64>) -> Result<Vec<SystemUsbEvent>, HayboxError> {
+  Err(HayboxError::Unsupported(
+    "The Event Log is only available on Windows".to_string(),
+  ))
+}
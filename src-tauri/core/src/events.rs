@@ -0,0 +1,43 @@
+use std::sync::Arc;
+
+use serde::Serialize;
+
+/// Receives the progress and state updates that long-running code reports,
+/// such as flash progress or input monitor samples. The app forwards them to
+/// the webview; the CLI prints the ones it cares about.
+pub trait EventSink: Send + Sync {
+  fn emit(&self, event: &str, payload: serde_json::Value);
+}
+
+struct Discard;
+
+impl EventSink for Discard {
+  fn emit(&self, _event: &str, _payload: serde_json::Value) {}
+}
+
+/// A cheaply cloneable handle to an `EventSink`, passed to anything that
+/// reports events.
+#[derive(Clone)]
+pub struct Events {
+  sink: Arc<dyn EventSink>,
+}
+
+impl Events {
+  pub fn new(sink: impl EventSink + 'static) -> Self {
+    Events { sink: Arc::new(sink) }
+  }
+
+  /// Drops every event, for callers with nowhere to show them.
+  pub fn discard() -> Self {
+    Events::new(Discard)
+  }
+
+  /// Sends `payload` to the sink. A payload that cannot be serialized is
+  /// logged and dropped; no caller could do more with it.
+  pub fn emit<S: Serialize>(&self, event: &str, payload: S) {
+    match serde_json::to_value(payload) {
+      Ok(payload) => self.sink.emit(event, payload),
+      Err(e) => tracing::warn!("failed to serialize {} event: {}", event, e),
+    }
+  }
+}
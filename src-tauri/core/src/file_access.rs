@@ -0,0 +1,162 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::error::HayboxError;
+use crate::paths::app_data_dir;
+
+/// Well-known SID for BUILTIN\Administrators, so the grant works regardless
+/// of the Windows display language.
+const ADMINISTRATORS_SID: &str = "*S-1-5-32-544";
+/// Owner of protected system files. Service account names are not localized.
+const TRUSTED_INSTALLER: &str = "NT SERVICE\\TrustedInstaller";
+
+/// A file's DACL in SDDL form, as written by `icacls /save`.
+struct SavedAcl {
+  sddl: String,
+}
+
+fn run_tool(command: &mut Command, tool: &str) -> Result<(), HayboxError> {
+  let output = command
+    .output()
+    .map_err(|e| HayboxError::Io(format!("Failed to execute {}: {}", tool, e)))?;
+
+  if !output.status.success() {
+    let error_message = String::from_utf8_lossy(&output.stdout);
+    return Err(HayboxError::Other(format!("{} failed: {}", tool, error_message.trim())));
+  }
+  Ok(())
+}
+
+fn acl_file() -> PathBuf {
+  app_data_dir().join(format!("acl-{}.txt", std::process::id()))
+}
+
+/// `icacls /save` and `/restore` use UTF-16LE files.
+fn decode_utf16(bytes: &[u8]) -> String {
+  let units: Vec<u16> = bytes
+    .chunks_exact(2)
+    .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+    .collect();
+  String::from_utf16_lossy(&units)
+    .trim_start_matches('\u{feff}')
+    .to_string()
+}
+
+fn encode_utf16(text: &str) -> Vec<u8> {
+  text.encode_utf16().flat_map(|unit| unit.to_le_bytes()).collect()
+}
+
+fn save_acl(path: &Path) -> Result<SavedAcl, HayboxError> {
+  let file = acl_file();
+  run_tool(Command::new("icacls").arg(path).arg("/save").arg(&file), "icacls /save")?;
+
+  let content = std::fs::read(&file).map_err(|e| HayboxError::Io(format!("Failed to read saved ACL: {}", e)));
+  let _ = std::fs::remove_file(&file);
+
+  // The file holds the file name on one line and its SDDL on the next.
+  decode_utf16(&content?)
+    .lines()
+    .nth(1)
+    .map(|line| line.trim().to_string())
+    .filter(|line| !line.is_empty())
+    .map(|sddl| SavedAcl { sddl })
+    .ok_or_else(|| HayboxError::Io(format!("icacls /save returned no ACL for {}", path.display())))
+}
+
+/// Applies a saved DACL to `path`, which may differ from the file it was saved
+/// from, and hands ownership back to TrustedInstaller.
+fn restore_acl(path: &Path, acl: &SavedAcl) -> Result<(), HayboxError> {
+  let dir = path
+    .parent()
+    .ok_or_else(|| HayboxError::Other(format!("{} has no parent directory", path.display())))?;
+  let name = path
+    .file_name()
+    .ok_or_else(|| HayboxError::Other(format!("{} has no file name", path.display())))?;
+
+  let file = acl_file();
+  std::fs::write(
+    &file,
+    encode_utf16(&format!("{}\r\n{}\r\n", name.to_string_lossy(), acl.sddl)),
+  )
+  .map_err(|e| HayboxError::Io(format!("Failed to write saved ACL: {}", e)))?;
+
+  let result = run_tool(
+    Command::new("icacls").arg(dir).arg("/restore").arg(&file),
+    "icacls /restore",
+  );
+  let _ = std::fs::remove_file(&file);
+  result?;
+
+  run_tool(
+    Command::new("icacls").arg(path).arg("/setowner").arg(TRUSTED_INSTALLER),
+    "icacls /setowner",
+  )
+}
+
+fn take_ownership(path: &Path) -> Result<(), HayboxError> {
+  run_tool(Command::new("takeown").arg("/f").arg(path).arg("/a"), "takeown")?;
+  run_tool(
+    Command::new("icacls")
+      .arg(path)
+      .arg("/grant")
+      .arg(format!("{}:F", ADMINISTRATORS_SID)),
+    "icacls /grant",
+  )
+}
+
+/// Runs `op`, which modifies the protected file at `path`. If that is denied
+/// and `allow_takeown` is set, Administrators take ownership of the file, are
+/// granted full control and `op` is retried. The original DACL and
+/// TrustedInstaller ownership are then put back on `result_path`, where the
+/// file ends up afterwards (`None` when `op` deletes it).
+pub fn with_write_access<T>(
+  path: &Path,
+  result_path: Option<&Path>,
+  allow_takeown: bool,
+  op: impl Fn() -> io::Result<T>,
+) -> Result<T, HayboxError> {
+  let denied = match op() {
+    Ok(value) => return Ok(value),
+    Err(e) if e.kind() == io::ErrorKind::PermissionDenied && allow_takeown && path.exists() => e,
+    Err(e) => return Err(e.into()),
+  };
+
+  let acl = save_acl(path)
+    .map_err(|e| HayboxError::Io(format!("{}; saving the original permissions failed: {}", denied, e)))?;
+
+  if let Err(e) = take_ownership(path) {
+    let restored = restore_acl(path, &acl)
+      .err()
+      .map(|restore_error| format!("; restoring permissions failed: {}", restore_error))
+      .unwrap_or_default();
+    return Err(HayboxError::Io(format!(
+      "{}; taking ownership failed: {}{}",
+      denied, e, restored
+    )));
+  }
+
+  let result = op();
+  let restore_target = match result {
+    Ok(_) => result_path,
+    Err(_) => Some(path),
+  };
+  let restored = match restore_target {
+    Some(target) => restore_acl(target, &acl)
+      .map_err(|e| HayboxError::Io(format!("restoring permissions on {} failed: {}", target.display(), e))),
+    None => Ok(()),
+  };
+
+  match (result, restored) {
+    (Ok(value), Ok(())) => Ok(value),
+    (Ok(_), Err(e)) => Err(HayboxError::Io(format!(
+      "the change was made after taking ownership, but {}",
+      e
+    ))),
+    (Err(e), Ok(())) => Err(HayboxError::Io(format!("{} (after taking ownership)", e))),
+    (Err(e), Err(restore_error)) => Err(HayboxError::Io(format!(
+      "{} (after taking ownership); {}",
+      e, restore_error
+    ))),
+  }
+}
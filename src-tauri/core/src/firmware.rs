@@ -0,0 +1,347 @@
+use std::io::Read;
+use std::path::PathBuf;
+
+use regex::Regex;
+use rusb::UsbContext;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::config_proto;
+use crate::device_mode::DeviceMode;
+use crate::error::HayboxError;
+use crate::integrity::sha256_file;
+use crate::paths::app_data_dir;
+use crate::uf2::{self, RP2040_FAMILY_ID};
+
+pub const DEFAULT_REPO: &str = "JonnyHaystack/HayBox";
+pub const USER_AGENT: &str = concat!("haybox-debugger/", env!("CARGO_PKG_VERSION"));
+const CACHE_DIR: &str = "firmware_cache";
+const RELEASES_FILE: &str = "releases.json";
+/// Checksum files some forks attach to their releases.
+const CHECKSUM_ASSETS: &[&str] = &["SHA256SUMS", "SHA256SUMS.txt", "sha256sums.txt", "checksums.txt"];
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FirmwareAsset {
+  pub name: String,
+  pub size: u64,
+  pub download_url: String,
+  pub sha256: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FirmwareRelease {
+  pub repo: String,
+  pub tag: String,
+  pub name: Option<String>,
+  pub published_at: Option<String>,
+  pub prerelease: bool,
+  /// UF2 images only; one per board/variant.
+  pub assets: Vec<FirmwareAsset>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CachedFirmware {
+  pub path: String,
+  pub sha256: String,
+  /// Whether the release published a checksum that the download matched.
+  pub verified: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum VersionSource {
+  /// A version number found in the product or serial string descriptor.
+  UsbString,
+  /// The device info read over the config protocol.
+  ConfigProtocol,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DeviceFirmwareVersion {
+  pub device_mode: String,
+  pub version: Option<String>,
+  pub source: Option<VersionSource>,
+  /// Newest stable release in the local release cache.
+  pub latest_version: Option<String>,
+  pub update_available: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct GithubAsset {
+  name: String,
+  size: u64,
+  browser_download_url: String,
+  /// `sha256:<hex>`, present on assets uploaded since mid-2025.
+  digest: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GithubRelease {
+  tag_name: String,
+  name: Option<String>,
+  published_at: Option<String>,
+  prerelease: bool,
+  assets: Vec<GithubAsset>,
+}
+
+fn validate_repo(repo: &str) -> Result<(), HayboxError> {
+  let re = Regex::new(r"^[A-Za-z0-9_.-]+/[A-Za-z0-9_.-]+$").unwrap();
+  if !re.is_match(repo) || repo.contains("..") {
+    return Err(HayboxError::Other(format!(
+      "Invalid repository '{}'; expected owner/name",
+      repo
+    )));
+  }
+  Ok(())
+}
+
+fn get(url: &str) -> Result<ureq::Response, HayboxError> {
+  ureq::get(url)
+    .set("User-Agent", USER_AGENT)
+    .call()
+    .map_err(|e| HayboxError::Network(format!("Request to {} failed: {}", url, e)))
+}
+
+fn fetch_releases(repo: &str) -> Result<Vec<GithubRelease>, HayboxError> {
+  validate_repo(repo)?;
+  ureq::get(&format!("https://api.github.com/repos/{}/releases", repo))
+    .set("User-Agent", USER_AGENT)
+    .set("Accept", "application/vnd.github+json")
+    .call()
+    .map_err(|e| HayboxError::Network(format!("Failed to fetch releases for {}: {}", repo, e)))?
+    .into_json()
+    .map_err(|e| HayboxError::Other(format!("Failed to parse releases for {}: {}", repo, e)))
+}
+
+/// Parses a `sha256sum`-style list into (file name, hash) pairs.
+fn parse_checksums(content: &str) -> Vec<(String, String)> {
+  content
+    .lines()
+    .filter_map(|line| {
+      let mut parts = line.split_whitespace();
+      let hash = parts.next()?.to_lowercase();
+      let name = parts.next()?.trim_start_matches('*').to_string();
+      (hash.len() == 64).then_some((name, hash))
+    })
+    .collect()
+}
+
+fn release_checksums(release: &GithubRelease) -> Vec<(String, String)> {
+  release
+    .assets
+    .iter()
+    .filter(|asset| CHECKSUM_ASSETS.contains(&asset.name.as_str()))
+    .filter_map(|asset| get(&asset.browser_download_url).ok()?.into_string().ok())
+    .flat_map(|content| parse_checksums(&content))
+    .collect()
+}
+
+fn to_firmware_release(repo: &str, release: GithubRelease) -> FirmwareRelease {
+  let checksums = release_checksums(&release);
+
+  FirmwareRelease {
+    repo: repo.to_string(),
+    assets: release
+      .assets
+      .iter()
+      .filter(|asset| asset.name.to_lowercase().ends_with(".uf2"))
+      .map(|asset| FirmwareAsset {
+        name: asset.name.clone(),
+        size: asset.size,
+        download_url: asset.browser_download_url.clone(),
+        sha256: asset
+          .digest
+          .as_deref()
+          .and_then(|digest| digest.strip_prefix("sha256:"))
+          .map(|hash| hash.to_lowercase())
+          .or_else(|| {
+            checksums
+              .iter()
+              .find(|(name, _)| *name == asset.name)
+              .map(|(_, hash)| hash.clone())
+          }),
+      })
+      .collect(),
+    tag: release.tag_name,
+    name: release.name,
+    published_at: release.published_at,
+    prerelease: release.prerelease,
+  }
+}
+
+fn sanitize(value: &str) -> String {
+  value.replace(['/', '\\', ':'], "_")
+}
+
+fn repo_cache_dir(repo: &str) -> PathBuf {
+  app_data_dir().join(CACHE_DIR).join(sanitize(repo))
+}
+
+fn cache_path(repo: &str, tag: &str, asset_name: &str) -> PathBuf {
+  repo_cache_dir(repo).join(sanitize(tag)).join(sanitize(asset_name))
+}
+
+/// Releases of `repo` (HayBox by default) that ship at least one UF2 image,
+/// newest first. The list is cached for offline update checks.
+pub fn list_releases(repo: &str) -> Result<Vec<FirmwareRelease>, HayboxError> {
+  let releases: Vec<FirmwareRelease> = fetch_releases(repo)?
+    .into_iter()
+    .map(|release| to_firmware_release(repo, release))
+    .filter(|release| !release.assets.is_empty())
+    .collect();
+
+  let dir = repo_cache_dir(repo);
+  let saved = std::fs::create_dir_all(&dir)
+    .map_err(|e| e.to_string())
+    .and_then(|_| serde_json::to_string_pretty(&releases).map_err(|e| e.to_string()))
+    .and_then(|content| std::fs::write(dir.join(RELEASES_FILE), content).map_err(|e| e.to_string()));
+  if let Err(e) = saved {
+    tracing::warn!("failed to cache firmware releases: {}", e);
+  }
+
+  Ok(releases)
+}
+
+pub fn cached_releases(repo: &str) -> Vec<FirmwareRelease> {
+  std::fs::read_to_string(repo_cache_dir(repo).join(RELEASES_FILE))
+    .ok()
+    .and_then(|content| serde_json::from_str(&content).ok())
+    .unwrap_or_default()
+}
+
+/// Numeric components of the first `x.y[.z]` in `text`, e.g. `v3.1.0` -> [3, 1, 0].
+pub fn parse_version(text: &str) -> Option<Vec<u32>> {
+  let re = Regex::new(r"(\d+)\.(\d+)(?:\.(\d+))?").unwrap();
+  let caps = re.captures(text)?;
+  Some(
+    caps
+      .iter()
+      .skip(1)
+      .flatten()
+      .filter_map(|m| m.as_str().parse().ok())
+      .collect(),
+  )
+}
+
+/// Reads the connected controller's version over the config protocol when
+/// it is in config mode and connected, and otherwise from its USB string
+/// descriptors. `bcdDevice` is not a firmware version, so without either the
+/// version is unknown.
+pub fn read_device_version() -> Option<(String, Option<String>, Option<VersionSource>)> {
+  let context = rusb::Context::new().ok()?;
+  let devices = context.devices().ok()?;
+
+  devices.iter().find_map(|device| {
+    let desc = device.device_descriptor().ok()?;
+    let device_mode =
+      DeviceMode::from_ids(desc.vendor_id(), desc.product_id()).filter(|mode| DeviceMode::FIRMWARE.contains(mode))?;
+    let mode = device_mode.device()?;
+
+    let from_config = (device_mode == DeviceMode::Config)
+      .then(|| config_proto::with_connection(|conn| Ok(conn.device_info.firmware_version.clone())).ok())
+      .flatten()
+      .and_then(|text| parse_version(&text));
+    let from_strings = || {
+      device.open().ok().and_then(|handle| {
+        [desc.product_string_index(), desc.serial_number_string_index()]
+          .into_iter()
+          .flatten()
+          .filter_map(|index| handle.read_string_descriptor_ascii(index).ok())
+          .find_map(|text| parse_version(&text))
+      })
+    };
+
+    let (version, source) = match from_config {
+      Some(version) => (Some(version), Some(VersionSource::ConfigProtocol)),
+      None => match from_strings() {
+        Some(version) => (Some(version), Some(VersionSource::UsbString)),
+        None => (None, None),
+      },
+    };
+
+    let version = version.map(|parts| parts.iter().map(|part| part.to_string()).collect::<Vec<_>>().join("."));
+    Some((mode.name.clone(), version, source))
+  })
+}
+
+/// The connected controller's firmware version compared with the newest
+/// stable release of `repo` in the local cache.
+pub fn check_device_version(repo: &str) -> Result<DeviceFirmwareVersion, HayboxError> {
+  let (device_mode, version, source) = read_device_version()
+    .ok_or_else(|| HayboxError::DeviceNotConnected("No HayBox controller is connected".to_string()))?;
+
+  let latest_version = cached_releases(repo)
+    .into_iter()
+    .find(|release| !release.prerelease)
+    .map(|release| release.tag);
+
+  let update_available = match (
+    version.as_deref().and_then(parse_version),
+    latest_version.as_deref().and_then(parse_version),
+  ) {
+    (Some(current), Some(latest)) => latest > current,
+    _ => false,
+  };
+
+  Ok(DeviceFirmwareVersion {
+    device_mode,
+    version,
+    source,
+    latest_version,
+    update_available,
+  })
+}
+
+/// Downloads a release's UF2 asset into the local cache, checking it against
+/// the published checksum (if any) and the RP2040 UF2 format. A cached copy
+/// with the right hash is reused.
+pub fn download(repo: &str, tag: &str, asset_name: &str) -> Result<CachedFirmware, HayboxError> {
+  let release = fetch_releases(repo)?
+    .into_iter()
+    .find(|release| release.tag_name == tag)
+    .ok_or_else(|| HayboxError::Other(format!("Release {} not found in {}", tag, repo)))?;
+  let asset = to_firmware_release(repo, release)
+    .assets
+    .into_iter()
+    .find(|asset| asset.name == asset_name)
+    .ok_or_else(|| HayboxError::Other(format!("Release {} has no UF2 asset named {}", tag, asset_name)))?;
+
+  let path = cache_path(repo, tag, asset_name);
+  if let (Ok(cached), Some(expected)) = (sha256_file(&path), &asset.sha256) {
+    if cached == *expected {
+      return Ok(CachedFirmware {
+        path: path.display().to_string(),
+        sha256: cached,
+        verified: true,
+      });
+    }
+  }
+
+  let mut data = Vec::new();
+  get(&asset.download_url)?
+    .into_reader()
+    .read_to_end(&mut data)
+    .map_err(|e| HayboxError::Network(format!("Failed to download {}: {}", asset_name, e)))?;
+
+  let sha256 = format!("{:x}", Sha256::digest(&data));
+  if let Some(expected) = &asset.sha256 {
+    if sha256 != *expected {
+      return Err(HayboxError::Other(format!(
+        "Checksum mismatch for {}: expected {}, got {}",
+        asset_name, expected, sha256
+      )));
+    }
+  }
+  uf2::validate(&data, RP2040_FAMILY_ID)?;
+
+  if let Some(dir) = path.parent() {
+    std::fs::create_dir_all(dir).map_err(|e| HayboxError::Io(format!("Failed to create {}: {}", dir.display(), e)))?;
+  }
+  std::fs::write(&path, &data).map_err(|e| HayboxError::Io(format!("Failed to write {}: {}", path.display(), e)))?;
+
+  Ok(CachedFirmware {
+    path: path.display().to_string(),
+    verified: asset.sha256.is_some(),
+    sha256,
+  })
+}
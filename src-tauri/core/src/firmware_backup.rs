@@ -0,0 +1,113 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::error::HayboxError;
+use crate::events::Events;
+use crate::picoboot::{Picoboot, FLASH_START, READ_CHUNK};
+use crate::uf2::{self, RP2040_FAMILY_ID};
+
+/// The flash size of a Raspberry Pi Pico and most HayBox boards.
+const DEFAULT_FLASH_SIZE: u32 = 2 * 1024 * 1024;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupFormat {
+  Bin,
+  Uf2,
+}
+
+/// Emitted as `backup_progress` while flash is being read.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BackupProgress {
+  pub bytes_read: u64,
+  pub total_bytes: u64,
+  /// Whether this is the second pass that checks the saved file.
+  pub verifying: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FirmwareBackup {
+  pub path: String,
+  pub format: BackupFormat,
+  pub flash_bytes: u64,
+  /// Hash of the raw flash contents, whatever the file format.
+  pub sha256: String,
+  /// `None` when verification was not requested.
+  pub verified: Option<bool>,
+}
+
+fn read_flash(events: &Events, picoboot: &mut Picoboot, size: u32, verifying: bool) -> Result<Vec<u8>, HayboxError> {
+  let mut flash = vec![0u8; size as usize];
+  for (index, chunk) in flash.chunks_mut(READ_CHUNK).enumerate() {
+    let addr = FLASH_START + (index * READ_CHUNK) as u32;
+    picoboot
+      .read(addr, chunk)
+      .map_err(|e| HayboxError::Io(format!("Failed to read flash at 0x{:08X}: {}", addr, e)))?;
+
+    events.emit(
+      "backup_progress",
+      BackupProgress {
+        bytes_read: ((index + 1) * READ_CHUNK).min(size as usize) as u64,
+        total_bytes: size as u64,
+        verifying,
+      },
+    );
+  }
+  Ok(flash)
+}
+
+fn encode(flash: &[u8], format: BackupFormat) -> Vec<u8> {
+  match format {
+    BackupFormat::Bin => flash.to_vec(),
+    BackupFormat::Uf2 => uf2::encode(flash, FLASH_START, RP2040_FAMILY_ID),
+  }
+}
+
+/// Reads the controller's flash over PICOBOOT and saves it to `path`, as a
+/// UF2 file if the extension says so and a raw image otherwise. With
+/// `verify`, flash is read a second time and compared with the saved file.
+pub fn backup_firmware(
+  events: &Events,
+  path: &Path,
+  flash_size: Option<u32>,
+  verify: bool,
+) -> Result<FirmwareBackup, HayboxError> {
+  let format = match path.extension().and_then(|ext| ext.to_str()) {
+    Some(ext) if ext.eq_ignore_ascii_case("uf2") => BackupFormat::Uf2,
+    _ => BackupFormat::Bin,
+  };
+  let size = flash_size.unwrap_or(DEFAULT_FLASH_SIZE);
+  if size == 0 || !size.is_multiple_of(READ_CHUNK as u32) {
+    return Err(HayboxError::Other(format!(
+      "Flash size must be a non-zero multiple of {} bytes",
+      READ_CHUNK
+    )));
+  }
+
+  let mut picoboot = Picoboot::open()?;
+  picoboot.exclusive_access(true)?;
+  picoboot.exit_xip()?;
+
+  let flash = read_flash(events, &mut picoboot, size, false)?;
+  std::fs::write(path, encode(&flash, format))
+    .map_err(|e| HayboxError::Io(format!("Failed to write {}: {}", path.display(), e)))?;
+
+  let verified = if verify {
+    let saved =
+      std::fs::read(path).map_err(|e| HayboxError::Io(format!("Failed to read back {}: {}", path.display(), e)))?;
+    let reread = read_flash(events, &mut picoboot, size, true)?;
+    Some(saved == encode(&reread, format))
+  } else {
+    None
+  };
+
+  Ok(FirmwareBackup {
+    path: path.display().to_string(),
+    format,
+    flash_bytes: flash.len() as u64,
+    sha256: format!("{:x}", Sha256::digest(&flash)),
+    verified,
+  })
+}
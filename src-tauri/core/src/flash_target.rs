@@ -0,0 +1,93 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::dfu;
+use crate::error::HayboxError;
+use crate::events::Events;
+use crate::flashing::{self, FlashResult};
+use crate::is_device_connected_batch;
+use crate::uf2::{RP2040_FAMILY_ID, RP2350_FAMILY_IDS};
+
+/// Boot ROM and bootloader USB IDs, which identify the chip before any
+/// firmware runs.
+const RP2040_BOOTLOADER: (u16, u16) = (0x2E8A, 0x0003);
+const RP2350_BOOTLOADER: (u16, u16) = (0x2E8A, 0x000F);
+/// Atmel's factory DFU bootloader on the ATmega32U4.
+const ATMEGA32U4_DFU: (u16, u16) = (0x03EB, 0x2FF4);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FlashTarget {
+  Rp2040,
+  /// Pico 2 and other RP2350 boards.
+  Rp2350,
+  /// ATmega32U4 boards such as the original B0XX, flashed over DFU.
+  Atmega32u4,
+}
+
+pub const ALL_TARGETS: &[FlashTarget] = &[FlashTarget::Rp2040, FlashTarget::Rp2350, FlashTarget::Atmega32u4];
+
+impl FlashTarget {
+  pub fn bootloader_id(self) -> (u16, u16) {
+    match self {
+      FlashTarget::Rp2040 => RP2040_BOOTLOADER,
+      FlashTarget::Rp2350 => RP2350_BOOTLOADER,
+      FlashTarget::Atmega32u4 => ATMEGA32U4_DFU,
+    }
+  }
+
+  /// UF2 family IDs the boot ROM accepts; empty for DFU targets.
+  pub fn uf2_families(self) -> &'static [u32] {
+    match self {
+      FlashTarget::Rp2040 => &[RP2040_FAMILY_ID],
+      FlashTarget::Rp2350 => RP2350_FAMILY_IDS,
+      FlashTarget::Atmega32u4 => &[],
+    }
+  }
+
+  /// The `Board-ID` line in the boot volume's INFO_UF2.TXT.
+  pub fn board_id(self) -> Option<&'static str> {
+    match self {
+      FlashTarget::Rp2040 => Some("RPI-RP2"),
+      FlashTarget::Rp2350 => Some("RP2350"),
+      FlashTarget::Atmega32u4 => None,
+    }
+  }
+}
+
+/// Family IDs of every UF2 target, for checks made before the target is
+/// known.
+pub fn all_uf2_families() -> Vec<u32> {
+  ALL_TARGETS
+    .iter()
+    .flat_map(|target| target.uf2_families().iter().copied())
+    .collect()
+}
+
+/// The target whose bootloader is connected, if exactly one kind is.
+pub fn detect() -> Option<FlashTarget> {
+  let ids: Vec<(u16, u16)> = ALL_TARGETS.iter().map(|target| target.bootloader_id()).collect();
+  let connected = is_device_connected_batch(&ids);
+  let mut found = ALL_TARGETS
+    .iter()
+    .zip(connected)
+    .filter(|(_, connected)| *connected)
+    .map(|(target, _)| *target);
+  match (found.next(), found.next()) {
+    (Some(target), None) => Some(target),
+    _ => None,
+  }
+}
+
+/// Flashes `path` using whichever bootloader is connected: a UF2 copy for
+/// RP2040/RP2350, DFU for the ATmega32U4.
+pub fn flash_firmware(events: &Events, path: &Path) -> Result<FlashResult, HayboxError> {
+  match detect() {
+    Some(FlashTarget::Rp2040) | Some(FlashTarget::Rp2350) => flashing::flash_uf2(events, path),
+    Some(FlashTarget::Atmega32u4) => dfu::flash_hex(events, path),
+    None => Err(HayboxError::DeviceNotConnected(
+      "No bootloader found; put exactly one controller into BOOTSEL or DFU mode".to_string(),
+    )),
+  }
+}
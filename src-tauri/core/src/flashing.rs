@@ -0,0 +1,412 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use rusb::UsbContext;
+use serde::{Deserialize, Serialize};
+
+use crate::bootsel;
+use crate::device_mode::DeviceMode;
+use crate::error::HayboxError;
+use crate::events::Events;
+use crate::firmware;
+use crate::flash_target::{self, FlashTarget, ALL_TARGETS};
+use crate::operations;
+use crate::uf2;
+use crate::volumes;
+
+/// The RP2040 and RP2350 boot ROMs write this file to the root of their mass
+/// storage volume.
+const INFO_FILE: &str = "INFO_UF2.TXT";
+/// Blocks written between progress events.
+const PROGRESS_CHUNK_BLOCKS: usize = 64;
+const REENUMERATION_TIMEOUT: Duration = Duration::from_secs(30);
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+const VOLUME_TIMEOUT: Duration = Duration::from_secs(15);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FlashStage {
+  Validating,
+  Copying,
+  WaitingForDevice,
+  Verifying,
+  Done,
+}
+
+/// Emitted as `flash_progress` while a flash is running.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FlashProgress {
+  pub stage: FlashStage,
+  pub bytes_written: u64,
+  pub total_bytes: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FlashResult {
+  pub volume: String,
+  pub bytes_written: u64,
+  /// Name of the mode the controller came back in, if it did.
+  pub device_mode: Option<String>,
+  pub verification: FlashVerification,
+}
+
+/// Whether the controller runs the image that was just flashed, judged by
+/// the version embedded in the image and the one the device reports.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FlashVerification {
+  pub expected_version: Option<String>,
+  pub running_version: Option<String>,
+  /// `None` when either version is unknown.
+  pub matches: Option<bool>,
+  pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DropFlashStatus {
+  /// The file is valid but nothing is written until the user confirms and
+  /// the UI calls `flash_dropped_file`.
+  AwaitingConfirmation,
+  Flashed,
+  /// The controller is running its firmware; the UI should offer to reboot
+  /// it into BOOTSEL and call `flash_dropped_file` again.
+  NeedsBootsel,
+  NoDevice,
+  /// The file is not a UF2 image for a supported chip.
+  Rejected,
+  Failed,
+}
+
+/// Emitted as `dropped_file_flash` after a UF2 file is dropped on the window,
+/// and returned by `flash_dropped_file`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DroppedFileResult {
+  pub path: String,
+  pub status: DropFlashStatus,
+  pub message: String,
+  pub flash: Option<FlashResult>,
+}
+
+pub fn emit_progress(events: &Events, stage: FlashStage, bytes_written: u64, total_bytes: u64) {
+  if total_bytes > 0 {
+    operations::report_progress(bytes_written as f32 / total_bytes as f32);
+  }
+  events.emit(
+    "flash_progress",
+    FlashProgress {
+      stage,
+      bytes_written,
+      total_bytes,
+    },
+  );
+}
+
+/// The chip whose boot ROM mounted `root`, from the Board-ID in its
+/// INFO_UF2.TXT.
+pub fn volume_target(root: &Path) -> Option<FlashTarget> {
+  let info = std::fs::read_to_string(root.join(INFO_FILE)).ok()?;
+  ALL_TARGETS.iter().copied().find(|target| {
+    target
+      .board_id()
+      .is_some_and(|board_id| info.contains(&format!("Board-ID: {}", board_id)))
+  })
+}
+
+pub fn is_bootsel_volume(root: &Path) -> bool {
+  volume_target(root).is_some()
+}
+
+/// Mounted RPI-RP2 and RP2350 volumes, found by their INFO_UF2.TXT.
+pub fn find_bootsel_volumes() -> Vec<PathBuf> {
+  volumes::candidate_roots()
+    .into_iter()
+    .filter(|root| is_bootsel_volume(root))
+    .collect()
+}
+
+/// The single mounted RPI-RP2 volume; flashing more than one controller at a
+/// time is refused.
+fn single_bootsel_volume() -> Result<PathBuf, HayboxError> {
+  match find_bootsel_volumes().as_slice() {
+    [] => Err(HayboxError::DeviceNotConnected(
+      "No BOOTSEL drive found; is the controller in BOOTSEL mode?".to_string(),
+    )),
+    [volume] => Ok(volume.clone()),
+    _ => Err(HayboxError::Other(
+      "More than one BOOTSEL drive found; connect one controller at a time".to_string(),
+    )),
+  }
+}
+
+/// Waits for the OS to mount the boot volume, which can lag a few seconds
+/// behind the USB device itself.
+pub fn wait_for_bootsel_volume(timeout: Duration) -> Option<PathBuf> {
+  let deadline = Instant::now() + timeout;
+
+  while Instant::now() < deadline {
+    if let Ok(volume) = single_bootsel_volume() {
+      return Some(volume);
+    }
+    std::thread::sleep(POLL_INTERVAL);
+  }
+  None
+}
+
+/// Where a USB device is plugged in. Unlike its address this stays the same
+/// when the controller reboots from its bootloader into the firmware, so it
+/// tells the flashed controller apart from any other one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UsbPort {
+  bus: u8,
+  ports: Vec<u8>,
+}
+
+impl UsbPort {
+  pub fn of_device<T: UsbContext>(device: &rusb::Device<T>) -> Option<UsbPort> {
+    Some(UsbPort {
+      bus: device.bus_number(),
+      ports: device.port_numbers().ok()?,
+    })
+  }
+
+  /// The port of the connected UF2 bootloader, for flashing through its
+  /// volume.
+  pub fn of_bootloader() -> Result<UsbPort, HayboxError> {
+    let ids: Vec<(u16, u16)> = ALL_TARGETS
+      .iter()
+      .filter(|target| target.board_id().is_some())
+      .map(|target| target.bootloader_id())
+      .collect();
+    let context = rusb::Context::new().map_err(|e| HayboxError::Usb(format!("Failed to create USB context: {}", e)))?;
+    let devices = context
+      .devices()
+      .map_err(|e| HayboxError::Usb(format!("Failed to list USB devices: {}", e)))?;
+
+    devices
+      .iter()
+      .find(|device| {
+        device
+          .device_descriptor()
+          .is_ok_and(|desc| ids.contains(&(desc.vendor_id(), desc.product_id())))
+      })
+      .and_then(|device| UsbPort::of_device(&device))
+      .ok_or_else(|| HayboxError::DeviceNotConnected("Could not find the bootloader on the USB bus".to_string()))
+  }
+}
+
+/// Waits for the controller on `port` to show up in any mode other than
+/// BOOTSEL after a flash and returns that mode's name. Controllers on other
+/// ports are ignored.
+pub fn wait_for_reenumeration(port: &UsbPort, timeout: Duration) -> Option<String> {
+  let deadline = Instant::now() + timeout;
+
+  while Instant::now() < deadline {
+    if let Some(mode) = firmware_mode_on(port) {
+      return mode.device().map(|device| device.name.clone());
+    }
+    std::thread::sleep(POLL_INTERVAL);
+  }
+  None
+}
+
+fn firmware_mode_on(port: &UsbPort) -> Option<DeviceMode> {
+  let context = rusb::Context::new().ok()?;
+  let devices = context.devices().ok()?;
+
+  devices.iter().find_map(|device| {
+    if UsbPort::of_device(&device).as_ref() != Some(port) {
+      return None;
+    }
+    let desc = device.device_descriptor().ok()?;
+    DeviceMode::from_ids(desc.vendor_id(), desc.product_id()).filter(|mode| DeviceMode::FIRMWARE.contains(mode))
+  })
+}
+
+/// Copies UF2 data onto `volume` in chunks, reporting the bytes written so far
+/// after each one.
+pub fn copy_to_volume(
+  data: &[u8],
+  volume: &Path,
+  file_name: &std::ffi::OsStr,
+  mut on_progress: impl FnMut(u64),
+) -> Result<u64, HayboxError> {
+  let target = volume.join(file_name);
+  let mut file = std::fs::File::create(&target)
+    .map_err(|e| HayboxError::Io(format!("Failed to create {}: {}", target.display(), e)))?;
+
+  let mut bytes_written = 0u64;
+  for chunk in data.chunks(uf2::BLOCK_SIZE * PROGRESS_CHUNK_BLOCKS) {
+    file
+      .write_all(chunk)
+      .map_err(|e| HayboxError::Io(format!("Failed to write firmware to {}: {}", volume.display(), e)))?;
+    bytes_written += chunk.len() as u64;
+    on_progress(bytes_written);
+  }
+
+  // The boot ROM reboots as soon as the last block lands, so a failed flush
+  // here is expected and not an error.
+  let _ = file.sync_all();
+  Ok(bytes_written)
+}
+
+/// Validates `path` as a UF2 image for the mounted chip and copies it onto the BOOTSEL
+/// volume, emitting `flash_progress` per chunk. Returns the volume and the
+/// number of bytes written.
+pub fn copy_to_bootsel(events: &Events, path: &Path) -> Result<(PathBuf, u64), HayboxError> {
+  emit_progress(events, FlashStage::Validating, 0, 0);
+
+  let data = std::fs::read(path).map_err(|e| HayboxError::Io(format!("Failed to read {}: {}", path.display(), e)))?;
+  let volume = single_bootsel_volume()?;
+  let target = volume_target(&volume).unwrap_or(FlashTarget::Rp2040);
+  uf2::validate_for(&data, target.uf2_families())?;
+  let total_bytes = data.len() as u64;
+
+  let file_name = path
+    .file_name()
+    .ok_or_else(|| HayboxError::Other("Invalid firmware file name".to_string()))?;
+  let bytes_written = copy_to_volume(&data, &volume, file_name, |bytes_written| {
+    emit_progress(events, FlashStage::Copying, bytes_written, total_bytes)
+  })?;
+
+  Ok((volume, bytes_written))
+}
+
+pub fn verify_flash(expected_version: Option<String>, reenumerated: bool) -> FlashVerification {
+  if !reenumerated {
+    return FlashVerification {
+      expected_version,
+      running_version: None,
+      matches: Some(false),
+      message: "The controller did not come back after flashing".to_string(),
+    };
+  }
+
+  let running_version = firmware::read_device_version().and_then(|(_, version, _)| version);
+  let matches = match (
+    expected_version.as_deref().and_then(firmware::parse_version),
+    running_version.as_deref().and_then(firmware::parse_version),
+  ) {
+    (Some(expected), Some(running)) => Some(expected == running),
+    _ => None,
+  };
+
+  let message = match (matches, &expected_version, &running_version) {
+    (Some(true), _, Some(running)) => format!("Controller is running the flashed version {}", running),
+    (Some(false), Some(expected), Some(running)) => format!(
+      "Controller reports version {} but the image is {}; the copy may have failed and the old firmware is still running",
+      running, expected
+    ),
+    (_, None, _) => "The image does not embed a version; only re-enumeration was checked".to_string(),
+    _ => "The controller does not report its version; only re-enumeration was checked".to_string(),
+  };
+
+  FlashVerification {
+    expected_version,
+    running_version,
+    matches,
+    message,
+  }
+}
+
+/// Copies a validated UF2 file onto the BOOTSEL volume and waits for
+/// the controller to reboot into its firmware and checks it runs the flashed
+/// version, emitting `flash_progress` along the way.
+pub fn flash_uf2(events: &Events, path: &Path) -> Result<FlashResult, HayboxError> {
+  let port = UsbPort::of_bootloader()?;
+  let (volume, bytes_written) = copy_to_bootsel(events, path)?;
+
+  emit_progress(events, FlashStage::WaitingForDevice, bytes_written, bytes_written);
+  let device_mode = wait_for_reenumeration(&port, REENUMERATION_TIMEOUT);
+
+  emit_progress(events, FlashStage::Verifying, bytes_written, bytes_written);
+  let expected_version = std::fs::read(path)
+    .ok()
+    .and_then(|data| uf2::inspect(&data).ok())
+    .and_then(|inspection| inspection.binary_info.version);
+  let verification = verify_flash(expected_version, device_mode.is_some());
+  emit_progress(events, FlashStage::Done, bytes_written, bytes_written);
+
+  Ok(FlashResult {
+    volume: volume.display().to_string(),
+    bytes_written,
+    device_mode,
+    verification,
+  })
+}
+
+pub fn dropped_result(path: &Path, status: DropFlashStatus, message: String) -> DroppedFileResult {
+  DroppedFileResult {
+    path: path.display().to_string(),
+    status,
+    message,
+    flash: None,
+  }
+}
+
+fn validate_dropped_file(path: &Path) -> Result<(), HayboxError> {
+  let data = std::fs::read(path).map_err(|e| HayboxError::Io(format!("Failed to read {}: {}", path.display(), e)))?;
+  uf2::validate_for(&data, &flash_target::all_uf2_families()).map(|_| ())
+}
+
+/// Checks a UF2 file dropped onto the window without writing anything. The
+/// UI asks the user and calls `flash_dropped_file` to go ahead.
+pub fn check_dropped_file(path: &Path) -> DroppedFileResult {
+  match validate_dropped_file(path) {
+    Ok(()) => dropped_result(
+      path,
+      DropFlashStatus::AwaitingConfirmation,
+      format!(
+        "Flash {} to the controller?",
+        path.file_name().unwrap_or_default().to_string_lossy()
+      ),
+    ),
+    Err(e) => dropped_result(path, DropFlashStatus::Rejected, e.to_string()),
+  }
+}
+
+/// Flashes a UF2 file dropped onto the window once the user has confirmed.
+/// Without a BOOTSEL drive the file is only checked, unless
+/// `reboot_into_bootsel` allows rebooting a connected controller first.
+pub fn flash_dropped_file(events: &Events, path: &Path, reboot_into_bootsel: bool) -> DroppedFileResult {
+  if let Err(e) = validate_dropped_file(path) {
+    return dropped_result(path, DropFlashStatus::Rejected, e.to_string());
+  }
+
+  if find_bootsel_volumes().is_empty() {
+    if !DeviceMode::FIRMWARE.contains(&DeviceMode::current()) {
+      return dropped_result(
+        path,
+        DropFlashStatus::NoDevice,
+        "No controller found; hold BOOTSEL while plugging it in".to_string(),
+      );
+    }
+    if !reboot_into_bootsel {
+      return dropped_result(
+        path,
+        DropFlashStatus::NeedsBootsel,
+        "The controller is not in BOOTSEL mode; reboot it into BOOTSEL to flash this file?".to_string(),
+      );
+    }
+    if let Err(e) = bootsel::enter_bootsel_mode() {
+      return dropped_result(path, DropFlashStatus::Failed, e.to_string());
+    }
+    if wait_for_bootsel_volume(VOLUME_TIMEOUT).is_none() {
+      return dropped_result(
+        path,
+        DropFlashStatus::Failed,
+        "The controller rebooted, but no RPI-RP2 drive appeared".to_string(),
+      );
+    }
+  }
+
+  match flash_uf2(events, path) {
+    Ok(flash) => DroppedFileResult {
+      path: path.display().to_string(),
+      status: DropFlashStatus::Flashed,
+      message: format!("Flashed {} bytes to {}", flash.bytes_written, flash.volume),
+      flash: Some(flash),
+    },
+    Err(e) => dropped_result(path, DropFlashStatus::Failed, e.to_string()),
+  }
+}
@@ -0,0 +1,238 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::HayboxError;
+use crate::events::Events;
+use crate::input_monitor::{self, InputState};
+
+/// Melee's NTSC frame rate.
+const DEFAULT_FRAME_RATE: f64 = 59.94;
+/// How long after the window closes the second input still counts as a
+/// late attempt rather than no attempt.
+const DEFAULT_GRACE_FRAMES: u32 = 10;
+const RECV_TIMEOUT: Duration = Duration::from_millis(100);
+/// Attempts listed individually in the report.
+const RECENT_ATTEMPTS: usize = 20;
+
+/// The two inputs to time and the window the second must land in. Frames
+/// are whole frames after the first input's frame: 0 is the same frame, 1
+/// the next.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TrainerConfig {
+  /// HID button numbers, e.g. jump then airdodge.
+  pub first_button: u16,
+  pub second_button: u16,
+  pub window_start: u32,
+  pub window_end: u32,
+  pub frame_rate: Option<f64>,
+  pub grace_frames: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AttemptOutcome {
+  Early,
+  Hit,
+  Late,
+}
+
+/// Emitted as `frame_trainer_attempt` for every attempt.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TrainerAttempt {
+  /// Report timestamp of the first input, from the input monitor.
+  pub at_us: u64,
+  pub interval_us: u64,
+  /// Interval in frames, fractional.
+  pub frames: f64,
+  pub frame: u32,
+  pub outcome: AttemptOutcome,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FrameCount {
+  pub frame: u32,
+  pub count: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TrainerReport {
+  pub config: TrainerConfig,
+  pub attempts: usize,
+  pub hits: usize,
+  pub early: usize,
+  pub late: usize,
+  pub success_rate: f64,
+  pub mean_frames: Option<f64>,
+  pub stddev_frames: Option<f64>,
+  /// Attempts per whole frame, in frame order.
+  pub distribution: Vec<FrameCount>,
+  /// The most recent attempts, oldest first.
+  pub recent: Vec<TrainerAttempt>,
+}
+
+struct Trainer {
+  config: TrainerConfig,
+  attempts: Arc<Mutex<Vec<TrainerAttempt>>>,
+  stop: Arc<AtomicBool>,
+}
+
+lazy_static::lazy_static! {
+  static ref TRAINER: Mutex<Option<Trainer>> = Mutex::new(None);
+}
+
+fn frame_us(config: &TrainerConfig) -> f64 {
+  1_000_000.0 / config.frame_rate.unwrap_or(DEFAULT_FRAME_RATE)
+}
+
+fn classify(config: &TrainerConfig, at_us: u64, interval_us: u64) -> TrainerAttempt {
+  let frames = interval_us as f64 / frame_us(config);
+  let frame = frames.floor() as u32;
+  let outcome = if frame < config.window_start {
+    AttemptOutcome::Early
+  } else if frame > config.window_end {
+    AttemptOutcome::Late
+  } else {
+    AttemptOutcome::Hit
+  };
+  TrainerAttempt {
+    at_us,
+    interval_us,
+    frames,
+    frame,
+    outcome,
+  }
+}
+
+fn run(
+  events: Events,
+  receiver: Receiver<InputState>,
+  config: TrainerConfig,
+  attempts: Arc<Mutex<Vec<TrainerAttempt>>>,
+  stop: Arc<AtomicBool>,
+) {
+  let grace_frames = config.grace_frames.unwrap_or(DEFAULT_GRACE_FRAMES);
+  let give_up_us = ((config.window_end + 1 + grace_frames) as f64 * frame_us(&config)) as u64;
+  let mut held: Vec<u16> = Vec::new();
+  // Press time of the first input while waiting for the second.
+  let mut pending: Option<u64> = None;
+
+  while !stop.load(Ordering::Relaxed) {
+    let state = match receiver.recv_timeout(RECV_TIMEOUT) {
+      Ok(state) => state,
+      Err(RecvTimeoutError::Timeout) => continue,
+      Err(RecvTimeoutError::Disconnected) => break,
+    };
+    let now = state.timestamp_us;
+    let pressed = |button: u16| state.pressed.contains(&button) && !held.contains(&button);
+
+    if pending.is_some_and(|first| now.saturating_sub(first) > give_up_us) {
+      pending = None;
+    }
+    // A new first input restarts the attempt; both on one report is a
+    // zero interval.
+    if pressed(config.first_button) {
+      pending = Some(now);
+    }
+    if pressed(config.second_button) {
+      if let Some(first) = pending.take() {
+        let attempt = classify(&config, first, now.saturating_sub(first));
+        attempts.lock().unwrap().push(attempt.clone());
+        events.emit("frame_trainer_attempt", attempt);
+      }
+    }
+    held = state.pressed;
+  }
+}
+
+fn report(trainer: &Trainer) -> TrainerReport {
+  let attempts = trainer.attempts.lock().unwrap();
+  let count = |outcome: AttemptOutcome| attempts.iter().filter(|attempt| attempt.outcome == outcome).count();
+  let hits = count(AttemptOutcome::Hit);
+
+  let mut distribution: Vec<FrameCount> = Vec::new();
+  for attempt in attempts.iter() {
+    match distribution.iter_mut().find(|bucket| bucket.frame == attempt.frame) {
+      Some(bucket) => bucket.count += 1,
+      None => distribution.push(FrameCount {
+        frame: attempt.frame,
+        count: 1,
+      }),
+    }
+  }
+  distribution.sort_by_key(|bucket| bucket.frame);
+
+  let n = attempts.len() as f64;
+  let mean = (!attempts.is_empty()).then(|| attempts.iter().map(|attempt| attempt.frames).sum::<f64>() / n);
+  let stddev = mean.map(|mean| {
+    (attempts
+      .iter()
+      .map(|attempt| (attempt.frames - mean).powi(2))
+      .sum::<f64>()
+      / n)
+      .sqrt()
+  });
+
+  TrainerReport {
+    config: trainer.config.clone(),
+    attempts: attempts.len(),
+    hits,
+    early: count(AttemptOutcome::Early),
+    late: count(AttemptOutcome::Late),
+    success_rate: hits as f64 / attempts.len().max(1) as f64,
+    mean_frames: mean,
+    stddev_frames: stddev,
+    distribution,
+    recent: attempts[attempts.len().saturating_sub(RECENT_ATTEMPTS)..].to_vec(),
+  }
+}
+
+/// Times the gap between two button presses against a frame window, from
+/// the input monitor's report timestamps, so the USB polling is the only
+/// source of error. The input monitor must be running.
+pub fn start_frame_trainer(events: &Events, config: TrainerConfig) -> Result<(), HayboxError> {
+  if config.window_start > config.window_end {
+    return Err(HayboxError::Other("The window starts after it ends".to_string()));
+  }
+  if config.first_button == config.second_button {
+    return Err(HayboxError::Other("Choose two different buttons".to_string()));
+  }
+  if config.frame_rate.is_some_and(|rate| rate <= 0.0) {
+    return Err(HayboxError::Other("The frame rate must be positive".to_string()));
+  }
+  let receiver = input_monitor::subscribe()?;
+  stop_trainer();
+
+  let attempts = Arc::new(Mutex::new(Vec::new()));
+  let stop = Arc::new(AtomicBool::new(false));
+  *TRAINER.lock().unwrap() = Some(Trainer {
+    config: config.clone(),
+    attempts: attempts.clone(),
+    stop: stop.clone(),
+  });
+  let events = events.clone();
+  std::thread::spawn(move || run(events, receiver, config, attempts, stop));
+  Ok(())
+}
+
+fn stop_trainer() -> Option<Trainer> {
+  let trainer = TRAINER.lock().unwrap().take()?;
+  trainer.stop.store(true, Ordering::Relaxed);
+  Some(trainer)
+}
+
+pub fn get_frame_trainer_report() -> Result<TrainerReport, HayboxError> {
+  let trainer = TRAINER.lock().unwrap();
+  let trainer = trainer
+    .as_ref()
+    .ok_or_else(|| HayboxError::Other("The frame trainer is not running".to_string()))?;
+  Ok(report(trainer))
+}
+
+pub fn stop_frame_trainer() -> Result<TrainerReport, HayboxError> {
+  let trainer = stop_trainer().ok_or_else(|| HayboxError::Other("The frame trainer is not running".to_string()))?;
+  Ok(report(&trainer))
+}
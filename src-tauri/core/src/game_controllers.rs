@@ -0,0 +1,138 @@
+use serde::{Deserialize, Serialize};
+
+use crate::error::HayboxError;
+#[cfg(target_os = "windows")]
+use crate::pnp::parse_usb_ids;
+#[cfg(target_os = "windows")]
+use crate::registry::{delete_value, read_binary, read_string, write_binary, write_string, HKEY_CURRENT_USER};
+
+/// Where joy.cpl keeps the joystick ID assignment. ID 1 is the "preferred
+/// device" that legacy (WinMM) games pick up first.
+#[cfg(target_os = "windows")]
+const JOYSTICK_SETTINGS_KEY: &str =
+  "System\\CurrentControlSet\\Control\\MediaResources\\Joystick\\DINPUT.DLL\\CurrentJoystickSettings";
+/// Per-device OEM names DirectInput shows in joy.cpl, keyed by `VID_xxxx&PID_xxxx`.
+#[cfg(target_os = "windows")]
+const OEM_KEY: &str = "System\\CurrentControlSet\\Control\\MediaProperties\\PrivateProperties\\Joystick\\OEM";
+/// WinMM supports joystick IDs 1 to 16.
+const MAX_JOYSTICK_ID: u32 = 16;
+const PREFERRED_ID: u32 = 1;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ControllerSlot {
+  /// Joystick ID as shown by joy.cpl, starting at 1.
+  pub id: u32,
+  pub oem_name: String,
+  pub display_name: Option<String>,
+  pub vendor_id: Option<u16>,
+  pub product_id: Option<u16>,
+}
+
+#[cfg(target_os = "windows")]
+fn oem_name_value(id: u32) -> String {
+  format!("Joystick{}OEMName", id)
+}
+
+#[cfg(target_os = "windows")]
+fn configuration_value(id: u32) -> String {
+  format!("Joystick{}Configuration", id)
+}
+
+#[cfg(target_os = "windows")]
+fn read_slot(id: u32) -> Option<ControllerSlot> {
+  let oem_name = read_string(HKEY_CURRENT_USER, JOYSTICK_SETTINGS_KEY, &oem_name_value(id))?;
+  let ids = parse_usb_ids(&oem_name);
+
+  Some(ControllerSlot {
+    id,
+    display_name: read_string(HKEY_CURRENT_USER, &format!("{}\\{}", OEM_KEY, oem_name), "OEMName"),
+    vendor_id: ids.map(|ids| ids.vendor_id),
+    product_id: ids.map(|ids| ids.product_id),
+    oem_name,
+  })
+}
+
+#[cfg(target_os = "windows")]
+fn read_configuration(id: u32) -> Option<Vec<u8>> {
+  read_binary(HKEY_CURRENT_USER, JOYSTICK_SETTINGS_KEY, &configuration_value(id))
+}
+
+/// Controllers in joystick ID order, as joy.cpl lists them.
+pub fn list_controller_order() -> Vec<ControllerSlot> {
+  (1..=MAX_JOYSTICK_ID).filter_map(read_slot).collect()
+}
+
+/// Copies the name and configuration of a slot into joystick ID `id`, or
+/// clears `id` when `slot` is `None`.
+#[cfg(target_os = "windows")]
+fn write_slot(id: u32, slot: Option<(&str, Option<Vec<u8>>)>) -> Result<(), HayboxError> {
+  match slot {
+    Some((oem_name, configuration)) => {
+      write_string(HKEY_CURRENT_USER, JOYSTICK_SETTINGS_KEY, &oem_name_value(id), oem_name)?;
+      match configuration {
+        Some(configuration) => write_binary(
+          HKEY_CURRENT_USER,
+          JOYSTICK_SETTINGS_KEY,
+          &configuration_value(id),
+          &configuration,
+        ),
+        None => delete_value(HKEY_CURRENT_USER, JOYSTICK_SETTINGS_KEY, &configuration_value(id)),
+      }
+    }
+    None => {
+      delete_value(HKEY_CURRENT_USER, JOYSTICK_SETTINGS_KEY, &oem_name_value(id))?;
+      delete_value(HKEY_CURRENT_USER, JOYSTICK_SETTINGS_KEY, &configuration_value(id))
+    }
+  }
+}
+
+/// joy.cpl and WinMM joystick IDs only exist on Windows.
+#[cfg(not(target_os = "windows"))]
+fn read_slot(_id: u32) -> Option<ControllerSlot> {
+  None
+}
+
+#[cfg(not(target_os = "windows"))]
+fn read_configuration(_id: u32) -> Option<Vec<u8>> {
+  None
+}
+
+#[cfg(not(target_os = "windows"))]
+fn write_slot(_id: u32, _slot: Option<(&str, Option<Vec<u8>>)>) -> Result<(), HayboxError> {
+  Err(HayboxError::Unsupported(
+    "Joystick IDs can only be changed on Windows".to_string(),
+  ))
+}
+
+/// Makes the controller the preferred device by swapping its joystick ID with
+/// ID 1, the same change joy.cpl's "Advanced" dialog makes. Returns the new
+/// order.
+pub fn set_preferred_controller(vendor_id: u16, product_id: u16) -> Result<Vec<ControllerSlot>, HayboxError> {
+  let slots = list_controller_order();
+  let target = slots
+    .iter()
+    .find(|slot| slot.vendor_id == Some(vendor_id) && slot.product_id == Some(product_id))
+    .ok_or_else(|| {
+      HayboxError::DeviceNotConnected(format!(
+        "{:04X}:{:04X} has no joystick ID yet; connect it and open it once in Game Controllers",
+        vendor_id, product_id
+      ))
+    })?;
+
+  if target.id == PREFERRED_ID {
+    return Ok(slots);
+  }
+
+  let current = slots.iter().find(|slot| slot.id == PREFERRED_ID);
+
+  let target_configuration = read_configuration(target.id);
+  let current_configuration = current.and_then(|_| read_configuration(PREFERRED_ID));
+
+  write_slot(
+    target.id,
+    current.map(|slot| (slot.oem_name.as_str(), current_configuration)),
+  )?;
+  write_slot(PREFERRED_ID, Some((target.oem_name.as_str(), target_configuration)))?;
+
+  Ok(list_controller_order())
+}
@@ -0,0 +1,187 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::check_admin_rights;
+use crate::error::HayboxError;
+use crate::events::Events;
+use crate::hidhide;
+use crate::paths::app_data_dir;
+#[cfg(target_os = "windows")]
+use crate::wmi_worker;
+use crate::DEVICES;
+
+const PROFILES_FILE: &str = "game_profiles.json";
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct GameProfile {
+  pub exe_path: String,
+  /// Hide the controller's XInput/HID interface through HidHide while the
+  /// game is running.
+  pub hide_xinput: bool,
+  pub created_at: u64,
+}
+
+/// Emitted whenever the watcher hides or restores the controller.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GameProfileEvent {
+  pub hidden: bool,
+  pub running_games: Vec<String>,
+  pub error: Option<String>,
+  /// The change was skipped because HidHide only takes it from an
+  /// administrator; restarting the app elevated makes hiding work.
+  pub elevation_required: bool,
+}
+
+fn profiles_path() -> PathBuf {
+  app_data_dir().join(PROFILES_FILE)
+}
+
+pub fn load_profiles() -> Vec<GameProfile> {
+  std::fs::read_to_string(profiles_path())
+    .ok()
+    .and_then(|content| serde_json::from_str(&content).ok())
+    .unwrap_or_default()
+}
+
+fn save_profiles(profiles: &[GameProfile]) -> Result<(), HayboxError> {
+  let content = serde_json::to_string_pretty(profiles)
+    .map_err(|e| HayboxError::Other(format!("Failed to serialize game profiles: {}", e)))?;
+  std::fs::write(profiles_path(), content).map_err(|e| HayboxError::Io(format!("Failed to write game profiles: {}", e)))
+}
+
+/// Creates or replaces the profile for `exe_path`.
+pub fn create_profile(exe_path: &str, hide_xinput: bool) -> Result<GameProfile, HayboxError> {
+  if !std::path::Path::new(exe_path).exists() {
+    return Err(HayboxError::Other(format!("{} does not exist", exe_path)));
+  }
+
+  let profile = GameProfile {
+    exe_path: exe_path.to_string(),
+    hide_xinput,
+    created_at: SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .map(|d| d.as_secs())
+      .unwrap_or_default(),
+  };
+
+  let mut profiles = load_profiles();
+  profiles.retain(|p| !p.exe_path.eq_ignore_ascii_case(exe_path));
+  profiles.push(profile.clone());
+  save_profiles(&profiles)?;
+
+  Ok(profile)
+}
+
+pub fn delete_profile(exe_path: &str) -> Result<(), HayboxError> {
+  let mut profiles = load_profiles();
+  profiles.retain(|p| !p.exe_path.eq_ignore_ascii_case(exe_path));
+  save_profiles(&profiles)
+}
+
+#[cfg(target_os = "windows")]
+#[derive(Debug, Deserialize)]
+struct WmiProcess {
+  #[serde(rename = "ExecutablePath")]
+  executable_path: Option<String>,
+}
+
+#[cfg(not(target_os = "windows"))]
+fn running_executables() -> Result<HashSet<String>, HayboxError> {
+  Err(HayboxError::Unsupported(
+    "Game profiles are only available on Windows".to_string(),
+  ))
+}
+
+#[cfg(target_os = "windows")]
+fn running_executables() -> Result<HashSet<String>, HayboxError> {
+  let processes: Vec<WmiProcess> = wmi_worker::query("SELECT ExecutablePath FROM Win32_Process")?;
+
+  Ok(
+    processes
+      .into_iter()
+      .filter_map(|process| process.executable_path)
+      .map(|path| path.to_lowercase())
+      .collect(),
+  )
+}
+
+/// HidHide only takes changes from an administrator. Prompting from a
+/// background thread would pop UAC up in the middle of a game, so an
+/// unelevated app skips the change and reports it instead.
+fn apply_hiding(hide: bool) -> Result<(), HayboxError> {
+  if !check_admin_rights() {
+    return Err(HayboxError::ElevationRequired(
+      "Hiding the controller for a game needs the app to run as administrator".to_string(),
+    ));
+  }
+
+  let device = &DEVICES.default_mode;
+  if hide {
+    hidhide::hide_device(device.vid, device.pid).map(|_| ())
+  } else {
+    hidhide::unhide_device(device.vid, device.pid).map(|_| ())
+  }
+}
+
+/// Starts the background watcher that hides the controller while any game
+/// with `hide_xinput` is running and restores it once they have all exited.
+pub fn start_watcher(events: Events) {
+  std::thread::spawn(move || {
+    let mut hidden = false;
+    // Avoid retrying (and re-emitting) a change that already failed until the
+    // desired state flips again.
+    let mut failed_attempt: Option<bool> = None;
+
+    loop {
+      std::thread::sleep(POLL_INTERVAL);
+
+      let profiles: Vec<GameProfile> = load_profiles().into_iter().filter(|p| p.hide_xinput).collect();
+      if profiles.is_empty() && !hidden {
+        continue;
+      }
+
+      let running = match running_executables() {
+        Ok(running) => running,
+        Err(e) => {
+          tracing::warn!("failed to list processes: {}", e);
+          continue;
+        }
+      };
+
+      let running_games: Vec<String> = profiles
+        .into_iter()
+        .filter(|p| running.contains(&p.exe_path.to_lowercase()))
+        .map(|p| p.exe_path)
+        .collect();
+
+      let should_hide = !running_games.is_empty();
+      if should_hide == hidden || failed_attempt == Some(should_hide) {
+        continue;
+      }
+
+      let error = apply_hiding(should_hide).err();
+      let elevation_required = matches!(error, Some(HayboxError::ElevationRequired(_)));
+      let error = error.map(|e| e.to_string());
+      if error.is_none() {
+        hidden = should_hide;
+        failed_attempt = None;
+      } else {
+        failed_attempt = Some(should_hide);
+      }
+
+      events.emit(
+        "game_profile_applied",
+        GameProfileEvent {
+          hidden,
+          running_games,
+          error,
+          elevation_required,
+        },
+      );
+    }
+  });
+}
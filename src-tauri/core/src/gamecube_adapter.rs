@@ -0,0 +1,326 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use rusb::{DeviceHandle, UsbContext};
+use serde::{Deserialize, Serialize};
+
+use crate::error::HayboxError;
+use crate::events::Events;
+use crate::DEVICES;
+
+pub const INTERFACE: u8 = 0;
+const ENDPOINT_OUT: u8 = 0x02;
+pub const ENDPOINT_IN: u8 = 0x81;
+const TIMEOUT: Duration = Duration::from_millis(500);
+
+/// HID SET_REPORT-style request the adapter expects before it accepts any
+/// commands; Dolphin sends the same one.
+const INIT_REQUEST_TYPE: u8 = 0x21;
+const INIT_REQUEST: u8 = 0x0B;
+const INIT_VALUE: u16 = 0x0001;
+
+const CMD_START_POLLING: u8 = 0x13;
+const CMD_RUMBLE: u8 = 0x11;
+/// First byte of every input report.
+pub const INPUT_REPORT_ID: u8 = 0x21;
+const INPUT_REPORT_LEN: usize = 37;
+const PORT_COUNT: usize = 4;
+/// Each port's block in the input report: one status byte, then 8 bytes of
+/// buttons and axes.
+const PORT_BLOCK_LEN: usize = 9;
+/// Set in port 1's status byte when the second (grey) USB plug supplies
+/// rumble power.
+const RUMBLE_POWER_FLAG: u8 = 0x04;
+const RUMBLE_DURATION: Duration = Duration::from_millis(200);
+/// Button bits of a port block's two button bytes, in report order.
+pub const BUTTONS: [(usize, u8, &str); 12] = [
+  (1, 0x01, "A"),
+  (1, 0x02, "B"),
+  (1, 0x04, "X"),
+  (1, 0x08, "Y"),
+  (1, 0x10, "DLeft"),
+  (1, 0x20, "DRight"),
+  (1, 0x40, "DDown"),
+  (1, 0x80, "DUp"),
+  (2, 0x01, "Start"),
+  (2, 0x02, "Z"),
+  (2, 0x04, "R"),
+  (2, 0x08, "L"),
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AdapterPort {
+  /// 1 to 4, as printed on the adapter.
+  pub port: u8,
+  pub connected: bool,
+  pub wireless: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct AdapterTestResult {
+  /// The adapter answered the start-polling command with an input report.
+  pub polling: bool,
+  pub rumble_powered: bool,
+  /// Whether the rumble on/off commands were accepted.
+  pub rumble_sent: bool,
+  pub ports: Vec<AdapterPort>,
+  pub message: String,
+}
+
+/// Analog values of one controller, centered on 128 except the triggers.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct GcAnalog {
+  pub stick_x: u8,
+  pub stick_y: u8,
+  pub c_stick_x: u8,
+  pub c_stick_y: u8,
+  pub l_analog: u8,
+  pub r_analog: u8,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AdapterPortState {
+  pub port: u8,
+  pub connected: bool,
+  pub wireless: bool,
+  pub pressed: Vec<String>,
+  pub analog: GcAnalog,
+  /// The values seen when the controller was plugged in, which games take
+  /// as its neutral position. A stick pushed while plugging in shifts it.
+  pub origin: Option<GcAnalog>,
+}
+
+/// One adapter poll, emitted as `adapter_state`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AdapterState {
+  /// Microseconds since the monitor was started.
+  pub timestamp_us: u64,
+  pub rumble_powered: bool,
+  pub ports: Vec<AdapterPortState>,
+}
+
+/// Emitted as `adapter_monitor_closed` when the reader stops on its own.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AdapterMonitorClosed {
+  pub error: Option<String>,
+}
+
+lazy_static::lazy_static! {
+  static ref ADAPTER_MONITOR: Mutex<Option<(Arc<AtomicBool>, JoinHandle<()>)>> = Mutex::new(None);
+}
+
+fn parse_ports(report: &[u8]) -> Vec<AdapterPort> {
+  (0..PORT_COUNT)
+    .map(|i| {
+      let controller_type = report[1 + PORT_BLOCK_LEN * i] >> 4;
+      AdapterPort {
+        port: i as u8 + 1,
+        connected: controller_type != 0,
+        wireless: controller_type == 2,
+      }
+    })
+    .collect()
+}
+
+/// Opens and claims the adapter, initializes it the way Dolphin does and
+/// starts polling. Fails if the adapter cannot be opened, which usually
+/// means it is not bound to WinUSB.
+pub fn open_adapter() -> Result<DeviceHandle<rusb::Context>, HayboxError> {
+  let device = &DEVICES.gamecube_mode;
+  let context = rusb::Context::new().map_err(|e| HayboxError::Usb(format!("Failed to create USB context: {}", e)))?;
+  let handle = context
+    .open_device_with_vid_pid(device.vid, device.pid)
+    .ok_or_else(|| {
+      HayboxError::DeviceNotConnected(format!(
+        "Could not open the {}; is it connected and bound to WinUSB?",
+        device.name
+      ))
+    })?;
+
+  handle
+    .claim_interface(INTERFACE)
+    .map_err(|e| HayboxError::DeviceBusy(format!("Failed to claim adapter interface: {}", e)))?;
+
+  handle
+    .write_control(INIT_REQUEST_TYPE, INIT_REQUEST, INIT_VALUE, 0, &[], TIMEOUT)
+    .map_err(|e| HayboxError::Usb(format!("Adapter rejected the init request: {}", e)))?;
+
+  handle
+    .write_interrupt(ENDPOINT_OUT, &[CMD_START_POLLING], TIMEOUT)
+    .map_err(|e| HayboxError::Usb(format!("Failed to send the start polling command: {}", e)))?;
+  Ok(handle)
+}
+
+/// Starts polling, reads one input report and pulses rumble on every port.
+pub fn test_adapter() -> Result<AdapterTestResult, HayboxError> {
+  let handle = open_adapter()?;
+
+  let mut report = [0u8; INPUT_REPORT_LEN];
+  let read = handle
+    .read_interrupt(ENDPOINT_IN, &mut report, TIMEOUT)
+    .map_err(|e| HayboxError::Timeout(format!("Adapter did not answer the start polling command: {}", e)))?;
+
+  if read != INPUT_REPORT_LEN || report[0] != INPUT_REPORT_ID {
+    let _ = handle.release_interface(INTERFACE);
+    return Ok(AdapterTestResult {
+      message: format!(
+        "Adapter answered with an unexpected report ({} bytes, id 0x{:02X})",
+        read, report[0]
+      ),
+      ..Default::default()
+    });
+  }
+
+  let rumble_powered = report[1] & RUMBLE_POWER_FLAG != 0;
+  let rumble_on = handle.write_interrupt(ENDPOINT_OUT, &[CMD_RUMBLE, 1, 1, 1, 1], TIMEOUT);
+  std::thread::sleep(RUMBLE_DURATION);
+  let rumble_off = handle.write_interrupt(ENDPOINT_OUT, &[CMD_RUMBLE, 0, 0, 0, 0], TIMEOUT);
+  let _ = handle.release_interface(INTERFACE);
+
+  let rumble_sent = rumble_on.is_ok() && rumble_off.is_ok();
+  let ports = parse_ports(&report);
+  let connected = ports.iter().filter(|port| port.connected).count();
+
+  Ok(AdapterTestResult {
+    polling: true,
+    rumble_powered,
+    rumble_sent,
+    message: format!(
+      "Adapter is polling with {} controller(s) connected{}",
+      connected,
+      if rumble_powered {
+        ""
+      } else {
+        "; plug in the grey USB cable for rumble"
+      }
+    ),
+    ports,
+  })
+}
+
+/// Plays on/off rumble steps on every port. Returns how many commands the
+/// adapter accepted and whether it reported rumble power; rumble is always
+/// turned off at the end.
+pub fn play_rumble(steps: &[(bool, Duration)]) -> Result<(usize, bool), HayboxError> {
+  let handle = open_adapter()?;
+  let mut report = [0u8; INPUT_REPORT_LEN];
+  let rumble_powered = handle
+    .read_interrupt(ENDPOINT_IN, &mut report, TIMEOUT)
+    .is_ok_and(|read| read == INPUT_REPORT_LEN && report[1] & RUMBLE_POWER_FLAG != 0);
+
+  let mut accepted = 0;
+  for &(on, duration) in steps {
+    let motor = on as u8;
+    if handle
+      .write_interrupt(ENDPOINT_OUT, &[CMD_RUMBLE, motor, motor, motor, motor], TIMEOUT)
+      .is_ok()
+    {
+      accepted += 1;
+    }
+    std::thread::sleep(duration);
+  }
+  let _ = handle.write_interrupt(ENDPOINT_OUT, &[CMD_RUMBLE, 0, 0, 0, 0], TIMEOUT);
+  let _ = handle.release_interface(INTERFACE);
+  Ok((accepted, rumble_powered))
+}
+
+fn parse_state(report: &[u8], origins: &mut [Option<GcAnalog>; PORT_COUNT], timestamp_us: u64) -> AdapterState {
+  let ports = parse_ports(report)
+    .into_iter()
+    .enumerate()
+    .map(|(i, port)| {
+      let block = &report[1 + PORT_BLOCK_LEN * i..1 + PORT_BLOCK_LEN * (i + 1)];
+      let analog = GcAnalog {
+        stick_x: block[3],
+        stick_y: block[4],
+        c_stick_x: block[5],
+        c_stick_y: block[6],
+        l_analog: block[7],
+        r_analog: block[8],
+      };
+      if !port.connected {
+        origins[i] = None;
+      } else if origins[i].is_none() {
+        origins[i] = Some(analog);
+      }
+      AdapterPortState {
+        port: port.port,
+        connected: port.connected,
+        wireless: port.wireless,
+        pressed: if port.connected {
+          BUTTONS
+            .iter()
+            .filter(|(byte, mask, _)| block[*byte] & mask != 0)
+            .map(|(_, _, name)| name.to_string())
+            .collect()
+        } else {
+          Vec::new()
+        },
+        analog,
+        origin: origins[i],
+      }
+    })
+    .collect();
+
+  AdapterState {
+    timestamp_us,
+    rumble_powered: report[1] & RUMBLE_POWER_FLAG != 0,
+    ports,
+  }
+}
+
+fn adapter_read_loop(events: Events, handle: DeviceHandle<rusb::Context>, stop: Arc<AtomicBool>) {
+  let started = Instant::now();
+  let mut origins = [None; PORT_COUNT];
+  let mut report = [0u8; INPUT_REPORT_LEN];
+
+  let error = loop {
+    if stop.load(Ordering::Relaxed) {
+      break None;
+    }
+    match handle.read_interrupt(ENDPOINT_IN, &mut report, TIMEOUT) {
+      Ok(INPUT_REPORT_LEN) if report[0] == INPUT_REPORT_ID => {
+        let state = parse_state(&report, &mut origins, started.elapsed().as_micros() as u64);
+        events.emit("adapter_state", state);
+      }
+      Ok(_) | Err(rusb::Error::Timeout) => {}
+      Err(e) => break Some(e.to_string()),
+    }
+  };
+  let _ = handle.release_interface(INTERFACE);
+
+  if stop.load(Ordering::Relaxed) {
+    return;
+  }
+  let mut monitor = ADAPTER_MONITOR.lock().unwrap();
+  if monitor.as_ref().is_some_and(|(current, _)| Arc::ptr_eq(current, &stop)) {
+    *monitor = None;
+  }
+  events.emit("adapter_monitor_closed", AdapterMonitorClosed { error });
+}
+
+/// Streams the adapter's poll reports for all four ports as
+/// `adapter_state` events. The adapter must be bound to WinUSB.
+pub fn start_adapter_monitor(events: &Events) -> Result<(), HayboxError> {
+  stop_adapter_monitor();
+  let handle = open_adapter()?;
+
+  let stop = Arc::new(AtomicBool::new(false));
+  let thread_stop = stop.clone();
+  let events = events.clone();
+  let thread = std::thread::spawn(move || adapter_read_loop(events, handle, thread_stop));
+  *ADAPTER_MONITOR.lock().unwrap() = Some((stop, thread));
+  Ok(())
+}
+
+/// Stops the monitor and waits for it to release the adapter, so the test
+/// or a new monitor can claim it straight away.
+pub fn stop_adapter_monitor() {
+  let monitor = ADAPTER_MONITOR.lock().unwrap().take();
+  if let Some((stop, thread)) = monitor {
+    stop.store(true, Ordering::Relaxed);
+    let _ = thread.join();
+  }
+}
@@ -0,0 +1,260 @@
+use std::path::PathBuf;
+use std::process::Command;
+
+use serde::{Deserialize, Serialize};
+
+use crate::audit::AuditEntry;
+use crate::check_admin_rights;
+use crate::error::HayboxError;
+use crate::integrity::is_signed;
+use crate::pnp::list_replaceable_devices;
+#[cfg(target_os = "windows")]
+use crate::registry::{self, HKEY_LOCAL_MACHINE};
+use crate::resources::driver_resource_dir;
+
+/// Where the HidHide driver keeps its settings. Users can read it, unlike
+/// the control device HidHideCLI talks to.
+#[cfg(target_os = "windows")]
+const PARAMETERS_KEY: &str = "SYSTEM\\CurrentControlSet\\Services\\HidHide\\Parameters";
+/// HidHide installer bundled with the driver resources, if any.
+const HIDHIDE_INSTALLER: &str = "HidHide_setup.exe";
+/// Installer exit code when the driver was installed but needs a reboot.
+const ERROR_SUCCESS_REBOOT_REQUIRED: i32 = 3010;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct HidHideStatus {
+  pub installed: bool,
+  /// Whether hiding is switched on ("cloaking" in HidHide's terms).
+  pub active: bool,
+  pub hidden_devices: Vec<String>,
+  /// Applications that can still see hidden devices.
+  pub allowed_apps: Vec<String>,
+  /// Read from the driver's registry settings because HidHideCLI needs
+  /// elevation. Allowed apps are NT device paths then, e.g.
+  /// `\Device\HarddiskVolume3\Games\Slippi\Dolphin.exe`.
+  #[serde(default)]
+  pub from_registry: bool,
+}
+
+fn cli_path() -> Option<PathBuf> {
+  let program_files = std::env::var("ProgramFiles").unwrap_or_else(|_| "C:\\Program Files".to_string());
+  let path = PathBuf::from(program_files)
+    .join("Nefarius Software Solutions")
+    .join("HidHide")
+    .join("x64")
+    .join("HidHideCLI.exe");
+  path.exists().then_some(path)
+}
+
+fn run_cli(args: &[&str]) -> Result<String, HayboxError> {
+  let cli = cli_path().ok_or_else(|| HayboxError::Driver("HidHide is not installed".to_string()))?;
+
+  let output = Command::new(&cli)
+    .args(args)
+    .output()
+    .map_err(|e| HayboxError::Driver(format!("Failed to execute HidHideCLI: {}", e)))?;
+
+  let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+  if !output.status.success() {
+    let error_message = String::from_utf8_lossy(&output.stderr);
+    return Err(HayboxError::Driver(format!(
+      "HidHideCLI failed: {}",
+      error_message.trim()
+    )));
+  }
+  Ok(stdout)
+}
+
+/// The list commands print one entry per line, quoted when it has spaces.
+fn parse_list(output: &str) -> Vec<String> {
+  output
+    .lines()
+    .map(|line| {
+      let line = line.trim();
+      match (line.find('"'), line.rfind('"')) {
+        (Some(start), Some(end)) if end > start => line[start + 1..end].to_string(),
+        _ => line.to_string(),
+      }
+    })
+    .filter(|line| !line.is_empty())
+    .collect()
+}
+
+pub fn is_installed() -> bool {
+  cli_path().is_some()
+}
+
+/// The settings as the driver stores them, for when the CLI cannot be used
+/// unelevated.
+#[cfg(target_os = "windows")]
+fn registry_status() -> HidHideStatus {
+  HidHideStatus {
+    installed: true,
+    active: registry::read_dword(HKEY_LOCAL_MACHINE, PARAMETERS_KEY, "Active").is_some_and(|active| active != 0),
+    hidden_devices: registry::read_multi_string(HKEY_LOCAL_MACHINE, PARAMETERS_KEY, "BlacklistedDevices")
+      .unwrap_or_default(),
+    allowed_apps: registry::read_multi_string(HKEY_LOCAL_MACHINE, PARAMETERS_KEY, "WhitelistedApplications")
+      .unwrap_or_default(),
+    from_registry: true,
+  }
+}
+
+/// HidHide is Windows-only, so `is_installed` keeps this from being reached.
+#[cfg(not(target_os = "windows"))]
+fn registry_status() -> HidHideStatus {
+  HidHideStatus::default()
+}
+
+pub fn status() -> Result<HidHideStatus, HayboxError> {
+  if !is_installed() {
+    return Ok(HidHideStatus::default());
+  }
+  if !check_admin_rights() {
+    return Ok(registry_status());
+  }
+
+  Ok(HidHideStatus {
+    installed: true,
+    active: run_cli(&["--cloak-state"])?.contains("--cloak-on"),
+    hidden_devices: parse_list(&run_cli(&["--dev-list"])?),
+    allowed_apps: parse_list(&run_cli(&["--app-list"])?),
+    from_registry: false,
+  })
+}
+
+/// Runs the bundled HidHide installer silently. Returns whether a reboot is
+/// needed before HidHide takes effect.
+pub fn install() -> Result<bool, HayboxError> {
+  if !check_admin_rights() {
+    return Err(HayboxError::elevation_required());
+  }
+
+  if is_installed() {
+    return Ok(false);
+  }
+
+  let installer = driver_resource_dir()
+    .map(|dir| dir.join(HIDHIDE_INSTALLER))
+    .filter(|path| path.exists())
+    .ok_or_else(|| HayboxError::Driver("HidHide is not installed and no installer is bundled".to_string()))?;
+
+  if !is_signed(&installer) {
+    return Err(HayboxError::Driver(format!(
+      "{} does not carry a valid signature",
+      HIDHIDE_INSTALLER
+    )));
+  }
+
+  let output = Command::new(&installer)
+    .args(["/quiet", "/norestart"])
+    .output()
+    .map_err(|e| HayboxError::Driver(format!("Failed to run HidHide installer: {}", e)))?;
+
+  let reboot_required = output.status.code() == Some(ERROR_SUCCESS_REBOOT_REQUIRED);
+  let result = if output.status.success() || reboot_required {
+    Ok(reboot_required)
+  } else {
+    Err(HayboxError::Driver(format!(
+      "HidHide installer exited with {}",
+      output.status
+    )))
+  };
+
+  AuditEntry::new("install_hidhide")
+    .inf(HIDHIDE_INSTALLER)
+    .tool_output(&String::from_utf8_lossy(&output.stdout))
+    .outcome(&result)
+    .record();
+
+  result
+}
+
+/// HID device instances belonging to the USB device, i.e. what games open.
+fn hid_instances(vendor_id: u16, product_id: u16) -> Result<Vec<String>, HayboxError> {
+  let instances: Vec<String> = list_replaceable_devices()?
+    .into_iter()
+    .filter(|device| device.instance_id.to_uppercase().starts_with("HID\\"))
+    .filter(|device| {
+      device
+        .ids
+        .map(|ids| ids.vendor_id == vendor_id && ids.product_id == product_id)
+        .unwrap_or(false)
+    })
+    .map(|device| device.instance_id)
+    .collect();
+
+  if instances.is_empty() {
+    return Err(HayboxError::DeviceNotConnected(format!(
+      "No HID interfaces found for {:04X}:{:04X}; is the controller connected?",
+      vendor_id, product_id
+    )));
+  }
+  Ok(instances)
+}
+
+/// Adds every HID interface of the device to HidHide's blocklist and turns
+/// hiding on.
+pub fn hide_device(vendor_id: u16, product_id: u16) -> Result<Vec<String>, HayboxError> {
+  if !check_admin_rights() {
+    return Err(HayboxError::elevation_required());
+  }
+
+  let instances = hid_instances(vendor_id, product_id)?;
+  for instance in &instances {
+    run_cli(&["--dev-hide", instance])?;
+  }
+  run_cli(&["--cloak-on"])?;
+
+  AuditEntry::new("hidhide_hide_device")
+    .target(vendor_id, product_id)
+    .outcome(&Ok::<(), HayboxError>(()))
+    .record();
+
+  Ok(instances)
+}
+
+pub fn unhide_device(vendor_id: u16, product_id: u16) -> Result<Vec<String>, HayboxError> {
+  if !check_admin_rights() {
+    return Err(HayboxError::elevation_required());
+  }
+
+  let instances = hid_instances(vendor_id, product_id)?;
+  for instance in &instances {
+    run_cli(&["--dev-unhide", instance])?;
+  }
+
+  AuditEntry::new("hidhide_unhide_device")
+    .target(vendor_id, product_id)
+    .outcome(&Ok::<(), HayboxError>(()))
+    .record();
+
+  Ok(instances)
+}
+
+/// Lets `exe_path` keep seeing hidden devices.
+pub fn allow_app(exe_path: &str) -> Result<(), HayboxError> {
+  if !check_admin_rights() {
+    return Err(HayboxError::elevation_required());
+  }
+
+  if !std::path::Path::new(exe_path).exists() {
+    return Err(HayboxError::Other(format!("{} does not exist", exe_path)));
+  }
+  run_cli(&["--app-reg", exe_path]).map(|_| ())
+}
+
+pub fn disallow_app(exe_path: &str) -> Result<(), HayboxError> {
+  if !check_admin_rights() {
+    return Err(HayboxError::elevation_required());
+  }
+
+  run_cli(&["--app-unreg", exe_path]).map(|_| ())
+}
+
+pub fn set_active(active: bool) -> Result<(), HayboxError> {
+  if !check_admin_rights() {
+    return Err(HayboxError::elevation_required());
+  }
+
+  run_cli(&[if active { "--cloak-on" } else { "--cloak-off" }]).map(|_| ())
+}
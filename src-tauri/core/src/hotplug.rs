@@ -0,0 +1,147 @@
+use std::collections::{HashSet, VecDeque};
+use std::sync::{mpsc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use rusb::UsbContext;
+use serde::{Deserialize, Serialize};
+
+use crate::events::Events;
+
+/// libusb has no hotplug support on Windows, so arrivals and removals are
+/// found by diffing the device list on an interval.
+const POLL_INTERVAL: Duration = Duration::from_millis(1000);
+/// Events kept for diagnostics, enough to cover a few mode switches and
+/// replugs.
+const HISTORY_LENGTH: usize = 200;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum HotplugKind {
+  Arrived,
+  Removed,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HotplugEvent {
+  pub kind: HotplugKind,
+  pub vendor_id: u16,
+  pub product_id: u16,
+}
+
+/// An event and when it was seen, in seconds since the Unix epoch.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct HotplugRecord {
+  pub timestamp: u64,
+  #[serde(flatten)]
+  pub event: HotplugEvent,
+}
+
+type HotplugListener = Box<dyn Fn(&Events, &HotplugEvent) + Send>;
+
+lazy_static::lazy_static! {
+  static ref LISTENERS: Mutex<Vec<HotplugListener>> = Mutex::new(Vec::new());
+  static ref WAITERS: Mutex<Vec<mpsc::Sender<HotplugEvent>>> = Mutex::new(Vec::new());
+  static ref HISTORY: Mutex<VecDeque<HotplugRecord>> = Mutex::new(VecDeque::with_capacity(HISTORY_LENGTH));
+}
+
+/// Arrivals and removals since the app started, oldest first.
+pub fn history() -> Vec<HotplugRecord> {
+  HISTORY.lock().unwrap().iter().copied().collect()
+}
+
+fn record(event: HotplugEvent) {
+  let mut history = HISTORY.lock().unwrap();
+  if history.len() == HISTORY_LENGTH {
+    history.pop_front();
+  }
+  history.push_back(HotplugRecord {
+    timestamp: SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .map(|d| d.as_secs())
+      .unwrap_or_default(),
+    event,
+  });
+}
+
+/// Registers a callback run on the watcher thread for every event. Listeners
+/// should hand long work off to their own thread.
+pub fn subscribe(listener: impl Fn(&Events, &HotplugEvent) + Send + 'static) {
+  LISTENERS.lock().unwrap().push(Box::new(listener));
+}
+
+/// Collects hotplug events from the moment it is created, so nothing is
+/// missed between triggering a change and waiting for it.
+pub struct HotplugWaiter {
+  receiver: mpsc::Receiver<HotplugEvent>,
+}
+
+pub fn waiter() -> HotplugWaiter {
+  let (sender, receiver) = mpsc::channel();
+  WAITERS.lock().unwrap().push(sender);
+  HotplugWaiter { receiver }
+}
+
+impl HotplugWaiter {
+  /// Blocks until an event matching `matches` arrives, or returns `None` after
+  /// `timeout`.
+  pub fn wait(&self, timeout: Duration, matches: impl Fn(&HotplugEvent) -> bool) -> Option<HotplugEvent> {
+    let deadline = Instant::now() + timeout;
+    loop {
+      let remaining = deadline.checked_duration_since(Instant::now())?;
+      match self.receiver.recv_timeout(remaining) {
+        Ok(event) if matches(&event) => return Some(event),
+        Ok(_) => continue,
+        Err(_) => return None,
+      }
+    }
+  }
+}
+
+fn connected_devices() -> HashSet<(u16, u16)> {
+  let devices = match rusb::Context::new().and_then(|context| context.devices()) {
+    Ok(devices) => devices,
+    Err(_) => return HashSet::new(),
+  };
+
+  devices
+    .iter()
+    .filter_map(|device| device.device_descriptor().ok())
+    .map(|desc| (desc.vendor_id(), desc.product_id()))
+    .collect()
+}
+
+/// Starts the watcher thread. Every change is emitted as a `usb_hotplug`
+/// event and passed to the registered listeners.
+pub fn start(events: Events) {
+  std::thread::spawn(move || {
+    let mut known = connected_devices();
+
+    loop {
+      std::thread::sleep(POLL_INTERVAL);
+      let current = connected_devices();
+
+      let arrived = current.difference(&known).map(|ids| (HotplugKind::Arrived, *ids));
+      let removed = known.difference(&current).map(|ids| (HotplugKind::Removed, *ids));
+      let changes: Vec<HotplugEvent> = arrived
+        .chain(removed)
+        .map(|(kind, (vendor_id, product_id))| HotplugEvent {
+          kind,
+          vendor_id,
+          product_id,
+        })
+        .collect();
+
+      for event in &changes {
+        record(*event);
+        events.emit("usb_hotplug", event);
+        for listener in LISTENERS.lock().unwrap().iter() {
+          listener(&events, event);
+        }
+        // Dropped waiters have closed their receiver.
+        WAITERS.lock().unwrap().retain(|waiter| waiter.send(*event).is_ok());
+      }
+
+      known = current;
+    }
+  });
+}
@@ -0,0 +1,155 @@
+use std::path::PathBuf;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::driver::DriverKind;
+use crate::error::HayboxError;
+use crate::paths::app_data_dir;
+use crate::resources::driver_resource_dir;
+
+/// Placeholders `prepare_driver` knows how to fill in.
+const KNOWN_PLACEHOLDERS: &[&str] = &["VID", "PID", "MI", "DESCRIPTION", "MANUFACTURER"];
+/// Without these the generated INF would not match the selected device.
+const REQUIRED_PLACEHOLDERS: &[&str] = &["VID", "PID"];
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct InfTemplate {
+  pub kind: DriverKind,
+  pub content: String,
+  /// Loaded from the user's override rather than the shipped template.
+  pub customized: bool,
+}
+
+fn override_path(kind: DriverKind) -> PathBuf {
+  app_data_dir().join("inf_templates").join(kind.template_name())
+}
+
+/// Checks the placeholders and the basic INF structure. Every problem found is
+/// reported, one per line.
+pub fn validate_template(content: &str) -> Result<(), HayboxError> {
+  let placeholder_re = Regex::new(r"\{\{(\w*)\}\}").unwrap();
+  let mut errors = Vec::new();
+
+  for required in REQUIRED_PLACEHOLDERS {
+    if !content.contains(&format!("{{{{{}}}}}", required)) {
+      errors.push(format!("Missing required placeholder {{{{{}}}}}", required));
+    }
+  }
+
+  for captures in placeholder_re.captures_iter(content) {
+    if !KNOWN_PLACEHOLDERS.contains(&&captures[1]) {
+      errors.push(format!("Unknown placeholder {}", &captures[0]));
+    }
+  }
+
+  let mut current_section: Option<String> = None;
+  let mut has_signature = false;
+
+  for (index, raw_line) in content.lines().enumerate() {
+    let line_number = index + 1;
+    // Semicolons start a comment unless they are inside a quoted string.
+    let mut in_quotes = false;
+    let line: String = raw_line
+      .chars()
+      .take_while(|c| {
+        if *c == '"' {
+          in_quotes = !in_quotes;
+        }
+        in_quotes || *c != ';'
+      })
+      .collect();
+    let line = line.trim();
+
+    if line.is_empty() {
+      continue;
+    }
+
+    if in_quotes {
+      errors.push(format!("Line {}: unterminated quoted string", line_number));
+    }
+
+    if line.starts_with('[') {
+      match line.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+        Some(name) if !name.trim().is_empty() => current_section = Some(name.trim().to_lowercase()),
+        _ => errors.push(format!("Line {}: malformed section header", line_number)),
+      }
+      continue;
+    }
+
+    match current_section.as_deref() {
+      None => errors.push(format!("Line {}: entry outside of any section", line_number)),
+      Some("version") => {
+        if let Some((key, value)) = line.split_once('=') {
+          if key.trim().eq_ignore_ascii_case("Signature") {
+            let value = value.trim().trim_matches('"').to_lowercase();
+            has_signature = value == "$windows nt$" || value == "$chicago$";
+          }
+        }
+      }
+      Some(_) => {}
+    }
+  }
+
+  if !has_signature {
+    errors.push("[Version] section must declare Signature=\"$Windows NT$\"".to_string());
+  }
+
+  if errors.is_empty() {
+    Ok(())
+  } else {
+    Err(HayboxError::Other(errors.join("\n")))
+  }
+}
+
+/// Returns the user's override for `kind` if one is saved, otherwise the
+/// template shipped with the driver resources.
+pub fn load_template(kind: DriverKind) -> Result<Option<InfTemplate>, HayboxError> {
+  let custom = override_path(kind);
+  if custom.exists() {
+    let content = std::fs::read_to_string(&custom)
+      .map_err(|e| HayboxError::Driver(format!("Failed to read custom INF template: {}", e)))?;
+    return Ok(Some(InfTemplate {
+      kind,
+      content,
+      customized: true,
+    }));
+  }
+
+  let shipped = match driver_resource_dir() {
+    Some(dir) => dir.join(kind.template_name()),
+    None => return Ok(None),
+  };
+  if !shipped.exists() {
+    return Ok(None);
+  }
+
+  let content = std::fs::read_to_string(&shipped)
+    .map_err(|e| HayboxError::Driver(format!("Failed to read INF template: {}", e)))?;
+  Ok(Some(InfTemplate {
+    kind,
+    content,
+    customized: false,
+  }))
+}
+
+pub fn save_template(kind: DriverKind, content: &str) -> Result<(), HayboxError> {
+  validate_template(content)?;
+
+  let path = override_path(kind);
+  if let Some(parent) = path.parent() {
+    std::fs::create_dir_all(parent)
+      .map_err(|e| HayboxError::Io(format!("Failed to create template directory: {}", e)))?;
+  }
+  std::fs::write(&path, content).map_err(|e| HayboxError::Driver(format!("Failed to write custom INF template: {}", e)))
+}
+
+/// Deletes the override so the shipped template is used again.
+pub fn reset_template(kind: DriverKind) -> Result<(), HayboxError> {
+  let path = override_path(kind);
+  if path.exists() {
+    std::fs::remove_file(&path)
+      .map_err(|e| HayboxError::Driver(format!("Failed to remove custom INF template: {}", e)))?;
+  }
+  Ok(())
+}
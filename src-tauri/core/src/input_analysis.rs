@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::HayboxError;
+use crate::input_monitor::{self, InputState};
+use crate::input_recording;
+
+/// Nobody holds a button this long in play; it is stuck or shorted.
+const DEFAULT_HELD_THRESHOLD: Duration = Duration::from_secs(30);
+/// Real presses last tens of milliseconds. Anything this short is bounce or
+/// electrical noise that debouncing should have caught.
+const DEFAULT_SPURIOUS_PRESS: Duration = Duration::from_millis(3);
+const DEFAULT_LIVE_WINDOW: Duration = Duration::from_secs(30);
+const MAX_LIVE_WINDOW: Duration = Duration::from_secs(600);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct AnalysisOptions {
+  #[serde(default)]
+  pub held_threshold_ms: Option<u64>,
+  #[serde(default)]
+  pub spurious_press_us: Option<u64>,
+  /// HID button numbers that SOCD cleaning should never report together,
+  /// such as the left and right buttons of a d-pad.
+  #[serde(default)]
+  pub opposing_buttons: Vec<[u16; 2]>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FindingKind {
+  StuckButton,
+  SpuriousPress,
+  SocdConflict,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct InputFinding {
+  pub kind: FindingKind,
+  pub button: u16,
+  /// The opposing button, for SOCD conflicts.
+  pub other_button: Option<u16>,
+  /// When it started, in the frames' time base.
+  pub at_us: u64,
+  pub duration_us: u64,
+  pub message: String,
+}
+
+/// Flags suspicious patterns in a run of input frames, ordered by time.
+/// Frames only need to be kept when something changes, so recordings work
+/// as well as the live stream.
+pub fn analyze_frames(frames: &[InputState], options: &AnalysisOptions) -> Vec<InputFinding> {
+  let held_threshold = options
+    .held_threshold_ms
+    .map(Duration::from_millis)
+    .unwrap_or(DEFAULT_HELD_THRESHOLD)
+    .as_micros() as u64;
+  let spurious_press = options
+    .spurious_press_us
+    .map(Duration::from_micros)
+    .unwrap_or(DEFAULT_SPURIOUS_PRESS)
+    .as_micros() as u64;
+
+  let mut findings = Vec::new();
+  let mut held_since: HashMap<u16, u64> = HashMap::new();
+  let mut conflicts_since: HashMap<[u16; 2], u64> = HashMap::new();
+
+  let stuck = |button: u16, since: u64, duration: u64| InputFinding {
+    kind: FindingKind::StuckButton,
+    button,
+    other_button: None,
+    at_us: since,
+    duration_us: duration,
+    message: format!("Button {} was held for {:.1} s", button, duration as f64 / 1_000_000.0),
+  };
+  let conflict = |pair: [u16; 2], since: u64, duration: u64| InputFinding {
+    kind: FindingKind::SocdConflict,
+    button: pair[0],
+    other_button: Some(pair[1]),
+    at_us: since,
+    duration_us: duration,
+    message: format!(
+      "Opposing buttons {} and {} were reported together for {} us",
+      pair[0], pair[1], duration
+    ),
+  };
+
+  for frame in frames {
+    let now = frame.timestamp_us;
+
+    let released: Vec<u16> = held_since
+      .keys()
+      .filter(|button| !frame.pressed.contains(button))
+      .copied()
+      .collect();
+    for button in released {
+      let since = held_since.remove(&button).unwrap_or(now);
+      let duration = now.saturating_sub(since);
+      if duration < spurious_press {
+        findings.push(InputFinding {
+          kind: FindingKind::SpuriousPress,
+          button,
+          other_button: None,
+          at_us: since,
+          duration_us: duration,
+          message: format!("Button {} was pressed for only {} us", button, duration),
+        });
+      } else if duration >= held_threshold {
+        findings.push(stuck(button, since, duration));
+      }
+    }
+    for &button in &frame.pressed {
+      held_since.entry(button).or_insert(now);
+    }
+
+    for &pair in &options.opposing_buttons {
+      let both = frame.pressed.contains(&pair[0]) && frame.pressed.contains(&pair[1]);
+      match (both, conflicts_since.get(&pair).copied()) {
+        (true, None) => {
+          conflicts_since.insert(pair, now);
+        }
+        (false, Some(since)) => {
+          conflicts_since.remove(&pair);
+          findings.push(conflict(pair, since, now - since));
+        }
+        _ => {}
+      }
+    }
+  }
+
+  // Whatever is still down when the frames end.
+  let end = frames.last().map(|frame| frame.timestamp_us).unwrap_or_default();
+  for (button, since) in held_since {
+    if end - since >= held_threshold {
+      findings.push(stuck(button, since, end - since));
+    }
+  }
+  for (pair, since) in conflicts_since {
+    findings.push(conflict(pair, since, end - since));
+  }
+
+  findings.sort_by_key(|finding| finding.at_us);
+  findings
+}
+
+pub fn analyze_recording(path: &Path, options: &AnalysisOptions) -> Result<Vec<InputFinding>, HayboxError> {
+  let recording = input_recording::load_recording(path)?;
+  Ok(analyze_frames(&recording.frames, options))
+}
+
+/// Watches the running input monitor for `window` and analyzes what it saw.
+pub fn analyze_live_input(
+  window: Option<Duration>,
+  options: &AnalysisOptions,
+) -> Result<Vec<InputFinding>, HayboxError> {
+  let window = window.unwrap_or(DEFAULT_LIVE_WINDOW).min(MAX_LIVE_WINDOW);
+  let receiver = input_monitor::subscribe()?;
+
+  let deadline = Instant::now() + window;
+  let mut frames: Vec<InputState> = Vec::new();
+  loop {
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    if remaining.is_zero() {
+      break;
+    }
+    match receiver.recv_timeout(remaining) {
+      Ok(state) => frames.push(state),
+      Err(RecvTimeoutError::Timeout) => break,
+      // Analyze what arrived before the monitor stopped.
+      Err(RecvTimeoutError::Disconnected) => break,
+    }
+  }
+  if frames.is_empty() {
+    return Err(HayboxError::Other(
+      "No input reports arrived during the analysis".to_string(),
+    ));
+  }
+  Ok(analyze_frames(&frames, options))
+}
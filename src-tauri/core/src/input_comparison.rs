@@ -0,0 +1,209 @@
+use std::collections::{BTreeMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Instant;
+
+use hidapi::HidDevice;
+use serde::{Deserialize, Serialize};
+
+use crate::error::HayboxError;
+use crate::input_monitor::{self, ReportField};
+use crate::polling_rate::percentile;
+
+const READ_TIMEOUT_MS: i32 = 100;
+const MAX_REPORT: usize = 64;
+/// Presses further apart than this are not the same event on both units.
+const MATCH_WINDOW_US: u64 = 50_000;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ButtonTiming {
+  pub button: u16,
+  pub matched: usize,
+  /// Median of B's press time minus A's; positive when B is later.
+  pub median_delta_us: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ComparisonReport {
+  pub device_a: String,
+  pub device_b: String,
+  pub duration_us: u64,
+  pub presses_a: usize,
+  pub presses_b: usize,
+  pub matched: usize,
+  pub min_delta_us: Option<i64>,
+  pub mean_delta_us: Option<f64>,
+  pub median_delta_us: Option<i64>,
+  pub max_delta_us: Option<i64>,
+  pub p95_abs_delta_us: Option<u64>,
+  pub buttons: Vec<ButtonTiming>,
+}
+
+/// Press times per button, in microseconds since the session started.
+type Presses = Arc<Mutex<BTreeMap<u16, Vec<u64>>>>;
+
+struct Session {
+  started: Instant,
+  paths: [String; 2],
+  presses: [Presses; 2],
+  stop: Arc<AtomicBool>,
+  threads: Vec<JoinHandle<Option<String>>>,
+}
+
+lazy_static::lazy_static! {
+  static ref SESSION: Mutex<Option<Session>> = Mutex::new(None);
+}
+
+/// Records every press on one device until stopped. Returns the error that
+/// ended it early, if any.
+fn read_presses(
+  device: HidDevice,
+  fields: Vec<ReportField>,
+  started: Instant,
+  presses: Presses,
+  stop: Arc<AtomicBool>,
+) -> Option<String> {
+  let mut buffer = [0u8; MAX_REPORT];
+  let mut held: HashSet<u16> = HashSet::new();
+  while !stop.load(Ordering::Relaxed) {
+    let count = match device.read_timeout(&mut buffer, READ_TIMEOUT_MS) {
+      Ok(0) => continue,
+      Ok(count) => count,
+      Err(e) => return Some(e.to_string()),
+    };
+    let now = started.elapsed().as_micros() as u64;
+    let Some(state) = input_monitor::decode_report(&fields, &buffer[..count], now) else {
+      continue;
+    };
+    let pressed: HashSet<u16> = state.pressed.into_iter().collect();
+    let mut presses = presses.lock().unwrap();
+    for &button in pressed.difference(&held) {
+      presses.entry(button).or_default().push(now);
+    }
+    held = pressed;
+  }
+  None
+}
+
+/// Opens two controllers by HID path and timestamps their button presses on
+/// one clock, so the same presses on both can be compared. Press buttons on
+/// both units together, e.g. with a two-controller fixture or by bridging
+/// the same switch.
+pub fn start_comparison_session(path_a: &str, path_b: &str) -> Result<(), HayboxError> {
+  if path_a == path_b {
+    return Err(HayboxError::Other("Choose two different controllers".to_string()));
+  }
+  let (device_a, fields_a) = input_monitor::open_hid_path(path_a)?;
+  let (device_b, fields_b) = input_monitor::open_hid_path(path_b)?;
+  stop_session();
+
+  let started = Instant::now();
+  let stop = Arc::new(AtomicBool::new(false));
+  let presses: [Presses; 2] = Default::default();
+  let threads = [(device_a, fields_a), (device_b, fields_b)]
+    .into_iter()
+    .zip(presses.iter().cloned())
+    .map(|((device, fields), presses)| {
+      let stop = stop.clone();
+      std::thread::spawn(move || read_presses(device, fields, started, presses, stop))
+    })
+    .collect();
+
+  *SESSION.lock().unwrap() = Some(Session {
+    started,
+    paths: [path_a.to_string(), path_b.to_string()],
+    presses,
+    stop,
+    threads,
+  });
+  Ok(())
+}
+
+fn stop_session() -> Option<Session> {
+  let mut session = SESSION.lock().unwrap().take()?;
+  session.stop.store(true, Ordering::Relaxed);
+  for thread in session.threads.drain(..) {
+    if let Ok(Some(error)) = thread.join() {
+      tracing::warn!("comparison reader stopped early: {}", error);
+    }
+  }
+  Some(session)
+}
+
+/// Pairs each press on A with the closest unused press of the same button
+/// on B within the match window.
+fn match_presses(a: &[u64], b: &[u64]) -> Vec<i64> {
+  let mut used = vec![false; b.len()];
+  let mut deltas = Vec::new();
+  for &time in a {
+    let closest = b
+      .iter()
+      .enumerate()
+      .filter(|(index, &other)| !used[*index] && time.abs_diff(other) <= MATCH_WINDOW_US)
+      .min_by_key(|(_, &other)| time.abs_diff(other));
+    if let Some((index, &other)) = closest {
+      used[index] = true;
+      deltas.push(other as i64 - time as i64);
+    }
+  }
+  deltas
+}
+
+fn median(sorted: &[i64]) -> i64 {
+  sorted[sorted.len() / 2]
+}
+
+fn build_report(session: &Session) -> ComparisonReport {
+  let a = session.presses[0].lock().unwrap().clone();
+  let b = session.presses[1].lock().unwrap().clone();
+
+  let mut all: Vec<i64> = Vec::new();
+  let mut buttons = Vec::new();
+  for (button, times) in &a {
+    let mut deltas = match_presses(times, b.get(button).map(Vec::as_slice).unwrap_or_default());
+    if deltas.is_empty() {
+      continue;
+    }
+    deltas.sort_unstable();
+    buttons.push(ButtonTiming {
+      button: *button,
+      matched: deltas.len(),
+      median_delta_us: median(&deltas),
+    });
+    all.extend(deltas);
+  }
+  all.sort_unstable();
+  let mut abs: Vec<u64> = all.iter().map(|delta| delta.unsigned_abs()).collect();
+  abs.sort_unstable();
+
+  let any = !all.is_empty();
+  ComparisonReport {
+    device_a: session.paths[0].clone(),
+    device_b: session.paths[1].clone(),
+    duration_us: session.started.elapsed().as_micros() as u64,
+    presses_a: a.values().map(Vec::len).sum(),
+    presses_b: b.values().map(Vec::len).sum(),
+    matched: all.len(),
+    min_delta_us: all.first().copied(),
+    mean_delta_us: any.then(|| all.iter().sum::<i64>() as f64 / all.len() as f64),
+    median_delta_us: any.then(|| median(&all)),
+    max_delta_us: all.last().copied(),
+    p95_abs_delta_us: any.then(|| percentile(&abs, 95)),
+    buttons,
+  }
+}
+
+/// Timing so far, without ending the session.
+pub fn get_comparison_report() -> Result<ComparisonReport, HayboxError> {
+  let session = SESSION.lock().unwrap();
+  let session = session
+    .as_ref()
+    .ok_or_else(|| HayboxError::Other("No comparison session is running".to_string()))?;
+  Ok(build_report(session))
+}
+
+pub fn stop_comparison_session() -> Result<ComparisonReport, HayboxError> {
+  let session = stop_session().ok_or_else(|| HayboxError::Other("No comparison session is running".to_string()))?;
+  Ok(build_report(&session))
+}
@@ -0,0 +1,629 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use hidapi::{HidApi, HidDevice};
+use rusb::{DeviceHandle, Direction, TransferType, UsbContext};
+use serde::{Deserialize, Serialize};
+
+use crate::error::HayboxError;
+use crate::events::Events;
+use crate::report_descriptor::{self, ReportType};
+use crate::{analog_trace, gamecube_adapter, switch_health, usage_stats, UsbDeviceInfo, DEVICES};
+
+const READ_TIMEOUT_MS: i32 = 100;
+/// Full-speed interrupt endpoints carry at most 64 bytes per report.
+const MAX_REPORT: usize = 64;
+
+const USAGE_PAGE_GENERIC_DESKTOP: u16 = 0x01;
+const USAGE_PAGE_BUTTON: u16 = 0x09;
+const USAGE_HAT_SWITCH: u16 = 0x39;
+/// Joystick and gamepad top-level collections.
+const GAME_CONTROLLER_USAGES: [u16; 2] = [0x04, 0x05];
+/// Class, subclass and protocol of an XInput controller's data interface.
+const XINPUT_INTERFACE: (u8, u8, u8) = (0xFF, 0x5D, 0x01);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldKind {
+  Button,
+  Axis,
+  Hat,
+}
+
+/// One input value in a report, from the report descriptor.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReportField {
+  pub report_id: u8,
+  /// Bits from the start of the report data, after the report id byte.
+  pub bit_offset: u32,
+  pub bit_size: u32,
+  pub usage_page: u16,
+  pub usage: u16,
+  pub logical_min: i32,
+  pub logical_max: i32,
+  pub kind: FieldKind,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AxisValue {
+  pub name: String,
+  pub value: i32,
+  /// `value` scaled to 0.0..=1.0 over the logical range.
+  pub normalized: f32,
+}
+
+/// A decoded input report, emitted as `input_state`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct InputState {
+  /// Microseconds since the monitor was started.
+  pub timestamp_us: u64,
+  pub report_id: u8,
+  /// Numbers of the buttons held, from 1.
+  pub pressed: Vec<u16>,
+  pub axes: Vec<AxisValue>,
+  /// 0 is up, counting clockwise; `None` when centered.
+  pub hat: Option<u8>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HidDeviceEntry {
+  /// Opaque OS path, unique per interface even for identical controllers.
+  pub path: String,
+  pub vendor_id: u16,
+  pub product_id: u16,
+  pub product: Option<String>,
+  pub serial_number: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct InputMonitorInfo {
+  pub vendor_id: u16,
+  pub product_id: u16,
+  pub product: Option<String>,
+  pub fields: Vec<ReportField>,
+}
+
+/// Emitted as `input_monitor_closed` when the reader stops on its own.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct InputMonitorClosed {
+  pub error: Option<String>,
+}
+
+/// Where the monitor reads reports from.
+enum Reader {
+  Hid(HidDevice),
+  /// An interrupt IN endpoint read through libusb, or WinUSB on Windows, for
+  /// interfaces without a HID descriptor.
+  Usb {
+    handle: DeviceHandle<rusb::Context>,
+    interface: u8,
+    endpoint: u8,
+  },
+}
+
+impl Reader {
+  /// Reads one report; 0 when none arrived in time.
+  fn read(&self, buffer: &mut [u8]) -> Result<usize, String> {
+    match self {
+      Reader::Hid(device) => device.read_timeout(buffer, READ_TIMEOUT_MS).map_err(|e| e.to_string()),
+      Reader::Usb { handle, endpoint, .. } => {
+        match handle.read_interrupt(*endpoint, buffer, Duration::from_millis(READ_TIMEOUT_MS as u64)) {
+          Ok(count) => Ok(count),
+          Err(rusb::Error::Timeout) => Ok(0),
+          Err(e) => Err(e.to_string()),
+        }
+      }
+    }
+  }
+}
+
+impl Drop for Reader {
+  fn drop(&mut self) {
+    if let Reader::Usb { handle, interface, .. } = self {
+      let _ = handle.release_interface(*interface);
+    }
+  }
+}
+
+/// An opened controller interface and the fields its reports hold.
+struct Source {
+  reader: Reader,
+  vendor_id: u16,
+  product_id: u16,
+  product: Option<String>,
+  serial_number: Option<String>,
+  fields: Vec<ReportField>,
+}
+
+struct Monitor {
+  vendor_id: u16,
+  product_id: u16,
+  /// What `InputState::timestamp_us` counts from.
+  started: Instant,
+  stop: Arc<AtomicBool>,
+}
+
+lazy_static::lazy_static! {
+  static ref MONITOR: Mutex<Option<Monitor>> = Mutex::new(None);
+  /// Analyses reading the stream alongside the viewer. Dropped receivers
+  /// are pruned on the next report.
+  static ref SUBSCRIBERS: Mutex<Vec<Sender<InputState>>> = Mutex::new(Vec::new());
+  static ref LATEST: Mutex<Option<InputState>> = Mutex::new(None);
+}
+
+/// Lists the input fields of a report descriptor. Array inputs, as used by
+/// keyboards, are skipped; gamepads report everything as variables.
+pub fn parse_report_descriptor(descriptor: &[u8]) -> Result<Vec<ReportField>, HayboxError> {
+  let fields: Vec<ReportField> = report_descriptor::decode_report_descriptor(descriptor)?
+    .fields
+    .into_iter()
+    .filter(|field| field.report_type == ReportType::Input && field.variable && !field.constant)
+    .map(|field| ReportField {
+      report_id: field.report_id,
+      bit_offset: field.bit_offset,
+      bit_size: field.bit_size,
+      usage_page: field.usage_page,
+      usage: field.usage,
+      logical_min: field.logical_min,
+      logical_max: field.logical_max,
+      kind: match (field.usage_page, field.usage) {
+        (USAGE_PAGE_BUTTON, _) => FieldKind::Button,
+        (USAGE_PAGE_GENERIC_DESKTOP, USAGE_HAT_SWITCH) => FieldKind::Hat,
+        _ => FieldKind::Axis,
+      },
+    })
+    .collect();
+
+  if fields.is_empty() {
+    return Err(HayboxError::Other("Report descriptor has no input fields".to_string()));
+  }
+  Ok(fields)
+}
+
+fn read_bits(data: &[u8], offset: u32, size: u32) -> Option<u32> {
+  if size == 0 || size > 32 || (offset + size).div_ceil(8) as usize > data.len() {
+    return None;
+  }
+  let mut value: u64 = 0;
+  for bit in 0..size {
+    let position = offset + bit;
+    if data[(position / 8) as usize] & (1 << (position % 8)) != 0 {
+      value |= 1 << bit;
+    }
+  }
+  Some(value as u32)
+}
+
+fn axis_name(field: &ReportField) -> String {
+  match (field.usage_page, field.usage) {
+    (USAGE_PAGE_GENERIC_DESKTOP, 0x30) => "X".to_string(),
+    (USAGE_PAGE_GENERIC_DESKTOP, 0x31) => "Y".to_string(),
+    (USAGE_PAGE_GENERIC_DESKTOP, 0x32) => "Z".to_string(),
+    (USAGE_PAGE_GENERIC_DESKTOP, 0x33) => "Rx".to_string(),
+    (USAGE_PAGE_GENERIC_DESKTOP, 0x34) => "Ry".to_string(),
+    (USAGE_PAGE_GENERIC_DESKTOP, 0x35) => "Rz".to_string(),
+    (USAGE_PAGE_GENERIC_DESKTOP, 0x36) => "Slider".to_string(),
+    (USAGE_PAGE_GENERIC_DESKTOP, 0x37) => "Dial".to_string(),
+    (page, usage) => format!("{:04X}:{:04X}", page, usage),
+  }
+}
+
+/// Decodes one report as read from the device, report id byte included
+/// when the descriptor uses report ids.
+pub fn decode_report(fields: &[ReportField], report: &[u8], timestamp_us: u64) -> Option<InputState> {
+  let uses_ids = fields.iter().any(|field| field.report_id != 0);
+  let (report_id, data) = if uses_ids {
+    let (&id, data) = report.split_first()?;
+    (id, data)
+  } else {
+    (0, report)
+  };
+
+  let mut state = InputState {
+    timestamp_us,
+    report_id,
+    pressed: Vec::new(),
+    axes: Vec::new(),
+    hat: None,
+  };
+  let mut matched = false;
+  for field in fields.iter().filter(|field| field.report_id == report_id) {
+    let Some(raw) = read_bits(data, field.bit_offset, field.bit_size) else {
+      continue;
+    };
+    matched = true;
+    let value = if field.logical_min < 0 && field.bit_size < 32 {
+      let shift = 32 - field.bit_size;
+      ((raw << shift) as i32) >> shift
+    } else {
+      raw as i32
+    };
+
+    match field.kind {
+      FieldKind::Button => {
+        if value != 0 {
+          state.pressed.push(field.usage);
+        }
+      }
+      FieldKind::Hat => {
+        if (field.logical_min..=field.logical_max).contains(&value) {
+          state.hat = Some((value - field.logical_min) as u8);
+        }
+      }
+      FieldKind::Axis => {
+        let range = (field.logical_max - field.logical_min) as f32;
+        state.axes.push(AxisValue {
+          name: axis_name(field),
+          value,
+          normalized: if range > 0.0 {
+            ((value - field.logical_min) as f32 / range).clamp(0.0, 1.0)
+          } else {
+            0.0
+          },
+        });
+      }
+    }
+  }
+  matched.then_some(state)
+}
+
+fn read_loop(events: Events, reader: Reader, fields: Vec<ReportField>, started: Instant, stop: Arc<AtomicBool>) {
+  let mut buffer = [0u8; MAX_REPORT];
+
+  let error = loop {
+    if stop.load(Ordering::Relaxed) {
+      return;
+    }
+    let count = match reader.read(&mut buffer) {
+      Ok(0) => continue,
+      Ok(count) => count,
+      Err(e) => break Some(e),
+    };
+    let timestamp_us = started.elapsed().as_micros() as u64;
+    if let Some(state) = decode_report(&fields, &buffer[..count], timestamp_us) {
+      switch_health::observe(&state);
+      usage_stats::observe(&state);
+      analog_trace::observe(&events, &state);
+      *LATEST.lock().unwrap() = Some(state.clone());
+      SUBSCRIBERS
+        .lock()
+        .unwrap()
+        .retain(|subscriber| subscriber.send(state.clone()).is_ok());
+      events.emit("input_state", state);
+    }
+  };
+  drop(reader);
+
+  let mut monitor = MONITOR.lock().unwrap();
+  if monitor
+    .as_ref()
+    .is_some_and(|monitor| Arc::ptr_eq(&monitor.stop, &stop))
+  {
+    *monitor = None;
+    SUBSCRIBERS.lock().unwrap().clear();
+    usage_stats::finish();
+  }
+  events.emit("input_monitor_closed", InputMonitorClosed { error });
+}
+
+fn read_fields(device: &HidDevice) -> Result<Vec<ReportField>, HayboxError> {
+  parse_report_descriptor(&report_descriptor::read_descriptor(device)?)
+}
+
+/// Game controllers on the HID bus: joysticks and gamepads.
+pub fn list_hid_devices() -> Result<Vec<HidDeviceEntry>, HayboxError> {
+  let api = HidApi::new().map_err(|e| HayboxError::Hid(format!("Failed to initialize HID: {}", e)))?;
+  Ok(
+    api
+      .device_list()
+      .filter(|info| info.usage_page() == USAGE_PAGE_GENERIC_DESKTOP && GAME_CONTROLLER_USAGES.contains(&info.usage()))
+      .map(|info| HidDeviceEntry {
+        path: info.path().to_string_lossy().into_owned(),
+        vendor_id: info.vendor_id(),
+        product_id: info.product_id(),
+        product: info.product_string().map(str::to_string),
+        serial_number: info.serial_number().map(str::to_string),
+      })
+      .collect(),
+  )
+}
+
+/// Opens a device from `list_hid_devices` by path, for readers other than
+/// the monitor.
+pub fn open_hid_path(path: &str) -> Result<(HidDevice, Vec<ReportField>), HayboxError> {
+  let api = HidApi::new().map_err(|e| HayboxError::Hid(format!("Failed to initialize HID: {}", e)))?;
+  let c_path = std::ffi::CString::new(path).map_err(|_| HayboxError::Other(format!("Invalid HID path {}", path)))?;
+  let device = api
+    .open_path(&c_path)
+    .map_err(|e| HayboxError::Hid(format!("Failed to open {}: {}", path, e)))?;
+  let fields = read_fields(&device)?;
+  Ok((device, fields))
+}
+
+fn usb_field(report_id: u8, bit_offset: u32, bit_size: u32, usage: (u16, u16), range: (i32, i32)) -> ReportField {
+  ReportField {
+    report_id,
+    bit_offset,
+    bit_size,
+    usage_page: usage.0,
+    usage: usage.1,
+    logical_min: range.0,
+    logical_max: range.1,
+    kind: if usage.0 == USAGE_PAGE_BUTTON {
+      FieldKind::Button
+    } else {
+      FieldKind::Axis
+    },
+  }
+}
+
+/// The XInput input report: type and length bytes, 16 button bits, the
+/// triggers a byte each, then the stick axes as signed 16-bit values.
+fn xinput_fields() -> Vec<ReportField> {
+  let mut fields: Vec<ReportField> = (0..16)
+    .map(|bit| usb_field(0, 16 + bit, 1, (USAGE_PAGE_BUTTON, bit as u16 + 1), (0, 1)))
+    .collect();
+  fields.push(usb_field(0, 32, 8, (USAGE_PAGE_GENERIC_DESKTOP, 0x32), (0, 255)));
+  fields.push(usb_field(0, 40, 8, (USAGE_PAGE_GENERIC_DESKTOP, 0x35), (0, 255)));
+  for (index, usage) in [0x30, 0x31, 0x33, 0x34].into_iter().enumerate() {
+    fields.push(usb_field(
+      0,
+      48 + 16 * index as u32,
+      16,
+      (USAGE_PAGE_GENERIC_DESKTOP, usage),
+      (i16::MIN as i32, i16::MAX as i32),
+    ));
+  }
+  fields
+}
+
+/// Port 1 of a GameCube adapter report, after the report id: a status byte,
+/// two button bytes, then the sticks and triggers a byte each.
+fn adapter_fields() -> Vec<ReportField> {
+  let report_id = gamecube_adapter::INPUT_REPORT_ID;
+  let mut fields: Vec<ReportField> = gamecube_adapter::BUTTONS
+    .iter()
+    .enumerate()
+    .map(|(index, &(byte, mask, _))| {
+      usb_field(
+        report_id,
+        byte as u32 * 8 + mask.trailing_zeros(),
+        1,
+        (USAGE_PAGE_BUTTON, index as u16 + 1),
+        (0, 1),
+      )
+    })
+    .collect();
+  for (index, usage) in [0x30, 0x31, 0x33, 0x34, 0x32, 0x35].into_iter().enumerate() {
+    fields.push(usb_field(
+      report_id,
+      (3 + index as u32) * 8,
+      8,
+      (USAGE_PAGE_GENERIC_DESKTOP, usage),
+      (0, 255),
+    ));
+  }
+  fields
+}
+
+fn open_hid(candidates: &[(u16, u16)]) -> Result<Option<Source>, HayboxError> {
+  let api = HidApi::new().map_err(|e| HayboxError::Hid(format!("Failed to initialize HID: {}", e)))?;
+  let Some(info) = api
+    .device_list()
+    .find(|info| candidates.contains(&(info.vendor_id(), info.product_id())))
+  else {
+    return Ok(None);
+  };
+  let device = info
+    .open_device(&api)
+    .map_err(|e| HayboxError::Hid(format!("Failed to open HID device: {}", e)))?;
+  let fields = read_fields(&device)?;
+  Ok(Some(Source {
+    reader: Reader::Hid(device),
+    vendor_id: info.vendor_id(),
+    product_id: info.product_id(),
+    product: info.product_string().map(str::to_string),
+    serial_number: info.serial_number().map(str::to_string),
+    fields,
+  }))
+}
+
+/// Claims the XInput data interface of a controller in its default mode,
+/// which has no HID interface once WinUSB is bound to it.
+fn open_xinput() -> Result<Source, HayboxError> {
+  let target = &DEVICES.default_mode;
+  let context = rusb::Context::new().map_err(|e| HayboxError::Usb(format!("Failed to create USB context: {}", e)))?;
+  let device = context
+    .devices()
+    .map_err(|e| HayboxError::Usb(format!("Failed to list USB devices: {}", e)))?
+    .iter()
+    .find(|device| {
+      device
+        .device_descriptor()
+        .is_ok_and(|desc| (desc.vendor_id(), desc.product_id()) == (target.vid, target.pid))
+    })
+    .ok_or_else(|| HayboxError::DeviceNotConnected(format!("No {} found", target.name)))?;
+  let config = device
+    .active_config_descriptor()
+    .map_err(|e| HayboxError::Usb(format!("Failed to read the configuration descriptor: {}", e)))?;
+  let (interface, endpoint) = config
+    .interfaces()
+    .flat_map(|interface| interface.descriptors())
+    .filter(|alt| (alt.class_code(), alt.sub_class_code(), alt.protocol_code()) == XINPUT_INTERFACE)
+    .find_map(|alt| {
+      alt
+        .endpoint_descriptors()
+        .find(|ep| ep.direction() == Direction::In && ep.transfer_type() == TransferType::Interrupt)
+        .map(|ep| (alt.interface_number(), ep.address()))
+    })
+    .ok_or_else(|| HayboxError::Usb(format!("The {} has no XInput interface", target.name)))?;
+
+  let handle = device.open().map_err(|e| {
+    HayboxError::Usb(format!(
+      "Failed to open the {} over USB; on Windows its XInput interface needs WinUSB bound: {}",
+      target.name, e
+    ))
+  })?;
+  // Linux binds xpad to the interface; not supported, and not needed, on Windows.
+  let _ = handle.set_auto_detach_kernel_driver(true);
+  handle
+    .claim_interface(interface)
+    .map_err(|e| HayboxError::DeviceBusy(format!("Failed to claim the XInput interface: {}", e)))?;
+  Ok(Source {
+    reader: Reader::Usb {
+      handle,
+      interface,
+      endpoint,
+    },
+    vendor_id: target.vid,
+    product_id: target.pid,
+    product: Some(target.name.clone()),
+    serial_number: None,
+    fields: xinput_fields(),
+  })
+}
+
+/// Claims a controller in GameCube adapter mode, or a real adapter, and
+/// monitors its port 1.
+fn open_adapter() -> Result<Source, HayboxError> {
+  let target = &DEVICES.gamecube_mode;
+  let handle = gamecube_adapter::open_adapter()?;
+  Ok(Source {
+    reader: Reader::Usb {
+      handle,
+      interface: gamecube_adapter::INTERFACE,
+      endpoint: gamecube_adapter::ENDPOINT_IN,
+    },
+    vendor_id: target.vid,
+    product_id: target.pid,
+    product: Some(target.name.clone()),
+    serial_number: None,
+    fields: adapter_fields(),
+  })
+}
+
+/// Opens the first of `candidates` found: over HID when it has a HID
+/// interface, otherwise through interrupt reads of its XInput interface or,
+/// in GameCube adapter mode, the adapter endpoint.
+fn open_source(candidates: &[(u16, u16)]) -> Result<Source, HayboxError> {
+  if let Some(source) = open_hid(candidates)? {
+    return Ok(source);
+  }
+  let wanted = |device: &UsbDeviceInfo| candidates.contains(&(device.vid, device.pid));
+  if wanted(&DEVICES.default_mode) {
+    match open_xinput() {
+      Err(HayboxError::DeviceNotConnected(_)) => {}
+      result => return result,
+    }
+  }
+  if wanted(&DEVICES.gamecube_mode) {
+    match open_adapter() {
+      Err(HayboxError::DeviceNotConnected(_)) => {}
+      result => return result,
+    }
+  }
+  Err(HayboxError::DeviceNotConnected(
+    "No HayBox HID, XInput or GameCube adapter interface found".to_string(),
+  ))
+}
+
+/// Opens the controller, the first HayBox found unless `vid`/`pid` are
+/// given, and streams its reports as `input_state` events. A controller
+/// hidden with HidHide has to be unhidden for the app first.
+pub fn start_input_monitor(
+  events: &Events,
+  vid: Option<u16>,
+  pid: Option<u16>,
+) -> Result<InputMonitorInfo, HayboxError> {
+  let candidates = match (vid, pid) {
+    (Some(vid), Some(pid)) => vec![(vid, pid)],
+    _ => vec![
+      (DEVICES.default_mode.vid, DEVICES.default_mode.pid),
+      (DEVICES.switch_mode.vid, DEVICES.switch_mode.pid),
+      (DEVICES.gamecube_mode.vid, DEVICES.gamecube_mode.pid),
+    ],
+  };
+  let source = open_source(&candidates)?;
+
+  let result = InputMonitorInfo {
+    vendor_id: source.vendor_id,
+    product_id: source.product_id,
+    product: source.product,
+    fields: source.fields.clone(),
+  };
+
+  stop_input_monitor();
+  switch_health::reset_switch_health();
+  usage_stats::begin(
+    source
+      .serial_number
+      .filter(|serial| !serial.is_empty())
+      .unwrap_or_else(|| format!("{:04x}:{:04x}", result.vendor_id, result.product_id)),
+    result.product.clone(),
+  );
+  *LATEST.lock().unwrap() = None;
+  let started = Instant::now();
+  let stop = Arc::new(AtomicBool::new(false));
+  *MONITOR.lock().unwrap() = Some(Monitor {
+    vendor_id: result.vendor_id,
+    product_id: result.product_id,
+    started,
+    stop: stop.clone(),
+  });
+  let events = events.clone();
+  let (reader, fields) = (source.reader, source.fields);
+  std::thread::spawn(move || read_loop(events, reader, fields, started, stop));
+  Ok(result)
+}
+
+pub fn is_running() -> bool {
+  MONITOR.lock().unwrap().is_some()
+}
+
+/// VID and PID of the device being monitored.
+pub fn monitored_device() -> Option<(u16, u16)> {
+  MONITOR
+    .lock()
+    .unwrap()
+    .as_ref()
+    .map(|monitor| (monitor.vendor_id, monitor.product_id))
+}
+
+/// The running monitor's clock, on the same scale as
+/// `InputState::timestamp_us`.
+pub fn elapsed_us() -> Option<u64> {
+  MONITOR
+    .lock()
+    .unwrap()
+    .as_ref()
+    .map(|monitor| monitor.started.elapsed().as_micros() as u64)
+}
+
+/// The last report the running monitor decoded.
+pub fn latest_state() -> Option<InputState> {
+  if !is_running() {
+    return None;
+  }
+  LATEST.lock().unwrap().clone()
+}
+
+/// Receives every decoded report from the running monitor. The channel
+/// closes when the monitor stops.
+pub fn subscribe() -> Result<Receiver<InputState>, HayboxError> {
+  if !is_running() {
+    return Err(HayboxError::Other("The input monitor is not running".to_string()));
+  }
+  let (sender, receiver) = mpsc::channel();
+  SUBSCRIBERS.lock().unwrap().push(sender);
+  Ok(receiver)
+}
+
+pub fn stop_input_monitor() {
+  if let Some(monitor) = MONITOR.lock().unwrap().take() {
+    monitor.stop.store(true, Ordering::Relaxed);
+  }
+  SUBSCRIBERS.lock().unwrap().clear();
+  usage_stats::finish();
+}
@@ -0,0 +1,148 @@
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::HayboxError;
+use crate::input_monitor::{self, InputState};
+
+const RECORDING_FORMAT_VERSION: u32 = 1;
+/// About 15 minutes of constant change at 1 kHz; the rest is dropped.
+const MAX_FRAMES: usize = 1_000_000;
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A capture of the input stream. Only reports that differ from the one
+/// before are kept, so a controller sitting idle costs nothing.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct InputRecording {
+  pub format_version: u32,
+  pub recorded_at: u64,
+  pub vendor_id: u16,
+  pub product_id: u16,
+  pub duration_us: u64,
+  /// Reports received, including the unchanged ones that were not kept.
+  pub reports: u64,
+  pub truncated: bool,
+  /// Timestamps count from the first report.
+  pub frames: Vec<InputState>,
+}
+
+struct Recorder {
+  stop: Arc<AtomicBool>,
+  thread: JoinHandle<InputRecording>,
+}
+
+lazy_static::lazy_static! {
+  static ref RECORDER: Mutex<Option<Recorder>> = Mutex::new(None);
+}
+
+fn same_input(a: &InputState, b: &InputState) -> bool {
+  a.report_id == b.report_id
+    && a.pressed == b.pressed
+    && a.hat == b.hat
+    && a.axes.len() == b.axes.len()
+    && a
+      .axes
+      .iter()
+      .zip(&b.axes)
+      .all(|(a, b)| a.name == b.name && a.value == b.value)
+}
+
+/// Starts capturing the running input monitor's reports in memory until
+/// `stop_recording` is called. A recording already in progress is
+/// discarded.
+pub fn start_recording() -> Result<(), HayboxError> {
+  let (vendor_id, product_id) =
+    input_monitor::monitored_device().ok_or_else(|| HayboxError::Other("Start the input monitor first".to_string()))?;
+  let receiver = input_monitor::subscribe()?;
+
+  let mut recorder = RECORDER.lock().unwrap();
+  if let Some(previous) = recorder.take() {
+    previous.stop.store(true, Ordering::Relaxed);
+  }
+
+  let stop = Arc::new(AtomicBool::new(false));
+  let thread_stop = stop.clone();
+  let thread = std::thread::spawn(move || {
+    let mut recording = InputRecording {
+      format_version: RECORDING_FORMAT_VERSION,
+      recorded_at: SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default(),
+      vendor_id,
+      product_id,
+      duration_us: 0,
+      reports: 0,
+      truncated: false,
+      frames: Vec::new(),
+    };
+    let mut first_us = None;
+
+    while !thread_stop.load(Ordering::Relaxed) {
+      let mut state = match receiver.recv_timeout(POLL_INTERVAL) {
+        Ok(state) => state,
+        Err(RecvTimeoutError::Timeout) => continue,
+        Err(RecvTimeoutError::Disconnected) => break,
+      };
+      let first_us = *first_us.get_or_insert(state.timestamp_us);
+      state.timestamp_us -= first_us;
+      recording.reports += 1;
+      recording.duration_us = state.timestamp_us;
+
+      if recording.frames.last().is_some_and(|last| same_input(last, &state)) {
+        continue;
+      }
+      if recording.frames.len() == MAX_FRAMES {
+        recording.truncated = true;
+        continue;
+      }
+      recording.frames.push(state);
+    }
+    recording
+  });
+
+  *recorder = Some(Recorder { stop, thread });
+  Ok(())
+}
+
+/// Ends the recording and writes it to `path` as JSON. It also ends on its
+/// own when the monitor stops; the frames up to then are kept.
+pub fn stop_recording(path: &Path) -> Result<InputRecording, HayboxError> {
+  let recorder = RECORDER
+    .lock()
+    .unwrap()
+    .take()
+    .ok_or_else(|| HayboxError::Other("Not recording".to_string()))?;
+  recorder.stop.store(true, Ordering::Relaxed);
+  let recording = recorder
+    .thread
+    .join()
+    .map_err(|_| HayboxError::Other("The recording thread panicked".to_string()))?;
+
+  let content = serde_json::to_string(&recording)
+    .map_err(|e| HayboxError::Other(format!("Failed to serialize recording: {}", e)))?;
+  std::fs::write(path, content).map_err(|e| HayboxError::Io(format!("Failed to write {}: {}", path.display(), e)))?;
+  Ok(recording)
+}
+
+/// Reads a recording for playback in the viewer.
+pub fn load_recording(path: &Path) -> Result<InputRecording, HayboxError> {
+  let content =
+    std::fs::read_to_string(path).map_err(|e| HayboxError::Io(format!("Failed to read {}: {}", path.display(), e)))?;
+  let recording: InputRecording = serde_json::from_str(&content)
+    .map_err(|e| HayboxError::Other(format!("{} is not an input recording: {}", path.display(), e)))?;
+  if recording.format_version > RECORDING_FORMAT_VERSION {
+    return Err(HayboxError::Other(format!(
+      "{} uses recording format {}, this version of the app reads up to {}",
+      path.display(),
+      recording.format_version,
+      RECORDING_FORMAT_VERSION
+    )));
+  }
+  Ok(recording)
+}
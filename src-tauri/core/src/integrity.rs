@@ -0,0 +1,367 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+#[cfg(target_os = "windows")]
+use windows::core::{w, PCWSTR};
+#[cfg(target_os = "windows")]
+use windows::Win32::Foundation::{CloseHandle, HWND};
+#[cfg(target_os = "windows")]
+use windows::Win32::Security::Cryptography::Catalog::{
+  CryptCATAdminAcquireContext2, CryptCATAdminCalcHashFromFileHandle2, CryptCATAdminEnumCatalogFromHash,
+  CryptCATAdminReleaseCatalogContext, CryptCATAdminReleaseContext, CryptCATCatalogInfoFromContext, CATALOG_INFO,
+};
+#[cfg(target_os = "windows")]
+use windows::Win32::Security::Cryptography::{CertGetNameStringW, CERT_NAME_SIMPLE_DISPLAY_TYPE};
+#[cfg(target_os = "windows")]
+use windows::Win32::Security::WinTrust::{
+  WTHelperProvDataFromStateData, WinVerifyTrust, WINTRUST_ACTION_GENERIC_VERIFY_V2, WINTRUST_CATALOG_INFO,
+  WINTRUST_DATA, WINTRUST_DATA_0, WINTRUST_FILE_INFO, WTD_CHOICE_CATALOG, WTD_CHOICE_FILE, WTD_REVOKE_NONE,
+  WTD_STATEACTION_CLOSE, WTD_STATEACTION_VERIFY, WTD_UI_NONE,
+};
+#[cfg(target_os = "windows")]
+use windows::Win32::Storage::FileSystem::{
+  CreateFileW, GetFileVersionInfoSizeW, GetFileVersionInfoW, VerQueryValueW, FILE_ATTRIBUTE_NORMAL, FILE_GENERIC_READ,
+  FILE_SHARE_READ, OPEN_EXISTING, VS_FIXEDFILEINFO,
+};
+
+use crate::resources::EMBEDDED_RESOURCES;
+
+/// Subject names of the certificates Microsoft signs its redistributables
+/// (embedded signatures) and system files (catalogs) with.
+#[cfg(target_os = "windows")]
+const MICROSOFT_SIGNERS: &[&str] = &["Microsoft Corporation", "Microsoft Windows"];
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ResourceVerification {
+  pub file_name: String,
+  pub sha256: Option<String>,
+  pub expected_sha256: Option<String>,
+  pub hash_matches: Option<bool>,
+  pub signature_valid: bool,
+  /// Publishers whose signature this file is accepted with.
+  pub trusted_signers: Vec<String>,
+}
+
+impl ResourceVerification {
+  pub fn is_trusted(&self) -> bool {
+    self.signature_valid && self.hash_matches == Some(true)
+  }
+
+  pub fn failure_reason(&self) -> Option<String> {
+    if self.sha256.is_none() {
+      Some(format!("{} could not be read", self.file_name))
+    } else if self.hash_matches.is_none() {
+      Some(format!(
+        "{} is not one of the files this build was made with",
+        self.file_name
+      ))
+    } else if self.hash_matches == Some(false) {
+      Some(format!(
+        "{} has SHA-256 {} but {} was expected",
+        self.file_name,
+        self.sha256.as_deref().unwrap_or_default(),
+        self.expected_sha256.as_deref().unwrap_or_default()
+      ))
+    } else if !self.signature_valid {
+      Some(format!(
+        "{} does not carry a valid Authenticode signature from {}",
+        self.file_name,
+        if self.trusted_signers.is_empty() {
+          "a trusted publisher".to_string()
+        } else {
+          self.trusted_signers.join(" or ")
+        }
+      ))
+    } else {
+      None
+    }
+  }
+}
+
+pub fn sha256_file(path: &Path) -> std::io::Result<String> {
+  let bytes = std::fs::read(path)?;
+  Ok(format!("{:x}", Sha256::digest(&bytes)))
+}
+
+/// SHA-256 of every driver resource, keyed by lowercase relative path. The
+/// hashes are taken by build.rs and compiled in, so they cannot be swapped
+/// together with the files they protect.
+pub fn load_expected_hashes() -> HashMap<String, String> {
+  EMBEDDED_RESOURCES
+    .iter()
+    .map(|(name, _, hash, _)| (name.to_lowercase(), hash.to_string()))
+    .collect()
+}
+
+/// Publishers build.rs recorded for the resource `file_name`; empty for files
+/// that are not part of the build, which no signature can then vouch for.
+pub fn trusted_signers(file_name: &str) -> &'static [&'static str] {
+  EMBEDDED_RESOURCES
+    .iter()
+    .find(|(name, _, _, _)| name.eq_ignore_ascii_case(file_name))
+    .map(|(_, _, _, signers)| *signers)
+    .unwrap_or_default()
+}
+
+pub fn to_wide(value: &str) -> Vec<u16> {
+  value.encode_utf16().chain(std::iter::once(0)).collect()
+}
+
+/// Runs a WinVerifyTrust check and releases the state it allocates. Returns
+/// the subject name of the signing certificate when the signature is valid.
+#[cfg(target_os = "windows")]
+fn run_win_verify_trust(trust_data: &mut WINTRUST_DATA) -> Option<String> {
+  let mut action = WINTRUST_ACTION_GENERIC_VERIFY_V2;
+  trust_data.dwStateAction = WTD_STATEACTION_VERIFY;
+  let status = unsafe { WinVerifyTrust(HWND::default(), &mut action, trust_data as *mut _ as *mut _) };
+
+  let signer = if status == 0 { signer_name(trust_data) } else { None };
+
+  // The verify call allocates state that must be released with a second call.
+  trust_data.dwStateAction = WTD_STATEACTION_CLOSE;
+  unsafe { WinVerifyTrust(HWND::default(), &mut action, trust_data as *mut _ as *mut _) };
+
+  signer
+}
+
+/// Subject name of the leaf certificate of the first signer, read from the
+/// state a successful verify call leaves behind.
+#[cfg(target_os = "windows")]
+fn signer_name(trust_data: &WINTRUST_DATA) -> Option<String> {
+  let provider_data = unsafe { WTHelperProvDataFromStateData(trust_data.hWVTStateData).as_ref()? };
+  if provider_data.csSigners == 0 {
+    return None;
+  }
+  let signer = unsafe { provider_data.pasSigners.as_ref()? };
+  if signer.csCertChain == 0 {
+    return None;
+  }
+  let certificate = unsafe { signer.pasCertChain.as_ref()? }.pCert;
+
+  let mut name = [0u16; 256];
+  let length = unsafe { CertGetNameStringW(certificate, CERT_NAME_SIMPLE_DISPLAY_TYPE, 0, None, Some(&mut name)) };
+  // The length includes the terminator; 1 means an empty name.
+  (length > 1).then(|| String::from_utf16_lossy(&name[..length as usize - 1]))
+}
+
+/// Whether the certificate belongs to Microsoft rather than whoever else
+/// managed to get code signed.
+#[cfg(target_os = "windows")]
+fn is_microsoft_signer(signer: Option<String>) -> bool {
+  signer.is_some_and(|signer| MICROSOFT_SIGNERS.contains(&signer.as_str()))
+}
+
+/// Checks the file's embedded signature, accepting only certificates issued
+/// to one of `signers`.
+#[cfg(target_os = "windows")]
+pub fn verify_authenticode(path: &Path, signers: &[&str]) -> bool {
+  authenticode_signer(path).is_some_and(|signer| signers.contains(&signer.as_str()))
+}
+
+#[cfg(target_os = "windows")]
+fn authenticode_signer(path: &Path) -> Option<String> {
+  let wide_path = to_wide(&path.to_string_lossy());
+
+  let mut file_info = WINTRUST_FILE_INFO {
+    cbStruct: std::mem::size_of::<WINTRUST_FILE_INFO>() as u32,
+    pcwszFilePath: PCWSTR(wide_path.as_ptr()),
+    ..Default::default()
+  };
+
+  let mut trust_data = WINTRUST_DATA {
+    cbStruct: std::mem::size_of::<WINTRUST_DATA>() as u32,
+    dwUIChoice: WTD_UI_NONE,
+    fdwRevocationChecks: WTD_REVOKE_NONE,
+    dwUnionChoice: WTD_CHOICE_FILE,
+    Anonymous: WINTRUST_DATA_0 { pFile: &mut file_info },
+    ..Default::default()
+  };
+
+  run_win_verify_trust(&mut trust_data)
+}
+
+/// Checks a file against the system catalogs and returns the catalog's
+/// signer. Windows system files such as xinput1_4.dll carry no embedded
+/// signature; they are signed through a catalog, which `verify_authenticode`
+/// cannot see.
+#[cfg(target_os = "windows")]
+fn catalog_signer(path: &Path) -> Option<String> {
+  let wide_path = to_wide(&path.to_string_lossy());
+
+  let file = match unsafe {
+    CreateFileW(
+      PCWSTR(wide_path.as_ptr()),
+      FILE_GENERIC_READ.0,
+      FILE_SHARE_READ,
+      None,
+      OPEN_EXISTING,
+      FILE_ATTRIBUTE_NORMAL,
+      None,
+    )
+  } {
+    Ok(file) => file,
+    Err(_) => return None,
+  };
+
+  let mut cat_admin = 0isize;
+  if unsafe { CryptCATAdminAcquireContext2(&mut cat_admin, None, w!("SHA256"), None, None) }.is_err() {
+    let _ = unsafe { CloseHandle(file) };
+    return None;
+  }
+
+  let mut hash_len = 0u32;
+  let _ = unsafe { CryptCATAdminCalcHashFromFileHandle2(cat_admin, file, &mut hash_len, None, None) };
+  let mut hash = vec![0u8; hash_len as usize];
+  let hashed = hash_len > 0
+    && unsafe { CryptCATAdminCalcHashFromFileHandle2(cat_admin, file, &mut hash_len, Some(hash.as_mut_ptr()), None) }
+      .is_ok();
+
+  let cat_info = if hashed {
+    unsafe { CryptCATAdminEnumCatalogFromHash(cat_admin, &hash, None, None) }
+  } else {
+    0
+  };
+
+  let mut signer = None;
+  if cat_info != 0 {
+    let mut info = CATALOG_INFO {
+      cbStruct: std::mem::size_of::<CATALOG_INFO>() as u32,
+      ..Default::default()
+    };
+
+    if unsafe { CryptCATCatalogInfoFromContext(cat_info, &mut info, 0) }.is_ok() {
+      let member_tag: String = hash.iter().map(|b| format!("{:02X}", b)).collect();
+      let wide_tag = to_wide(&member_tag);
+
+      let mut catalog_info = WINTRUST_CATALOG_INFO {
+        cbStruct: std::mem::size_of::<WINTRUST_CATALOG_INFO>() as u32,
+        pcwszCatalogFilePath: PCWSTR(info.wszCatalogFile.as_ptr()),
+        pcwszMemberTag: PCWSTR(wide_tag.as_ptr()),
+        pcwszMemberFilePath: PCWSTR(wide_path.as_ptr()),
+        hMemberFile: file,
+        pbCalculatedFileHash: hash.as_mut_ptr(),
+        cbCalculatedFileHash: hash_len,
+        hCatAdmin: cat_admin,
+        ..Default::default()
+      };
+
+      let mut trust_data = WINTRUST_DATA {
+        cbStruct: std::mem::size_of::<WINTRUST_DATA>() as u32,
+        dwUIChoice: WTD_UI_NONE,
+        fdwRevocationChecks: WTD_REVOKE_NONE,
+        dwUnionChoice: WTD_CHOICE_CATALOG,
+        Anonymous: WINTRUST_DATA_0 {
+          pCatalog: &mut catalog_info,
+        },
+        ..Default::default()
+      };
+
+      signer = run_win_verify_trust(&mut trust_data);
+    }
+
+    let _ = unsafe { CryptCATAdminReleaseCatalogContext(cat_admin, cat_info, 0) };
+  }
+
+  let _ = unsafe { CryptCATAdminReleaseContext(cat_admin, 0) };
+  let _ = unsafe { CloseHandle(file) };
+  signer
+}
+
+/// Subject name of whoever signed the file, through an embedded or catalog
+/// signature, whichever the file uses. `None` when neither is valid.
+#[cfg(target_os = "windows")]
+pub fn signer(path: &Path) -> Option<String> {
+  authenticode_signer(path).or_else(|| catalog_signer(path))
+}
+
+/// Embedded or catalog signature by anyone.
+#[cfg(target_os = "windows")]
+pub fn is_signed(path: &Path) -> bool {
+  signer(path).is_some()
+}
+
+/// Embedded or catalog signature by Microsoft.
+#[cfg(target_os = "windows")]
+pub fn is_microsoft_signed(path: &Path) -> bool {
+  is_microsoft_signer(signer(path))
+}
+
+/// Fixed file version from the version resource, e.g. `10.0.19041.1`.
+#[cfg(target_os = "windows")]
+pub fn file_version(path: &Path) -> Option<String> {
+  let wide_path = to_wide(&path.to_string_lossy());
+
+  let size = unsafe { GetFileVersionInfoSizeW(PCWSTR(wide_path.as_ptr()), None) };
+  if size == 0 {
+    return None;
+  }
+
+  let mut data = vec![0u8; size as usize];
+  unsafe { GetFileVersionInfoW(PCWSTR(wide_path.as_ptr()), None, size, data.as_mut_ptr() as *mut _) }.ok()?;
+
+  let mut info_ptr: *mut std::ffi::c_void = std::ptr::null_mut();
+  let mut info_len = 0u32;
+  let found = unsafe { VerQueryValueW(data.as_ptr() as *const _, w!("\\"), &mut info_ptr, &mut info_len) };
+  if !found.as_bool() || info_ptr.is_null() || (info_len as usize) < std::mem::size_of::<VS_FIXEDFILEINFO>() {
+    return None;
+  }
+
+  let info = unsafe { &*(info_ptr as *const VS_FIXEDFILEINFO) };
+  Some(format!(
+    "{}.{}.{}.{}",
+    info.dwFileVersionMS >> 16,
+    info.dwFileVersionMS & 0xFFFF,
+    info.dwFileVersionLS >> 16,
+    info.dwFileVersionLS & 0xFFFF
+  ))
+}
+
+/// Authenticode is a Windows format; nothing is trusted by signature
+/// elsewhere.
+#[cfg(not(target_os = "windows"))]
+pub fn verify_authenticode(_path: &Path, _signers: &[&str]) -> bool {
+  false
+}
+
+#[cfg(not(target_os = "windows"))]
+pub fn is_signed(_path: &Path) -> bool {
+  false
+}
+
+#[cfg(not(target_os = "windows"))]
+pub fn is_microsoft_signed(_path: &Path) -> bool {
+  false
+}
+
+/// Version resources only exist in PE files.
+#[cfg(not(target_os = "windows"))]
+pub fn file_version(_path: &Path) -> Option<String> {
+  None
+}
+
+pub fn verify_resource(
+  resource_dir: &Path,
+  file_name: &str,
+  expected: &HashMap<String, String>,
+) -> ResourceVerification {
+  let path = resource_dir.join(file_name);
+  let sha256 = sha256_file(&path).ok();
+  let expected_sha256 = expected.get(&file_name.to_lowercase()).cloned();
+  let hash_matches = match (&sha256, &expected_sha256) {
+    (Some(actual), Some(expected)) => Some(actual == expected),
+    _ => None,
+  };
+
+  let signers = trusted_signers(file_name);
+
+  ResourceVerification {
+    file_name: file_name.to_string(),
+    signature_valid: sha256.is_some() && verify_authenticode(&path, signers),
+    sha256,
+    expected_sha256,
+    hash_matches,
+    trusted_signers: signers.iter().map(|signer| signer.to_string()).collect(),
+  }
+}
@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+use std::process::Command;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::HayboxError;
+
+const USB_DEVICE_CLASS: &str = "IOUSBHostDevice";
+const USB_INTERFACE_CLASS: &str = "IOUSBHostInterface";
+
+/// One object from the IOKit registry as printed by `ioreg -l`.
+struct RegistryEntry {
+  name: String,
+  class: String,
+  /// Nesting level in the printed tree; children are one deeper.
+  depth: usize,
+  properties: HashMap<String, String>,
+}
+
+impl RegistryEntry {
+  fn number(&self, key: &str) -> Option<u16> {
+    let value = self.properties.get(key)?;
+    match value.strip_prefix("0x") {
+      Some(hex) => u16::from_str_radix(hex, 16).ok(),
+      None => value.parse().ok(),
+    }
+  }
+}
+
+/// A connected USB device and the driver matched to each of its interfaces.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IoUsbDevice {
+  pub vendor_id: u16,
+  pub product_id: u16,
+  pub product: Option<String>,
+  pub location_id: Option<String>,
+  pub interfaces: Vec<IoUsbInterface>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IoUsbInterface {
+  pub number: Option<u16>,
+  pub class: Option<u16>,
+  /// Class of the driver matched to the interface, e.g.
+  /// `AppleUserUSBHostHIDDevice`. None when nothing has claimed it, which is
+  /// when libusb can.
+  pub driver: Option<String>,
+}
+
+/// Splits an `ioreg` object line, `+-o WUP-028@14200000  <class
+/// IOUSBHostDevice, id 0x100000a1c, ...>`, into its name and class.
+fn parse_object_line(line: &str) -> Option<RegistryEntry> {
+  let start = line.find("+-o ")?;
+  let rest = &line[start + 4..];
+  let (name, attributes) = rest.split_once("  <class ")?;
+  let class = attributes.split([',', '>']).next()?.trim();
+  Some(RegistryEntry {
+    name: name.split('@').next().unwrap_or(name).to_string(),
+    class: class.to_string(),
+    depth: start / 2,
+    properties: HashMap::new(),
+  })
+}
+
+/// Splits a property line, `  |   "idVendor" = 1406`, into key and value.
+/// Quoted values are unquoted; anything else is kept as printed.
+fn parse_property_line(line: &str) -> Option<(String, String)> {
+  let line = line.trim_start_matches([' ', '|']);
+  let (key, value) = line.strip_prefix('"')?.split_once("\" = ")?;
+  let value = value.trim();
+  let value = value
+    .strip_prefix('"')
+    .and_then(|value| value.strip_suffix('"'))
+    .unwrap_or(value);
+  Some((key.to_string(), value.to_string()))
+}
+
+fn parse_registry(output: &str) -> Vec<RegistryEntry> {
+  let mut entries: Vec<RegistryEntry> = Vec::new();
+  for line in output.lines() {
+    if let Some(entry) = parse_object_line(line) {
+      entries.push(entry);
+    } else if let (Some((key, value)), Some(entry)) = (parse_property_line(line), entries.last_mut()) {
+      entry.properties.insert(key, value);
+    }
+  }
+  entries
+}
+
+/// USB devices and everything attached beneath them in the IOKit registry,
+/// read through `ioreg`, which needs no entitlements or root.
+fn usb_registry() -> Result<Vec<RegistryEntry>, HayboxError> {
+  let output = Command::new("ioreg")
+    .args(["-r", "-c", USB_DEVICE_CLASS, "-l", "-w0"])
+    .output()
+    .map_err(|e| HayboxError::Io(format!("Failed to run ioreg: {}", e)))?;
+  if !output.status.success() {
+    return Err(HayboxError::Other(format!(
+      "ioreg failed: {}",
+      String::from_utf8_lossy(&output.stderr).trim()
+    )));
+  }
+  Ok(parse_registry(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// The entries directly beneath `entries[index]`.
+fn children(entries: &[RegistryEntry], index: usize) -> impl Iterator<Item = &RegistryEntry> {
+  let depth = entries[index].depth;
+  entries[index + 1..]
+    .iter()
+    .take_while(move |entry| entry.depth > depth)
+    .filter(move |entry| entry.depth == depth + 1)
+}
+
+/// Connected USB devices, optionally filtered by VID and PID.
+pub fn usb_devices(vendor_id: Option<u16>, product_id: Option<u16>) -> Result<Vec<IoUsbDevice>, HayboxError> {
+  let entries = usb_registry()?;
+  let mut devices = Vec::new();
+  for (index, entry) in entries.iter().enumerate() {
+    if entry.class != USB_DEVICE_CLASS {
+      continue;
+    }
+    let (Some(vid), Some(pid)) = (entry.number("idVendor"), entry.number("idProduct")) else {
+      continue;
+    };
+    if vendor_id.is_some_and(|wanted| wanted != vid) || product_id.is_some_and(|wanted| wanted != pid) {
+      continue;
+    }
+
+    let interfaces = entries[index + 1..]
+      .iter()
+      .enumerate()
+      .take_while(|(_, child)| child.depth > entry.depth)
+      .filter(|(_, child)| child.class == USB_INTERFACE_CLASS)
+      .map(|(offset, interface)| IoUsbInterface {
+        number: interface.number("bInterfaceNumber"),
+        class: interface.number("bInterfaceClass"),
+        driver: children(&entries, index + 1 + offset)
+          .next()
+          .map(|driver| driver.class.clone()),
+      })
+      .collect();
+
+    devices.push(IoUsbDevice {
+      vendor_id: vid,
+      product_id: pid,
+      product: entry
+        .properties
+        .get("USB Product Name")
+        .or_else(|| entry.properties.get("kUSBProductString"))
+        .cloned()
+        .or_else(|| Some(entry.name.clone()).filter(|name| !name.is_empty())),
+      location_id: entry.properties.get("locationID").cloned(),
+      interfaces,
+    });
+  }
+  Ok(devices)
+}
+
+/// Whether libusb can claim the device: it is connected and no macOS driver
+/// has matched any of its interfaces. The macOS counterpart of a WinUSB
+/// binding.
+pub fn libusb_claimable(vendor_id: u16, product_id: u16) -> Result<bool, HayboxError> {
+  Ok(
+    usb_devices(Some(vendor_id), Some(product_id))?
+      .iter()
+      .any(|device| device.interfaces.iter().all(|interface| interface.driver.is_none())),
+  )
+}
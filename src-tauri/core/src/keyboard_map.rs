@@ -0,0 +1,258 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config_proto::{self, Button, ButtonToKeycodeMapping, GameModeId, KeyboardModeConfig};
+use crate::error::HayboxError;
+
+/// HID keyboard usage IDs (usage page 0x07) that keyboard mode can send.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration, Serialize, Deserialize)]
+#[repr(i32)]
+pub enum Keycode {
+  None = 0,
+  A = 4,
+  B = 5,
+  C = 6,
+  D = 7,
+  E = 8,
+  F = 9,
+  G = 10,
+  H = 11,
+  I = 12,
+  J = 13,
+  K = 14,
+  L = 15,
+  M = 16,
+  N = 17,
+  O = 18,
+  P = 19,
+  Q = 20,
+  R = 21,
+  S = 22,
+  T = 23,
+  U = 24,
+  V = 25,
+  W = 26,
+  X = 27,
+  Y = 28,
+  Z = 29,
+  Num1 = 30,
+  Num2 = 31,
+  Num3 = 32,
+  Num4 = 33,
+  Num5 = 34,
+  Num6 = 35,
+  Num7 = 36,
+  Num8 = 37,
+  Num9 = 38,
+  Num0 = 39,
+  Enter = 40,
+  Escape = 41,
+  Backspace = 42,
+  Tab = 43,
+  Space = 44,
+  Minus = 45,
+  Equal = 46,
+  LeftBracket = 47,
+  RightBracket = 48,
+  Backslash = 49,
+  Semicolon = 51,
+  Quote = 52,
+  Grave = 53,
+  Comma = 54,
+  Period = 55,
+  Slash = 56,
+  CapsLock = 57,
+  F1 = 58,
+  F2 = 59,
+  F3 = 60,
+  F4 = 61,
+  F5 = 62,
+  F6 = 63,
+  F7 = 64,
+  F8 = 65,
+  F9 = 66,
+  F10 = 67,
+  F11 = 68,
+  F12 = 69,
+  Insert = 73,
+  Home = 74,
+  PageUp = 75,
+  Delete = 76,
+  End = 77,
+  PageDown = 78,
+  Right = 79,
+  Left = 80,
+  Down = 81,
+  Up = 82,
+  LeftCtrl = 224,
+  LeftShift = 225,
+  LeftAlt = 226,
+  LeftGui = 227,
+  RightCtrl = 228,
+  RightShift = 229,
+  RightAlt = 230,
+  RightGui = 231,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyMapping {
+  pub button: Button,
+  pub keycode: Keycode,
+}
+
+/// Buttons that send the same key, so the game cannot tell them apart.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct KeyConflict {
+  pub keycode: Keycode,
+  pub buttons: Vec<Button>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct KeyboardMap {
+  pub profile: u32,
+  pub keyboard_mode_id: u32,
+  pub mappings: Vec<KeyMapping>,
+  pub conflicts: Vec<KeyConflict>,
+}
+
+fn find_conflicts(mappings: &[KeyMapping]) -> Vec<KeyConflict> {
+  let mut by_key: HashMap<Keycode, Vec<Button>> = HashMap::new();
+  for mapping in mappings {
+    by_key.entry(mapping.keycode).or_default().push(mapping.button);
+  }
+  let mut conflicts: Vec<KeyConflict> = by_key
+    .into_iter()
+    .filter(|(_, buttons)| buttons.len() > 1)
+    .map(|(keycode, buttons)| KeyConflict { keycode, buttons })
+    .collect();
+  conflicts.sort_by_key(|conflict| conflict.keycode);
+  conflicts
+}
+
+fn keyboard_map(profile: u32, mode: Option<&KeyboardModeConfig>) -> KeyboardMap {
+  let mappings: Vec<KeyMapping> = mode
+    .map(|mode| {
+      mode
+        .buttons_to_keycodes
+        .iter()
+        .filter_map(|mapping| {
+          Some(KeyMapping {
+            button: Button::try_from(mapping.button).ok()?,
+            keycode: Keycode::try_from(mapping.keycode as i32).ok()?,
+          })
+        })
+        .collect()
+    })
+    .unwrap_or_default();
+  KeyboardMap {
+    profile,
+    keyboard_mode_id: mode.map(|mode| mode.id).unwrap_or_default(),
+    conflicts: find_conflicts(&mappings),
+    mappings,
+  }
+}
+
+fn check_keyboard_profile(mode_id: i32, name: &str) -> Result<(), HayboxError> {
+  if mode_id != GameModeId::Keyboard as i32 {
+    return Err(HayboxError::Other(format!(
+      "Profile '{}' is not a keyboard mode profile",
+      name
+    )));
+  }
+  Ok(())
+}
+
+pub fn get_keyboard_map(profile: u32) -> Result<KeyboardMap, HayboxError> {
+  let config = config_proto::get_config()?;
+  let mode = config
+    .game_mode_configs
+    .get(profile as usize)
+    .ok_or_else(|| HayboxError::Other(format!("Profile {} does not exist", profile)))?;
+  check_keyboard_profile(mode.mode_id, &mode.name)?;
+
+  let keyboard = config
+    .keyboard_modes
+    .iter()
+    .find(|keyboard| keyboard.id == mode.keyboard_mode_config);
+  Ok(keyboard_map(profile, keyboard))
+}
+
+/// Replaces a keyboard profile's keymap. Two buttons sending the same key
+/// are rejected unless `allow_conflicts` is set. Keymaps shared with other
+/// profiles are copied first so theirs stay as they were.
+pub fn set_keyboard_map(
+  profile: u32,
+  mappings: &[KeyMapping],
+  allow_conflicts: bool,
+) -> Result<KeyboardMap, HayboxError> {
+  let mut seen = HashMap::new();
+  for mapping in mappings {
+    if mapping.button == Button::Unspecified || mapping.keycode == Keycode::None {
+      return Err(HayboxError::Other("Every mapping needs a button and a key".to_string()));
+    }
+    if let Some(previous) = seen.insert(mapping.button, mapping.keycode) {
+      return Err(HayboxError::Other(format!(
+        "{:?} is mapped to both {:?} and {:?}",
+        mapping.button, previous, mapping.keycode
+      )));
+    }
+  }
+  let conflicts = find_conflicts(mappings);
+  if !allow_conflicts && !conflicts.is_empty() {
+    let described: Vec<String> = conflicts
+      .iter()
+      .map(|conflict| format!("{:?} on {:?}", conflict.keycode, conflict.buttons))
+      .collect();
+    return Err(HayboxError::Other(format!(
+      "Several buttons send the same key: {}",
+      described.join("; ")
+    )));
+  }
+
+  config_proto::with_connection(|conn| {
+    let mut config = conn.get_config()?;
+    let mode = config
+      .game_mode_configs
+      .get(profile as usize)
+      .ok_or_else(|| HayboxError::Other(format!("Profile {} does not exist", profile)))?;
+    check_keyboard_profile(mode.mode_id, &mode.name)?;
+
+    let id = mode.keyboard_mode_config;
+    let shared = config
+      .game_mode_configs
+      .iter()
+      .enumerate()
+      .any(|(index, other)| index != profile as usize && other.keyboard_mode_config == id);
+    let existing = config.keyboard_modes.iter().position(|keyboard| keyboard.id == id);
+
+    let index = match existing {
+      Some(index) if !shared => index,
+      _ => {
+        let new_id = config
+          .keyboard_modes
+          .iter()
+          .map(|keyboard| keyboard.id + 1)
+          .max()
+          .unwrap_or(0);
+        config.keyboard_modes.push(KeyboardModeConfig {
+          id: new_id,
+          buttons_to_keycodes: Vec::new(),
+        });
+        config.game_mode_configs[profile as usize].keyboard_mode_config = new_id;
+        config.keyboard_modes.len() - 1
+      }
+    };
+
+    config.keyboard_modes[index].buttons_to_keycodes = mappings
+      .iter()
+      .map(|mapping| ButtonToKeycodeMapping {
+        button: mapping.button as i32,
+        keycode: mapping.keycode as u32,
+      })
+      .collect();
+
+    conn.set_config(&config)?;
+    Ok(keyboard_map(profile, config.keyboard_modes.get(index)))
+  })
+}
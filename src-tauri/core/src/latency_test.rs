@@ -0,0 +1,210 @@
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use hidapi::HidApi;
+use serde::{Deserialize, Serialize};
+
+use crate::error::HayboxError;
+use crate::input_monitor::{self, InputState};
+use crate::operations;
+use crate::paths::app_data_dir;
+use crate::polling_rate::percentile;
+use crate::report_descriptor::{self, ReportType};
+
+/// Vendor output report a firmware with latency test support declares and
+/// answers by holding a button for `hold_ms`: `[report id, command, button,
+/// hold_ms]`. No HayBox release has it yet, so the test checks the report
+/// descriptor before sending anything.
+const LATENCY_REPORT_ID: u8 = 0xF0;
+const CMD_ASSERT_BUTTON: u8 = 0x01;
+const HOLD_MS: u8 = 20;
+
+const DEFAULT_SAMPLES: u32 = 100;
+const MAX_SAMPLES: u32 = 1000;
+const DEFAULT_BUTTON: u16 = 1;
+const SAMPLE_TIMEOUT: Duration = Duration::from_millis(500);
+/// Base pause between samples; a varying extra up to `SPREAD_MS` keeps the
+/// commands from locking to the polling interval.
+const PAUSE: Duration = Duration::from_millis(30);
+const SPREAD_MS: u64 = 7;
+const BUCKET_US: u64 = 250;
+const RESULTS_FILE: &str = "latency_results.json";
+/// Saved runs kept for comparison.
+const MAX_RESULTS: usize = 100;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LatencyBucket {
+  pub from_us: u64,
+  pub count: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LatencyResult {
+  /// Free text such as the cable or port used, to tell runs apart.
+  pub label: Option<String>,
+  pub measured_at: u64,
+  pub vendor_id: u16,
+  pub product_id: u16,
+  pub samples: usize,
+  pub timeouts: usize,
+  pub min_us: u64,
+  pub avg_us: f64,
+  pub max_us: u64,
+  pub p50_us: u64,
+  pub p95_us: u64,
+  pub p99_us: u64,
+  pub histogram: Vec<LatencyBucket>,
+}
+
+/// Waits for the next report where `button` is (or is not) held and returns
+/// when the monitor read it.
+fn wait_for(receiver: &Receiver<InputState>, button: u16, pressed: bool) -> Result<Option<u64>, HayboxError> {
+  let deadline = Instant::now() + SAMPLE_TIMEOUT;
+  loop {
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    match receiver.recv_timeout(remaining) {
+      Ok(state) if state.pressed.contains(&button) == pressed => return Ok(Some(state.timestamp_us)),
+      Ok(_) => {}
+      Err(RecvTimeoutError::Timeout) => return Ok(None),
+      Err(RecvTimeoutError::Disconnected) => {
+        return Err(HayboxError::Other(
+          "The input monitor stopped during the test".to_string(),
+        ))
+      }
+    }
+  }
+}
+
+fn histogram(sorted: &[u64]) -> Vec<LatencyBucket> {
+  let mut buckets: Vec<LatencyBucket> = Vec::new();
+  for &latency in sorted {
+    let from_us = latency / BUCKET_US * BUCKET_US;
+    match buckets.last_mut() {
+      Some(bucket) if bucket.from_us == from_us => bucket.count += 1,
+      _ => buckets.push(LatencyBucket { from_us, count: 1 }),
+    }
+  }
+  buckets
+}
+
+fn results_path() -> std::path::PathBuf {
+  app_data_dir().join(RESULTS_FILE)
+}
+
+pub fn get_latency_results() -> Vec<LatencyResult> {
+  std::fs::read_to_string(results_path())
+    .ok()
+    .and_then(|content| serde_json::from_str(&content).ok())
+    .unwrap_or_default()
+}
+
+fn save_result(result: &LatencyResult) -> Result<(), HayboxError> {
+  let mut results = get_latency_results();
+  results.push(result.clone());
+  if results.len() > MAX_RESULTS {
+    results.drain(..results.len() - MAX_RESULTS);
+  }
+  let path = results_path();
+  let content = serde_json::to_string_pretty(&results)
+    .map_err(|e| HayboxError::Other(format!("Failed to serialize latency results: {}", e)))?;
+  std::fs::write(&path, content).map_err(|e| HayboxError::Io(format!("Failed to write {}: {}", path.display(), e)))
+}
+
+/// Asks the firmware to press `button` (a HID button number) over and over
+/// and times how long each press takes to show up in the input monitor,
+/// which must be running. The delay covers the command's trip to the
+/// controller as well as the report's trip back, and the run is saved for
+/// comparison.
+pub fn run_latency_test(
+  samples: Option<u32>,
+  button: Option<u16>,
+  label: Option<String>,
+) -> Result<LatencyResult, HayboxError> {
+  let samples = samples.unwrap_or(DEFAULT_SAMPLES).clamp(1, MAX_SAMPLES);
+  let button = button.unwrap_or(DEFAULT_BUTTON);
+  let button_byte =
+    u8::try_from(button).map_err(|_| HayboxError::Other(format!("Button {} is out of range", button)))?;
+
+  let (vendor_id, product_id) =
+    input_monitor::monitored_device().ok_or_else(|| HayboxError::Other("Start the input monitor first".to_string()))?;
+  let receiver = input_monitor::subscribe()?;
+  let api = HidApi::new().map_err(|e| HayboxError::Hid(format!("Failed to initialize HID: {}", e)))?;
+  let device = api
+    .open(vendor_id, product_id)
+    .map_err(|e| HayboxError::Hid(format!("Failed to open HID device: {}", e)))?;
+  let descriptor = report_descriptor::decode_report_descriptor(&report_descriptor::read_descriptor(&device)?)?;
+  if !descriptor
+    .reports
+    .iter()
+    .any(|report| report.report_type == ReportType::Output && report.report_id == LATENCY_REPORT_ID)
+  {
+    return Err(HayboxError::Unsupported(
+      "The controller's firmware has no latency test command".to_string(),
+    ));
+  }
+
+  let mut latencies = Vec::new();
+  let mut timeouts = 0;
+  let mut held = false;
+  for sample in 0..samples {
+    if operations::is_cancelled() {
+      return Err(HayboxError::Other("Latency test cancelled".to_string()));
+    }
+    operations::report_progress(sample as f32 / samples as f32);
+    std::thread::sleep(PAUSE + Duration::from_millis(sample as u64 * 3 % SPREAD_MS));
+    while let Ok(state) = receiver.try_recv() {
+      held = state.pressed.contains(&button);
+    }
+    if held && wait_for(&receiver, button, false)?.is_none() {
+      return Err(HayboxError::Other(format!(
+        "Button {} is held; release it before testing",
+        button
+      )));
+    }
+
+    let sent_us = input_monitor::elapsed_us()
+      .ok_or_else(|| HayboxError::Other("The input monitor stopped during the test".to_string()))?;
+    device
+      .write(&[LATENCY_REPORT_ID, CMD_ASSERT_BUTTON, button_byte, HOLD_MS])
+      .map_err(|e| HayboxError::Usb(format!("Failed to send the latency command: {}", e)))?;
+    match wait_for(&receiver, button, true)? {
+      Some(seen_us) => {
+        latencies.push(seen_us.saturating_sub(sent_us));
+        held = true;
+      }
+      None if sample == 0 => {
+        return Err(HayboxError::Unsupported(
+          "The controller did not respond; its firmware needs latency test support".to_string(),
+        ))
+      }
+      None => timeouts += 1,
+    }
+  }
+  if latencies.is_empty() {
+    return Err(HayboxError::Timeout("No press was seen within the timeout".to_string()));
+  }
+  latencies.sort_unstable();
+
+  let result = LatencyResult {
+    label,
+    measured_at: SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .map(|d| d.as_secs())
+      .unwrap_or_default(),
+    vendor_id,
+    product_id,
+    samples: latencies.len(),
+    timeouts,
+    min_us: latencies[0],
+    avg_us: latencies.iter().sum::<u64>() as f64 / latencies.len() as f64,
+    max_us: latencies[latencies.len() - 1],
+    p50_us: percentile(&latencies, 50),
+    p95_us: percentile(&latencies, 95),
+    p99_us: percentile(&latencies, 99),
+    histogram: histogram(&latencies),
+  };
+  if let Err(e) = save_result(&result) {
+    tracing::warn!("failed to save latency result: {}", e);
+  }
+  Ok(result)
+}
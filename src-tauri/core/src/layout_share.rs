@@ -0,0 +1,206 @@
+use std::io::Read;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::config_diff::ConfigSource;
+use crate::config_file::{self, ConfigFile};
+use crate::error::HayboxError;
+use crate::firmware::USER_AGENT;
+
+const PASTE_URL: &str = "https://paste.rs";
+const GIST_API: &str = "https://api.github.com/gists";
+const GIST_FILE_NAME: &str = "haybox-config.json";
+/// Hex digits of the SHA-256 kept in share codes; enough to catch a wrong or
+/// altered paste while keeping codes short.
+const CHECKSUM_LEN: usize = 16;
+/// Configs are a few KB; refuse to download anything far larger.
+const MAX_DOWNLOAD: u64 = 1024 * 1024;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ShareTarget {
+  /// paste.rs, which needs no account.
+  Paste,
+  /// A secret GitHub gist created with the user's token.
+  Gist { token: String },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SharedLayout {
+  /// Short code such as `paste:Xy1z:0123456789abcdef` to pass to
+  /// `fetch_shared_config`.
+  pub code: String,
+  pub url: String,
+  pub sha256: String,
+}
+
+#[derive(Debug, PartialEq)]
+enum Host {
+  Paste,
+  Gist,
+}
+
+fn checksum(content: &str) -> String {
+  format!("{:x}", Sha256::digest(content.as_bytes()))
+}
+
+fn read_body(response: ureq::Response, what: &str) -> Result<String, HayboxError> {
+  let mut body = String::new();
+  response
+    .into_reader()
+    .take(MAX_DOWNLOAD + 1)
+    .read_to_string(&mut body)
+    .map_err(|e| HayboxError::Io(format!("Failed to read {}: {}", what, e)))?;
+  if body.len() as u64 > MAX_DOWNLOAD {
+    return Err(HayboxError::Other(format!("{} is too large to be a config", what)));
+  }
+  Ok(body)
+}
+
+fn publish_paste(content: &str) -> Result<(String, String), HayboxError> {
+  let response = ureq::post(PASTE_URL)
+    .set("User-Agent", USER_AGENT)
+    .send_string(content)
+    .map_err(|e| HayboxError::Network(format!("Failed to upload to {}: {}", PASTE_URL, e)))?;
+  let url = read_body(response, "paste.rs response")?.trim().to_string();
+  let id = url
+    .rsplit('/')
+    .next()
+    .filter(|id| !id.is_empty())
+    .ok_or_else(|| HayboxError::Network(format!("Unexpected paste.rs response: {}", url)))?
+    .to_string();
+  Ok((id, url))
+}
+
+fn publish_gist(content: &str, token: &str) -> Result<(String, String), HayboxError> {
+  let body = serde_json::json!({
+    "description": "HayBox controller config",
+    "public": false,
+    "files": { GIST_FILE_NAME: { "content": content } },
+  });
+  let gist: serde_json::Value = ureq::post(GIST_API)
+    .set("User-Agent", USER_AGENT)
+    .set("Accept", "application/vnd.github+json")
+    .set("Authorization", &format!("Bearer {}", token))
+    .send_json(body)
+    .map_err(|e| HayboxError::Network(format!("Failed to create gist: {}", e)))?
+    .into_json()
+    .map_err(|e| HayboxError::Other(format!("Failed to parse gist response: {}", e)))?;
+
+  let id = gist["id"]
+    .as_str()
+    .ok_or_else(|| HayboxError::Other("Gist response has no id".to_string()))?
+    .to_string();
+  let url = gist["html_url"].as_str().unwrap_or_default().to_string();
+  Ok((id, url))
+}
+
+/// Uploads a config and returns a share code and URL that include its
+/// checksum.
+pub fn publish_config(source: &ConfigSource, target: &ShareTarget) -> Result<SharedLayout, HayboxError> {
+  let file = match source {
+    ConfigSource::Device => config_file::device_config_file()?,
+    ConfigSource::File { path } => config_file::read_config_file(path)?,
+  };
+  let content = serde_json::to_string_pretty(&file)
+    .map_err(|e| HayboxError::Other(format!("Failed to serialize config: {}", e)))?;
+  let sha256 = checksum(&content);
+
+  let (host, (id, url)) = match target {
+    ShareTarget::Paste => ("paste", publish_paste(&content)?),
+    ShareTarget::Gist { token } => ("gist", publish_gist(&content, token)?),
+  };
+
+  let prefix = &sha256[..CHECKSUM_LEN];
+  Ok(SharedLayout {
+    code: format!("{}:{}:{}", host, id, prefix),
+    url: format!("{}#{}", url, prefix),
+    sha256,
+  })
+}
+
+/// Splits a share code or URL into host, id and checksum prefix. URLs carry
+/// the checksum as their fragment. References without one are rejected, as
+/// nothing could show the download is the config that was shared.
+fn parse_reference(reference: &str) -> Result<(Host, String, String), HayboxError> {
+  let reference = reference.trim();
+  let checksum = format!("([0-9a-fA-F]{{{},64}})", CHECKSUM_LEN);
+  let code = Regex::new(&format!(r"^(paste|gist):([A-Za-z0-9]+):{}$", checksum)).unwrap();
+  let url = Regex::new(&format!(
+    r"^https://(paste\.rs|gist\.github\.com(?:/[A-Za-z0-9_-]+)?)/([A-Za-z0-9]+)(?:\.[a-z]+)?/?#{}$",
+    checksum
+  ))
+  .unwrap();
+
+  let (host, id, checksum) = if let Some(caps) = code.captures(reference) {
+    let host = if &caps[1] == "paste" { Host::Paste } else { Host::Gist };
+    (host, caps[2].to_string(), caps[3].to_string())
+  } else if let Some(caps) = url.captures(reference) {
+    let host = if &caps[1] == "paste.rs" {
+      Host::Paste
+    } else {
+      Host::Gist
+    };
+    (host, caps[2].to_string(), caps[3].to_string())
+  } else {
+    return Err(HayboxError::Other(format!(
+      "'{}' is not a share code or a paste.rs/gist URL with a checksum; ask for the full share code",
+      reference
+    )));
+  };
+  Ok((host, id, checksum.to_lowercase()))
+}
+
+fn fetch_gist(id: &str) -> Result<String, HayboxError> {
+  let gist: serde_json::Value = ureq::get(&format!("{}/{}", GIST_API, id))
+    .set("User-Agent", USER_AGENT)
+    .set("Accept", "application/vnd.github+json")
+    .call()
+    .map_err(|e| HayboxError::Network(format!("Failed to fetch gist {}: {}", id, e)))?
+    .into_json()
+    .map_err(|e| HayboxError::Other(format!("Failed to parse gist {}: {}", id, e)))?;
+
+  let files = gist["files"]
+    .as_object()
+    .ok_or_else(|| HayboxError::Other("Gist has no files".to_string()))?;
+  let file = files
+    .get(GIST_FILE_NAME)
+    .or_else(|| files.values().next())
+    .ok_or_else(|| HayboxError::Other("Gist has no files".to_string()))?;
+  if file["truncated"].as_bool().unwrap_or(false) {
+    return Err(HayboxError::Other("Gist file is too large to be a config".to_string()));
+  }
+  file["content"]
+    .as_str()
+    .map(str::to_string)
+    .ok_or_else(|| HayboxError::Other("Gist file has no content".to_string()))
+}
+
+/// Downloads a shared config by code or URL, verifies its checksum and
+/// validates it like an imported file. Nothing is written to the controller.
+pub fn fetch_shared_config(reference: &str) -> Result<ConfigFile, HayboxError> {
+  let (host, id, expected) = parse_reference(reference)?;
+  let content = match host {
+    Host::Paste => {
+      let url = format!("{}/{}", PASTE_URL, id);
+      let response = ureq::get(&url)
+        .set("User-Agent", USER_AGENT)
+        .call()
+        .map_err(|e| HayboxError::Network(format!("Failed to fetch {}: {}", url, e)))?;
+      read_body(response, &url)?
+    }
+    Host::Gist => fetch_gist(&id)?,
+  };
+
+  let actual = checksum(&content);
+  if !actual.starts_with(&expected) {
+    return Err(HayboxError::Other(format!(
+      "Checksum mismatch: the share code expects {}, the download is {}",
+      expected,
+      &actual[..expected.len()]
+    )));
+  }
+  config_file::parse_config_file(&content, &id)
+}
@@ -0,0 +1,856 @@
+use audit::AuditEntry;
+use driver::{ConfigBuilder, DeviceBinding, DriverKind, InstallOutcome};
+use error::HayboxError;
+use operations::OperationOutcome;
+use platform::EnumerationBackend;
+use serde::{Deserialize, Serialize};
+use virtual_controllers::VirtualControllerStack;
+
+pub mod analog_trace;
+pub mod architecture;
+pub mod audit;
+pub mod batch_flash;
+pub mod binary_info;
+pub mod bootsel;
+pub mod build_config;
+pub mod bulk_apply;
+pub mod button_mapping;
+pub mod capabilities;
+pub mod config_backup;
+pub mod config_diff;
+pub mod config_file;
+pub mod config_migration;
+pub mod config_proto;
+pub mod config_trial;
+pub mod controller_profiles;
+pub mod coordinate_legality;
+pub mod crash;
+pub mod device_mode;
+pub mod device_tree;
+pub mod device_usage;
+pub mod dfu;
+pub mod diagnostics;
+pub mod doctor;
+pub mod driver;
+pub mod driver_cache;
+pub mod driver_store;
+pub mod environment;
+pub mod error;
+pub mod event_log;
+pub mod events;
+pub mod file_access;
+pub mod firmware;
+pub mod firmware_backup;
+pub mod flash_target;
+pub mod flashing;
+pub mod frame_trainer;
+pub mod game_controllers;
+pub mod game_profiles;
+pub mod gamecube_adapter;
+pub mod hidhide;
+pub mod hotplug;
+pub mod inf_template;
+pub mod input_analysis;
+pub mod input_comparison;
+pub mod input_monitor;
+pub mod input_recording;
+pub mod integrity;
+#[cfg(target_os = "macos")]
+pub mod iokit;
+pub mod keyboard_map;
+pub mod latency_test;
+pub mod layout_share;
+pub mod lighting;
+pub mod logging;
+pub mod operations;
+pub mod paths;
+pub mod pending;
+pub mod picoboot;
+pub mod platform;
+pub mod pnp;
+pub mod polling_rate;
+pub mod presentation_test;
+pub mod privileges;
+pub mod protocol_trace;
+pub mod recovery;
+#[cfg(target_os = "windows")]
+pub mod registry;
+pub mod report_descriptor;
+pub mod resources;
+pub mod rumble;
+pub mod serial_console;
+pub mod serial_ports;
+pub mod socd_test;
+pub mod staging;
+pub mod steam;
+pub mod steamos;
+pub mod switch_health;
+pub mod telemetry;
+pub mod udev;
+pub mod uf2;
+pub mod usage_stats;
+pub mod virtual_controllers;
+pub mod virtualization;
+pub mod volumes;
+#[cfg(target_os = "windows")]
+pub mod wmi_worker;
+pub mod xinput;
+
+/// How many times a batch install is tried per device before it is reported
+/// as failed.
+const BATCH_INSTALL_ATTEMPTS: u32 = 2;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UsbDeviceInfo {
+  pub vid: u16,
+  pub pid: u16,
+  pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DeviceIdentifiers {
+  pub default_mode: UsbDeviceInfo,
+  pub config_mode: UsbDeviceInfo,
+  pub bootsel_mode: UsbDeviceInfo,
+  pub switch_mode: UsbDeviceInfo,
+  pub gamecube_mode: UsbDeviceInfo,
+}
+
+lazy_static::lazy_static! {
+  pub static ref DEVICES: DeviceIdentifiers = DeviceIdentifiers {
+    default_mode: UsbDeviceInfo {
+      vid: 0x0738,
+      pid: 0x4726,
+      name: "Default Mode".to_string(),
+    },
+    config_mode: UsbDeviceInfo {
+      vid: 0x2E8A,
+      pid: 0x000A,
+      name: "Config Mode".to_string(),
+    },
+    bootsel_mode: UsbDeviceInfo {
+      vid: 0x2E8A,
+      pid: 0x0003,
+      name: "BOOTSEL Mode".to_string(),
+    },
+    switch_mode: UsbDeviceInfo {
+      vid: 0x0F0D,
+      pid: 0x0092,
+      name: "Switch Mode".to_string(),
+    },
+    gamecube_mode: UsbDeviceInfo {
+      vid: 0x057E,
+      pid: 0x0337,
+      name: "GameCube Adapter".to_string(),
+    }
+  };
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DeviceStatus {
+  pub default_mode_connected: bool,
+  pub config_mode_connected: bool,
+  pub bootsel_mode_connected: bool,
+  pub switch_mode_connected: bool,
+  pub xinput_installed: bool,
+  pub gamecube_adapter_connected: bool,
+  pub winusb_installed: bool,
+  pub virtual_controller_stack: VirtualControllerStack,
+  /// Device nodes the user cannot open; only reported on Linux.
+  #[serde(default)]
+  pub usb_access_problems: Vec<String>,
+  /// libusb normally; the OS's own device list when libusb is unavailable.
+  #[serde(default = "default_enumeration_backend")]
+  pub enumeration_backend: EnumerationBackend,
+}
+
+fn default_enumeration_backend() -> EnumerationBackend {
+  EnumerationBackend::Libusb
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct DriverOperationResult {
+  pub success: bool,
+  pub message: String,
+  #[serde(default)]
+  pub reboot_required: bool,
+  #[serde(default)]
+  pub verified: bool,
+  #[serde(default)]
+  pub bound_provider: Option<String>,
+  /// Per-device results for operations covering several devices.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub devices: Vec<DeviceInstallResult>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct DeviceInstallResult {
+  pub vendor_id: u16,
+  pub product_id: u16,
+  #[serde(default)]
+  pub interface: Option<u8>,
+  pub connected: bool,
+  pub verified: bool,
+  #[serde(default)]
+  pub reboot_required: bool,
+  pub bound_service: Option<String>,
+  pub bound_provider: Option<String>,
+  /// Why the install failed after retrying.
+  #[serde(default)]
+  pub error: Option<String>,
+}
+
+/// A device to install WinUSB for in a batch. Composite devices name the
+/// interface to bind, which becomes the `MI_xx` part of the hardware ID.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct BatchDevice {
+  pub vendor_id: u16,
+  pub product_id: u16,
+  #[serde(default)]
+  pub interface: Option<u8>,
+}
+
+impl OperationOutcome for DriverOperationResult {
+  fn failure(&self) -> Option<String> {
+    (!self.success).then(|| self.message.clone())
+  }
+
+  fn cancelled() -> Self {
+    DriverOperationResult {
+      success: false,
+      message: "Operation cancelled".to_string(),
+      ..Default::default()
+    }
+  }
+}
+
+impl DriverOperationResult {
+  pub fn failed(error: HayboxError) -> Self {
+    DriverOperationResult {
+      success: false,
+      message: error.to_string(),
+      ..Default::default()
+    }
+  }
+
+  pub fn unsupported(action: &str) -> Self {
+    DriverOperationResult {
+      success: false,
+      message: format!("{} is only available on Windows", action),
+      ..Default::default()
+    }
+  }
+
+  /// Reports an install by what the device is actually bound to afterwards,
+  /// not by pnputil's exit code alone.
+  pub fn from_install(outcome: InstallOutcome, message: String) -> Self {
+    let bound_provider = outcome.binding.as_ref().and_then(|b| b.provider.clone());
+
+    if outcome.bind_on_plug {
+      return DriverOperationResult {
+        success: true,
+        message: format!("{}; it will bind the next time the device is plugged in", message),
+        ..Default::default()
+      };
+    }
+
+    if outcome.reboot_required {
+      return DriverOperationResult {
+        success: true,
+        message: format!("{}; restart Windows to finish binding it", message),
+        reboot_required: true,
+        ..Default::default()
+      };
+    }
+
+    if outcome.verified {
+      return DriverOperationResult {
+        success: true,
+        message,
+        verified: true,
+        bound_provider,
+        ..Default::default()
+      };
+    }
+
+    let actual = match &outcome.binding {
+      Some(DeviceBinding {
+        config_manager_error_code: Some(code),
+        ..
+      }) if *code != 0 => format!("reports device error code {}", code),
+      Some(binding) => format!(
+        "is still bound to {}",
+        binding.service.as_deref().unwrap_or("no driver")
+      ),
+      None => "was not found after installation".to_string(),
+    };
+
+    DriverOperationResult {
+      success: false,
+      message: format!("{}, but the device {}", message, actual),
+      bound_provider,
+      ..Default::default()
+    }
+  }
+}
+
+pub fn is_device_connected_batch(devices_to_check: &[(u16, u16)]) -> Vec<bool> {
+  let connected = platform::current().connected_devices();
+  devices_to_check
+    .iter()
+    .map(|device| connected.contains(device))
+    .collect()
+}
+
+pub fn get_current_device_status() -> Result<DeviceStatus, Box<dyn std::error::Error>> {
+  let (devices, enumeration_backend) = platform::current().usb_devices();
+  let connected = |device: &UsbDeviceInfo| devices.contains(&(device.vid, device.pid));
+
+  let xinput_installed = xinput::is_installed();
+  let winusb_installed = platform::current().libusb_ready(DEVICES.gamecube_mode.vid, DEVICES.gamecube_mode.pid)?;
+  let virtual_controller_stack = virtual_controllers::detect().unwrap_or_else(|e| {
+    tracing::warn!("failed to detect virtual controller drivers: {}", e);
+    VirtualControllerStack::default()
+  });
+
+  Ok(DeviceStatus {
+    default_mode_connected: connected(&DEVICES.default_mode),
+    config_mode_connected: connected(&DEVICES.config_mode),
+    bootsel_mode_connected: connected(&DEVICES.bootsel_mode),
+    switch_mode_connected: connected(&DEVICES.switch_mode),
+    xinput_installed,
+    gamecube_adapter_connected: connected(&DEVICES.gamecube_mode),
+    winusb_installed,
+    virtual_controller_stack,
+    usb_access_problems: udev::access_problems(),
+    enumeration_backend,
+  })
+}
+
+/// The body of `uninstall_xinput`, which the elevated helper also runs directly.
+pub fn run_uninstall_xinput(take_ownership: Option<bool>) -> DriverOperationResult {
+  if !platform::current().capabilities().xinput_management {
+    return DriverOperationResult::unsupported("Removing XInput");
+  }
+
+  let result = xinput::uninstall(take_ownership.unwrap_or(false));
+  AuditEntry::new("uninstall_xinput").outcome(&result).record();
+
+  match result {
+    Ok(_) => DriverOperationResult {
+      success: true,
+      message: "XInput driver successfully uninstalled".to_string(),
+      ..Default::default()
+    },
+    Err(e) => DriverOperationResult {
+      success: false,
+      message: format!("Failed to uninstall XInput driver: {}", e),
+      ..Default::default()
+    },
+  }
+}
+
+/// The body of `reinstall_xinput`, which the elevated helper also runs directly.
+pub fn run_reinstall_xinput(take_ownership: Option<bool>) -> DriverOperationResult {
+  if !platform::current().capabilities().xinput_management {
+    return DriverOperationResult::unsupported("Restoring XInput");
+  }
+
+  let result = xinput::reinstall(take_ownership.unwrap_or(false));
+  AuditEntry::new("reinstall_xinput").outcome(&result).record();
+
+  match result {
+    Ok(_) => DriverOperationResult {
+      success: true,
+      message: "XInput driver successfully reinstalled".to_string(),
+      ..Default::default()
+    },
+    Err(e) => DriverOperationResult {
+      success: false,
+      message: format!("Failed to reinstall XInput driver: {}", e),
+      ..Default::default()
+    },
+  }
+}
+
+/// The body of `restore_xinput_from_backup`, which the elevated helper also runs directly.
+pub fn run_restore_xinput_from_backup(take_ownership: Option<bool>) -> DriverOperationResult {
+  let result = xinput::restore_from_backup(take_ownership.unwrap_or(false));
+  AuditEntry::new("restore_xinput_from_backup").outcome(&result).record();
+
+  match result {
+    Ok(_) => DriverOperationResult {
+      success: true,
+      message: "XInput driver restored from backup".to_string(),
+      ..Default::default()
+    },
+    Err(e) => DriverOperationResult {
+      success: false,
+      message: format!("Failed to restore XInput driver from backup: {}", e),
+      ..Default::default()
+    },
+  }
+}
+
+/// The body of `delete_xinput_backup`, which the elevated helper also runs directly.
+pub fn run_delete_xinput_backup(take_ownership: Option<bool>) -> DriverOperationResult {
+  let result = xinput::delete_backup(take_ownership.unwrap_or(false));
+  AuditEntry::new("delete_xinput_backup").outcome(&result).record();
+
+  match result {
+    Ok(_) => DriverOperationResult {
+      success: true,
+      message: "XInput backup deleted".to_string(),
+      ..Default::default()
+    },
+    Err(e) => DriverOperationResult {
+      success: false,
+      message: format!("Failed to delete XInput backup: {}", e),
+      ..Default::default()
+    },
+  }
+}
+
+/// The body of `install_hidhide`, which the elevated helper also runs directly.
+pub fn run_install_hidhide() -> DriverOperationResult {
+  if !platform::current().capabilities().hidhide {
+    return DriverOperationResult::unsupported("HidHide");
+  }
+
+  match hidhide::install() {
+    Ok(reboot_required) => DriverOperationResult {
+      success: true,
+      message: if reboot_required {
+        "HidHide installed; restart Windows to finish setting it up".to_string()
+      } else {
+        "HidHide is installed".to_string()
+      },
+      reboot_required,
+      ..Default::default()
+    },
+    Err(e) => DriverOperationResult {
+      success: false,
+      message: format!("Failed to install HidHide: {}", e),
+      ..Default::default()
+    },
+  }
+}
+
+/// The body of `hide_controller`, which the elevated helper also runs directly.
+pub fn run_hide_controller(vid: u16, pid: u16) -> DriverOperationResult {
+  match hidhide::hide_device(vid, pid) {
+    Ok(instances) => DriverOperationResult {
+      success: true,
+      message: format!("Hidden {} HID interface(s) of {:04X}:{:04X}", instances.len(), vid, pid),
+      ..Default::default()
+    },
+    Err(e) => DriverOperationResult {
+      success: false,
+      message: format!("Failed to hide controller: {}", e),
+      ..Default::default()
+    },
+  }
+}
+
+/// The body of `unhide_controller`, which the elevated helper also runs directly.
+pub fn run_unhide_controller(vid: u16, pid: u16) -> DriverOperationResult {
+  match hidhide::unhide_device(vid, pid) {
+    Ok(instances) => DriverOperationResult {
+      success: true,
+      message: format!(
+        "Unhidden {} HID interface(s) of {:04X}:{:04X}",
+        instances.len(),
+        vid,
+        pid
+      ),
+      ..Default::default()
+    },
+    Err(e) => DriverOperationResult {
+      success: false,
+      message: format!("Failed to unhide controller: {}", e),
+      ..Default::default()
+    },
+  }
+}
+
+/// The body of `allow_hidhide_app`, which the elevated helper also runs directly.
+pub fn run_allow_hidhide_app(exe_path: String) -> DriverOperationResult {
+  match hidhide::allow_app(&exe_path) {
+    Ok(_) => DriverOperationResult {
+      success: true,
+      message: format!("{} can now see hidden controllers", exe_path),
+      ..Default::default()
+    },
+    Err(e) => DriverOperationResult {
+      success: false,
+      message: format!("Failed to allow {}: {}", exe_path, e),
+      ..Default::default()
+    },
+  }
+}
+
+/// The body of `disallow_hidhide_app`, which the elevated helper also runs directly.
+pub fn run_disallow_hidhide_app(exe_path: String) -> DriverOperationResult {
+  match hidhide::disallow_app(&exe_path) {
+    Ok(_) => DriverOperationResult {
+      success: true,
+      message: format!("{} no longer sees hidden controllers", exe_path),
+      ..Default::default()
+    },
+    Err(e) => DriverOperationResult {
+      success: false,
+      message: format!("Failed to remove {}: {}", exe_path, e),
+      ..Default::default()
+    },
+  }
+}
+
+/// The body of `set_hidhide_active`, which the elevated helper also runs directly.
+pub fn run_set_hidhide_active(active: bool) -> DriverOperationResult {
+  match hidhide::set_active(active) {
+    Ok(_) => DriverOperationResult {
+      success: true,
+      message: format!("HidHide {}", if active { "enabled" } else { "disabled" }),
+      ..Default::default()
+    },
+    Err(e) => DriverOperationResult {
+      success: false,
+      message: format!("Failed to change HidHide state: {}", e),
+      ..Default::default()
+    },
+  }
+}
+
+/// `install_winusb` without the operation queue, for the elevated helper and
+/// the CLI.
+pub fn run_install_winusb() -> DriverOperationResult {
+  if !platform::current().capabilities().driver_install {
+    return DriverOperationResult::unsupported("Installing drivers");
+  }
+
+  if !check_admin_rights() {
+    return DriverOperationResult::failed(HayboxError::elevation_required());
+  }
+
+  let gamecube_mode = &DEVICES.gamecube_mode;
+  // Checked through PnP like the batch install: libusb cannot see the
+  // adapter until WinUSB is bound, which is what this installs.
+  let is_connected = is_device_connected_batch(&[(gamecube_mode.vid, gamecube_mode.pid)])[0];
+
+  // Without the adapter plugged in the driver can only be added to the
+  // store; the hotplug watcher verifies the binding once it shows up.
+  let config = ConfigBuilder::new()
+    .vendor_id(gamecube_mode.vid)
+    .product_id(gamecube_mode.pid)
+    .description(&gamecube_mode.name)
+    .manufacturer("Nintendo")
+    .preinstall(!is_connected)
+    .build();
+
+  match platform::current().install_driver(&config) {
+    Ok(outcome) => {
+      DriverOperationResult::from_install(outcome, "WinUSB driver installed for GameCube adapter".to_string())
+    }
+    Err(e) => DriverOperationResult {
+      success: false,
+      message: format!("Failed to install WinUSB driver: {}", e),
+      ..Default::default()
+    },
+  }
+}
+
+/// `install_driver_for` without the operation queue, for the elevated helper.
+pub fn run_install_driver_for(kind: DriverKind, vid: u16, pid: u16, interface: Option<u8>) -> DriverOperationResult {
+  if !platform::current().capabilities().driver_install {
+    return DriverOperationResult::unsupported("Installing drivers");
+  }
+
+  if !check_admin_rights() {
+    return DriverOperationResult::failed(HayboxError::elevation_required());
+  }
+
+  if !is_device_connected_batch(&[(vid, pid)])[0] {
+    return DriverOperationResult {
+      success: false,
+      message: format!(
+        "Device {:04X}:{:04X} not found. Please make sure it is connected.",
+        vid, pid
+      ),
+      ..Default::default()
+    };
+  }
+
+  let (description, manufacturer) = if vid == DEVICES.gamecube_mode.vid && pid == DEVICES.gamecube_mode.pid {
+    (DEVICES.gamecube_mode.name.clone(), "Nintendo")
+  } else {
+    (format!("USB Device ({:04X}:{:04X})", vid, pid), "HayBox")
+  };
+
+  let mut builder = ConfigBuilder::new()
+    .vendor_id(vid)
+    .product_id(pid)
+    .description(&description)
+    .manufacturer(manufacturer)
+    .kind(kind);
+  if let Some(interface) = interface {
+    builder = builder.interface(interface);
+  }
+  let config = builder.build();
+
+  match platform::current().install_driver(&config) {
+    Ok(outcome) => DriverOperationResult::from_install(
+      outcome,
+      format!("{} driver installed for {}", kind, config.hardware_id()),
+    ),
+    Err(e) => DriverOperationResult {
+      success: false,
+      message: format!("Failed to install {} driver: {}", kind, e),
+      ..Default::default()
+    },
+  }
+}
+
+/// `install_winusb_batch` without the operation queue, for the elevated helper.
+/// Each device gets its own package so composite devices can be bound per
+/// interface and one failure does not take the rest down with it.
+pub fn run_install_winusb_batch(devices: Vec<BatchDevice>) -> DriverOperationResult {
+  if !platform::current().capabilities().driver_install {
+    return DriverOperationResult::unsupported("Installing drivers");
+  }
+
+  if !check_admin_rights() {
+    return DriverOperationResult::failed(HayboxError::elevation_required());
+  }
+
+  if devices.is_empty() {
+    return DriverOperationResult {
+      success: false,
+      message: "No devices selected".to_string(),
+      ..Default::default()
+    };
+  }
+
+  let ids: Vec<(u16, u16)> = devices.iter().map(|d| (d.vendor_id, d.product_id)).collect();
+  let connected = is_device_connected_batch(&ids);
+
+  let results: Vec<DeviceInstallResult> = devices
+    .iter()
+    .zip(connected)
+    .map(|(device, connected)| install_batch_device(device, connected))
+    .collect();
+
+  let present = results.iter().filter(|r| r.connected).count();
+  let verified = results.iter().filter(|r| r.verified).count();
+  let failed = results.iter().filter(|r| r.error.is_some()).count();
+  let reboot_required = results.iter().any(|r| r.reboot_required);
+
+  let mut message = format!(
+    "WinUSB driver installed; {} of {} connected device(s) bound, {} will bind when plugged in",
+    verified,
+    present,
+    results.len() - present
+  );
+  if failed > 0 {
+    message = format!("{}, {} failed", message, failed);
+  }
+  if reboot_required {
+    message = format!("{}; restart Windows to finish binding the rest", message);
+  }
+
+  DriverOperationResult {
+    success: failed == 0 && results.iter().all(|r| !r.connected || r.verified || r.reboot_required),
+    message,
+    reboot_required,
+    verified: verified == results.len(),
+    devices: results,
+    ..Default::default()
+  }
+}
+
+/// Installs the package for one device of a batch, retrying once when
+/// pnputil fails or the device does not pick the driver up.
+fn install_batch_device(device: &BatchDevice, connected: bool) -> DeviceInstallResult {
+  let mut result = DeviceInstallResult {
+    vendor_id: device.vendor_id,
+    product_id: device.product_id,
+    interface: device.interface,
+    connected,
+    ..Default::default()
+  };
+
+  for attempt in 0..BATCH_INSTALL_ATTEMPTS {
+    if attempt > 0 {
+      tracing::warn!(
+        "retrying the WinUSB install for {:04X}:{:04X}",
+        device.vendor_id,
+        device.product_id
+      );
+    }
+
+    let mut builder = ConfigBuilder::new()
+      .vendor_id(device.vendor_id)
+      .product_id(device.product_id)
+      .description("HayBox USB Device")
+      .manufacturer("HayBox")
+      .preinstall(!connected);
+    if let Some(interface) = device.interface {
+      builder = builder.interface(interface);
+    }
+
+    match platform::current().install_driver(&builder.build()) {
+      Ok(outcome) => {
+        result.error = None;
+        result.reboot_required = outcome.reboot_required;
+        result.verified = outcome.verified;
+        result.bound_service = outcome.binding.as_ref().and_then(|b| b.service.clone());
+        result.bound_provider = outcome.binding.and_then(|b| b.provider);
+        if outcome.verified || outcome.reboot_required || outcome.bind_on_plug {
+          break;
+        }
+      }
+      Err(e) => result.error = Some(e.to_string()),
+    }
+  }
+
+  result
+}
+
+/// `restore_default_driver` without the operation queue, for the elevated helper.
+pub fn run_restore_default_driver(vid: u16, pid: u16) -> DriverOperationResult {
+  match driver::restore_default_driver(vid, pid) {
+    Ok(_) => DriverOperationResult {
+      success: true,
+      message: format!("Default HID driver restored for {:04X}:{:04X}", vid, pid),
+      ..Default::default()
+    },
+    Err(e) => DriverOperationResult {
+      success: false,
+      message: format!("Failed to restore default driver: {}", e),
+      ..Default::default()
+    },
+  }
+}
+
+/// The body of `remove_stale_drivers`, which the elevated helper also runs directly.
+pub fn run_remove_stale_drivers() -> DriverOperationResult {
+  match driver_store::remove_stale_drivers() {
+    Ok(removed) if removed.is_empty() => DriverOperationResult {
+      success: true,
+      message: "No stale driver packages found".to_string(),
+      ..Default::default()
+    },
+    Ok(removed) => DriverOperationResult {
+      success: true,
+      message: format!(
+        "Removed {} stale driver package(s): {}",
+        removed.len(),
+        removed.join(", ")
+      ),
+      ..Default::default()
+    },
+    Err(e) => DriverOperationResult {
+      success: false,
+      message: format!("Failed to remove stale drivers: {}", e),
+      ..Default::default()
+    },
+  }
+}
+
+/// The body of `clean_driver_cache`, which the elevated helper also runs directly.
+pub fn run_clean_driver_cache() -> DriverOperationResult {
+  if !check_admin_rights() {
+    return DriverOperationResult::failed(HayboxError::elevation_required());
+  }
+
+  match staging::clean_driver_cache() {
+    Ok(removed) => DriverOperationResult {
+      success: true,
+      message: format!("Removed {} cached staging folder(s)", removed),
+      ..Default::default()
+    },
+    Err(e) => DriverOperationResult {
+      success: false,
+      message: format!("Failed to clean driver cache: {}", e),
+      ..Default::default()
+    },
+  }
+}
+
+/// The body of `replace_driver`, which the elevated helper also runs directly.
+pub fn run_replace_driver(device_instance_id: String, kind: DriverKind) -> DriverOperationResult {
+  if !platform::current().capabilities().driver_install {
+    return DriverOperationResult::unsupported("Replacing drivers");
+  }
+
+  if !check_admin_rights() {
+    return DriverOperationResult::failed(HayboxError::elevation_required());
+  }
+
+  let device = match pnp::find_device(&device_instance_id) {
+    Ok(Some(device)) => device,
+    Ok(None) => {
+      return DriverOperationResult {
+        success: false,
+        message: format!("Device {} not found", device_instance_id),
+        ..Default::default()
+      }
+    }
+    Err(e) => {
+      return DriverOperationResult {
+        success: false,
+        message: e.to_string(),
+        ..Default::default()
+      }
+    }
+  };
+
+  let ids = match device.ids.filter(|_| device.replaceable) {
+    Some(ids) => ids,
+    None => {
+      return DriverOperationResult {
+        success: false,
+        message: format!(
+          "{} cannot be rebound; select its parent USB device instead",
+          device.name
+        ),
+        ..Default::default()
+      }
+    }
+  };
+
+  let mut builder = ConfigBuilder::new()
+    .vendor_id(ids.vendor_id)
+    .product_id(ids.product_id)
+    .description(&device.name)
+    .manufacturer(device.driver_provider.as_deref().unwrap_or("HayBox"))
+    .kind(kind);
+  if let Some(interface) = ids.interface {
+    builder = builder.interface(interface);
+  }
+  let config = builder.build();
+
+  match platform::current().install_driver(&config) {
+    Ok(outcome) => {
+      DriverOperationResult::from_install(outcome, format!("{} driver installed for {}", kind, device.name))
+    }
+    Err(e) => DriverOperationResult {
+      success: false,
+      message: format!("Failed to replace driver for {}: {}", device.name, e),
+      ..Default::default()
+    },
+  }
+}
+
+pub fn check_admin_rights() -> bool {
+  platform::current().is_elevated()
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DriverInfo {
+  device_id: String,
+  device_name: String,
+  driver_provider: Option<String>,
+  driver_version: Option<String>,
+  driver_date: Option<String>,
+  is_winusb: bool,
+}
@@ -0,0 +1,149 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config_proto::{self, Button, ButtonToColorMapping, Config, RgbConfig};
+use crate::error::HayboxError;
+
+const MAX_COLOR: u32 = 0xFF_FFFF;
+const MAX_BRIGHTNESS: u32 = 255;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ButtonColor {
+  pub button: Button,
+  /// 0xRRGGBB.
+  pub color: u32,
+}
+
+/// A profile's lighting. Brightness is shared by every profile on the
+/// controller.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LightingSettings {
+  pub brightness: u32,
+  pub default_color: u32,
+  pub button_colors: Vec<ButtonColor>,
+}
+
+fn button(value: i32) -> Button {
+  Button::try_from(value).unwrap_or(Button::Unspecified)
+}
+
+fn settings(config: &Config, rgb: Option<&RgbConfig>) -> LightingSettings {
+  let rgb = rgb.cloned().unwrap_or_default();
+  LightingSettings {
+    brightness: config.rgb_brightness,
+    default_color: rgb.default_color,
+    button_colors: rgb
+      .button_colors
+      .iter()
+      .map(|mapping| ButtonColor {
+        button: button(mapping.button),
+        color: mapping.color,
+      })
+      .collect(),
+  }
+}
+
+fn validate(settings: &LightingSettings) -> Result<(), HayboxError> {
+  if settings.brightness > MAX_BRIGHTNESS {
+    return Err(HayboxError::Other(format!(
+      "Brightness must be between 0 and {}",
+      MAX_BRIGHTNESS
+    )));
+  }
+  if settings.default_color > MAX_COLOR {
+    return Err(HayboxError::Other(format!(
+      "Default color {:#X} is not an 0xRRGGBB value",
+      settings.default_color
+    )));
+  }
+
+  let mut seen = HashSet::new();
+  for entry in &settings.button_colors {
+    if entry.button == Button::Unspecified {
+      return Err(HayboxError::Other("Button colors must name a button".to_string()));
+    }
+    if entry.color > MAX_COLOR {
+      return Err(HayboxError::Other(format!(
+        "Color {:#X} for {:?} is not an 0xRRGGBB value",
+        entry.color, entry.button
+      )));
+    }
+    if !seen.insert(entry.button) {
+      return Err(HayboxError::Other(format!(
+        "{:?} has more than one color",
+        entry.button
+      )));
+    }
+  }
+  Ok(())
+}
+
+pub fn get_lighting(profile: u32) -> Result<LightingSettings, HayboxError> {
+  let config = config_proto::get_config()?;
+  let mode = config
+    .game_mode_configs
+    .get(profile as usize)
+    .ok_or_else(|| HayboxError::Other(format!("Profile {} does not exist", profile)))?;
+  Ok(settings(&config, config.rgb_configs.get(mode.rgb_config as usize)))
+}
+
+/// Writes a profile's lighting. Profiles share lighting entries by index, so
+/// an entry used by other profiles is copied first to leave theirs alone; the
+/// copy goes into an entry no profile uses when there is one.
+pub fn set_lighting(profile: u32, lighting: &LightingSettings) -> Result<LightingSettings, HayboxError> {
+  validate(lighting)?;
+
+  config_proto::with_connection(|conn| {
+    let mut config = conn.get_config()?;
+    let current = config
+      .game_mode_configs
+      .get(profile as usize)
+      .ok_or_else(|| HayboxError::Other(format!("Profile {} does not exist", profile)))?
+      .rgb_config as usize;
+    let shared = config
+      .game_mode_configs
+      .iter()
+      .enumerate()
+      .any(|(index, mode)| index != profile as usize && mode.rgb_config as usize == current);
+
+    let index = if current < config.rgb_configs.len() && !shared {
+      current
+    } else {
+      let copy = config.rgb_configs.get(current).cloned().unwrap_or_default();
+      let unused = (0..config.rgb_configs.len()).find(|&index| {
+        index != current
+          && !config
+            .game_mode_configs
+            .iter()
+            .any(|mode| mode.rgb_config as usize == index)
+      });
+      match unused {
+        Some(index) => {
+          config.rgb_configs[index] = copy;
+          index
+        }
+        None => {
+          config.rgb_configs.push(copy);
+          config.rgb_configs.len() - 1
+        }
+      }
+    };
+    config.game_mode_configs[profile as usize].rgb_config = index as u32;
+
+    config.rgb_brightness = lighting.brightness;
+    let rgb = &mut config.rgb_configs[index];
+    rgb.button_colors = lighting
+      .button_colors
+      .iter()
+      .map(|entry| ButtonToColorMapping {
+        button: entry.button as i32,
+        color: entry.color,
+      })
+      .collect();
+    rgb.default_color = lighting.default_color;
+
+    conn.set_config(&config)?;
+    Ok(settings(&config, config.rgb_configs.get(index)))
+  })
+}
@@ -0,0 +1,209 @@
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, reload, Layer, Registry};
+
+use crate::error::HayboxError;
+use crate::paths::app_data_dir;
+
+const LOG_DIR: &str = "logs";
+const LOG_FILE_PREFIX: &str = "haybox-debugger";
+/// Daily log files kept before the oldest is deleted.
+const MAX_LOG_FILES: usize = 7;
+/// Entries kept in memory for `get_recent_logs`.
+const RECENT_LOG_CAPACITY: usize = 2000;
+const DEFAULT_RECENT_LOG_LIMIT: usize = 200;
+
+lazy_static::lazy_static! {
+  static ref RECENT_LOGS: Mutex<VecDeque<LogEntry>> = Mutex::new(VecDeque::with_capacity(RECENT_LOG_CAPACITY));
+  static ref LEVEL_HANDLE: Mutex<Option<reload::Handle<LevelFilter, Registry>>> = Mutex::new(None);
+}
+
+/// Ordered from most to least severe, so `level <= filter` keeps everything
+/// at least as severe as `filter`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum LogLevel {
+  Error,
+  Warn,
+  Info,
+  Debug,
+  Trace,
+}
+
+impl From<&Level> for LogLevel {
+  fn from(level: &Level) -> Self {
+    match *level {
+      Level::ERROR => LogLevel::Error,
+      Level::WARN => LogLevel::Warn,
+      Level::INFO => LogLevel::Info,
+      Level::DEBUG => LogLevel::Debug,
+      Level::TRACE => LogLevel::Trace,
+    }
+  }
+}
+
+impl From<LogLevel> for LevelFilter {
+  fn from(level: LogLevel) -> Self {
+    match level {
+      LogLevel::Error => LevelFilter::ERROR,
+      LogLevel::Warn => LevelFilter::WARN,
+      LogLevel::Info => LevelFilter::INFO,
+      LogLevel::Debug => LevelFilter::DEBUG,
+      LogLevel::Trace => LevelFilter::TRACE,
+    }
+  }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LogEntry {
+  /// Milliseconds since the Unix epoch.
+  pub timestamp_ms: u64,
+  pub level: LogLevel,
+  /// Module the event came from, e.g. `tauri_template_lib::driver`.
+  pub target: String,
+  pub message: String,
+}
+
+/// Formats an event's message followed by its other fields as `key=value`.
+#[derive(Default)]
+struct MessageVisitor(String);
+
+impl Visit for MessageVisitor {
+  fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+    if field.name() == "message" {
+      let _ = write!(self.0, "{:?}", value);
+    } else {
+      let _ = write!(self.0, " {}={:?}", field.name(), value);
+    }
+  }
+}
+
+/// Keeps the most recent events in memory so the UI can show them without
+/// reading the log files.
+struct RecentLogsLayer;
+
+impl<S: Subscriber> Layer<S> for RecentLogsLayer {
+  fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+    let mut visitor = MessageVisitor::default();
+    event.record(&mut visitor);
+    let entry = LogEntry {
+      timestamp_ms: SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default(),
+      level: event.metadata().level().into(),
+      target: event.metadata().target().to_string(),
+      message: visitor.0.trim_start().to_string(),
+    };
+
+    let mut logs = RECENT_LOGS.lock().unwrap();
+    if logs.len() == RECENT_LOG_CAPACITY {
+      logs.pop_front();
+    }
+    logs.push_back(entry);
+  }
+}
+
+fn file_appender() -> Result<RollingFileAppender, HayboxError> {
+  RollingFileAppender::builder()
+    .rotation(Rotation::DAILY)
+    .filename_prefix(LOG_FILE_PREFIX)
+    .filename_suffix("log")
+    .max_log_files(MAX_LOG_FILES)
+    .build(app_data_dir().join(LOG_DIR))
+    .map_err(|e| HayboxError::Io(format!("Failed to open log file: {}", e)))
+}
+
+/// Log files currently on disk, oldest first.
+pub fn log_files() -> Vec<PathBuf> {
+  let Ok(entries) = std::fs::read_dir(app_data_dir().join(LOG_DIR)) else {
+    return Vec::new();
+  };
+  let mut files: Vec<PathBuf> = entries
+    .flatten()
+    .map(|entry| entry.path())
+    .filter(|path| {
+      path.is_file()
+        && path
+          .file_name()
+          .is_some_and(|name| name.to_string_lossy().starts_with(LOG_FILE_PREFIX))
+    })
+    .collect();
+  // Daily files are suffixed with the date, so names sort by age.
+  files.sort();
+  files
+}
+
+/// Sends events to stdout, a daily log file under the app data directory
+/// and the in-memory buffer behind `get_recent_logs`. Call once, before
+/// anything logs.
+pub fn init() {
+  let default_level = if cfg!(debug_assertions) {
+    LevelFilter::DEBUG
+  } else {
+    LevelFilter::INFO
+  };
+  let (level_filter, handle) = reload::Layer::new(default_level);
+
+  let file_layer = match file_appender() {
+    Ok(appender) => Some(fmt::layer().with_ansi(false).with_writer(appender)),
+    Err(e) => {
+      eprintln!("Warning: {}", e);
+      None
+    }
+  };
+
+  let initialized = tracing_subscriber::registry()
+    .with(level_filter)
+    .with(RecentLogsLayer)
+    .with(fmt::layer())
+    .with(file_layer)
+    .try_init();
+  if initialized.is_ok() {
+    *LEVEL_HANDLE.lock().unwrap() = Some(handle);
+  }
+}
+
+pub fn set_level(level: LogLevel) -> Result<(), HayboxError> {
+  let handle = LEVEL_HANDLE.lock().unwrap();
+  let handle = handle
+    .as_ref()
+    .ok_or_else(|| HayboxError::Other("Logging is not initialized".to_string()))?;
+  handle
+    .reload(LevelFilter::from(level))
+    .map_err(|e| HayboxError::Other(format!("Failed to change log level: {}", e)))
+}
+
+/// Like `recent_logs`, but returns nothing instead of waiting when the buffer
+/// is locked. For the crash handler, which may run while it is held.
+pub fn try_recent_logs(limit: usize) -> Vec<LogEntry> {
+  let Ok(logs) = RECENT_LOGS.try_lock() else {
+    return Vec::new();
+  };
+  logs.iter().skip(logs.len().saturating_sub(limit)).cloned().collect()
+}
+
+/// The newest `limit` entries at `level` or more severe, oldest first.
+pub fn recent_logs(level: Option<LogLevel>, limit: Option<usize>) -> Vec<LogEntry> {
+  let logs = RECENT_LOGS.lock().unwrap();
+  let mut entries: Vec<LogEntry> = logs
+    .iter()
+    .rev()
+    .filter(|entry| level.is_none_or(|level| entry.level <= level))
+    .take(limit.unwrap_or(DEFAULT_RECENT_LOG_LIMIT))
+    .cloned()
+    .collect();
+  entries.reverse();
+  entries
+}
@@ -0,0 +1,371 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::{self, Read};
+use std::process::{Command, Output, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::HayboxError;
+use crate::events::Events;
+
+/// Finished operations kept for the task list.
+const FINISHED_KEPT: usize = 20;
+/// How often a child process is checked for exit or cancellation.
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+const CANCELLED: &str = "Operation cancelled";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OperationStatus {
+  Queued,
+  Running,
+  Succeeded,
+  Failed,
+  Cancelled,
+}
+
+/// One entry of the task list, emitted as a whole as `operations_changed`
+/// on every change.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OperationInfo {
+  pub id: u64,
+  pub description: String,
+  pub status: OperationStatus,
+  /// From 0 to 1, for operations that report it.
+  pub progress: Option<f32>,
+  /// Set when a running operation was asked to stop but has not yet.
+  pub cancel_requested: bool,
+  pub error: Option<String>,
+  /// Seconds since the Unix epoch.
+  pub queued_at: u64,
+}
+
+/// Lets the queue tell whether an operation failed and build the result of
+/// one that was cancelled before it started.
+pub trait OperationOutcome {
+  fn failure(&self) -> Option<String>;
+  fn cancelled() -> Self;
+}
+
+impl<T> OperationOutcome for Result<T, HayboxError> {
+  fn failure(&self) -> Option<String> {
+    self.as_ref().err().map(|e| e.to_string())
+  }
+
+  fn cancelled() -> Self {
+    Err(HayboxError::Other(CANCELLED.to_string()))
+  }
+}
+
+struct Operation {
+  info: OperationInfo,
+  cancel: Arc<AtomicBool>,
+}
+
+#[derive(Default)]
+struct QueueState {
+  next_id: u64,
+  /// Oldest first. Queued operations run in this order.
+  operations: VecDeque<Operation>,
+}
+
+impl QueueState {
+  fn get_mut(&mut self, id: u64) -> Option<&mut Operation> {
+    self.operations.iter_mut().find(|operation| operation.info.id == id)
+  }
+
+  /// The operation that runs now or next.
+  fn head(&self) -> Option<u64> {
+    self
+      .operations
+      .iter()
+      .find(|operation| {
+        matches!(
+          operation.info.status,
+          OperationStatus::Queued | OperationStatus::Running
+        )
+      })
+      .map(|operation| operation.info.id)
+  }
+
+  fn prune(&mut self) {
+    let finished = |operation: &Operation| {
+      !matches!(
+        operation.info.status,
+        OperationStatus::Queued | OperationStatus::Running
+      )
+    };
+    let mut excess = self.operations.iter().filter(|operation| finished(operation)).count();
+    self.operations.retain(|operation| {
+      if excess > FINISHED_KEPT && finished(operation) {
+        excess -= 1;
+        return false;
+      }
+      true
+    });
+  }
+}
+
+struct Shared {
+  events: Events,
+  state: Mutex<QueueState>,
+  changed: Condvar,
+}
+
+impl Shared {
+  fn list(&self) -> Vec<OperationInfo> {
+    let state = self.state.lock().unwrap();
+    state
+      .operations
+      .iter()
+      .map(|operation| operation.info.clone())
+      .collect()
+  }
+
+  fn notify(&self) {
+    self.changed.notify_all();
+    self.events.emit("operations_changed", self.list());
+  }
+
+  fn finish(&self, id: u64, failure: Option<String>) {
+    {
+      let mut state = self.state.lock().unwrap();
+      if let Some(operation) = state.get_mut(id) {
+        let cancelled = operation.cancel.load(Ordering::SeqCst);
+        operation.info.status = match (&failure, cancelled) {
+          (None, _) => OperationStatus::Succeeded,
+          (Some(_), true) => OperationStatus::Cancelled,
+          (Some(_), false) => OperationStatus::Failed,
+        };
+        operation.info.cancel_requested = false;
+        operation.info.error = failure;
+      }
+      state.prune();
+    }
+    self.notify();
+  }
+}
+
+#[derive(Clone)]
+struct CurrentOperation {
+  shared: Arc<Shared>,
+  id: u64,
+  cancel: Arc<AtomicBool>,
+}
+
+thread_local! {
+  /// The operation running on this thread, so code deep in a driver install
+  /// can report progress and notice cancellation without extra parameters.
+  static CURRENT: RefCell<Option<CurrentOperation>> = const { RefCell::new(None) };
+}
+
+fn current() -> Option<CurrentOperation> {
+  CURRENT.with(|current| current.borrow().clone())
+}
+
+/// Clears the thread's current operation, and marks it failed if it is
+/// still running because the work panicked.
+struct RunningGuard<'a> {
+  shared: &'a Shared,
+  id: u64,
+}
+
+impl Drop for RunningGuard<'_> {
+  fn drop(&mut self) {
+    CURRENT.with(|current| current.borrow_mut().take());
+    let running = self
+      .shared
+      .state
+      .lock()
+      .map(|mut state| {
+        state
+          .get_mut(self.id)
+          .is_some_and(|operation| operation.info.status == OperationStatus::Running)
+      })
+      .unwrap_or(false);
+    if running {
+      self.shared.finish(self.id, Some("The operation panicked".to_string()));
+    }
+  }
+}
+
+/// Runs long operations such as driver installs, flashing and latency tests
+/// one at a time, so they cannot race each other. Kept in Tauri's managed
+/// state.
+#[derive(Clone)]
+pub struct OperationQueue {
+  shared: Arc<Shared>,
+}
+
+impl OperationQueue {
+  pub fn new(events: Events) -> Self {
+    OperationQueue {
+      shared: Arc::new(Shared {
+        events,
+        state: Mutex::new(QueueState::default()),
+        changed: Condvar::new(),
+      }),
+    }
+  }
+
+  /// Queued and running operations, then the most recent finished ones,
+  /// oldest first.
+  pub fn list(&self) -> Vec<OperationInfo> {
+    self.shared.list()
+  }
+
+  /// Waits until every operation queued earlier has finished, then runs `f`
+  /// on the calling thread. Returns `T::cancelled()` without running `f` if
+  /// the operation is cancelled while it waits.
+  pub fn run<T: OperationOutcome>(&self, description: &str, f: impl FnOnce() -> T) -> T {
+    let cancel = Arc::new(AtomicBool::new(false));
+    let id = {
+      let mut state = self.shared.state.lock().unwrap();
+      let id = state.next_id;
+      state.next_id += 1;
+      state.operations.push_back(Operation {
+        info: OperationInfo {
+          id,
+          description: description.to_string(),
+          status: OperationStatus::Queued,
+          progress: None,
+          cancel_requested: false,
+          error: None,
+          queued_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default(),
+        },
+        cancel: cancel.clone(),
+      });
+      id
+    };
+    self.shared.notify();
+
+    {
+      let mut state = self.shared.state.lock().unwrap();
+      loop {
+        // A cancelled operation may already have been pruned.
+        match state.get_mut(id).map(|operation| operation.info.status) {
+          Some(OperationStatus::Cancelled) | None => return T::cancelled(),
+          _ => {}
+        }
+        if state.head() == Some(id) {
+          if let Some(operation) = state.get_mut(id) {
+            operation.info.status = OperationStatus::Running;
+          }
+          break;
+        }
+        state = self.shared.changed.wait(state).unwrap();
+      }
+    }
+    self.shared.notify();
+
+    let guard = RunningGuard {
+      shared: &self.shared,
+      id,
+    };
+    CURRENT.with(|current| {
+      *current.borrow_mut() = Some(CurrentOperation {
+        shared: self.shared.clone(),
+        id,
+        cancel,
+      })
+    });
+    let outcome = f();
+    self.shared.finish(id, outcome.failure());
+    drop(guard);
+    outcome
+  }
+
+  /// Drops a queued operation, or asks a running one to stop. A running
+  /// operation stops at its next cancellation check; a child process it
+  /// started through `output` is killed.
+  pub fn cancel(&self, id: u64) -> Result<(), HayboxError> {
+    {
+      let mut state = self.shared.state.lock().unwrap();
+      let operation = state
+        .get_mut(id)
+        .ok_or_else(|| HayboxError::Other(format!("No operation with ID {}", id)))?;
+      match operation.info.status {
+        OperationStatus::Queued => {
+          operation.info.status = OperationStatus::Cancelled;
+          operation.info.error = Some(CANCELLED.to_string());
+        }
+        OperationStatus::Running => {
+          operation.cancel.store(true, Ordering::SeqCst);
+          operation.info.cancel_requested = true;
+        }
+        _ => return Err(HayboxError::Other(format!("Operation {} has already finished", id))),
+      }
+      state.prune();
+    }
+    self.shared.notify();
+    Ok(())
+  }
+}
+
+/// Whether the operation running on this thread was cancelled. Always false
+/// outside the queue.
+pub fn is_cancelled() -> bool {
+  current().is_some_and(|current| current.cancel.load(Ordering::SeqCst))
+}
+
+/// Sets the progress, from 0 to 1, of the operation running on this thread.
+/// Does nothing outside the queue.
+pub fn report_progress(progress: f32) {
+  let Some(current) = current() else {
+    return;
+  };
+  if let Some(operation) = current.shared.state.lock().unwrap().get_mut(current.id) {
+    operation.info.progress = Some(progress.clamp(0.0, 1.0));
+  }
+  current.shared.events.emit("operations_changed", current.shared.list());
+}
+
+fn read_in_background(mut pipe: impl Read + Send + 'static) -> JoinHandle<Vec<u8>> {
+  std::thread::spawn(move || {
+    let mut buffer = Vec::new();
+    let _ = pipe.read_to_end(&mut buffer);
+    buffer
+  })
+}
+
+/// Like `Command::output`, but kills the process when the operation running
+/// on this thread is cancelled, so a stuck pnputil call can be aborted.
+pub fn output(command: &mut Command) -> io::Result<Output> {
+  let Some(current) = current() else {
+    return command.output();
+  };
+
+  let mut child = command
+    .stdin(Stdio::null())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped())
+    .spawn()?;
+  let stdout = child.stdout.take().map(read_in_background);
+  let stderr = child.stderr.take().map(read_in_background);
+
+  let status = loop {
+    if let Some(status) = child.try_wait()? {
+      break status;
+    }
+    if current.cancel.load(Ordering::SeqCst) {
+      let _ = child.kill();
+      let _ = child.wait();
+      return Err(io::Error::new(io::ErrorKind::Interrupted, CANCELLED));
+    }
+    std::thread::sleep(CANCEL_POLL_INTERVAL);
+  };
+
+  let collect = |reader: Option<JoinHandle<Vec<u8>>>| reader.and_then(|reader| reader.join().ok()).unwrap_or_default();
+  Ok(Output {
+    status,
+    stdout: collect(stdout),
+    stderr: collect(stderr),
+  })
+}
@@ -0,0 +1,82 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+/// Matches the bundle identifier in tauri.conf.json so files end up in the
+/// same directory Tauri's path resolver would use.
+const APP_IDENTIFIER: &str = "com.haybox-debugger.app";
+
+/// A file with this name next to the executable turns on portable mode.
+const PORTABLE_FLAG_FILE: &str = "portable";
+/// Command line switch that turns on portable mode without the flag file.
+pub const PORTABLE_SWITCH: &str = "--portable";
+/// Directory next to the executable that holds state in portable mode.
+const PORTABLE_DATA_DIR: &str = "haybox-debugger-data";
+
+lazy_static::lazy_static! {
+  static ref PORTABLE_DIR: Option<PathBuf> = portable_data_dir();
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DataLocation {
+  pub path: String,
+  pub portable: bool,
+  /// Whether portable mode came from the command line switch rather than
+  /// the flag file.
+  pub from_switch: bool,
+}
+
+fn exe_dir() -> Option<PathBuf> {
+  std::env::current_exe().ok()?.parent().map(PathBuf::from)
+}
+
+fn portable_switch_given() -> bool {
+  std::env::args().skip(1).any(|arg| arg == PORTABLE_SWITCH)
+}
+
+fn portable_data_dir() -> Option<PathBuf> {
+  let exe_dir = exe_dir()?;
+  (portable_switch_given() || exe_dir.join(PORTABLE_FLAG_FILE).is_file()).then(|| exe_dir.join(PORTABLE_DATA_DIR))
+}
+
+/// Whether state lives beside the executable, e.g. when the debugger runs
+/// from a USB stick, instead of in the user profile.
+pub fn is_portable() -> bool {
+  PORTABLE_DIR.is_some()
+}
+
+/// Per-user directory for state the app persists between launches. Available
+/// without an `AppHandle` so driver code and the elevated helper can use it.
+/// In portable mode this is a directory next to the executable instead.
+pub fn app_data_dir() -> PathBuf {
+  let dir = PORTABLE_DIR.clone().unwrap_or_else(|| {
+    std::env::var("APPDATA")
+      .map(PathBuf::from)
+      .unwrap_or_else(|_| std::env::temp_dir())
+      .join(APP_IDENTIFIER)
+  });
+
+  if !dir.exists() {
+    if let Err(e) = std::fs::create_dir_all(&dir) {
+      tracing::warn!("failed to create app data directory {}: {}", dir.display(), e);
+    }
+  }
+  dir
+}
+
+/// Keeps the WebView2 profile with the rest of the portable state, so
+/// nothing is left in `%LOCALAPPDATA%` on a tournament machine. Must run
+/// before the first window is created.
+pub fn apply_portable_webview_dir() {
+  if is_portable() && std::env::var_os("WEBVIEW2_USER_DATA_FOLDER").is_none() {
+    std::env::set_var("WEBVIEW2_USER_DATA_FOLDER", app_data_dir().join("webview"));
+  }
+}
+
+pub fn data_location() -> DataLocation {
+  DataLocation {
+    path: app_data_dir().display().to_string(),
+    portable: is_portable(),
+    from_switch: portable_switch_given(),
+  }
+}
@@ -0,0 +1,161 @@
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+#[cfg(target_os = "windows")]
+use windows::Win32::System::SystemInformation::GetTickCount64;
+
+use crate::driver::{query_bound_service, DriverKind};
+use crate::error::HayboxError;
+use crate::paths::app_data_dir;
+
+const PENDING_ACTIONS_FILE: &str = "pending_actions.json";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum PendingReason {
+  /// Windows will only finish applying the binding after a reboot.
+  #[default]
+  Reboot,
+  /// The package was added to the driver store while the device was
+  /// unplugged; it binds the next time the device is connected.
+  NextPlug,
+}
+
+/// A driver binding that has been installed but not yet applied.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PendingAction {
+  pub kind: DriverKind,
+  pub vendor_id: u16,
+  pub product_id: u16,
+  pub interface: Option<u8>,
+  pub recorded_at: u64,
+  #[serde(default)]
+  pub reason: PendingReason,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PendingActionVerification {
+  pub action: PendingAction,
+  pub bound_service: Option<String>,
+  pub verified: bool,
+}
+
+fn pending_actions_path() -> PathBuf {
+  app_data_dir().join(PENDING_ACTIONS_FILE)
+}
+
+fn now_secs() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_secs())
+    .unwrap_or_default()
+}
+
+#[cfg(target_os = "windows")]
+fn last_boot_secs() -> u64 {
+  let uptime_secs = unsafe { GetTickCount64() } / 1000;
+  now_secs().saturating_sub(uptime_secs)
+}
+
+/// Driver installs only happen on Windows, so nothing recorded elsewhere is
+/// waiting for a reboot.
+#[cfg(not(target_os = "windows"))]
+fn last_boot_secs() -> u64 {
+  0
+}
+
+pub fn load_pending_actions() -> Vec<PendingAction> {
+  std::fs::read_to_string(pending_actions_path())
+    .ok()
+    .and_then(|content| serde_json::from_str(&content).ok())
+    .unwrap_or_default()
+}
+
+fn save_pending_actions(actions: &[PendingAction]) -> Result<(), HayboxError> {
+  let path = pending_actions_path();
+  if actions.is_empty() {
+    if path.exists() {
+      std::fs::remove_file(&path)
+        .map_err(|e| HayboxError::Io(format!("Failed to remove pending actions file: {}", e)))?;
+    }
+    return Ok(());
+  }
+
+  let content = serde_json::to_string_pretty(actions)
+    .map_err(|e| HayboxError::Other(format!("Failed to serialize pending actions: {}", e)))?;
+  std::fs::write(&path, content).map_err(|e| HayboxError::Io(format!("Failed to write pending actions file: {}", e)))
+}
+
+pub fn record_pending_action(
+  kind: DriverKind,
+  vendor_id: u16,
+  product_id: u16,
+  interface: Option<u8>,
+  reason: PendingReason,
+) {
+  let mut actions = load_pending_actions();
+  actions.retain(|action| {
+    !(action.vendor_id == vendor_id && action.product_id == product_id && action.interface == interface)
+  });
+  actions.push(PendingAction {
+    kind,
+    vendor_id,
+    product_id,
+    interface,
+    recorded_at: now_secs(),
+    reason,
+  });
+
+  if let Err(e) = save_pending_actions(&actions) {
+    tracing::warn!("{}", e);
+  }
+}
+
+/// Checks every action recorded before the last boot and drops it from the
+/// pending list. Actions recorded since the last boot are still waiting for
+/// their reboot and are left untouched.
+pub fn verify_pending_actions() -> Vec<PendingActionVerification> {
+  let booted_at = last_boot_secs();
+  verify_where(|action| action.reason == PendingReason::Reboot && action.recorded_at < booted_at)
+}
+
+/// Checks actions waiting for this device to be plugged in. Called from the
+/// hotplug watcher when a device arrives.
+pub fn verify_on_arrival(vendor_id: u16, product_id: u16) -> Vec<PendingActionVerification> {
+  verify_where(|action| {
+    action.reason == PendingReason::NextPlug && action.vendor_id == vendor_id && action.product_id == product_id
+  })
+}
+
+fn verify_where(is_due: impl Fn(&PendingAction) -> bool) -> Vec<PendingActionVerification> {
+  let (due, waiting): (Vec<_>, Vec<_>) = load_pending_actions().into_iter().partition(|action| is_due(action));
+
+  if due.is_empty() {
+    return vec![];
+  }
+
+  let results = due
+    .into_iter()
+    .map(|action| {
+      let bound_service =
+        query_bound_service(action.vendor_id, action.product_id, action.interface).unwrap_or_default();
+      let verified = bound_service
+        .as_deref()
+        .map(|service| service.eq_ignore_ascii_case(action.kind.service_name()))
+        .unwrap_or(false);
+
+      PendingActionVerification {
+        action,
+        bound_service,
+        verified,
+      }
+    })
+    .collect();
+
+  if let Err(e) = save_pending_actions(&waiting) {
+    tracing::warn!("{}", e);
+  }
+
+  results
+}
@@ -0,0 +1,225 @@
+use std::time::Duration;
+
+use rusb::{Context, DeviceHandle, Direction, TransferType, UsbContext};
+
+use crate::error::HayboxError;
+use crate::DEVICES;
+
+/// The RP2040 boot ROM's vendor interface, next to the mass storage one.
+const INTERFACE_CLASS: u8 = 0xFF;
+const COMMAND_MAGIC: u32 = 0x431F_D10B;
+const COMMAND_LEN: usize = 32;
+/// Commands with bit 7 set send data to the host.
+const CMD_EXCLUSIVE_ACCESS: u8 = 0x01;
+const CMD_REBOOT: u8 = 0x02;
+const CMD_EXIT_XIP: u8 = 0x06;
+const CMD_READ: u8 = 0x84;
+/// Vendor, interface recipient.
+const REQUEST_TYPE_OUT: u8 = 0x41;
+const REQUEST_TYPE_IN: u8 = 0xC1;
+const REQUEST_RESET: u8 = 0x41;
+const REQUEST_STATUS: u8 = 0x42;
+const TIMEOUT: Duration = Duration::from_secs(3);
+/// Lets the boot ROM acknowledge the reboot command before it resets.
+const REBOOT_DELAY_MS: u32 = 500;
+
+pub const FLASH_START: u32 = 0x1000_0000;
+/// Largest single READ the boot ROM accepts comfortably.
+pub const READ_CHUNK: usize = 4096;
+
+/// A claimed PICOBOOT interface on a controller in BOOTSEL mode. Exclusive
+/// access is dropped again when this goes out of scope so the RPI-RP2 drive
+/// comes back.
+pub struct Picoboot {
+  handle: DeviceHandle<Context>,
+  interface: u8,
+  endpoint_out: u8,
+  endpoint_in: u8,
+  token: u32,
+}
+
+fn status_message(code: u32) -> &'static str {
+  match code {
+    1 => "unknown command",
+    2 => "invalid command length",
+    3 => "invalid transfer length",
+    4 => "invalid address",
+    5 => "bad alignment",
+    6 => "interleaved write",
+    7 => "rebooting",
+    _ => "unknown error",
+  }
+}
+
+impl Picoboot {
+  /// Opens the BOOTSEL device's PICOBOOT interface. On Windows this needs
+  /// WinUSB bound to interface 1, which Zadig or the driver installer does.
+  pub fn open() -> Result<Self, HayboxError> {
+    let device = &DEVICES.bootsel_mode;
+    let context = Context::new().map_err(|e| HayboxError::Usb(format!("Failed to create USB context: {}", e)))?;
+    let handle = context
+      .open_device_with_vid_pid(device.vid, device.pid)
+      .ok_or_else(|| {
+        HayboxError::Usb(format!(
+          "Could not open the {} device; is WinUSB bound to its PICOBOOT interface?",
+          device.name
+        ))
+      })?;
+
+    let config = handle
+      .device()
+      .active_config_descriptor()
+      .map_err(|e| HayboxError::Usb(format!("Failed to read configuration descriptor: {}", e)))?;
+    let descriptor = config
+      .interfaces()
+      .flat_map(|interface| interface.descriptors())
+      .find(|descriptor| descriptor.class_code() == INTERFACE_CLASS)
+      .ok_or_else(|| {
+        HayboxError::Usb("BOOTSEL device has no PICOBOOT interface; is the USB boot interface disabled?".to_string())
+      })?;
+
+    let bulk_endpoint = |direction: Direction| {
+      descriptor
+        .endpoint_descriptors()
+        .find(|endpoint| endpoint.transfer_type() == TransferType::Bulk && endpoint.direction() == direction)
+        .map(|endpoint| endpoint.address())
+    };
+    let (Some(endpoint_out), Some(endpoint_in)) = (bulk_endpoint(Direction::Out), bulk_endpoint(Direction::In)) else {
+      return Err(HayboxError::Usb(
+        "PICOBOOT interface is missing its bulk endpoints".to_string(),
+      ));
+    };
+    let interface = descriptor.interface_number();
+
+    handle
+      .claim_interface(interface)
+      .map_err(|e| HayboxError::DeviceBusy(format!("Failed to claim the PICOBOOT interface: {}", e)))?;
+
+    let mut picoboot = Picoboot {
+      handle,
+      interface,
+      endpoint_out,
+      endpoint_in,
+      token: 1,
+    };
+    picoboot.reset()?;
+    Ok(picoboot)
+  }
+
+  /// Clears any half-finished command and stalled endpoints.
+  fn reset(&mut self) -> Result<(), HayboxError> {
+    self
+      .handle
+      .write_control(REQUEST_TYPE_OUT, REQUEST_RESET, 0, self.interface as u16, &[], TIMEOUT)
+      .map_err(|e| HayboxError::Usb(format!("PICOBOOT reset failed: {}", e)))?;
+    Ok(())
+  }
+
+  /// The boot ROM's reason for the last failed command.
+  fn last_error(&mut self) -> String {
+    let mut status = [0u8; 16];
+    let message = match self.handle.read_control(
+      REQUEST_TYPE_IN,
+      REQUEST_STATUS,
+      0,
+      self.interface as u16,
+      &mut status,
+      TIMEOUT,
+    ) {
+      Ok(16) => status_message(u32::from_le_bytes([status[4], status[5], status[6], status[7]])).to_string(),
+      Ok(_) => "short status response".to_string(),
+      Err(e) => e.to_string(),
+    };
+    let _ = self.reset();
+    message
+  }
+
+  fn command(&mut self, id: u8, args: &[u8], data_in: &mut [u8]) -> Result<(), HayboxError> {
+    let mut command = [0u8; COMMAND_LEN];
+    command[0..4].copy_from_slice(&COMMAND_MAGIC.to_le_bytes());
+    command[4..8].copy_from_slice(&self.token.to_le_bytes());
+    command[8] = id;
+    command[9] = args.len() as u8;
+    command[12..16].copy_from_slice(&(data_in.len() as u32).to_le_bytes());
+    command[16..16 + args.len()].copy_from_slice(args);
+    self.token = self.token.wrapping_add(1);
+
+    if self.handle.write_bulk(self.endpoint_out, &command, TIMEOUT).is_err() {
+      return Err(HayboxError::Usb(format!(
+        "PICOBOOT command 0x{:02X} failed: {}",
+        id,
+        self.last_error()
+      )));
+    }
+
+    let mut received = 0;
+    while received < data_in.len() {
+      match self
+        .handle
+        .read_bulk(self.endpoint_in, &mut data_in[received..], TIMEOUT)
+      {
+        Ok(0) => break,
+        Ok(count) => received += count,
+        Err(_) => {
+          return Err(HayboxError::Usb(format!(
+            "PICOBOOT command 0x{:02X} failed: {}",
+            id,
+            self.last_error()
+          )))
+        }
+      }
+    }
+    if received != data_in.len() {
+      return Err(HayboxError::Usb(format!(
+        "PICOBOOT command 0x{:02X} returned {} of {} bytes",
+        id,
+        received,
+        data_in.len()
+      )));
+    }
+
+    // The status phase runs opposite to the data phase: an empty packet out
+    // after reading, an empty packet in otherwise.
+    let acknowledged = if data_in.is_empty() {
+      let mut ack = [0u8; 64];
+      self.handle.read_bulk(self.endpoint_in, &mut ack, TIMEOUT).map(|_| ())
+    } else {
+      self.handle.write_bulk(self.endpoint_out, &[], TIMEOUT).map(|_| ())
+    };
+    acknowledged.map_err(|_| HayboxError::Usb(format!("PICOBOOT command 0x{:02X} failed: {}", id, self.last_error())))
+  }
+
+  /// Locks out the mass storage interface so it cannot write flash underneath
+  /// us.
+  pub fn exclusive_access(&mut self, exclusive: bool) -> Result<(), HayboxError> {
+    self.command(CMD_EXCLUSIVE_ACCESS, &[exclusive as u8], &mut [])
+  }
+
+  /// Leaves execute-in-place mode so flash can be read and written directly.
+  pub fn exit_xip(&mut self) -> Result<(), HayboxError> {
+    self.command(CMD_EXIT_XIP, &[], &mut [])
+  }
+
+  /// Reads `buffer.len()` bytes of memory starting at `addr`.
+  pub fn read(&mut self, addr: u32, buffer: &mut [u8]) -> Result<(), HayboxError> {
+    let mut args = [0u8; 8];
+    args[0..4].copy_from_slice(&addr.to_le_bytes());
+    args[4..8].copy_from_slice(&(buffer.len() as u32).to_le_bytes());
+    self.command(CMD_READ, &args, buffer)
+  }
+
+  /// Leaves BOOTSEL and starts the program in flash. A zero entry point and
+  /// stack pointer mean a normal boot.
+  pub fn reboot(&mut self) -> Result<(), HayboxError> {
+    let mut args = [0u8; 12];
+    args[8..12].copy_from_slice(&REBOOT_DELAY_MS.to_le_bytes());
+    self.command(CMD_REBOOT, &args, &mut [])
+  }
+}
+
+impl Drop for Picoboot {
+  fn drop(&mut self) {
+    let _ = self.exclusive_access(false);
+    let _ = self.handle.release_interface(self.interface);
+  }
+}
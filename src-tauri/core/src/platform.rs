@@ -0,0 +1,443 @@
+use std::collections::BTreeMap;
+
+use rusb::UsbContext;
+use serde::{Deserialize, Serialize};
+
+use crate::driver::{Config, InstallOutcome};
+use crate::error::HayboxError;
+#[cfg(target_os = "macos")]
+use crate::iokit;
+#[cfg(target_os = "linux")]
+use crate::udev;
+use crate::DriverInfo;
+#[cfg(target_os = "windows")]
+use crate::{driver, privileges, wmi_worker};
+
+/// What the app can do on the platform it was built for, so the frontend
+/// can hide unsupported actions instead of offering buttons that always
+/// fail.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PlatformCapabilities {
+  pub os: String,
+  pub device_enumeration: bool,
+  pub driver_query: bool,
+  /// WinUSB and other INF-based driver installs through pnputil.
+  pub driver_install: bool,
+  pub xinput_management: bool,
+  pub hidhide: bool,
+  pub udev_rules: bool,
+  /// Running as Administrator on Windows, or as root elsewhere.
+  pub elevated: bool,
+  /// Every command keyed by name, true for those that fail or prompt
+  /// without elevation, so the UI can mark just those.
+  pub requires_elevation: BTreeMap<String, bool>,
+}
+
+/// Where a command needs elevation. Each command declares this next to its
+/// registration in the app.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Elevation {
+  Never,
+  /// Changes drivers, system files or HidHide settings.
+  Windows,
+  /// Prompts through pkexec.
+  Linux,
+}
+
+impl Elevation {
+  /// Whether the command fails or prompts without elevation on this OS.
+  pub fn required(self) -> bool {
+    match self {
+      Elevation::Never => false,
+      Elevation::Windows => cfg!(target_os = "windows"),
+      Elevation::Linux => cfg!(target_os = "linux"),
+    }
+  }
+}
+
+impl PlatformCapabilities {
+  /// Fills in `requires_elevation` from the app's command table.
+  pub fn with_commands(mut self, commands: &[(&str, Elevation)]) -> Self {
+    self.requires_elevation = commands
+      .iter()
+      .map(|(command, elevation)| (command.to_string(), elevation.required()))
+      .collect();
+    self
+  }
+}
+
+/// Where the list of connected USB devices came from.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EnumerationBackend {
+  Libusb,
+  Wmi,
+  Sysfs,
+  Iokit,
+  /// Neither libusb nor the OS could list devices.
+  Unavailable,
+}
+
+/// Device and driver management that differs per OS. Everything else in
+/// the app goes through libusb, hidapi or serialport, which hide the
+/// differences themselves.
+pub trait Platform: Sync {
+  fn capabilities(&self) -> PlatformCapabilities;
+
+  /// VID and PID of every connected USB device, and where the list came
+  /// from. libusb is preferred; when it cannot start, e.g. with a missing or
+  /// broken runtime, the OS's own view is used so devices do not all show
+  /// as disconnected.
+  fn usb_devices(&self) -> (Vec<(u16, u16)>, EnumerationBackend) {
+    let libusb = rusb::Context::new().and_then(|context| context.devices());
+    let error = match libusb {
+      Ok(list) => {
+        let devices = list
+          .iter()
+          .filter_map(|device| device.device_descriptor().ok())
+          .map(|descriptor| (descriptor.vendor_id(), descriptor.product_id()))
+          .collect();
+        return (devices, EnumerationBackend::Libusb);
+      }
+      Err(e) => e,
+    };
+
+    tracing::warn!("libusb enumeration failed, using the OS device list: {}", error);
+    match self.native_usb_devices() {
+      Ok(devices) => (devices, self.native_backend()),
+      Err(e) => {
+        tracing::warn!("{}", e);
+        (Vec::new(), EnumerationBackend::Unavailable)
+      }
+    }
+  }
+
+  fn connected_devices(&self) -> Vec<(u16, u16)> {
+    self.usb_devices().0
+  }
+
+  /// Connected devices from the OS alone, without libusb.
+  fn native_usb_devices(&self) -> Result<Vec<(u16, u16)>, HayboxError>;
+
+  fn native_backend(&self) -> EnumerationBackend;
+
+  /// Drivers bound to connected devices, optionally filtered by VID and
+  /// PID. `is_winusb` marks entries libusb can open as they are.
+  fn driver_info(&self, vendor_id: Option<u16>, product_id: Option<u16>) -> Result<Vec<DriverInfo>, HayboxError>;
+
+  /// Whether libusb can open the device without any further setup: the
+  /// WinUSB binding on Windows, device node permissions on Linux.
+  fn libusb_ready(&self, vendor_id: u16, product_id: u16) -> Result<bool, HayboxError>;
+
+  fn install_driver(&self, _config: &Config) -> Result<InstallOutcome, HayboxError> {
+    Err(HayboxError::unsupported("Installing drivers"))
+  }
+
+  fn is_elevated(&self) -> bool;
+}
+
+#[cfg(target_os = "windows")]
+pub struct Windows;
+#[cfg(target_os = "linux")]
+pub struct Linux;
+#[cfg(target_os = "macos")]
+pub struct MacOs;
+
+/// The implementation for the OS the app was built for.
+#[cfg(target_os = "windows")]
+pub fn current() -> &'static dyn Platform {
+  &Windows
+}
+
+#[cfg(target_os = "linux")]
+pub fn current() -> &'static dyn Platform {
+  &Linux
+}
+
+#[cfg(target_os = "macos")]
+pub fn current() -> &'static dyn Platform {
+  &MacOs
+}
+
+fn capabilities(platform: &dyn Platform) -> PlatformCapabilities {
+  let windows = cfg!(target_os = "windows");
+  PlatformCapabilities {
+    os: std::env::consts::OS.to_string(),
+    device_enumeration: true,
+    driver_query: true,
+    driver_install: windows,
+    xinput_management: windows,
+    hidhide: windows,
+    udev_rules: cfg!(target_os = "linux"),
+    elevated: platform.is_elevated(),
+    requires_elevation: BTreeMap::new(),
+  }
+}
+
+#[cfg(not(target_os = "windows"))]
+fn is_root() -> bool {
+  std::process::Command::new("id")
+    .arg("-u")
+    .output()
+    .map(|output| String::from_utf8_lossy(&output.stdout).trim() == "0")
+    .unwrap_or(false)
+}
+
+#[cfg(target_os = "windows")]
+#[derive(Debug, Deserialize)]
+struct WmiDeviceId {
+  #[serde(rename = "DeviceID")]
+  device_id: String,
+}
+
+/// VID and PID from a device instance ID such as `USB\VID_057E&PID_0337\5&1A2B`.
+#[cfg(target_os = "windows")]
+fn parse_usb_device_id(device_id: &str) -> Option<(u16, u16)> {
+  let upper = device_id.to_uppercase();
+  let hex_after = |prefix: &str| {
+    let start = upper.find(prefix)? + prefix.len();
+    u16::from_str_radix(upper.get(start..start + 4)?, 16).ok()
+  };
+  Some((hex_after("VID_")?, hex_after("PID_")?))
+}
+
+#[cfg(target_os = "windows")]
+#[derive(Debug, Deserialize)]
+struct WmiPnPEntity {
+  #[serde(rename = "DriverProvider")]
+  driver_provider: Option<String>,
+}
+
+#[cfg(target_os = "windows")]
+#[derive(Debug, Deserialize)]
+struct WmiDeviceInfo {
+  #[serde(rename = "DeviceID")]
+  device_id: String,
+  #[serde(rename = "Name")]
+  name: Option<String>,
+  #[serde(rename = "DriverProvider")]
+  driver_provider: Option<String>,
+  #[serde(rename = "DriverVersion")]
+  driver_version: Option<String>,
+  #[serde(rename = "DriverDate")]
+  driver_date: Option<String>,
+}
+
+#[cfg(target_os = "windows")]
+impl Platform for Windows {
+  fn capabilities(&self) -> PlatformCapabilities {
+    capabilities(self)
+  }
+
+  fn native_usb_devices(&self) -> Result<Vec<(u16, u16)>, HayboxError> {
+    let entities: Vec<WmiDeviceId> =
+      wmi_worker::query("SELECT DeviceID FROM Win32_PnPEntity WHERE DeviceID LIKE 'USB\\\\VID%'")?;
+
+    let mut devices: Vec<(u16, u16)> = entities
+      .iter()
+      .filter_map(|entity| parse_usb_device_id(&entity.device_id))
+      .collect();
+    // Each interface of a composite device is its own entity.
+    devices.sort();
+    devices.dedup();
+    Ok(devices)
+  }
+
+  fn native_backend(&self) -> EnumerationBackend {
+    EnumerationBackend::Wmi
+  }
+
+  fn driver_info(&self, vendor_id: Option<u16>, product_id: Option<u16>) -> Result<Vec<DriverInfo>, HayboxError> {
+    if let (Some(vid), Some(pid)) = (vendor_id, product_id) {
+      if !self.connected_devices().contains(&(vid, pid)) {
+        return Ok(vec![]);
+      }
+    }
+
+    let query = match (vendor_id, product_id) {
+      (Some(vid), Some(pid)) => format!(
+        "SELECT DeviceID, Name, DriverProvider, DriverVersion, DriverDate FROM Win32_PnPEntity WHERE DeviceID LIKE \
+         '%VID_{0:04X}%' AND DeviceID LIKE '%PID_{1:04X}%'",
+        vid, pid
+      ),
+      (Some(vid), None) => format!(
+        "SELECT DeviceID, Name, DriverProvider, DriverVersion, DriverDate FROM Win32_PnPEntity WHERE DeviceID LIKE \
+         '%VID_{0:04X}%'",
+        vid
+      ),
+      (None, Some(pid)) => format!(
+        "SELECT DeviceID, Name, DriverProvider, DriverVersion, DriverDate FROM Win32_PnPEntity WHERE DeviceID LIKE \
+         '%PID_{0:04X}%'",
+        pid
+      ),
+      (None, None) => "SELECT DeviceID, Name, DriverProvider, DriverVersion, DriverDate FROM Win32_PnPEntity WHERE \
+                       DeviceID LIKE '%USB%'"
+        .to_string(),
+    };
+
+    let devices: Vec<WmiDeviceInfo> = match wmi_worker::query(&query) {
+      Ok(devices) => {
+        tracing::debug!("WMI query successful. Found {} device(s)", devices.len());
+        devices
+      }
+      Err(e) => {
+        tracing::error!("{}", e);
+        return Err(e);
+      }
+    };
+
+    let driver_info: Vec<DriverInfo> = devices
+      .into_iter()
+      .map(|device| {
+        let is_winusb = device
+          .driver_provider
+          .as_ref()
+          .map(|provider| provider.contains("WinUSB"))
+          .unwrap_or(false);
+
+        DriverInfo {
+          device_id: device.device_id,
+          device_name: device.name.unwrap_or_else(|| "Unknown Device".to_string()),
+          driver_provider: device.driver_provider,
+          driver_version: device.driver_version,
+          driver_date: device.driver_date,
+          is_winusb,
+        }
+      })
+      .collect();
+
+    tracing::debug!("Returning {} driver info records", driver_info.len());
+    Ok(driver_info)
+  }
+
+  fn libusb_ready(&self, vendor_id: u16, product_id: u16) -> Result<bool, HayboxError> {
+    if !self.connected_devices().contains(&(vendor_id, product_id)) {
+      return Ok(false);
+    }
+
+    let query = format!(
+      "SELECT DeviceID, DriverProvider FROM Win32_PnPEntity WHERE DeviceID LIKE '%VID\\_{0:04X}%' AND DeviceID LIKE \
+       '%PID\\_{1:04X}%'",
+      vendor_id, product_id
+    );
+
+    tracing::debug!("Executing WMI query: {}", query);
+    let devices: Vec<WmiPnPEntity> = wmi_worker::query(&query)?;
+
+    Ok(devices.into_iter().any(|device| {
+      device
+        .driver_provider
+        .is_some_and(|provider| provider.contains("WinUSB"))
+    }))
+  }
+
+  fn install_driver(&self, config: &Config) -> Result<InstallOutcome, HayboxError> {
+    driver::install_driver_package(config)
+  }
+
+  fn is_elevated(&self) -> bool {
+    privileges::is_elevated()
+  }
+}
+
+#[cfg(target_os = "linux")]
+impl Platform for Linux {
+  fn capabilities(&self) -> PlatformCapabilities {
+    capabilities(self)
+  }
+
+  fn native_usb_devices(&self) -> Result<Vec<(u16, u16)>, HayboxError> {
+    Ok(
+      udev::sysfs_devices()
+        .into_iter()
+        .map(|device| (device.vendor_id, device.product_id))
+        .collect(),
+    )
+  }
+
+  fn native_backend(&self) -> EnumerationBackend {
+    EnumerationBackend::Sysfs
+  }
+
+  /// Kernel drivers bound to each interface, from sysfs. Interfaces with no
+  /// driver or usbfs can be claimed without detaching anything.
+  fn driver_info(&self, vendor_id: Option<u16>, product_id: Option<u16>) -> Result<Vec<DriverInfo>, HayboxError> {
+    Ok(
+      udev::driver_bindings(vendor_id, product_id)
+        .into_iter()
+        .map(|binding| DriverInfo {
+          device_id: binding.interface,
+          device_name: binding.product.unwrap_or_else(|| "Unknown Device".to_string()),
+          is_winusb: binding.driver.as_deref().is_none_or(|driver| driver == "usbfs"),
+          driver_provider: binding.driver,
+          driver_version: None,
+          driver_date: None,
+        })
+        .collect(),
+    )
+  }
+
+  /// Nothing has to be bound; libusb works once the user may open the
+  /// device node.
+  fn libusb_ready(&self, vendor_id: u16, product_id: u16) -> Result<bool, HayboxError> {
+    Ok(udev::usb_accessible(vendor_id, product_id))
+  }
+
+  fn is_elevated(&self) -> bool {
+    is_root()
+  }
+}
+
+#[cfg(target_os = "macos")]
+impl Platform for MacOs {
+  fn capabilities(&self) -> PlatformCapabilities {
+    capabilities(self)
+  }
+
+  fn native_usb_devices(&self) -> Result<Vec<(u16, u16)>, HayboxError> {
+    Ok(
+      iokit::usb_devices(None, None)?
+        .into_iter()
+        .map(|device| (device.vendor_id, device.product_id))
+        .collect(),
+    )
+  }
+
+  fn native_backend(&self) -> EnumerationBackend {
+    EnumerationBackend::Iokit
+  }
+
+  /// Drivers IOKit matched to each interface. Interfaces nothing has
+  /// claimed are the ones libusb can open.
+  fn driver_info(&self, vendor_id: Option<u16>, product_id: Option<u16>) -> Result<Vec<DriverInfo>, HayboxError> {
+    let mut driver_info = Vec::new();
+    for device in iokit::usb_devices(vendor_id, product_id)? {
+      for interface in device.interfaces {
+        driver_info.push(DriverInfo {
+          device_id: format!(
+            "USB\\VID_{:04X}&PID_{:04X}&MI_{:02X}",
+            device.vendor_id,
+            device.product_id,
+            interface.number.unwrap_or_default()
+          ),
+          device_name: device.product.clone().unwrap_or_else(|| "Unknown Device".to_string()),
+          is_winusb: interface.driver.is_none(),
+          driver_provider: interface.driver,
+          driver_version: None,
+          driver_date: None,
+        });
+      }
+    }
+    Ok(driver_info)
+  }
+
+  /// No driver is needed either, as long as no kext or dext has matched
+  /// the device's interfaces.
+  fn libusb_ready(&self, vendor_id: u16, product_id: u16) -> Result<bool, HayboxError> {
+    iokit::libusb_claimable(vendor_id, product_id)
+  }
+
+  fn is_elevated(&self) -> bool {
+    is_root()
+  }
+}
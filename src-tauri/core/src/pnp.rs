@@ -0,0 +1,98 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::error::HayboxError;
+#[cfg(target_os = "windows")]
+use crate::wmi_worker;
+
+/// VID/PID/interface parsed out of a PnP device instance ID such as
+/// `USB\VID_057E&PID_0337&MI_00\7&2A3B&0&0000`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UsbIds {
+  pub vendor_id: u16,
+  pub product_id: u16,
+  pub interface: Option<u8>,
+}
+
+pub fn parse_usb_ids(instance_id: &str) -> Option<UsbIds> {
+  let re = Regex::new(r"(?i)VID_([0-9A-F]{4})&PID_([0-9A-F]{4})(?:&MI_([0-9A-F]{2}))?").unwrap();
+  let captures = re.captures(instance_id)?;
+
+  Some(UsbIds {
+    vendor_id: u16::from_str_radix(&captures[1], 16).ok()?,
+    product_id: u16::from_str_radix(&captures[2], 16).ok()?,
+    interface: captures.get(3).and_then(|m| u8::from_str_radix(m.as_str(), 16).ok()),
+  })
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReplaceableDevice {
+  pub instance_id: String,
+  pub name: String,
+  pub ids: Option<UsbIds>,
+  pub driver_provider: Option<String>,
+  pub driver_version: Option<String>,
+  pub inf_name: Option<String>,
+  /// Only USB nodes can be rebound; HID children follow their parent.
+  pub replaceable: bool,
+}
+
+#[cfg(target_os = "windows")]
+#[derive(Debug, Deserialize)]
+struct WmiSignedDriverInfo {
+  #[serde(rename = "DeviceID")]
+  device_id: Option<String>,
+  #[serde(rename = "DeviceName")]
+  device_name: Option<String>,
+  #[serde(rename = "DriverProviderName")]
+  driver_provider_name: Option<String>,
+  #[serde(rename = "DriverVersion")]
+  driver_version: Option<String>,
+  #[serde(rename = "InfName")]
+  inf_name: Option<String>,
+}
+
+#[cfg(not(target_os = "windows"))]
+pub fn list_replaceable_devices() -> Result<Vec<ReplaceableDevice>, HayboxError> {
+  Err(HayboxError::Unsupported(
+    "Replacing drivers is only available on Windows".to_string(),
+  ))
+}
+
+#[cfg(target_os = "windows")]
+pub fn list_replaceable_devices() -> Result<Vec<ReplaceableDevice>, HayboxError> {
+  let query = "SELECT DeviceID, DeviceName, DriverProviderName, DriverVersion, InfName FROM Win32_PnPSignedDriver WHERE DeviceID LIKE 'USB\\\\%' OR DeviceID LIKE 'HID\\\\%'";
+
+  let drivers: Vec<WmiSignedDriverInfo> = wmi_worker::query(query)?;
+
+  let mut devices: Vec<ReplaceableDevice> = drivers
+    .into_iter()
+    .filter_map(|driver| {
+      let instance_id = driver.device_id?;
+      let ids = parse_usb_ids(&instance_id);
+      // Hubs and root hubs carry no VID/PID and must never be rebound.
+      let replaceable = instance_id.to_uppercase().starts_with("USB\\") && ids.is_some();
+
+      Some(ReplaceableDevice {
+        name: driver.device_name.unwrap_or_else(|| "Unknown Device".to_string()),
+        instance_id,
+        ids,
+        driver_provider: driver.driver_provider_name,
+        driver_version: driver.driver_version,
+        inf_name: driver.inf_name,
+        replaceable,
+      })
+    })
+    .collect();
+
+  devices.sort_by(|a, b| a.instance_id.cmp(&b.instance_id));
+  Ok(devices)
+}
+
+pub fn find_device(instance_id: &str) -> Result<Option<ReplaceableDevice>, HayboxError> {
+  Ok(
+    list_replaceable_devices()?
+      .into_iter()
+      .find(|device| device.instance_id.eq_ignore_ascii_case(instance_id)),
+  )
+}
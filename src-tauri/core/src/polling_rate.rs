@@ -0,0 +1,115 @@
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::HayboxError;
+use crate::input_monitor;
+
+const DEFAULT_WINDOW: Duration = Duration::from_secs(5);
+const MAX_WINDOW: Duration = Duration::from_secs(60);
+/// Fewer intervals than this say more about the window than the controller.
+const MIN_SAMPLES: usize = 20;
+/// Rates USB full-speed interrupt endpoints can be polled at, from the
+/// 1, 2, 4 and 8 ms bInterval values.
+const STANDARD_RATES_HZ: [u32; 4] = [125, 250, 500, 1000];
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PollingRateResult {
+  pub window_ms: u64,
+  pub reports: usize,
+  pub min_us: u64,
+  pub avg_us: f64,
+  pub max_us: u64,
+  pub p50_us: u64,
+  pub p95_us: u64,
+  pub p99_us: u64,
+  /// Standard deviation of the intervals.
+  pub jitter_us: f64,
+  /// From the median interval.
+  pub rate_hz: f64,
+  /// The standard USB polling rate closest to `rate_hz`.
+  pub nominal_rate_hz: u32,
+  pub warning: Option<String>,
+}
+
+/// Nearest-rank percentile of sorted values.
+pub fn percentile(sorted: &[u64], percent: usize) -> u64 {
+  let rank = (sorted.len() * percent).div_ceil(100).max(1);
+  sorted[rank - 1]
+}
+
+/// Times report arrivals from the running input monitor over `window`.
+/// Firmware only sends reports while something changes on some backends, so
+/// the sticks or buttons should be moving during the measurement.
+pub fn measure_polling_rate(window: Option<Duration>) -> Result<PollingRateResult, HayboxError> {
+  let window = window.unwrap_or(DEFAULT_WINDOW).min(MAX_WINDOW);
+  let receiver = input_monitor::subscribe()?;
+
+  let deadline = Instant::now() + window;
+  let mut timestamps = Vec::new();
+  loop {
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    if remaining.is_zero() {
+      break;
+    }
+    match receiver.recv_timeout(remaining) {
+      Ok(state) => timestamps.push(state.timestamp_us),
+      Err(RecvTimeoutError::Timeout) => break,
+      Err(RecvTimeoutError::Disconnected) => {
+        return Err(HayboxError::Other(
+          "The input monitor stopped during the measurement".to_string(),
+        ))
+      }
+    }
+  }
+
+  let mut intervals: Vec<u64> = timestamps.windows(2).map(|pair| pair[1] - pair[0]).collect();
+  if intervals.len() < MIN_SAMPLES {
+    return Err(HayboxError::Other(format!(
+      "Only {} reports arrived in {} ms; move a stick or press buttons while measuring",
+      timestamps.len(),
+      window.as_millis()
+    )));
+  }
+  intervals.sort_unstable();
+
+  let count = intervals.len() as f64;
+  let avg_us = intervals.iter().sum::<u64>() as f64 / count;
+  let variance = intervals
+    .iter()
+    .map(|&interval| (interval as f64 - avg_us).powi(2))
+    .sum::<f64>()
+    / count;
+  let p50_us = percentile(&intervals, 50);
+  let rate_hz = 1_000_000.0 / p50_us.max(1) as f64;
+  let nominal_rate_hz = STANDARD_RATES_HZ
+    .into_iter()
+    .min_by(|a, b| (*a as f64 - rate_hz).abs().total_cmp(&(*b as f64 - rate_hz).abs()))
+    .unwrap_or_default();
+
+  let warning = if nominal_rate_hz < 1000 {
+    Some(format!(
+      "Reports arrive at about {} Hz rather than 1000 Hz. USB hubs, front panel ports and some \
+       adapters poll slower; try a port on the motherboard.",
+      nominal_rate_hz
+    ))
+  } else {
+    None
+  };
+
+  Ok(PollingRateResult {
+    window_ms: window.as_millis() as u64,
+    reports: timestamps.len(),
+    min_us: intervals[0],
+    avg_us,
+    max_us: intervals[intervals.len() - 1],
+    p50_us,
+    p95_us: percentile(&intervals, 95),
+    p99_us: percentile(&intervals, 99),
+    jitter_us: variance.sqrt(),
+    rate_hz,
+    nominal_rate_hz,
+    warning,
+  })
+}
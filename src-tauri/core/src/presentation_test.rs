@@ -0,0 +1,308 @@
+#[cfg(target_os = "windows")]
+use std::time::{Duration, Instant};
+
+#[cfg(target_os = "windows")]
+use hidapi::HidApi;
+use serde::{Deserialize, Serialize};
+#[cfg(target_os = "windows")]
+use windows::Win32::Foundation::HANDLE;
+#[cfg(target_os = "windows")]
+use windows::Win32::UI::Input::{
+  GetRawInputDeviceInfoW, GetRawInputDeviceList, RAWINPUTDEVICELIST, RIDI_DEVICEINFO, RIDI_DEVICENAME, RID_DEVICE_INFO,
+  RIM_TYPEHID,
+};
+
+use crate::error::HayboxError;
+#[cfg(target_os = "windows")]
+use crate::xinput::{XInputLibrary, XUSER_MAX_COUNT};
+#[cfg(target_os = "windows")]
+use crate::DEVICES;
+
+#[cfg(target_os = "windows")]
+const DEFAULT_WINDOW: Duration = Duration::from_secs(5);
+/// HID reads block for at most this long between XInput polls.
+#[cfg(target_os = "windows")]
+const HID_READ_TIMEOUT_MS: i32 = 2;
+#[cfg(target_os = "windows")]
+const MAX_REPORT: usize = 64;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RawInputDevice {
+  pub name: String,
+  pub vendor_id: u16,
+  pub product_id: u16,
+  pub usage_page: u16,
+  pub usage: u16,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct XInputPresence {
+  /// Whether xinput1_4.dll could be loaded at all.
+  pub available: bool,
+  pub error: Option<String>,
+  pub connected_slots: Vec<u32>,
+  pub input_seen: bool,
+}
+
+/// The HID side, which DirectInput and Raw Input both read.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct HidPresence {
+  pub devices: Vec<RawInputDevice>,
+  pub error: Option<String>,
+  pub input_seen: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PresentationDiagnosis {
+  Healthy,
+  /// Not in the Raw Input device list, so no API can see it.
+  NotEnumerated,
+  /// The XInput DLL is missing or broken.
+  XInputUnavailable,
+  /// HID works but no XInput slot is connected.
+  XInputNotConnected,
+  /// Input arrived over HID but not through XInput.
+  NoXInputInput,
+  /// Input arrived through XInput but not over HID.
+  NoHidInput,
+  NoInput,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PresentationTestResult {
+  pub vendor_id: u16,
+  pub product_id: u16,
+  pub window_ms: u64,
+  pub xinput: XInputPresence,
+  pub hid: HidPresence,
+  pub diagnosis: PresentationDiagnosis,
+  pub message: String,
+}
+
+#[cfg(target_os = "windows")]
+fn raw_input_name(device: HANDLE) -> String {
+  let mut length = 0u32;
+  unsafe { GetRawInputDeviceInfoW(Some(device), RIDI_DEVICENAME, None, &mut length) };
+  let mut name = vec![0u16; length as usize];
+  let written = unsafe {
+    GetRawInputDeviceInfoW(
+      Some(device),
+      RIDI_DEVICENAME,
+      Some(name.as_mut_ptr() as *mut _),
+      &mut length,
+    )
+  };
+  if written == u32::MAX {
+    return String::new();
+  }
+  String::from_utf16_lossy(&name[..written as usize])
+    .trim_end_matches('\0')
+    .to_string()
+}
+
+/// HID devices in the Raw Input list with the given VID and PID.
+#[cfg(target_os = "windows")]
+fn raw_input_devices(vid: u16, pid: u16) -> Result<Vec<RawInputDevice>, HayboxError> {
+  let size = std::mem::size_of::<RAWINPUTDEVICELIST>() as u32;
+  let mut count = 0u32;
+  if unsafe { GetRawInputDeviceList(None, &mut count, size) } == u32::MAX {
+    return Err(HayboxError::Other("Failed to count Raw Input devices".to_string()));
+  }
+  let mut list = vec![RAWINPUTDEVICELIST::default(); count as usize];
+  let returned = unsafe { GetRawInputDeviceList(Some(list.as_mut_ptr()), &mut count, size) };
+  if returned == u32::MAX {
+    return Err(HayboxError::Other("Failed to list Raw Input devices".to_string()));
+  }
+  list.truncate(returned as usize);
+
+  let mut devices = Vec::new();
+  for entry in list.iter().filter(|entry| entry.dwType == RIM_TYPEHID) {
+    let mut info = RID_DEVICE_INFO {
+      cbSize: std::mem::size_of::<RID_DEVICE_INFO>() as u32,
+      ..Default::default()
+    };
+    let mut info_size = info.cbSize;
+    let result = unsafe {
+      GetRawInputDeviceInfoW(
+        Some(entry.hDevice),
+        RIDI_DEVICEINFO,
+        Some(&mut info as *mut _ as *mut _),
+        &mut info_size,
+      )
+    };
+    if result == u32::MAX {
+      continue;
+    }
+    let hid = unsafe { info.Anonymous.hid };
+    if hid.dwVendorId != vid as u32 || hid.dwProductId != pid as u32 {
+      continue;
+    }
+    devices.push(RawInputDevice {
+      name: raw_input_name(entry.hDevice),
+      vendor_id: vid,
+      product_id: pid,
+      usage_page: hid.usUsagePage,
+      usage: hid.usUsage,
+    });
+  }
+  Ok(devices)
+}
+
+#[cfg(target_os = "windows")]
+fn diagnose(xinput: &XInputPresence, hid: &HidPresence) -> (PresentationDiagnosis, String) {
+  if hid.devices.is_empty() {
+    return (
+      PresentationDiagnosis::NotEnumerated,
+      "The controller is not in the Raw Input device list, so neither DirectInput nor XInput can see it. Check the \
+       cable and that the controller is in its default mode."
+        .to_string(),
+    );
+  }
+  if !xinput.available {
+    return (
+      PresentationDiagnosis::XInputUnavailable,
+      format!(
+        "USB works but XInput does not: {}. Games using XInput will not see the controller until the DLL is restored.",
+        xinput.error.as_deref().unwrap_or("xinput1_4.dll could not be loaded")
+      ),
+    );
+  }
+  if xinput.connected_slots.is_empty() {
+    return (
+      PresentationDiagnosis::XInputNotConnected,
+      "The controller is visible over HID but no XInput slot is connected. The XInput driver may not be bound to \
+       the controller."
+        .to_string(),
+    );
+  }
+  match (xinput.input_seen, hid.input_seen) {
+    (true, true) => (
+      PresentationDiagnosis::Healthy,
+      "Input arrives through both XInput and HID.".to_string(),
+    ),
+    (false, true) => (
+      PresentationDiagnosis::NoXInputInput,
+      "Input arrives over HID but not through XInput. Another XInput controller may hold the slot, or the XInput \
+       layer is broken."
+        .to_string(),
+    ),
+    (true, false) => (
+      PresentationDiagnosis::NoHidInput,
+      "Input arrives through XInput but not over HID, so DirectInput games will not see it. HidHide may be hiding \
+       the controller from the app."
+        .to_string(),
+    ),
+    (false, false) => (
+      PresentationDiagnosis::NoInput,
+      "No input arrived through either API. Press buttons while the test runs.".to_string(),
+    ),
+  }
+}
+
+/// Checks that the controller is visible to XInput and to Raw Input, the
+/// HID enumeration DirectInput also uses, then watches both for input while
+/// the user presses buttons. XInput does not say which device a slot
+/// belongs to, so any connected slot changing counts.
+#[cfg(target_os = "windows")]
+pub fn run_presentation_test(
+  vid: Option<u16>,
+  pid: Option<u16>,
+  window: Option<Duration>,
+) -> Result<PresentationTestResult, HayboxError> {
+  let vid = vid.unwrap_or(DEVICES.default_mode.vid);
+  let pid = pid.unwrap_or(DEVICES.default_mode.pid);
+  let window = window.unwrap_or(DEFAULT_WINDOW);
+
+  let mut hid = HidPresence::default();
+  match raw_input_devices(vid, pid) {
+    Ok(devices) => hid.devices = devices,
+    Err(e) => hid.error = Some(e.to_string()),
+  }
+
+  let mut xinput = XInputPresence::default();
+  let library = match XInputLibrary::load() {
+    Ok(library) => {
+      xinput.available = true;
+      Some(library)
+    }
+    Err(e) => {
+      xinput.error = Some(e.to_string());
+      None
+    }
+  };
+  let mut packets: Vec<(u32, u32)> = Vec::new();
+  if let Some(library) = &library {
+    for slot in 0..XUSER_MAX_COUNT {
+      if let Some(state) = library.state(slot) {
+        xinput.connected_slots.push(slot);
+        packets.push((slot, state.dwPacketNumber));
+      }
+    }
+  }
+
+  let api = HidApi::new().map_err(|e| HayboxError::Hid(format!("Failed to initialize HID: {}", e)))?;
+  let device = match api.open(vid, pid) {
+    Ok(device) => Some(device),
+    Err(e) => {
+      hid
+        .error
+        .get_or_insert_with(|| format!("Failed to open HID device: {}", e));
+      None
+    }
+  };
+
+  let mut buffer = [0u8; MAX_REPORT];
+  let mut first_report: Option<Vec<u8>> = None;
+  let started = Instant::now();
+  while started.elapsed() < window && !(xinput.input_seen && hid.input_seen) {
+    if let Some(device) = &device {
+      match device.read_timeout(&mut buffer, HID_READ_TIMEOUT_MS) {
+        Ok(0) => {}
+        Ok(count) => match &first_report {
+          Some(first) => hid.input_seen |= first[..] != buffer[..count],
+          None => first_report = Some(buffer[..count].to_vec()),
+        },
+        Err(e) => {
+          hid.error = Some(format!("HID read failed: {}", e));
+          break;
+        }
+      }
+    } else {
+      std::thread::sleep(Duration::from_millis(HID_READ_TIMEOUT_MS as u64));
+    }
+
+    if let Some(library) = &library {
+      for (slot, packet) in &packets {
+        if library
+          .state(*slot)
+          .is_some_and(|state| state.dwPacketNumber != *packet)
+        {
+          xinput.input_seen = true;
+        }
+      }
+    }
+  }
+
+  let (diagnosis, message) = diagnose(&xinput, &hid);
+  Ok(PresentationTestResult {
+    vendor_id: vid,
+    product_id: pid,
+    window_ms: window.as_millis() as u64,
+    xinput,
+    hid,
+    diagnosis,
+    message,
+  })
+}
+
+#[cfg(not(target_os = "windows"))]
+pub fn run_presentation_test(
+  _vid: Option<u16>,
+  _pid: Option<u16>,
+  _window: Option<std::time::Duration>,
+) -> Result<PresentationTestResult, HayboxError> {
+  Err(HayboxError::Unsupported(
+    "The XInput and Raw Input presentation test is only available on Windows".to_string(),
+  ))
+}
@@ -0,0 +1,141 @@
+use serde::{Deserialize, Serialize};
+#[cfg(target_os = "windows")]
+use windows::core::PWSTR;
+#[cfg(target_os = "windows")]
+use windows::Win32::Foundation::{CloseHandle, LocalFree, HANDLE, HLOCAL};
+#[cfg(target_os = "windows")]
+use windows::Win32::Security::Authorization::ConvertSidToStringSidW;
+#[cfg(target_os = "windows")]
+use windows::Win32::Security::{
+  GetTokenInformation, TokenElevation, TokenElevationType, TokenElevationTypeFull, TokenElevationTypeLimited,
+  TokenUser, TOKEN_ELEVATION, TOKEN_ELEVATION_TYPE, TOKEN_QUERY, TOKEN_USER,
+};
+#[cfg(target_os = "windows")]
+use windows::Win32::System::Threading::{GetCurrentProcess, OpenProcessToken};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PrivilegeLevel {
+  /// Running with a full administrator token.
+  Elevated,
+  /// Administrator account running with the filtered UAC token; a UAC prompt
+  /// is enough to elevate.
+  AdminNotElevated,
+  /// Standard user; elevating requires another account's credentials.
+  StandardUser,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PrivilegeStatus {
+  pub level: PrivilegeLevel,
+  pub is_elevated: bool,
+  pub is_admin_account: bool,
+}
+
+#[cfg(target_os = "windows")]
+fn query_token<T: Default>(token: HANDLE, class: windows::Win32::Security::TOKEN_INFORMATION_CLASS) -> Option<T> {
+  let mut value = T::default();
+  let mut returned = 0u32;
+  unsafe {
+    GetTokenInformation(
+      token,
+      class,
+      Some(&mut value as *mut T as *mut _),
+      std::mem::size_of::<T>() as u32,
+      &mut returned,
+    )
+  }
+  .ok()?;
+  Some(value)
+}
+
+#[cfg(target_os = "windows")]
+pub fn get_privilege_status() -> PrivilegeStatus {
+  let mut token = HANDLE::default();
+  if unsafe { OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token) }.is_err() {
+    return PrivilegeStatus {
+      level: PrivilegeLevel::StandardUser,
+      is_elevated: false,
+      is_admin_account: false,
+    };
+  }
+
+  let is_elevated = query_token::<TOKEN_ELEVATION>(token, TokenElevation)
+    .map(|elevation| elevation.TokenIsElevated != 0)
+    .unwrap_or(false);
+  let elevation_type = query_token::<TOKEN_ELEVATION_TYPE>(token, TokenElevationType);
+
+  let _ = unsafe { CloseHandle(token) };
+
+  // A limited token only exists for admin accounts under UAC. A default token
+  // that is not elevated belongs to a standard user.
+  let level = if is_elevated || elevation_type == Some(TokenElevationTypeFull) {
+    PrivilegeLevel::Elevated
+  } else if elevation_type == Some(TokenElevationTypeLimited) {
+    PrivilegeLevel::AdminNotElevated
+  } else {
+    PrivilegeLevel::StandardUser
+  };
+
+  PrivilegeStatus {
+    level,
+    is_elevated: level == PrivilegeLevel::Elevated,
+    is_admin_account: level != PrivilegeLevel::StandardUser,
+  }
+}
+
+/// Root counts as elevated. Anyone else is reported as a standard user,
+/// since there is no split token to tell admins apart.
+#[cfg(not(target_os = "windows"))]
+pub fn get_privilege_status() -> PrivilegeStatus {
+  let is_elevated = crate::platform::current().is_elevated();
+  PrivilegeStatus {
+    level: if is_elevated {
+      PrivilegeLevel::Elevated
+    } else {
+      PrivilegeLevel::StandardUser
+    },
+    is_elevated,
+    is_admin_account: is_elevated,
+  }
+}
+
+/// String SID of the account the process runs as, e.g. `S-1-5-21-...`.
+#[cfg(target_os = "windows")]
+pub fn current_user_sid() -> Option<String> {
+  let mut token = HANDLE::default();
+  unsafe { OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token) }.ok()?;
+
+  // TOKEN_USER is followed by the SID it points to, so ask for the size
+  // first. A u64 buffer keeps the struct's pointer aligned.
+  let mut length = 0u32;
+  let _ = unsafe { GetTokenInformation(token, TokenUser, None, 0, &mut length) };
+  let mut buffer = vec![0u64; (length as usize).div_ceil(8)];
+  let queried = length > 0
+    && unsafe {
+      GetTokenInformation(
+        token,
+        TokenUser,
+        Some(buffer.as_mut_ptr() as *mut _),
+        length,
+        &mut length,
+      )
+    }
+    .is_ok();
+  let _ = unsafe { CloseHandle(token) };
+  if !queried {
+    return None;
+  }
+
+  let user = unsafe { &*(buffer.as_ptr() as *const TOKEN_USER) };
+  let mut string_sid = PWSTR::null();
+  unsafe { ConvertSidToStringSidW(user.User.Sid, &mut string_sid) }.ok()?;
+  let sid = unsafe { string_sid.to_string() }.ok();
+  let _ = unsafe { LocalFree(Some(HLOCAL(string_sid.0 as *mut _))) };
+  sid
+}
+
+#[cfg(target_os = "windows")]
+pub fn is_elevated() -> bool {
+  get_privilege_status().is_elevated
+}
@@ -0,0 +1,87 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config_proto;
+
+/// Enough for a full connect/read/write session; older entries are dropped.
+const TRACE_CAPACITY: usize = 500;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TraceDirection {
+  Sent,
+  Received,
+}
+
+/// One decoded config protocol packet, before COBS framing.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TraceEntry {
+  pub timestamp_ms: u64,
+  pub direction: TraceDirection,
+  pub command: u8,
+  pub command_name: String,
+  pub length: usize,
+  /// Space-separated hex of the whole packet, command byte included.
+  pub hex: String,
+  /// The payload as JSON when its message type is known.
+  pub decoded: Option<serde_json::Value>,
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+lazy_static::lazy_static! {
+  static ref TRACE: Mutex<VecDeque<TraceEntry>> = Mutex::new(VecDeque::new());
+}
+
+pub fn set_enabled(enabled: bool) {
+  ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+  ENABLED.load(Ordering::Relaxed)
+}
+
+/// Adds a packet to the trace if tracing is on.
+pub fn record(direction: TraceDirection, packet: &[u8]) {
+  if !is_enabled() {
+    return;
+  }
+  let Some((&command, payload)) = packet.split_first() else {
+    return;
+  };
+
+  let entry = TraceEntry {
+    timestamp_ms: SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .map(|d| d.as_millis() as u64)
+      .unwrap_or_default(),
+    direction,
+    command,
+    command_name: config_proto::command_name(command).to_string(),
+    length: packet.len(),
+    hex: packet
+      .iter()
+      .map(|byte| format!("{:02X}", byte))
+      .collect::<Vec<_>>()
+      .join(" "),
+    decoded: config_proto::decode_payload(command, direction == TraceDirection::Sent, payload),
+  };
+
+  let mut trace = TRACE.lock().unwrap();
+  if trace.len() == TRACE_CAPACITY {
+    trace.pop_front();
+  }
+  trace.push_back(entry);
+}
+
+pub fn get_protocol_trace() -> Vec<TraceEntry> {
+  TRACE.lock().unwrap().iter().cloned().collect()
+}
+
+pub fn clear_protocol_trace() {
+  TRACE.lock().unwrap().clear();
+}
@@ -0,0 +1,149 @@
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::bootsel;
+use crate::error::HayboxError;
+use crate::events::Events;
+use crate::firmware::USER_AGENT;
+use crate::flashing::{self, FlashResult};
+use crate::hotplug::{self, HotplugKind};
+use crate::paths::app_data_dir;
+use crate::resources::driver_resource_dir;
+use crate::uf2::{self, RP2040_FAMILY_ID};
+use crate::DEVICES;
+
+/// Raspberry Pi's RAM-only image that erases the whole flash and reboots
+/// straight back into BOOTSEL.
+const FLASH_NUKE_URL: &str = "https://datasheets.raspberrypi.com/soft/flash_nuke.uf2";
+const FLASH_NUKE_FILE: &str = "flash_nuke.uf2";
+const CACHE_DIR: &str = "firmware_cache";
+/// Erasing 2 MB of flash takes a few seconds before the boot ROM comes back.
+const NUKE_TIMEOUT: Duration = Duration::from_secs(30);
+const VOLUME_TIMEOUT: Duration = Duration::from_secs(15);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ResetStage {
+  PreparingNuke,
+  EnteringBootsel,
+  Erasing,
+  WaitingForBootsel,
+  FlashingFirmware,
+  Done,
+}
+
+/// Emitted as `factory_reset_progress` at the start of each stage.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ResetProgress {
+  pub stage: ResetStage,
+  pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FactoryResetResult {
+  pub nuke_path: String,
+  pub flash: FlashResult,
+}
+
+fn emit_stage(events: &Events, stage: ResetStage, message: &str) {
+  events.emit(
+    "factory_reset_progress",
+    ResetProgress {
+      stage,
+      message: message.to_string(),
+    },
+  );
+}
+
+/// `flash_nuke.uf2` from the bundled resources if present, otherwise a cached
+/// download from raspberrypi.com.
+fn flash_nuke_path() -> Result<PathBuf, HayboxError> {
+  if let Some(path) = driver_resource_dir()
+    .map(|dir| dir.join(FLASH_NUKE_FILE))
+    .filter(|path| path.exists())
+  {
+    return Ok(path);
+  }
+
+  let path = app_data_dir().join(CACHE_DIR).join(FLASH_NUKE_FILE);
+  if std::fs::read(&path)
+    .map(|data| uf2::validate(&data, RP2040_FAMILY_ID).is_ok())
+    .unwrap_or(false)
+  {
+    return Ok(path);
+  }
+
+  let mut data = Vec::new();
+  ureq::get(FLASH_NUKE_URL)
+    .set("User-Agent", USER_AGENT)
+    .call()
+    .map_err(|e| HayboxError::Network(format!("Failed to download {}: {}", FLASH_NUKE_FILE, e)))?
+    .into_reader()
+    .read_to_end(&mut data)
+    .map_err(|e| HayboxError::Network(format!("Failed to download {}: {}", FLASH_NUKE_FILE, e)))?;
+  uf2::validate(&data, RP2040_FAMILY_ID)?;
+
+  if let Some(dir) = path.parent() {
+    std::fs::create_dir_all(dir).map_err(|e| HayboxError::Io(format!("Failed to create {}: {}", dir.display(), e)))?;
+  }
+  std::fs::write(&path, &data).map_err(|e| HayboxError::Io(format!("Failed to write {}: {}", path.display(), e)))?;
+  Ok(path)
+}
+
+/// Erases the controller's flash with flash_nuke.uf2, waits for it to come
+/// back in BOOTSEL and flashes `firmware_path`, emitting
+/// `factory_reset_progress` for each stage.
+pub fn factory_reset_device(events: &Events, firmware_path: &Path) -> Result<FactoryResetResult, HayboxError> {
+  let firmware = std::fs::read(firmware_path)
+    .map_err(|e| HayboxError::Io(format!("Failed to read {}: {}", firmware_path.display(), e)))?;
+  uf2::validate(&firmware, RP2040_FAMILY_ID)?;
+
+  emit_stage(events, ResetStage::PreparingNuke, "Locating flash_nuke.uf2");
+  let nuke_path = flash_nuke_path()?;
+
+  if flashing::find_bootsel_volumes().is_empty() {
+    emit_stage(
+      events,
+      ResetStage::EnteringBootsel,
+      "Rebooting the controller into BOOTSEL mode",
+    );
+    bootsel::enter_bootsel_mode()?;
+  }
+  flashing::wait_for_bootsel_volume(VOLUME_TIMEOUT).ok_or_else(|| {
+    HayboxError::DeviceNotConnected(
+      "No RPI-RP2 drive appeared; hold BOOTSEL while plugging the controller in".to_string(),
+    )
+  })?;
+
+  emit_stage(events, ResetStage::Erasing, "Erasing flash");
+  let bootsel = &DEVICES.bootsel_mode;
+  let waiter = hotplug::waiter();
+  flashing::copy_to_bootsel(events, &nuke_path)?;
+
+  emit_stage(
+    events,
+    ResetStage::WaitingForBootsel,
+    "Waiting for the controller to return to BOOTSEL",
+  );
+  waiter
+    .wait(NUKE_TIMEOUT, |event| {
+      event.kind == HotplugKind::Arrived && event.vendor_id == bootsel.vid && event.product_id == bootsel.pid
+    })
+    .ok_or_else(|| {
+      HayboxError::DeviceNotConnected("The controller did not return to BOOTSEL after erasing".to_string())
+    })?;
+  flashing::wait_for_bootsel_volume(VOLUME_TIMEOUT)
+    .ok_or_else(|| HayboxError::DeviceNotConnected("The RPI-RP2 drive did not reappear after erasing".to_string()))?;
+
+  emit_stage(events, ResetStage::FlashingFirmware, "Flashing firmware");
+  let flash = flashing::flash_uf2(events, firmware_path)?;
+
+  emit_stage(events, ResetStage::Done, "Factory reset complete");
+  Ok(FactoryResetResult {
+    nuke_path: nuke_path.display().to_string(),
+    flash,
+  })
+}
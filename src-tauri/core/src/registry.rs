@@ -0,0 +1,151 @@
+use windows::core::PCWSTR;
+use windows::Win32::Foundation::{ERROR_FILE_NOT_FOUND, ERROR_SUCCESS};
+use windows::Win32::System::Registry::{
+  RegDeleteKeyValueW, RegGetValueW, RegSetKeyValueW, HKEY, REG_BINARY, REG_ROUTINE_FLAGS, REG_SZ, RRF_RT_REG_BINARY,
+  RRF_RT_REG_DWORD, RRF_RT_REG_MULTI_SZ, RRF_RT_REG_SZ,
+};
+
+use crate::error::HayboxError;
+use crate::integrity::to_wide;
+
+pub use windows::Win32::System::Registry::{HKEY_CURRENT_USER, HKEY_LOCAL_MACHINE};
+
+/// Reads a value's raw bytes. Returns `None` if the key or value is missing or
+/// has a different type.
+fn read_raw(root: HKEY, subkey: &str, value: &str, flags: REG_ROUTINE_FLAGS) -> Option<Vec<u8>> {
+  let subkey = to_wide(subkey);
+  let value = to_wide(value);
+
+  let mut size = 0u32;
+  let status = unsafe {
+    RegGetValueW(
+      root,
+      PCWSTR(subkey.as_ptr()),
+      PCWSTR(value.as_ptr()),
+      flags,
+      None,
+      None,
+      Some(&mut size),
+    )
+  };
+  if status != ERROR_SUCCESS {
+    return None;
+  }
+
+  let mut buffer = vec![0u8; size as usize];
+  let status = unsafe {
+    RegGetValueW(
+      root,
+      PCWSTR(subkey.as_ptr()),
+      PCWSTR(value.as_ptr()),
+      flags,
+      None,
+      Some(buffer.as_mut_ptr() as *mut _),
+      Some(&mut size),
+    )
+  };
+  if status != ERROR_SUCCESS {
+    return None;
+  }
+
+  buffer.truncate(size as usize);
+  Some(buffer)
+}
+
+pub fn read_string(root: HKEY, subkey: &str, value: &str) -> Option<String> {
+  let buffer = read_raw(root, subkey, value, RRF_RT_REG_SZ)?;
+  let units: Vec<u16> = buffer
+    .chunks_exact(2)
+    .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+    .take_while(|&c| c != 0)
+    .collect();
+  Some(String::from_utf16_lossy(&units))
+}
+
+/// Reads a REG_MULTI_SZ value as its list of strings.
+pub fn read_multi_string(root: HKEY, subkey: &str, value: &str) -> Option<Vec<String>> {
+  let buffer = read_raw(root, subkey, value, RRF_RT_REG_MULTI_SZ)?;
+  let units: Vec<u16> = buffer
+    .chunks_exact(2)
+    .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+    .collect();
+  Some(
+    units
+      .split(|&c| c == 0)
+      .filter(|entry| !entry.is_empty())
+      .map(String::from_utf16_lossy)
+      .collect(),
+  )
+}
+
+pub fn read_binary(root: HKEY, subkey: &str, value: &str) -> Option<Vec<u8>> {
+  read_raw(root, subkey, value, RRF_RT_REG_BINARY)
+}
+
+pub fn read_dword(root: HKEY, subkey: &str, value: &str) -> Option<u32> {
+  let subkey = to_wide(subkey);
+  let value = to_wide(value);
+
+  let mut data = 0u32;
+  let mut size = std::mem::size_of::<u32>() as u32;
+  let status = unsafe {
+    RegGetValueW(
+      root,
+      PCWSTR(subkey.as_ptr()),
+      PCWSTR(value.as_ptr()),
+      RRF_RT_REG_DWORD,
+      None,
+      Some(&mut data as *mut u32 as *mut _),
+      Some(&mut size),
+    )
+  };
+  (status == ERROR_SUCCESS).then_some(data)
+}
+
+fn write_raw(root: HKEY, subkey: &str, value: &str, value_type: u32, data: &[u8]) -> Result<(), HayboxError> {
+  let wide_subkey = to_wide(subkey);
+  let wide_value = to_wide(value);
+
+  let status = unsafe {
+    RegSetKeyValueW(
+      root,
+      PCWSTR(wide_subkey.as_ptr()),
+      PCWSTR(wide_value.as_ptr()),
+      value_type,
+      Some(data.as_ptr() as *const _),
+      data.len() as u32,
+    )
+  };
+  if status != ERROR_SUCCESS {
+    return Err(HayboxError::Io(format!(
+      "Failed to write registry value {}\\{}: {:?}",
+      subkey, value, status
+    )));
+  }
+  Ok(())
+}
+
+/// Creates the key if needed and writes a REG_SZ value.
+pub fn write_string(root: HKEY, subkey: &str, value: &str, data: &str) -> Result<(), HayboxError> {
+  let bytes: Vec<u8> = to_wide(data).into_iter().flat_map(|unit| unit.to_le_bytes()).collect();
+  write_raw(root, subkey, value, REG_SZ.0, &bytes)
+}
+
+pub fn write_binary(root: HKEY, subkey: &str, value: &str, data: &[u8]) -> Result<(), HayboxError> {
+  write_raw(root, subkey, value, REG_BINARY.0, data)
+}
+
+/// Deletes a value. A value that does not exist counts as deleted.
+pub fn delete_value(root: HKEY, subkey: &str, value: &str) -> Result<(), HayboxError> {
+  let wide_subkey = to_wide(subkey);
+  let wide_value = to_wide(value);
+
+  let status = unsafe { RegDeleteKeyValueW(root, PCWSTR(wide_subkey.as_ptr()), PCWSTR(wide_value.as_ptr())) };
+  if status != ERROR_SUCCESS && status != ERROR_FILE_NOT_FOUND {
+    return Err(HayboxError::Io(format!(
+      "Failed to delete registry value {}\\{}: {:?}",
+      subkey, value, status
+    )));
+  }
+  Ok(())
+}
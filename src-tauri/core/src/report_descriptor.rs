@@ -0,0 +1,531 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+
+use hidapi::{HidApi, HidDevice};
+use serde::{Deserialize, Serialize};
+
+use crate::error::HayboxError;
+
+/// HID_API_MAX_REPORT_DESCRIPTOR_SIZE.
+const MAX_DESCRIPTOR: usize = 4096;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportType {
+  Input,
+  Output,
+  Feature,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemType {
+  Main,
+  Global,
+  Local,
+  Long,
+}
+
+/// One item of the descriptor, in order.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DescriptorItem {
+  /// Byte offset of the item's prefix.
+  pub offset: usize,
+  pub bytes: Vec<u8>,
+  pub item_type: ItemType,
+  pub tag: u8,
+  pub name: String,
+  /// Data as an integer, sign-extended where the item is signed.
+  pub value: Option<i32>,
+  /// Collection nesting the item sits in.
+  pub depth: usize,
+  /// `name` with its value spelled out, e.g. `Usage (X)`.
+  pub text: String,
+}
+
+/// One value in a report. Variable fields have a single usage; array fields
+/// hold an index into `usage..=usage_max`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DescriptorField {
+  pub report_type: ReportType,
+  pub report_id: u8,
+  /// Bits from the start of the report data, after the report id byte.
+  pub bit_offset: u32,
+  pub bit_size: u32,
+  pub usage_page: u16,
+  pub usage: u16,
+  pub usage_max: Option<u16>,
+  pub usage_name: String,
+  pub logical_min: i32,
+  pub logical_max: i32,
+  pub constant: bool,
+  pub variable: bool,
+  pub relative: bool,
+  /// Usages of the collections the field is in, outermost first.
+  pub collections: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReportLayout {
+  pub report_type: ReportType,
+  pub report_id: u8,
+  pub bit_length: u32,
+  /// Bytes on the wire, report id included.
+  pub byte_length: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ParsedDescriptor {
+  pub vendor_id: Option<u16>,
+  pub product_id: Option<u16>,
+  pub raw: Vec<u8>,
+  pub items: Vec<DescriptorItem>,
+  pub fields: Vec<DescriptorField>,
+  pub reports: Vec<ReportLayout>,
+  /// The items as an indented listing, one per line with their bytes.
+  pub pretty: String,
+}
+
+/// Global item state, saved and restored by Push and Pop items.
+#[derive(Clone, Default)]
+struct Globals {
+  usage_page: u16,
+  logical_min: i32,
+  logical_max: i32,
+  report_size: u32,
+  report_count: u32,
+  report_id: u8,
+}
+
+fn item_value(data: &[u8], signed: bool) -> i32 {
+  let mut bytes = [0u8; 4];
+  bytes[..data.len()].copy_from_slice(data);
+  let value = u32::from_le_bytes(bytes);
+  if !signed || data.is_empty() {
+    return value as i32;
+  }
+  let shift = 32 - 8 * data.len() as u32;
+  ((value << shift) as i32) >> shift
+}
+
+fn item_name(item_type: ItemType, tag: u8) -> &'static str {
+  match (item_type, tag) {
+    (ItemType::Main, 0x8) => "Input",
+    (ItemType::Main, 0x9) => "Output",
+    (ItemType::Main, 0xA) => "Collection",
+    (ItemType::Main, 0xB) => "Feature",
+    (ItemType::Main, 0xC) => "End Collection",
+    (ItemType::Global, 0x0) => "Usage Page",
+    (ItemType::Global, 0x1) => "Logical Minimum",
+    (ItemType::Global, 0x2) => "Logical Maximum",
+    (ItemType::Global, 0x3) => "Physical Minimum",
+    (ItemType::Global, 0x4) => "Physical Maximum",
+    (ItemType::Global, 0x5) => "Unit Exponent",
+    (ItemType::Global, 0x6) => "Unit",
+    (ItemType::Global, 0x7) => "Report Size",
+    (ItemType::Global, 0x8) => "Report ID",
+    (ItemType::Global, 0x9) => "Report Count",
+    (ItemType::Global, 0xA) => "Push",
+    (ItemType::Global, 0xB) => "Pop",
+    (ItemType::Local, 0x0) => "Usage",
+    (ItemType::Local, 0x1) => "Usage Minimum",
+    (ItemType::Local, 0x2) => "Usage Maximum",
+    (ItemType::Local, 0x3) => "Designator Index",
+    (ItemType::Local, 0x4) => "Designator Minimum",
+    (ItemType::Local, 0x5) => "Designator Maximum",
+    (ItemType::Local, 0x7) => "String Index",
+    (ItemType::Local, 0x8) => "String Minimum",
+    (ItemType::Local, 0x9) => "String Maximum",
+    (ItemType::Local, 0xA) => "Delimiter",
+    (ItemType::Long, _) => "Long Item",
+    _ => "Reserved",
+  }
+}
+
+fn usage_page_name(page: u16) -> String {
+  match page {
+    0x01 => "Generic Desktop".to_string(),
+    0x02 => "Simulation Controls".to_string(),
+    0x05 => "Game Controls".to_string(),
+    0x07 => "Keyboard/Keypad".to_string(),
+    0x08 => "LED".to_string(),
+    0x09 => "Button".to_string(),
+    0x0C => "Consumer".to_string(),
+    0x0F => "Physical Interface Device".to_string(),
+    0xFF00..=0xFFFF => format!("Vendor Defined 0x{:04X}", page),
+    _ => format!("0x{:04X}", page),
+  }
+}
+
+/// Name of a usage on its page, for the pages game controllers use.
+pub fn usage_name(page: u16, usage: u16) -> String {
+  let name = match (page, usage) {
+    (0x01, 0x01) => "Pointer",
+    (0x01, 0x02) => "Mouse",
+    (0x01, 0x04) => "Joystick",
+    (0x01, 0x05) => "Gamepad",
+    (0x01, 0x06) => "Keyboard",
+    (0x01, 0x08) => "Multi-axis Controller",
+    (0x01, 0x30) => "X",
+    (0x01, 0x31) => "Y",
+    (0x01, 0x32) => "Z",
+    (0x01, 0x33) => "Rx",
+    (0x01, 0x34) => "Ry",
+    (0x01, 0x35) => "Rz",
+    (0x01, 0x36) => "Slider",
+    (0x01, 0x37) => "Dial",
+    (0x01, 0x38) => "Wheel",
+    (0x01, 0x39) => "Hat Switch",
+    (0x01, 0x90) => "D-pad Up",
+    (0x01, 0x91) => "D-pad Down",
+    (0x01, 0x92) => "D-pad Right",
+    (0x01, 0x93) => "D-pad Left",
+    (0x02, 0xC4) => "Accelerator",
+    (0x02, 0xC5) => "Brake",
+    (0x09, 0) => "No Button",
+    (0x09, button) => return format!("Button {}", button),
+    _ => return format!("0x{:04X}", usage),
+  };
+  name.to_string()
+}
+
+fn collection_name(kind: i32) -> String {
+  match kind {
+    0x00 => "Physical".to_string(),
+    0x01 => "Application".to_string(),
+    0x02 => "Logical".to_string(),
+    0x03 => "Report".to_string(),
+    0x04 => "Named Array".to_string(),
+    0x05 => "Usage Switch".to_string(),
+    0x06 => "Usage Modifier".to_string(),
+    kind => format!("0x{:02X}", kind),
+  }
+}
+
+/// Data flags of Input, Output and Feature items, bit 0 first. Past the
+/// first three, only flags that are set are listed.
+fn main_flags(flags: i32) -> String {
+  const FLAGS: [(&str, &str); 9] = [
+    ("Data", "Const"),
+    ("Array", "Var"),
+    ("Abs", "Rel"),
+    ("No Wrap", "Wrap"),
+    ("Linear", "Non-linear"),
+    ("Preferred State", "No Preferred"),
+    ("No Null Position", "Null State"),
+    ("Non-volatile", "Volatile"),
+    ("Bit Field", "Buffered Bytes"),
+  ];
+  FLAGS
+    .iter()
+    .enumerate()
+    .filter(|(bit, _)| *bit < 3 || flags & (1 << bit) != 0)
+    .map(|(bit, (clear, set))| if flags & (1 << bit) != 0 { *set } else { *clear })
+    .collect::<Vec<_>>()
+    .join(",")
+}
+
+/// Decodes every item of a report descriptor, and lays out the fields of
+/// each input, output and feature report.
+pub fn decode_report_descriptor(descriptor: &[u8]) -> Result<ParsedDescriptor, HayboxError> {
+  let mut items = Vec::new();
+  let mut fields = Vec::new();
+  let mut globals = Globals::default();
+  let mut stack: Vec<Globals> = Vec::new();
+  let mut usages: Vec<(u16, u16)> = Vec::new();
+  let mut usage_range: (Option<u32>, Option<u32>) = (None, None);
+  let mut collections: Vec<String> = Vec::new();
+  let mut offsets: HashMap<(ReportType, u8), u32> = HashMap::new();
+  let mut report_order: Vec<(ReportType, u8)> = Vec::new();
+
+  let mut index = 0;
+  while index < descriptor.len() {
+    let offset = index;
+    let prefix = descriptor[index];
+    if prefix == 0xFE {
+      // Long item: size byte, tag byte, data.
+      let size = *descriptor
+        .get(index + 1)
+        .ok_or_else(|| HayboxError::Other("Truncated long item in report descriptor".to_string()))?
+        as usize;
+      let bytes = descriptor
+        .get(index..index + 3 + size)
+        .ok_or_else(|| HayboxError::Other("Truncated long item in report descriptor".to_string()))?;
+      index += 3 + size;
+      items.push(DescriptorItem {
+        offset,
+        bytes: bytes.to_vec(),
+        item_type: ItemType::Long,
+        tag: bytes[2],
+        name: item_name(ItemType::Long, bytes[2]).to_string(),
+        value: None,
+        depth: collections.len(),
+        text: format!("Long Item (tag 0x{:02X}, {} bytes)", bytes[2], size),
+      });
+      continue;
+    }
+    let size = match prefix & 0x03 {
+      3 => 4,
+      size => size as usize,
+    };
+    let data = descriptor
+      .get(index + 1..index + 1 + size)
+      .ok_or_else(|| HayboxError::Other("Truncated item in report descriptor".to_string()))?;
+    index += 1 + size;
+
+    let tag = prefix >> 4;
+    let item_type = match (prefix >> 2) & 0x03 {
+      0 => ItemType::Main,
+      1 => ItemType::Global,
+      2 => ItemType::Local,
+      _ => {
+        return Err(HayboxError::Other(format!(
+          "Reserved item type at byte {} of report descriptor",
+          offset
+        )))
+      }
+    };
+    let signed = item_type == ItemType::Global && (0x1..=0x5).contains(&tag);
+    let value = item_value(data, signed);
+    let name = item_name(item_type, tag);
+    let mut depth = collections.len();
+    let mut detail = None;
+
+    match item_type {
+      ItemType::Main => {
+        let report_type = match tag {
+          0x8 => Some(ReportType::Input),
+          0x9 => Some(ReportType::Output),
+          0xB => Some(ReportType::Feature),
+          _ => None,
+        };
+        if let Some(report_type) = report_type {
+          detail = Some(main_flags(value));
+          let constant = value & 0x01 != 0;
+          let variable = value & 0x02 != 0;
+          let relative = value & 0x04 != 0;
+          let key = (report_type, globals.report_id);
+          if !report_order.contains(&key) {
+            report_order.push(key);
+          }
+          let offset = offsets.entry(key).or_default();
+
+          let mut push_field = |bit_offset: u32, bit_size: u32, usage_page: u16, usage: u16, usage_max: Option<u16>| {
+            let usage_page = resolve_page(usage_page, &globals);
+            fields.push(DescriptorField {
+              report_type,
+              report_id: globals.report_id,
+              bit_offset,
+              bit_size,
+              usage_page,
+              usage,
+              usage_max,
+              usage_name: match usage_max {
+                Some(max) => format!("{}..{}", usage_name(usage_page, usage), usage_name(usage_page, max)),
+                None if constant => "Padding".to_string(),
+                None => usage_name(usage_page, usage),
+              },
+              logical_min: globals.logical_min,
+              logical_max: globals.logical_max,
+              constant,
+              variable,
+              relative,
+              collections: collections.clone(),
+            });
+          };
+
+          let bit_length = globals.report_size * globals.report_count;
+          // Padding is one field however many slots it spans.
+          if constant && bit_length > 0 {
+            push_field(*offset, bit_length, 0, 0, None);
+          }
+          for i in 0..globals.report_count {
+            let bit_offset = *offset + i * globals.report_size;
+            if constant {
+              break;
+            }
+            if !variable {
+              let (min, max) = match usage_range {
+                (Some(min), Some(max)) => (min, max),
+                _ => {
+                  let first = usages.first().copied().unwrap_or_default();
+                  let last = usages.last().copied().unwrap_or_default();
+                  (
+                    ((first.0 as u32) << 16) | first.1 as u32,
+                    ((last.0 as u32) << 16) | last.1 as u32,
+                  )
+                }
+              };
+              push_field(
+                bit_offset,
+                globals.report_size,
+                (min >> 16) as u16,
+                min as u16,
+                Some(max as u16),
+              );
+              continue;
+            }
+            let (usage_page, usage) = usages.get(i as usize).copied().unwrap_or_else(|| match usage_range {
+              (Some(min), Some(max)) => {
+                let usage = (min + i).min(max);
+                ((usage >> 16) as u16, usage as u16)
+              }
+              _ => usages.last().copied().unwrap_or_default(),
+            });
+            push_field(bit_offset, globals.report_size, usage_page, usage, None);
+          }
+          *offset += bit_length;
+        } else if tag == 0xA {
+          detail = Some(collection_name(value));
+          let (usage_page, usage) = usages.first().copied().unwrap_or_default();
+          collections.push(usage_name(resolve_page(usage_page, &globals), usage));
+        } else if tag == 0xC {
+          collections
+            .pop()
+            .ok_or_else(|| HayboxError::Other(format!("End Collection without a Collection at byte {}", offset)))?;
+          depth = collections.len();
+        }
+        usages.clear();
+        usage_range = (None, None);
+      }
+      ItemType::Global => match tag {
+        0x0 => {
+          globals.usage_page = value as u16;
+          detail = Some(usage_page_name(globals.usage_page));
+        }
+        0x1 => globals.logical_min = value,
+        0x2 => {
+          globals.logical_max = value;
+          // Descriptors often write e.g. 255 in one byte, which reads as -1
+          // when sign-extended.
+          if globals.logical_max < globals.logical_min {
+            globals.logical_max = item_value(data, false);
+          }
+        }
+        0x7 => globals.report_size = value as u32,
+        0x8 => globals.report_id = value as u8,
+        0x9 => globals.report_count = value as u32,
+        0xA => stack.push(globals.clone()),
+        0xB => {
+          globals = stack
+            .pop()
+            .ok_or_else(|| HayboxError::Other("Unbalanced pop in report descriptor".to_string()))?
+        }
+        _ => {}
+      },
+      // Four-byte usages carry their own usage page.
+      ItemType::Local => {
+        let value = value as u32;
+        let extended = if size == 4 {
+          value
+        } else {
+          ((globals.usage_page as u32) << 16) | value
+        };
+        let (usage_page, usage) = ((extended >> 16) as u16, value as u16);
+        match tag {
+          0x0 => {
+            usages.push((usage_page, usage));
+            detail = Some(usage_name(usage_page, usage));
+          }
+          0x1 => {
+            usage_range.0 = Some(extended);
+            detail = Some(usage_name(usage_page, usage));
+          }
+          0x2 => {
+            usage_range.1 = Some(extended);
+            detail = Some(usage_name(usage_page, usage));
+          }
+          _ => {}
+        }
+      }
+      ItemType::Long => {}
+    }
+
+    let text = match (&detail, data.is_empty()) {
+      (Some(detail), _) => format!("{} ({})", name, detail),
+      (None, true) => name.to_string(),
+      (None, false) => format!("{} ({})", name, value),
+    };
+    items.push(DescriptorItem {
+      offset,
+      bytes: descriptor[offset..index].to_vec(),
+      item_type,
+      tag,
+      name: name.to_string(),
+      value: (!data.is_empty()).then_some(value),
+      depth,
+      text,
+    });
+  }
+
+  if !collections.is_empty() {
+    tracing::warn!("report descriptor ends inside {} collection(s)", collections.len());
+  }
+
+  let reports = report_order
+    .into_iter()
+    .map(|key| {
+      let bit_length = offsets[&key];
+      ReportLayout {
+        report_type: key.0,
+        report_id: key.1,
+        bit_length,
+        byte_length: bit_length.div_ceil(8) + u32::from(key.1 != 0),
+      }
+    })
+    .collect();
+
+  Ok(ParsedDescriptor {
+    vendor_id: None,
+    product_id: None,
+    raw: descriptor.to_vec(),
+    pretty: pretty_print(&items),
+    items,
+    fields,
+    reports,
+  })
+}
+
+/// Usages given without a page are on the current usage page.
+fn resolve_page(usage_page: u16, globals: &Globals) -> u16 {
+  if usage_page == 0 {
+    globals.usage_page
+  } else {
+    usage_page
+  }
+}
+
+fn pretty_print(items: &[DescriptorItem]) -> String {
+  let mut text = String::new();
+  for item in items {
+    let bytes = item
+      .bytes
+      .iter()
+      .map(|byte| format!("{:02X}", byte))
+      .collect::<Vec<_>>()
+      .join(" ");
+    let _ = writeln!(text, "{:<16}{}{}", bytes, "  ".repeat(item.depth), item.text);
+  }
+  text
+}
+
+pub fn read_descriptor(device: &HidDevice) -> Result<Vec<u8>, HayboxError> {
+  let mut descriptor = [0u8; MAX_DESCRIPTOR];
+  let length = device
+    .get_report_descriptor(&mut descriptor)
+    .map_err(|e| HayboxError::Hid(format!("Failed to read report descriptor: {}", e)))?;
+  Ok(descriptor[..length].to_vec())
+}
+
+/// Fetches the report descriptor of a HID interface and decodes it.
+pub fn read_report_descriptor(vid: u16, pid: u16) -> Result<ParsedDescriptor, HayboxError> {
+  let api = HidApi::new().map_err(|e| HayboxError::Hid(format!("Failed to initialize HID: {}", e)))?;
+  let device = api
+    .open(vid, pid)
+    .map_err(|e| HayboxError::Hid(format!("Failed to open HID device {:04x}:{:04x}: {}", vid, pid, e)))?;
+
+  let mut parsed = decode_report_descriptor(&read_descriptor(&device)?)?;
+  parsed.vendor_id = Some(vid);
+  parsed.product_id = Some(pid);
+  Ok(parsed)
+}
@@ -0,0 +1,68 @@
+use std::path::PathBuf;
+
+use crate::error::HayboxError;
+use crate::integrity::sha256_file;
+use crate::paths::app_data_dir;
+
+include!(concat!(env!("OUT_DIR"), "/embedded_resources.rs"));
+
+const RESOURCE_DIR_NAME: &str = "driver_resources";
+
+/// `driver_resources/` next to the executable, if present. Used to override
+/// the embedded copies without rebuilding.
+fn override_dir() -> Option<PathBuf> {
+  let dir = std::env::current_exe().ok()?.parent()?.join(RESOURCE_DIR_NAME);
+  dir.exists().then_some(dir)
+}
+
+/// Writes the embedded resources into the app data directory, skipping files
+/// whose hash already matches and checking every file written against the
+/// hash taken at build time.
+fn extract_embedded() -> Result<PathBuf, HayboxError> {
+  let dir = app_data_dir().join(RESOURCE_DIR_NAME);
+  std::fs::create_dir_all(&dir).map_err(|e| HayboxError::Io(format!("Failed to create {}: {}", dir.display(), e)))?;
+
+  for (file_name, bytes, expected, _) in EMBEDDED_RESOURCES {
+    let path = dir.join(file_name);
+
+    if sha256_file(&path).ok().as_deref() == Some(*expected) {
+      continue;
+    }
+
+    if let Some(parent) = path.parent() {
+      std::fs::create_dir_all(parent)
+        .map_err(|e| HayboxError::Io(format!("Failed to create {}: {}", parent.display(), e)))?;
+    }
+    std::fs::write(&path, bytes).map_err(|e| HayboxError::Io(format!("Failed to extract {}: {}", file_name, e)))?;
+
+    if sha256_file(&path).ok().as_deref() != Some(*expected) {
+      return Err(HayboxError::Other(format!(
+        "Extracted {} does not match the hash taken at build time",
+        file_name
+      )));
+    }
+  }
+
+  Ok(dir)
+}
+
+/// Directory holding the INF templates, coinstallers and devcon builds. Loose
+/// files next to the executable win; otherwise the embedded copies are
+/// extracted. Returns `None` when neither source is available.
+pub fn driver_resource_dir() -> Option<PathBuf> {
+  if let Some(dir) = override_dir() {
+    return Some(dir);
+  }
+
+  if EMBEDDED_RESOURCES.is_empty() {
+    return None;
+  }
+
+  match extract_embedded() {
+    Ok(dir) => Some(dir),
+    Err(e) => {
+      tracing::warn!("{}", e);
+      None
+    }
+  }
+}
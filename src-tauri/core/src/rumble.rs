@@ -0,0 +1,283 @@
+use std::time::Duration;
+
+use hidapi::HidApi;
+use serde::{Deserialize, Serialize};
+
+use crate::error::HayboxError;
+use crate::report_descriptor::{self, DescriptorField, ReportType};
+#[cfg(target_os = "windows")]
+use crate::xinput::{XInputLibrary, XUSER_MAX_COUNT};
+use crate::{gamecube_adapter, DEVICES};
+
+/// The adapter's rumble is on or off; steps at least this strong turn it on.
+const ADAPTER_ON_INTENSITY: f32 = 0.5;
+/// Usage pages whose output usages drive force feedback: Physical Interface
+/// Device and Haptics.
+const PID_PAGE: u16 = 0x0F;
+const HAPTICS_PAGE: u16 = 0x0E;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RumbleStep {
+  /// 0.0 is off, 1.0 full strength.
+  pub intensity: f32,
+  pub duration_ms: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RumblePattern {
+  Pulse,
+  DoublePulse,
+  /// Quarter, half, three quarters and full strength in turn.
+  Ramp,
+  Custom {
+    steps: Vec<RumbleStep>,
+  },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RumbleTransport {
+  GamecubeAdapter,
+  XInput,
+  HidOutputReport,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RumbleResult {
+  pub vendor_id: u16,
+  pub product_id: u16,
+  pub transport: RumbleTransport,
+  pub steps: usize,
+  /// Steps whose transfer the device or driver accepted.
+  pub steps_sent: usize,
+  pub success: bool,
+  /// Length of the output report sent, report id included.
+  pub report_length: Option<usize>,
+  pub message: String,
+}
+
+impl RumblePattern {
+  fn steps(&self) -> Vec<RumbleStep> {
+    let step = |intensity: f32, duration_ms: u64| RumbleStep { intensity, duration_ms };
+    match self {
+      RumblePattern::Pulse => vec![step(1.0, 300)],
+      RumblePattern::DoublePulse => vec![step(1.0, 150), step(0.0, 150), step(1.0, 150)],
+      RumblePattern::Ramp => vec![step(0.25, 250), step(0.5, 250), step(0.75, 250), step(1.0, 250)],
+      RumblePattern::Custom { steps } => steps.clone(),
+    }
+  }
+}
+
+fn write_bits(data: &mut [u8], offset: u32, size: u32, value: u32) {
+  for bit in 0..size.min(32) {
+    let position = offset + bit;
+    let Some(byte) = data.get_mut((position / 8) as usize) else {
+      return;
+    };
+    if value & (1 << bit) != 0 {
+      *byte |= 1 << (position % 8);
+    } else {
+      *byte &= !(1 << (position % 8));
+    }
+  }
+}
+
+/// Builds an output report with the force feedback fields set to
+/// `intensity` of their logical range and everything else zero.
+fn build_report(report_id: u8, length: usize, fields: &[&DescriptorField], intensity: f32) -> Vec<u8> {
+  let mut report = vec![0u8; length];
+  report[0] = report_id;
+  for field in fields {
+    let range = (field.logical_max - field.logical_min) as f32;
+    let value = field.logical_min + (range * intensity.clamp(0.0, 1.0)).round() as i32;
+    write_bits(&mut report[1..], field.bit_offset, field.bit_size, value as u32);
+  }
+  report
+}
+
+fn rumble_hid(vid: u16, pid: u16, steps: &[RumbleStep]) -> Result<RumbleResult, HayboxError> {
+  let api = HidApi::new().map_err(|e| HayboxError::Hid(format!("Failed to initialize HID: {}", e)))?;
+  let device = api
+    .open(vid, pid)
+    .map_err(|e| HayboxError::Hid(format!("Failed to open HID device {:04x}:{:04x}: {}", vid, pid, e)))?;
+  let descriptor = report_descriptor::decode_report_descriptor(&report_descriptor::read_descriptor(&device)?)?;
+
+  // Other output usages can be LEDs or vendor settings, so only force
+  // feedback ones are written.
+  let is_feedback = |field: &&DescriptorField| {
+    field.report_type == ReportType::Output && !field.constant && matches!(field.usage_page, PID_PAGE | HAPTICS_PAGE)
+  };
+  let report_id = descriptor
+    .fields
+    .iter()
+    .find(is_feedback)
+    .map(|field| field.report_id)
+    .ok_or_else(|| {
+      HayboxError::Unsupported("The device declares no force feedback output, so it cannot be sent rumble".to_string())
+    })?;
+  let layout = descriptor
+    .reports
+    .iter()
+    .find(|report| report.report_type == ReportType::Output && report.report_id == report_id)
+    .ok_or_else(|| HayboxError::Other(format!("Output report {} has no layout", report_id)))?;
+  let fields: Vec<&DescriptorField> = descriptor
+    .fields
+    .iter()
+    .filter(is_feedback)
+    .filter(|field| field.report_id == report_id)
+    .collect();
+  // hidapi always takes the report id first, 0 when the device has none.
+  let length = layout.bit_length.div_ceil(8) as usize + 1;
+
+  let mut steps_sent = 0;
+  let mut last_error = None;
+  for step in steps {
+    match device.write(&build_report(layout.report_id, length, &fields, step.intensity)) {
+      Ok(_) => steps_sent += 1,
+      Err(e) => last_error = Some(e.to_string()),
+    }
+    std::thread::sleep(Duration::from_millis(step.duration_ms));
+  }
+  if let Err(e) = device.write(&build_report(layout.report_id, length, &fields, 0.0)) {
+    tracing::warn!("failed to stop rumble: {}", e);
+  }
+
+  let success = steps_sent == steps.len();
+  Ok(RumbleResult {
+    vendor_id: vid,
+    product_id: pid,
+    transport: RumbleTransport::HidOutputReport,
+    steps: steps.len(),
+    steps_sent,
+    success,
+    report_length: Some(length),
+    message: match last_error {
+      None => format!(
+        "Sent {} output report(s) of {} bytes to report {}",
+        steps_sent, length, layout.report_id
+      ),
+      Some(e) => format!(
+        "The driver rejected {} of {} output report(s): {}",
+        steps.len() - steps_sent,
+        steps.len(),
+        e
+      ),
+    },
+  })
+}
+
+/// XInput does not say which device a slot belongs to, so every connected
+/// slot rumbles.
+#[cfg(target_os = "windows")]
+fn rumble_xinput(vid: u16, pid: u16, steps: &[RumbleStep]) -> Result<RumbleResult, HayboxError> {
+  let library = XInputLibrary::load()?;
+  let slots: Vec<u32> = (0..XUSER_MAX_COUNT)
+    .filter(|&slot| library.state(slot).is_some())
+    .collect();
+  if slots.is_empty() {
+    return Err(HayboxError::DeviceNotConnected(
+      "No XInput controller is connected".to_string(),
+    ));
+  }
+
+  let mut steps_sent = 0;
+  for step in steps {
+    let speed = (u16::MAX as f32 * step.intensity.clamp(0.0, 1.0)).round() as u16;
+    if slots.iter().all(|&slot| library.set_vibration(slot, speed, speed)) {
+      steps_sent += 1;
+    }
+    std::thread::sleep(Duration::from_millis(step.duration_ms));
+  }
+  for &slot in &slots {
+    if !library.set_vibration(slot, 0, 0) {
+      tracing::warn!("failed to stop rumble on XInput slot {}", slot);
+    }
+  }
+
+  let success = steps_sent == steps.len();
+  let slot_list = slots.iter().map(|slot| slot.to_string()).collect::<Vec<_>>().join(", ");
+  Ok(RumbleResult {
+    vendor_id: vid,
+    product_id: pid,
+    transport: RumbleTransport::XInput,
+    steps: steps.len(),
+    steps_sent,
+    success,
+    report_length: None,
+    message: if success {
+      format!("Sent {} rumble command(s) to XInput slot(s) {}", steps_sent, slot_list)
+    } else {
+      format!(
+        "XInput rejected {} of {} rumble command(s) for slot(s) {}",
+        steps.len() - steps_sent,
+        steps.len(),
+        slot_list
+      )
+    },
+  })
+}
+
+#[cfg(not(target_os = "windows"))]
+fn rumble_xinput(_vid: u16, _pid: u16, _steps: &[RumbleStep]) -> Result<RumbleResult, HayboxError> {
+  Err(HayboxError::Unsupported(
+    "Rumble in XInput mode is only available on Windows".to_string(),
+  ))
+}
+
+fn rumble_adapter(steps: &[RumbleStep]) -> Result<RumbleResult, HayboxError> {
+  let adapter = &DEVICES.gamecube_mode;
+  let commands: Vec<(bool, Duration)> = steps
+    .iter()
+    .map(|step| {
+      (
+        step.intensity >= ADAPTER_ON_INTENSITY,
+        Duration::from_millis(step.duration_ms),
+      )
+    })
+    .collect();
+  let (steps_sent, rumble_powered) = gamecube_adapter::play_rumble(&commands)?;
+
+  let success = steps_sent == steps.len();
+  Ok(RumbleResult {
+    vendor_id: adapter.vid,
+    product_id: adapter.pid,
+    transport: RumbleTransport::GamecubeAdapter,
+    steps: steps.len(),
+    steps_sent,
+    success,
+    report_length: None,
+    message: if !success {
+      format!(
+        "The adapter rejected {} of {} rumble command(s)",
+        steps.len() - steps_sent,
+        steps.len()
+      )
+    } else if !rumble_powered {
+      "Rumble commands were accepted, but the adapter has no rumble power; plug in the grey USB cable".to_string()
+    } else {
+      "Rumble commands were accepted".to_string()
+    },
+  })
+}
+
+/// Plays a rumble pattern on a device and reports whether each transfer
+/// succeeded. The GameCube adapter gets its own rumble command and the
+/// controller's default XInput mode goes through XInputSetState; anything
+/// else is sent HID output reports built from its report descriptor.
+pub fn test_rumble(vid: u16, pid: u16, pattern: Option<RumblePattern>) -> Result<RumbleResult, HayboxError> {
+  let steps = pattern.unwrap_or(RumblePattern::Pulse).steps();
+  if steps.is_empty() {
+    return Err(HayboxError::Other("The rumble pattern has no steps".to_string()));
+  }
+
+  let adapter = &DEVICES.gamecube_mode;
+  let xinput = &DEVICES.default_mode;
+  if (vid, pid) == (adapter.vid, adapter.pid) {
+    rumble_adapter(&steps)
+  } else if (vid, pid) == (xinput.vid, xinput.pid) {
+    rumble_xinput(vid, pid, &steps)
+  } else {
+    rumble_hid(vid, pid, &steps)
+  }
+}
@@ -0,0 +1,214 @@
+use std::collections::VecDeque;
+use std::io::Read;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::error::HayboxError;
+use crate::events::Events;
+use crate::{config_proto, serial_ports, DEVICES};
+
+const DEFAULT_BAUD_RATE: u32 = 115_200;
+const READ_TIMEOUT: Duration = Duration::from_millis(100);
+/// Lines kept for `get_console_lines` and saving; older ones are dropped.
+const MAX_LINES: usize = 10_000;
+/// A line this long without a newline is flushed as is.
+const MAX_LINE_BYTES: usize = 4096;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ConsoleLine {
+  pub timestamp_ms: u64,
+  pub text: String,
+}
+
+/// Emitted as `console_closed` when the reader stops on its own, usually
+/// because the controller was unplugged or rebooted.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ConsoleClosed {
+  pub port: String,
+  pub error: Option<String>,
+}
+
+struct Console {
+  port: String,
+  stop: Arc<AtomicBool>,
+  reader: JoinHandle<()>,
+}
+
+lazy_static::lazy_static! {
+  static ref CONSOLE: Mutex<Option<Console>> = Mutex::new(None);
+  static ref LINES: Mutex<VecDeque<ConsoleLine>> = Mutex::new(VecDeque::new());
+  static ref FILTER: Mutex<Option<Regex>> = Mutex::new(None);
+}
+
+fn now_ms() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_millis() as u64)
+    .unwrap_or_default()
+}
+
+fn push_line(events: &Events, bytes: &[u8]) {
+  let text = String::from_utf8_lossy(bytes).trim_end_matches('\r').to_string();
+  let line = ConsoleLine {
+    timestamp_ms: now_ms(),
+    text,
+  };
+
+  {
+    let mut lines = LINES.lock().unwrap();
+    if lines.len() == MAX_LINES {
+      lines.pop_front();
+    }
+    lines.push_back(line.clone());
+  }
+
+  let matches = FILTER
+    .lock()
+    .unwrap()
+    .as_ref()
+    .is_none_or(|filter| filter.is_match(&line.text));
+  if matches {
+    events.emit("console_line", line);
+  }
+}
+
+fn read_loop(events: Events, mut port: Box<dyn serialport::SerialPort>, port_name: String, stop: Arc<AtomicBool>) {
+  let mut buffer = [0u8; 1024];
+  let mut pending: Vec<u8> = Vec::new();
+
+  let error = loop {
+    if stop.load(Ordering::Relaxed) {
+      return;
+    }
+    match port.read(&mut buffer) {
+      Ok(count) => pending.extend_from_slice(&buffer[..count]),
+      Err(e) if e.kind() == std::io::ErrorKind::TimedOut => continue,
+      Err(e) => break Some(e.to_string()),
+    }
+
+    while let Some(end) = pending.iter().position(|&byte| byte == b'\n') {
+      let line: Vec<u8> = pending.drain(..=end).take(end).collect();
+      push_line(&events, &line);
+    }
+    if pending.len() > MAX_LINE_BYTES {
+      push_line(&events, &pending);
+      pending.clear();
+    }
+  };
+
+  if !pending.is_empty() {
+    push_line(&events, &pending);
+  }
+  let mut console = CONSOLE.lock().unwrap();
+  if console
+    .as_ref()
+    .is_some_and(|console| Arc::ptr_eq(&console.stop, &stop))
+  {
+    *console = None;
+  }
+  events.emit("console_closed", ConsoleClosed { port: port_name, error });
+}
+
+/// Opens `port` (the config mode port if not given) and streams its output
+/// as `console_line` events. Any console already open is closed first.
+pub fn open_console(events: &Events, port: Option<&str>, baud_rate: Option<u32>) -> Result<String, HayboxError> {
+  let port_name = match port {
+    Some(port) => port.to_string(),
+    None => {
+      let config = &DEVICES.config_mode;
+      serial_ports::single_port_for_device(config.vid, config.pid)?
+        .ok_or_else(|| HayboxError::DeviceNotConnected("No HayBox serial port found".to_string()))?
+    }
+  };
+  if config_proto::with_connection(|conn| Ok(conn.port_name() == port_name)).unwrap_or(false) {
+    return Err(HayboxError::DeviceBusy(format!(
+      "{} is in use by the config connection; disconnect it first",
+      port_name
+    )));
+  }
+
+  close_console();
+  let mut port = serialport::new(&port_name, baud_rate.unwrap_or(DEFAULT_BAUD_RATE))
+    .timeout(READ_TIMEOUT)
+    .open()
+    .map_err(|e| HayboxError::SerialPort(format!("Failed to open {}: {}", port_name, e)))?;
+  // TinyUSB holds output back until the host asserts DTR.
+  port
+    .write_data_terminal_ready(true)
+    .map_err(|e| HayboxError::SerialPort(format!("Failed to set DTR on {}: {}", port_name, e)))?;
+
+  // Held until the console is stored, so a reader that fails straight away
+  // still finds and clears it.
+  let mut console = CONSOLE.lock().unwrap();
+  let stop = Arc::new(AtomicBool::new(false));
+  let events = events.clone();
+  let thread_port = port_name.clone();
+  let thread_stop = stop.clone();
+  let reader = std::thread::spawn(move || read_loop(events, port, thread_port, thread_stop));
+  *console = Some(Console {
+    port: port_name.clone(),
+    stop,
+    reader,
+  });
+  Ok(port_name)
+}
+
+/// Stops the reader and waits for it to drop the port, so the port can be
+/// reopened straight away.
+pub fn close_console() {
+  let console = CONSOLE.lock().unwrap().take();
+  if let Some(console) = console {
+    console.stop.store(true, Ordering::Relaxed);
+    let _ = console.reader.join();
+  }
+}
+
+/// The port the console is reading, if one is open.
+pub fn console_port() -> Option<String> {
+  CONSOLE.lock().unwrap().as_ref().map(|console| console.port.clone())
+}
+
+/// Limits `console_line` events to lines matching `pattern`. Buffered lines
+/// are kept regardless so the filter can be changed afterwards.
+pub fn set_console_filter(pattern: Option<&str>) -> Result<(), HayboxError> {
+  let filter = match pattern.filter(|pattern| !pattern.is_empty()) {
+    Some(pattern) => Some(Regex::new(pattern).map_err(|e| HayboxError::Other(format!("Invalid filter: {}", e)))?),
+    None => None,
+  };
+  *FILTER.lock().unwrap() = filter;
+  Ok(())
+}
+
+/// Buffered lines that pass the current filter.
+pub fn get_console_lines() -> Vec<ConsoleLine> {
+  let filter = FILTER.lock().unwrap();
+  LINES
+    .lock()
+    .unwrap()
+    .iter()
+    .filter(|line| filter.as_ref().is_none_or(|filter| filter.is_match(&line.text)))
+    .cloned()
+    .collect()
+}
+
+pub fn clear_console() {
+  LINES.lock().unwrap().clear();
+}
+
+/// Writes the buffered lines that pass the current filter to `path`, one
+/// per line with a millisecond timestamp.
+pub fn save_console_log(path: &Path) -> Result<usize, HayboxError> {
+  let lines = get_console_lines();
+  let content: String = lines
+    .iter()
+    .map(|line| format!("[{}] {}\n", line.timestamp_ms, line.text))
+    .collect();
+  std::fs::write(path, content).map_err(|e| HayboxError::Io(format!("Failed to write {}: {}", path.display(), e)))?;
+  Ok(lines.len())
+}
@@ -0,0 +1,112 @@
+use serde::{Deserialize, Serialize};
+
+use crate::error::HayboxError;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DeviceSerialPort {
+  /// `COM5` on Windows, `/dev/ttyACM0` on Linux, `/dev/cu.usbmodem…` on macOS.
+  pub port: String,
+  pub vendor_id: u16,
+  pub product_id: u16,
+  pub serial_number: Option<String>,
+  pub product: Option<String>,
+}
+
+/// Serial ports enumerated through the OS serial API: SetupAPI on Windows,
+/// IOKit on macOS.
+#[cfg(not(target_os = "linux"))]
+fn usb_serial_ports() -> Result<Vec<DeviceSerialPort>, HayboxError> {
+  let ports = serialport::available_ports()
+    .map_err(|e| HayboxError::SerialPort(format!("Failed to list serial ports: {}", e)))?;
+  Ok(
+    ports
+      .into_iter()
+      .filter_map(|port| match port.port_type {
+        serialport::SerialPortType::UsbPort(info) => Some(DeviceSerialPort {
+          port: port.port_name,
+          vendor_id: info.vid,
+          product_id: info.pid,
+          serial_number: info.serial_number,
+          product: info.product,
+        }),
+        _ => None,
+      })
+      .collect(),
+  )
+}
+
+/// CDC ACM ports from sysfs. Each `/sys/class/tty/ttyACM*/device` links to
+/// the USB interface, whose parent directory holds the device descriptor.
+#[cfg(target_os = "linux")]
+fn usb_serial_ports() -> Result<Vec<DeviceSerialPort>, HayboxError> {
+  use std::path::Path;
+
+  let read = |path: &Path, name: &str| {
+    std::fs::read_to_string(path.join(name))
+      .ok()
+      .map(|value| value.trim().to_string())
+  };
+  let read_id = |path: &Path, name: &str| read(path, name).and_then(|value| u16::from_str_radix(&value, 16).ok());
+
+  let entries = std::fs::read_dir("/sys/class/tty")
+    .map_err(|e| HayboxError::SerialPort(format!("Failed to read /sys/class/tty: {}", e)))?;
+  let mut ports: Vec<DeviceSerialPort> = entries
+    .flatten()
+    .filter(|entry| entry.file_name().to_string_lossy().starts_with("ttyACM"))
+    .filter_map(|entry| {
+      let interface = std::fs::canonicalize(entry.path().join("device")).ok()?;
+      let device = interface.parent()?;
+      Some(DeviceSerialPort {
+        port: format!("/dev/{}", entry.file_name().to_string_lossy()),
+        vendor_id: read_id(device, "idVendor")?,
+        product_id: read_id(device, "idProduct")?,
+        serial_number: read(device, "serial"),
+        product: read(device, "product"),
+      })
+    })
+    .collect();
+  ports.sort_by(|a, b| a.port.cmp(&b.port));
+  Ok(ports)
+}
+
+/// Serial ports belonging to USB devices with this VID/PID.
+pub fn get_serial_ports_for_device(vendor_id: u16, product_id: u16) -> Result<Vec<DeviceSerialPort>, HayboxError> {
+  Ok(
+    usb_serial_ports()?
+      .into_iter()
+      .filter(|port| port.vendor_id == vendor_id && port.product_id == product_id)
+      .collect(),
+  )
+}
+
+/// USB serial number of the device behind `port`, if the OS reports one.
+pub fn serial_number_for_port(port: &str) -> Option<String> {
+  usb_serial_ports()
+    .ok()?
+    .into_iter()
+    .find(|candidate| candidate.port == port)
+    .and_then(|candidate| candidate.serial_number)
+}
+
+/// The one serial port for this VID/PID. With several controllers plugged
+/// in the caller has to pick, so that is an error listing the candidates.
+pub fn single_port_for_device(vendor_id: u16, product_id: u16) -> Result<Option<String>, HayboxError> {
+  let mut ports = get_serial_ports_for_device(vendor_id, product_id)?;
+  match ports.len() {
+    0 => Ok(None),
+    1 => Ok(Some(ports.remove(0).port)),
+    _ => {
+      let names: Vec<String> = ports
+        .iter()
+        .map(|port| match &port.serial_number {
+          Some(serial) => format!("{} (serial {})", port.port, serial),
+          None => port.port.clone(),
+        })
+        .collect();
+      Err(HayboxError::Other(format!(
+        "Several matching controllers are connected; choose a port: {}",
+        names.join(", ")
+      )))
+    }
+  }
+}
@@ -0,0 +1,247 @@
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config_proto::SocdType;
+use crate::error::HayboxError;
+use crate::input_monitor::{self, InputState};
+
+/// Distance from center, as a share of the axis range, that counts as the
+/// stick being pushed.
+const AXIS_THRESHOLD: f32 = 0.25;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SocdAxis {
+  Horizontal,
+  Vertical,
+}
+
+/// One SOCD pair to verify. Direction 1 is left or down, direction 2 right
+/// or up, matching the order HayBox lists its pairs in.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SocdRule {
+  pub axis: SocdAxis,
+  pub socd_type: SocdType,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Resolved {
+  Neutral,
+  Dir1,
+  Dir2,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SocdStepResult {
+  pub prompt: String,
+  pub expected: Resolved,
+  pub observed: Resolved,
+  pub passed: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SocdRuleResult {
+  pub rule: SocdRule,
+  pub steps: Vec<SocdStepResult>,
+  /// Set once every step of the rule has been checked.
+  pub passed: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SocdTestStatus {
+  /// What the user should hold before the next check; `None` when done.
+  pub prompt: Option<String>,
+  pub step: usize,
+  pub total_steps: usize,
+  pub rules: Vec<SocdRuleResult>,
+}
+
+/// The sequence of holds, as (hold direction 1, hold direction 2, which was
+/// pressed last). Each exercises a different part of the SOCD behavior.
+const STEPS: [(bool, bool, Resolved); 4] = [
+  (true, true, Resolved::Dir2),
+  (true, false, Resolved::Dir2),
+  (true, true, Resolved::Dir1),
+  (false, true, Resolved::Dir1),
+];
+
+struct SocdTest {
+  rules: Vec<SocdRuleResult>,
+  step: usize,
+}
+
+lazy_static::lazy_static! {
+  static ref TEST: Mutex<Option<SocdTest>> = Mutex::new(None);
+}
+
+fn direction_names(axis: SocdAxis) -> (&'static str, &'static str) {
+  match axis {
+    SocdAxis::Horizontal => ("Left", "Right"),
+    SocdAxis::Vertical => ("Down", "Up"),
+  }
+}
+
+fn prompt(rule: &SocdRule, step: usize) -> String {
+  let (dir1, dir2) = direction_names(rule.axis);
+  match step {
+    0 => format!("Hold {}, then also press {}", dir1, dir2),
+    1 => format!("Keep holding {} and release {}", dir1, dir2),
+    2 => format!("Release everything. Hold {}, then also press {}", dir2, dir1),
+    _ => format!("Keep holding {} and release {}", dir2, dir1),
+  }
+}
+
+/// What the rule should output for a step. Steps 1 and 3 release the newer
+/// direction after the older one was overridden, which is where the
+/// reactivation variants differ.
+fn expected(socd_type: SocdType, step: usize) -> Resolved {
+  let (hold1, hold2, last) = STEPS[step];
+  match (hold1, hold2) {
+    (true, true) => match socd_type {
+      SocdType::Neutral => Resolved::Neutral,
+      SocdType::Dir1Priority => Resolved::Dir1,
+      SocdType::Dir2Priority => Resolved::Dir2,
+      _ => last,
+    },
+    (true, false) | (false, true) => {
+      let remaining = if hold1 { Resolved::Dir1 } else { Resolved::Dir2 };
+      // Only no-reactivation keeps the older direction off, and only when
+      // it had lost to the newer one.
+      let was_overridden = expected(socd_type, step - 1) != remaining;
+      if socd_type == SocdType::SecondInputPriorityNoReactivation && was_overridden {
+        Resolved::Neutral
+      } else {
+        remaining
+      }
+    }
+    (false, false) => Resolved::Neutral,
+  }
+}
+
+fn axis_value(state: &InputState, name: &str) -> Option<f32> {
+  state
+    .axes
+    .iter()
+    .find(|axis| axis.name == name)
+    .map(|axis| axis.normalized)
+}
+
+/// Reads the direction the controller outputs on `axis`, from the left
+/// stick or else the hat switch. HID's Y axis grows downward.
+fn observe(state: &InputState, axis: SocdAxis) -> Result<Resolved, HayboxError> {
+  let (name, dir1_is_low) = match axis {
+    SocdAxis::Horizontal => ("X", true),
+    SocdAxis::Vertical => ("Y", false),
+  };
+  if let Some(value) = axis_value(state, name) {
+    let low = value < 0.5 - AXIS_THRESHOLD;
+    let high = value > 0.5 + AXIS_THRESHOLD;
+    return Ok(match (low, high) {
+      (true, _) if dir1_is_low => Resolved::Dir1,
+      (true, _) => Resolved::Dir2,
+      (_, true) if dir1_is_low => Resolved::Dir2,
+      (_, true) => Resolved::Dir1,
+      _ => Resolved::Neutral,
+    });
+  }
+
+  // Hat: 0 up, counting clockwise in eighths.
+  let Some(hat) = state.hat else {
+    return Ok(Resolved::Neutral);
+  };
+  let (dir1, dir2) = match axis {
+    SocdAxis::Horizontal => ([5, 6, 7], [1, 2, 3]),
+    SocdAxis::Vertical => ([3, 4, 5], [7, 0, 1]),
+  };
+  if dir1.contains(&hat) {
+    Ok(Resolved::Dir1)
+  } else if dir2.contains(&hat) {
+    Ok(Resolved::Dir2)
+  } else if hat < 8 {
+    Ok(Resolved::Neutral)
+  } else {
+    Err(HayboxError::Other(format!("Unexpected hat value {}", hat)))
+  }
+}
+
+fn status(test: &SocdTest) -> SocdTestStatus {
+  let total_steps = test.rules.len() * STEPS.len();
+  let prompt = (test.step < total_steps).then(|| {
+    let rule = &test.rules[test.step / STEPS.len()].rule;
+    prompt(rule, test.step % STEPS.len())
+  });
+  SocdTestStatus {
+    prompt,
+    step: test.step,
+    total_steps,
+    rules: test.rules.clone(),
+  }
+}
+
+/// Starts a guided check of the given rules against the controller's HID
+/// output. The input monitor must be running with the controller in a HID
+/// mode.
+pub fn start_socd_test(rules: Vec<SocdRule>) -> Result<SocdTestStatus, HayboxError> {
+  if rules.is_empty() {
+    return Err(HayboxError::Other("No SOCD rules to test".to_string()));
+  }
+  if let Some(rule) = rules.iter().find(|rule| rule.socd_type == SocdType::Unspecified) {
+    return Err(HayboxError::Other(format!("The {:?} rule has no SOCD type", rule.axis)));
+  }
+  if !input_monitor::is_running() {
+    return Err(HayboxError::Other("Start the input monitor first".to_string()));
+  }
+
+  let test = SocdTest {
+    rules: rules
+      .into_iter()
+      .map(|rule| SocdRuleResult {
+        rule,
+        steps: Vec::new(),
+        passed: None,
+      })
+      .collect(),
+    step: 0,
+  };
+  let result = status(&test);
+  *TEST.lock().unwrap() = Some(test);
+  Ok(result)
+}
+
+/// Compares the current output with what the step expects, records the
+/// result and moves on to the next prompt.
+pub fn check_socd_step() -> Result<SocdTestStatus, HayboxError> {
+  let mut guard = TEST.lock().unwrap();
+  let test = guard
+    .as_mut()
+    .ok_or_else(|| HayboxError::Other("No SOCD test is running".to_string()))?;
+  let total_steps = test.rules.len() * STEPS.len();
+  if test.step >= total_steps {
+    return Ok(status(test));
+  }
+
+  let state =
+    input_monitor::latest_state().ok_or_else(|| HayboxError::Other("No input has been received yet".to_string()))?;
+  let step = test.step % STEPS.len();
+  let rule = &mut test.rules[test.step / STEPS.len()];
+  let expected = expected(rule.rule.socd_type, step);
+  let observed = observe(&state, rule.rule.axis)?;
+  rule.steps.push(SocdStepResult {
+    prompt: prompt(&rule.rule, step),
+    expected,
+    observed,
+    passed: expected == observed,
+  });
+  if rule.steps.len() == STEPS.len() {
+    rule.passed = Some(rule.steps.iter().all(|step| step.passed));
+  }
+
+  test.step += 1;
+  Ok(status(test))
+}
+
+pub fn cancel_socd_test() {
+  *TEST.lock().unwrap() = None;
+}
@@ -0,0 +1,91 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::HayboxError;
+use crate::paths::app_data_dir;
+
+/// Well-known SIDs for BUILTIN\Administrators and LocalSystem, so the grant
+/// works regardless of the Windows display language.
+const ADMINISTRATORS_SID: &str = "*S-1-5-32-544";
+const SYSTEM_SID: &str = "*S-1-5-18";
+
+fn staging_root() -> PathBuf {
+  app_data_dir().join("staging")
+}
+
+/// `%TEMP%\haybox_drivers`, used by older versions and never cleaned up.
+fn legacy_staging_dir() -> PathBuf {
+  std::env::temp_dir().join("haybox_drivers")
+}
+
+/// A fresh path for one install. The directory is only created by
+/// [`create_staging_dir`].
+pub fn unique_staging_dir() -> PathBuf {
+  let nanos = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_nanos())
+    .unwrap_or_default();
+  staging_root().join(format!("{}-{}", std::process::id(), nanos))
+}
+
+/// Creates the directory and replaces its inherited ACL with full control for
+/// Administrators and SYSTEM only, so nothing unprivileged can swap the INF or
+/// DLLs between staging and pnputil reading them.
+pub fn create_staging_dir(dir: &Path) -> Result<(), HayboxError> {
+  std::fs::create_dir_all(dir).map_err(|e| HayboxError::Io(format!("Failed to create staging directory: {}", e)))?;
+
+  let grant = |sid: &str| format!("{}:(OI)(CI)F", sid);
+  let output = Command::new("icacls")
+    .arg(dir)
+    .args(["/inheritance:r", "/grant:r"])
+    .arg(grant(ADMINISTRATORS_SID))
+    .arg(grant(SYSTEM_SID))
+    .output()
+    .map_err(|e| HayboxError::Driver(format!("Failed to execute icacls: {}", e)))?;
+
+  if !output.status.success() {
+    let _ = std::fs::remove_dir_all(dir);
+    let error_message = String::from_utf8_lossy(&output.stdout);
+    return Err(HayboxError::Io(format!(
+      "Failed to restrict staging directory permissions: {}",
+      error_message.trim()
+    )));
+  }
+
+  Ok(())
+}
+
+pub fn remove_staging_dir(dir: &Path) {
+  if dir.exists() {
+    if let Err(e) = std::fs::remove_dir_all(dir) {
+      tracing::warn!("failed to remove staging directory {}: {}", dir.display(), e);
+    }
+  }
+}
+
+/// Removes every staging directory left behind by interrupted installs, plus
+/// the legacy temp directory. Returns the number of directories removed.
+pub fn clean_driver_cache() -> Result<usize, HayboxError> {
+  let mut removed = 0;
+
+  if let Ok(entries) = std::fs::read_dir(staging_root()) {
+    for entry in entries.flatten() {
+      let path = entry.path();
+      if path.is_dir() {
+        std::fs::remove_dir_all(&path)
+          .map_err(|e| HayboxError::Io(format!("Failed to remove {}: {}", path.display(), e)))?;
+        removed += 1;
+      }
+    }
+  }
+
+  let legacy = legacy_staging_dir();
+  if legacy.exists() {
+    std::fs::remove_dir_all(&legacy)
+      .map_err(|e| HayboxError::Io(format!("Failed to remove {}: {}", legacy.display(), e)))?;
+    removed += 1;
+  }
+
+  Ok(removed)
+}
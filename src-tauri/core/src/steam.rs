@@ -0,0 +1,131 @@
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+
+use crate::environment::{EnvironmentIssue, EnvironmentWarning};
+use crate::error::HayboxError;
+#[cfg(target_os = "windows")]
+use crate::registry::{read_dword, read_string, HKEY_CURRENT_USER};
+
+#[cfg(target_os = "windows")]
+const STEAM_KEY: &str = "Software\\Valve\\Steam";
+/// Holds the PID of the running Steam client, or 0 once it has exited.
+#[cfg(target_os = "windows")]
+const ACTIVE_PROCESS_KEY: &str = "Software\\Valve\\Steam\\ActiveProcess";
+
+/// Steam Input settings that make Steam grab and remap the controller, with a
+/// readable name for each.
+const STEAM_INPUT_SETTINGS: &[(&str, &str)] = &[
+  ("SteamController_GenericGamepadSupport", "generic gamepads"),
+  ("SteamController_XBoxSupport", "Xbox controllers"),
+  ("SteamController_SwitchSupport", "Switch Pro controllers"),
+  ("SteamController_PSSupport", "PlayStation controllers"),
+];
+
+#[cfg(target_os = "windows")]
+fn steam_dir() -> Option<PathBuf> {
+  read_string(HKEY_CURRENT_USER, STEAM_KEY, "SteamPath")
+    .map(PathBuf::from)
+    .filter(|path| path.exists())
+}
+
+#[cfg(target_os = "windows")]
+fn is_steam_running() -> bool {
+  read_dword(HKEY_CURRENT_USER, ACTIVE_PROCESS_KEY, "pid")
+    .map(|pid| pid != 0)
+    .unwrap_or(false)
+}
+
+/// Linux is covered by `steamos::detect_steam_claim`, which sees the
+/// controller held open rather than reading Steam's settings.
+#[cfg(not(target_os = "windows"))]
+fn steam_dir() -> Option<PathBuf> {
+  None
+}
+
+#[cfg(not(target_os = "windows"))]
+fn is_steam_running() -> bool {
+  false
+}
+
+/// The global config plus every local user's config, which is where newer
+/// Steam clients keep the controller settings.
+fn config_files(steam_dir: &Path) -> Vec<PathBuf> {
+  let mut files = vec![steam_dir.join("config").join("config.vdf")];
+
+  if let Ok(entries) = std::fs::read_dir(steam_dir.join("userdata")) {
+    files.extend(
+      entries
+        .flatten()
+        .map(|entry| entry.path().join("config").join("localconfig.vdf")),
+    );
+  }
+
+  files.into_iter().filter(|path| path.exists()).collect()
+}
+
+/// Names of the Steam Input settings that are switched on in `content`.
+fn enabled_settings(content: &str) -> Vec<&'static str> {
+  let re = Regex::new(r#""(SteamController_\w+Support)"\s+"(\d+)""#).unwrap();
+
+  let mut enabled: Vec<&'static str> = re
+    .captures_iter(content)
+    .filter(|caps| &caps[2] != "0")
+    .filter_map(|caps| {
+      STEAM_INPUT_SETTINGS
+        .iter()
+        .find(|(key, _)| *key == &caps[1])
+        .map(|(_, name)| *name)
+    })
+    .collect();
+  enabled.sort();
+  enabled.dedup();
+  enabled
+}
+
+/// Warns when Steam is running with Steam Input enabled for a controller type
+/// HayBox can present as, since Steam then remaps its inputs in other games.
+pub fn detect_steam_input() -> Result<Vec<EnvironmentWarning>, HayboxError> {
+  let steam_dir = match steam_dir() {
+    Some(dir) => dir,
+    None => return Ok(vec![]),
+  };
+
+  if !is_steam_running() {
+    return Ok(vec![]);
+  }
+
+  let mut enabled: Vec<&'static str> = Vec::new();
+  let mut sources: Vec<String> = Vec::new();
+  for file in config_files(&steam_dir) {
+    let content = match std::fs::read_to_string(&file) {
+      Ok(content) => content,
+      Err(e) => {
+        tracing::warn!("failed to read {}: {}", file.display(), e);
+        continue;
+      }
+    };
+
+    let found = enabled_settings(&content);
+    if !found.is_empty() {
+      sources.push(file.display().to_string());
+      enabled.extend(found);
+    }
+  }
+
+  if enabled.is_empty() {
+    return Ok(vec![]);
+  }
+  enabled.sort();
+  enabled.dedup();
+
+  Ok(vec![EnvironmentWarning {
+    issue: EnvironmentIssue::SteamInput,
+    message: "Steam is running with Steam Input enabled and may remap the controller in other games".to_string(),
+    detail: Some(format!(
+      "Enabled for {} (from {})",
+      enabled.join(", "),
+      sources.join(", ")
+    )),
+  }])
+}
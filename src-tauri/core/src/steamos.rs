@@ -0,0 +1,130 @@
+use std::process::Command;
+
+use serde::{Deserialize, Serialize};
+
+use crate::environment::{EnvironmentIssue, EnvironmentWarning};
+use crate::error::HayboxError;
+use crate::udev;
+
+const OS_RELEASE: &str = "/etc/os-release";
+const DMI_DIR: &str = "/sys/class/dmi/id";
+/// Board names of the LCD and OLED Steam Decks.
+const STEAM_DECK_BOARDS: &[&str] = &["Jupiter", "Galileo"];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SteamOsInfo {
+  pub is_steamos: bool,
+  pub is_steam_deck: bool,
+  pub version: Option<String>,
+  /// Whether `steamos-readonly` reports the root filesystem locked. None off
+  /// SteamOS or when the tool is missing.
+  pub readonly: Option<bool>,
+  /// Controller nodes the Steam client holds open, which means Steam Input
+  /// is translating the controller.
+  pub steam_held_nodes: Vec<String>,
+}
+
+pub fn os_release_value(key: &str) -> Option<String> {
+  let content = std::fs::read_to_string(OS_RELEASE).ok()?;
+  content.lines().find_map(|line| {
+    let value = line.strip_prefix(key)?.strip_prefix('=')?;
+    Some(value.trim().trim_matches('"').to_string())
+  })
+}
+
+fn dmi(name: &str) -> Option<String> {
+  std::fs::read_to_string(format!("{}/{}", DMI_DIR, name))
+    .ok()
+    .map(|value| value.trim().to_string())
+}
+
+pub fn is_steamos() -> bool {
+  os_release_value("ID").as_deref() == Some("steamos")
+}
+
+fn is_steam_deck() -> bool {
+  dmi("board_vendor").as_deref() == Some("Valve")
+    && dmi("board_name").is_some_and(|board| STEAM_DECK_BOARDS.contains(&board.as_str()))
+}
+
+pub fn readonly_enabled() -> Option<bool> {
+  if !is_steamos() {
+    return None;
+  }
+  let output = Command::new("steamos-readonly").arg("status").output().ok()?;
+  match String::from_utf8_lossy(&output.stdout).trim() {
+    "enabled" => Some(true),
+    "disabled" => Some(false),
+    _ => None,
+  }
+}
+
+/// Nodes of connected HayBox devices that a Steam process has open, found
+/// through `/proc/<pid>/fd`. Steam runs as the same user, so no root is
+/// needed.
+pub fn steam_held_nodes() -> Vec<String> {
+  let nodes: Vec<String> = udev::device_nodes().into_iter().map(|node| node.node).collect();
+  if nodes.is_empty() {
+    return Vec::new();
+  }
+  let Ok(processes) = std::fs::read_dir("/proc") else {
+    return Vec::new();
+  };
+
+  let mut held = Vec::new();
+  for process in processes.flatten() {
+    let path = process.path();
+    let is_steam = std::fs::read_to_string(path.join("comm")).is_ok_and(|comm| comm.trim().starts_with("steam"));
+    if !is_steam {
+      continue;
+    }
+    let Ok(fds) = std::fs::read_dir(path.join("fd")) else {
+      continue;
+    };
+    for fd in fds.flatten() {
+      let Ok(target) = std::fs::read_link(fd.path()) else {
+        continue;
+      };
+      let target = target.display().to_string();
+      if nodes.contains(&target) && !held.contains(&target) {
+        held.push(target);
+      }
+    }
+  }
+  held
+}
+
+pub fn steamos_info() -> SteamOsInfo {
+  if !is_steamos() {
+    return SteamOsInfo {
+      steam_held_nodes: steam_held_nodes(),
+      ..Default::default()
+    };
+  }
+  SteamOsInfo {
+    is_steamos: true,
+    is_steam_deck: is_steam_deck(),
+    version: os_release_value("VERSION_ID"),
+    readonly: readonly_enabled(),
+    steam_held_nodes: steam_held_nodes(),
+  }
+}
+
+/// Warns when Steam holds the controller open on Linux, where Steam Input
+/// then hands games a virtual pad instead. Gaming Mode on a Steam Deck
+/// always does this.
+pub fn detect_steam_claim() -> Result<Vec<EnvironmentWarning>, HayboxError> {
+  if !cfg!(target_os = "linux") {
+    return Ok(vec![]);
+  }
+  let held = steam_held_nodes();
+  if held.is_empty() {
+    return Ok(vec![]);
+  }
+
+  Ok(vec![EnvironmentWarning {
+    issue: EnvironmentIssue::SteamInput,
+    message: "Steam has the controller open and Steam Input may be translating it for games".to_string(),
+    detail: Some(format!("Held by Steam: {}", held.join(", "))),
+  }])
+}
@@ -0,0 +1,144 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::input_monitor::InputState;
+
+/// HayBox's default debounce. A release and press closer together than
+/// this is the switch bouncing, not the player.
+const DEFAULT_DEBOUNCE_WINDOW_US: u64 = 5_000;
+/// Release-to-press gaps shorter than this are kept so the report can be
+/// run against any debounce window up to it.
+const MAX_TRACKED_GAP_US: u64 = 20_000;
+/// Share of presses that chatter before a switch is reported.
+const SUSPECT_RATE: f64 = 0.01;
+const FAILING_RATE: f64 = 0.05;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SwitchCondition {
+  Ok,
+  Suspect,
+  Failing,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SwitchHealth {
+  pub button: u16,
+  pub presses: u64,
+  /// Presses that followed the previous release within the debounce window.
+  pub chatter: u64,
+  pub chatter_rate: f64,
+  pub shortest_gap_us: Option<u64>,
+  /// Mean time between consecutive presses.
+  pub avg_press_interval_us: Option<u64>,
+  pub condition: SwitchCondition,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SwitchHealthReport {
+  pub session_us: u64,
+  pub debounce_window_us: u64,
+  /// Worst first.
+  pub buttons: Vec<SwitchHealth>,
+}
+
+#[derive(Default)]
+struct ButtonStats {
+  presses: u64,
+  held: bool,
+  first_press_us: Option<u64>,
+  last_press_us: u64,
+  last_release_us: Option<u64>,
+  short_gaps_us: Vec<u64>,
+}
+
+#[derive(Default)]
+struct Session {
+  first_us: Option<u64>,
+  last_us: u64,
+  buttons: BTreeMap<u16, ButtonStats>,
+}
+
+lazy_static::lazy_static! {
+  static ref SESSION: Mutex<Session> = Mutex::new(Session::default());
+}
+
+/// Called by the input monitor for every report.
+pub fn observe(state: &InputState) {
+  let mut session = SESSION.lock().unwrap();
+  let now = state.timestamp_us;
+  session.first_us.get_or_insert(now);
+  session.last_us = now;
+
+  for (button, stats) in session.buttons.iter_mut() {
+    if stats.held && !state.pressed.contains(button) {
+      stats.held = false;
+      stats.last_release_us = Some(now);
+    }
+  }
+  for &button in &state.pressed {
+    let stats = session.buttons.entry(button).or_default();
+    if stats.held {
+      continue;
+    }
+    stats.held = true;
+    stats.presses += 1;
+    stats.first_press_us.get_or_insert(now);
+    stats.last_press_us = now;
+    if let Some(gap) = stats.last_release_us.map(|release| now - release) {
+      if gap < MAX_TRACKED_GAP_US {
+        stats.short_gaps_us.push(gap);
+      }
+    }
+  }
+}
+
+/// Starts a new session. The input monitor calls this when it starts.
+pub fn reset_switch_health() {
+  *SESSION.lock().unwrap() = Session::default();
+}
+
+/// Press counts and chatter per button since the input monitor started.
+pub fn get_switch_health_report(debounce_window_us: Option<u64>) -> SwitchHealthReport {
+  let window = debounce_window_us
+    .unwrap_or(DEFAULT_DEBOUNCE_WINDOW_US)
+    .min(MAX_TRACKED_GAP_US);
+  let session = SESSION.lock().unwrap();
+
+  let mut buttons: Vec<SwitchHealth> = session
+    .buttons
+    .iter()
+    .map(|(&button, stats)| {
+      let chatter = stats.short_gaps_us.iter().filter(|&&gap| gap < window).count() as u64;
+      let chatter_rate = chatter as f64 / stats.presses.max(1) as f64;
+      let condition = if chatter_rate >= FAILING_RATE {
+        SwitchCondition::Failing
+      } else if chatter_rate >= SUSPECT_RATE {
+        SwitchCondition::Suspect
+      } else {
+        SwitchCondition::Ok
+      };
+      SwitchHealth {
+        button,
+        presses: stats.presses,
+        chatter,
+        chatter_rate,
+        shortest_gap_us: stats.short_gaps_us.iter().min().copied(),
+        avg_press_interval_us: stats
+          .first_press_us
+          .filter(|_| stats.presses > 1)
+          .map(|first| (stats.last_press_us - first) / (stats.presses - 1)),
+        condition,
+      }
+    })
+    .collect();
+  buttons.sort_by(|a, b| b.chatter_rate.total_cmp(&a.chatter_rate));
+
+  SwitchHealthReport {
+    session_us: session.last_us - session.first_us.unwrap_or(session.last_us),
+    debounce_window_us: window,
+    buttons,
+  }
+}
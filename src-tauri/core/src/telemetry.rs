@@ -0,0 +1,182 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::audit::AuditEntry;
+use crate::error::HayboxError;
+use crate::firmware::USER_AGENT;
+use crate::paths::app_data_dir;
+
+const TELEMETRY_FILE: &str = "telemetry.json";
+/// How often pending counts are sent.
+const UPLOAD_INTERVAL: Duration = Duration::from_secs(60 * 60);
+/// Wait after startup before the first upload, so it does not compete with
+/// device enumeration.
+const FIRST_UPLOAD_DELAY: Duration = Duration::from_secs(60);
+
+lazy_static::lazy_static! {
+  static ref STATE: Mutex<TelemetryState> = Mutex::new(load_state());
+  /// Windows error codes as pnputil and SetupAPI print them, e.g. 0xE0000247.
+  static ref ERROR_CODE_RE: Regex = Regex::new(r"0x[0-9A-Fa-f]{8}").unwrap();
+}
+
+/// What is stored on disk. Only the counters are ever sent.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+struct TelemetryState {
+  enabled: bool,
+  endpoint: Option<String>,
+  /// Outcome counts since the last successful upload, keyed like
+  /// `winusb_install_driver_failed:0xe0000247`.
+  pending: BTreeMap<String, u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TelemetryStatus {
+  pub enabled: bool,
+  pub endpoint: Option<String>,
+  /// Exactly what the next upload will contain, so users can check it.
+  pub pending: BTreeMap<String, u64>,
+}
+
+#[derive(Serialize)]
+struct TelemetryBatch<'a> {
+  app_version: &'a str,
+  os: &'a str,
+  arch: &'a str,
+  counts: &'a BTreeMap<String, u64>,
+}
+
+fn state_path() -> PathBuf {
+  app_data_dir().join(TELEMETRY_FILE)
+}
+
+fn load_state() -> TelemetryState {
+  std::fs::read_to_string(state_path())
+    .ok()
+    .and_then(|content| serde_json::from_str(&content).ok())
+    .unwrap_or_default()
+}
+
+fn save_state(state: &TelemetryState) -> Result<(), HayboxError> {
+  let content = serde_json::to_string_pretty(state)
+    .map_err(|e| HayboxError::Other(format!("Failed to serialize telemetry settings: {}", e)))?;
+  std::fs::write(state_path(), content)
+    .map_err(|e| HayboxError::Io(format!("Failed to write telemetry settings: {}", e)))
+}
+
+/// Reduces an operation's outcome to a key without device serials, paths or
+/// other free text: the driver kind, the operation and, for failures, a
+/// Windows error code or the error category.
+fn outcome_key(entry: &AuditEntry) -> String {
+  let operation = match entry.inf.as_deref().and_then(|inf| inf.strip_suffix("_driver.inf")) {
+    Some(kind) => format!("{}_{}", kind, entry.operation),
+    None => entry.operation.clone(),
+  };
+  if entry.success {
+    return format!("{}_succeeded", operation);
+  }
+
+  let code = match ERROR_CODE_RE.find(&entry.message) {
+    Some(code) => code.as_str().to_lowercase(),
+    None => entry.error_code.clone().unwrap_or_else(|| "other".to_string()),
+  };
+  format!("{}_failed:{}", operation, code)
+}
+
+/// Counts the outcome of an audited operation. Does nothing unless the user
+/// opted in.
+pub fn count_outcome(entry: &AuditEntry) {
+  let mut state = STATE.lock().unwrap();
+  if !state.enabled {
+    return;
+  }
+  *state.pending.entry(outcome_key(entry)).or_default() += 1;
+  if let Err(e) = save_state(&state) {
+    tracing::warn!("{}", e);
+  }
+}
+
+pub fn status() -> TelemetryStatus {
+  let state = STATE.lock().unwrap();
+  TelemetryStatus {
+    enabled: state.enabled,
+    endpoint: state.endpoint.clone(),
+    pending: state.pending.clone(),
+  }
+}
+
+/// Turning telemetry off also discards counts that were not sent yet.
+pub fn set_settings(enabled: bool, endpoint: Option<String>) -> Result<TelemetryStatus, HayboxError> {
+  let endpoint = endpoint.map(|url| url.trim().to_string()).filter(|url| !url.is_empty());
+  if let Some(url) = &endpoint {
+    if !url.starts_with("https://") {
+      return Err(HayboxError::Other(
+        "The telemetry endpoint must be an https:// URL".to_string(),
+      ));
+    }
+  }
+
+  {
+    let mut state = STATE.lock().unwrap();
+    state.enabled = enabled;
+    state.endpoint = endpoint;
+    if !enabled {
+      state.pending.clear();
+    }
+    save_state(&state)?;
+  }
+  Ok(status())
+}
+
+/// Sends pending counts to the configured endpoint and clears them. Counts
+/// are kept for the next attempt when the upload fails.
+pub fn flush() -> Result<(), HayboxError> {
+  let (endpoint, counts) = {
+    let state = STATE.lock().unwrap();
+    match (&state.endpoint, state.enabled) {
+      (Some(endpoint), true) if !state.pending.is_empty() => (endpoint.clone(), state.pending.clone()),
+      _ => return Ok(()),
+    }
+  };
+
+  let batch = TelemetryBatch {
+    app_version: env!("CARGO_PKG_VERSION"),
+    os: std::env::consts::OS,
+    arch: std::env::consts::ARCH,
+    counts: &counts,
+  };
+  ureq::post(&endpoint)
+    .set("User-Agent", USER_AGENT)
+    .send_json(&batch)
+    .map_err(|e| HayboxError::Network(format!("Failed to send telemetry to {}: {}", endpoint, e)))?;
+
+  // Subtract what was sent; operations may have been counted meanwhile.
+  let mut state = STATE.lock().unwrap();
+  for (key, sent) in counts {
+    if let Some(count) = state.pending.get_mut(&key) {
+      *count = count.saturating_sub(sent);
+      if *count == 0 {
+        state.pending.remove(&key);
+      }
+    }
+  }
+  save_state(&state)
+}
+
+/// Uploads pending counts in the background once after startup and then
+/// every hour.
+pub fn start_uploader() {
+  std::thread::spawn(|| {
+    std::thread::sleep(FIRST_UPLOAD_DELAY);
+    loop {
+      if let Err(e) = flush() {
+        tracing::debug!("{}", e);
+      }
+      std::thread::sleep(UPLOAD_INTERVAL);
+    }
+  });
+}
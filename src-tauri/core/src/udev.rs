@@ -0,0 +1,306 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::HayboxError;
+use crate::paths::app_data_dir;
+use crate::{serial_ports, steamos, UsbDeviceInfo, DEVICES};
+
+const RULES_PATH: &str = "/etc/udev/rules.d/60-haybox-debugger.rules";
+/// pkexec's exit codes when the user dismisses or fails the prompt.
+const PKEXEC_NOT_AUTHORIZED: i32 = 126;
+const PKEXEC_DISMISSED: i32 = 127;
+
+/// A device node the app opens, and whether the current user can.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DeviceNode {
+  pub vendor_id: u16,
+  pub product_id: u16,
+  pub name: String,
+  pub node: String,
+  pub accessible: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UdevStatus {
+  pub rules_path: String,
+  pub rules_installed: bool,
+  /// The installed rules match what this version of the app would write.
+  pub rules_current: bool,
+  /// Nodes of connected HayBox devices.
+  pub nodes: Vec<DeviceNode>,
+  pub problems: Vec<String>,
+}
+
+/// Kernel driver bound to one interface of a USB device, from sysfs.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct InterfaceBinding {
+  /// sysfs name of the interface, e.g. `1-2:1.0`.
+  pub interface: String,
+  pub vendor_id: u16,
+  pub product_id: u16,
+  pub product: Option<String>,
+  pub driver: Option<String>,
+}
+
+fn supported_devices() -> [&'static UsbDeviceInfo; 5] {
+  [
+    &DEVICES.default_mode,
+    &DEVICES.config_mode,
+    &DEVICES.bootsel_mode,
+    &DEVICES.switch_mode,
+    &DEVICES.gamecube_mode,
+  ]
+}
+
+/// Rules granting the logged-in user the raw USB device, for libusb and
+/// picoboot, and its hidraw and serial nodes.
+pub fn rules() -> String {
+  let mut rules =
+    String::from("# Written by HayBox Debugger. Gives the logged-in user access to HayBox controllers.\n");
+  for device in supported_devices() {
+    rules.push_str(&format!(
+      "\n# {}\nSUBSYSTEM==\"usb\", ATTR{{idVendor}}==\"{:04x}\", ATTR{{idProduct}}==\"{:04x}\", MODE=\"0660\", \
+       TAG+=\"uaccess\"\nKERNEL==\"hidraw*|ttyACM*\", ATTRS{{idVendor}}==\"{:04x}\", ATTRS{{idProduct}}==\"{:04x}\", \
+       MODE=\"0660\", TAG+=\"uaccess\"\n",
+      device.name, device.vid, device.pid, device.vid, device.pid
+    ));
+  }
+  rules
+}
+
+fn read(path: &Path, name: &str) -> Option<String> {
+  std::fs::read_to_string(path.join(name))
+    .ok()
+    .map(|value| value.trim().to_string())
+}
+
+fn read_id(path: &Path, name: &str) -> Option<u16> {
+  read(path, name).and_then(|value| u16::from_str_radix(&value, 16).ok())
+}
+
+fn can_open(node: &str) -> bool {
+  std::fs::OpenOptions::new().read(true).write(true).open(node).is_ok()
+}
+
+/// hidraw nodes with their VID and PID, which sysfs puts in the HID
+/// device's name: `0003:2E8A:000A.0001`.
+fn hidraw_nodes() -> Vec<(String, u16, u16)> {
+  let Ok(entries) = std::fs::read_dir("/sys/class/hidraw") else {
+    return Vec::new();
+  };
+  entries
+    .flatten()
+    .filter_map(|entry| {
+      let device = std::fs::canonicalize(entry.path().join("device")).ok()?;
+      let name = device.file_name()?.to_string_lossy().into_owned();
+      let mut parts = name.split(['.', ':']);
+      let _bus = parts.next()?;
+      let vid = u16::from_str_radix(parts.next()?, 16).ok()?;
+      let pid = u16::from_str_radix(parts.next()?, 16).ok()?;
+      Some((format!("/dev/{}", entry.file_name().to_string_lossy()), vid, pid))
+    })
+    .collect()
+}
+
+/// A USB device as sysfs lists it, with its usbfs node.
+pub struct SysfsDevice {
+  pub vendor_id: u16,
+  pub product_id: u16,
+  pub node: String,
+  /// The device's directory under `/sys/bus/usb/devices`, a link into the
+  /// host controller it hangs off.
+  pub path: PathBuf,
+}
+
+/// Every connected USB device, read from sysfs rather than libusb so it
+/// still works when libusb cannot start.
+pub fn sysfs_devices() -> Vec<SysfsDevice> {
+  let Ok(entries) = std::fs::read_dir("/sys/bus/usb/devices") else {
+    return Vec::new();
+  };
+  entries
+    .flatten()
+    .filter_map(|entry| {
+      let path = entry.path();
+      let bus: u32 = read(&path, "busnum")?.parse().ok()?;
+      let address: u32 = read(&path, "devnum")?.parse().ok()?;
+      Some(SysfsDevice {
+        vendor_id: read_id(&path, "idVendor")?,
+        product_id: read_id(&path, "idProduct")?,
+        node: format!("/dev/bus/usb/{:03}/{:03}", bus, address),
+        path,
+      })
+    })
+    .collect()
+}
+
+/// The nodes of every connected HayBox device: the usbfs node libusb
+/// opens, plus any hidraw and serial nodes.
+pub fn device_nodes() -> Vec<DeviceNode> {
+  let mut nodes = Vec::new();
+  let devices = supported_devices();
+  let find = |vid: u16, pid: u16| devices.iter().find(|device| device.vid == vid && device.pid == pid);
+  let mut push = |device: &UsbDeviceInfo, node: String| {
+    nodes.push(DeviceNode {
+      vendor_id: device.vid,
+      product_id: device.pid,
+      name: device.name.clone(),
+      accessible: can_open(&node),
+      node,
+    });
+  };
+
+  for usb_device in sysfs_devices() {
+    if let Some(device) = find(usb_device.vendor_id, usb_device.product_id) {
+      push(device, usb_device.node);
+    }
+  }
+  for (node, vid, pid) in hidraw_nodes() {
+    if let Some(device) = find(vid, pid) {
+      push(device, node);
+    }
+  }
+  for device in devices {
+    for port in serial_ports::get_serial_ports_for_device(device.vid, device.pid).unwrap_or_default() {
+      push(device, port.port);
+    }
+  }
+  nodes
+}
+
+fn node_problems(nodes: &[DeviceNode]) -> Vec<String> {
+  nodes
+    .iter()
+    .filter(|node| !node.accessible)
+    .map(|node| format!("No permission to open {} ({})", node.node, node.name))
+    .collect()
+}
+
+/// Messages for connected devices the current user cannot open. Empty
+/// off Linux, where access is not managed by device node permissions.
+pub fn access_problems() -> Vec<String> {
+  if !cfg!(target_os = "linux") {
+    return Vec::new();
+  }
+  node_problems(&device_nodes())
+}
+
+/// Whether the current user can open the USB device itself, which is what
+/// libusb needs. The Linux counterpart of a WinUSB binding.
+pub fn usb_accessible(vendor_id: u16, product_id: u16) -> bool {
+  device_nodes()
+    .iter()
+    .any(|node| node.vendor_id == vendor_id && node.product_id == product_id && node.node.starts_with("/dev/bus/usb/"))
+}
+
+pub fn udev_status() -> UdevStatus {
+  let installed = std::fs::read_to_string(RULES_PATH).ok();
+  let nodes = device_nodes();
+  let mut problems = node_problems(&nodes);
+  if !problems.is_empty() {
+    problems.push(match installed {
+      None => "The udev rules are not installed".to_string(),
+      Some(_) => "The udev rules are installed; unplug and replug the controller to apply them".to_string(),
+    });
+  }
+
+  UdevStatus {
+    rules_path: RULES_PATH.to_string(),
+    rules_installed: installed.is_some(),
+    rules_current: installed.as_deref() == Some(rules().as_str()),
+    nodes,
+    problems,
+  }
+}
+
+/// Writes the udev rules through pkexec and reloads udev, so config mode,
+/// BOOTSEL and the input tools work without root. On SteamOS with the
+/// read-only lock on, the lock is lifted for the install and restored
+/// afterwards, whether or not the install succeeded.
+pub fn install_udev_rules() -> Result<UdevStatus, HayboxError> {
+  if !cfg!(target_os = "linux") {
+    return Err(HayboxError::Unsupported("udev rules only apply on Linux".to_string()));
+  }
+
+  let staged: PathBuf = app_data_dir().join("60-haybox-debugger.rules");
+  std::fs::write(&staged, rules())
+    .map_err(|e| HayboxError::Io(format!("Failed to write {}: {}", staged.display(), e)))?;
+
+  let mut script = "install -m 0644 \"$1\" \"$2\" && udevadm control --reload-rules && udevadm trigger \
+                    --action=change --subsystem-match=usb --subsystem-match=hidraw --subsystem-match=tty"
+    .to_string();
+  if steamos::readonly_enabled() == Some(true) {
+    script = format!(
+      "steamos-readonly disable || exit 1; {}; status=$?; steamos-readonly enable; exit $status",
+      script
+    );
+  }
+
+  // Paths are passed as arguments rather than spliced into the script.
+  let output = Command::new("pkexec")
+    .args(["sh", "-c", &script, "sh"])
+    .arg(&staged)
+    .arg(RULES_PATH)
+    .output()
+    .map_err(|e| HayboxError::Io(format!("Failed to run pkexec: {}", e)))?;
+  let _ = std::fs::remove_file(&staged);
+
+  match output.status.code() {
+    Some(0) => Ok(udev_status()),
+    Some(PKEXEC_NOT_AUTHORIZED) | Some(PKEXEC_DISMISSED) if steamos::is_steamos() => {
+      Err(HayboxError::ElevationRequired(
+        "Authorization was denied or dismissed; the rules were not installed. SteamOS only accepts a password once \
+       one is set: run passwd in Konsole first"
+          .to_string(),
+      ))
+    }
+    Some(PKEXEC_NOT_AUTHORIZED) | Some(PKEXEC_DISMISSED) => Err(HayboxError::ElevationRequired(
+      "Authorization was denied or dismissed; the rules were not installed".to_string(),
+    )),
+    _ => Err(HayboxError::Other(format!(
+      "Failed to install the udev rules: {}",
+      String::from_utf8_lossy(&output.stderr).trim()
+    ))),
+  }
+}
+
+/// Interfaces of connected USB devices and the kernel drivers bound to
+/// them, optionally filtered by VID and PID.
+pub fn driver_bindings(vendor_id: Option<u16>, product_id: Option<u16>) -> Vec<InterfaceBinding> {
+  let Ok(entries) = std::fs::read_dir("/sys/bus/usb/devices") else {
+    return Vec::new();
+  };
+  let mut bindings = Vec::new();
+  for entry in entries.flatten() {
+    let path = entry.path();
+    let (Some(vid), Some(pid)) = (read_id(&path, "idVendor"), read_id(&path, "idProduct")) else {
+      continue;
+    };
+    if vendor_id.is_some_and(|wanted| wanted != vid) || product_id.is_some_and(|wanted| wanted != pid) {
+      continue;
+    }
+    let device_name = entry.file_name().to_string_lossy().into_owned();
+    let Ok(interfaces) = std::fs::read_dir(&path) else {
+      continue;
+    };
+    for interface in interfaces.flatten() {
+      let name = interface.file_name().to_string_lossy().into_owned();
+      if !name.starts_with(&format!("{}:", device_name)) {
+        continue;
+      }
+      bindings.push(InterfaceBinding {
+        interface: name,
+        vendor_id: vid,
+        product_id: pid,
+        product: read(&path, "product"),
+        driver: std::fs::read_link(interface.path().join("driver"))
+          .ok()
+          .and_then(|driver| driver.file_name().map(|name| name.to_string_lossy().into_owned())),
+      });
+    }
+  }
+  bindings.sort_by(|a, b| a.interface.cmp(&b.interface));
+  bindings
+}
@@ -0,0 +1,264 @@
+use serde::{Deserialize, Serialize};
+
+use crate::binary_info::{self, BinaryInfo};
+use crate::error::HayboxError;
+
+/// Every UF2 file is a sequence of independent 512-byte blocks.
+pub const BLOCK_SIZE: usize = 512;
+
+const MAGIC_START0: u32 = 0x0A32_4655;
+const MAGIC_START1: u32 = 0x9E5D_5157;
+const MAGIC_END: u32 = 0x0AB1_6F30;
+/// The word at offset 28 holds the family ID rather than the file size.
+const FLAG_FAMILY_ID_PRESENT: u32 = 0x0000_2000;
+/// Largest payload that fits between the header and the end magic.
+const MAX_PAYLOAD: u32 = 476;
+
+pub const RP2040_FAMILY_ID: u32 = 0xE48B_FF56;
+/// RP2350 images carry one of these; SDK builds add an "absolute" block to
+/// work around boot ROM erratum E10.
+pub const RP2350_FAMILY_IDS: &[u32] = &[0xE48B_FF57, 0xE48B_FF58, 0xE48B_FF59, 0xE48B_FF5A, 0xE48B_FF5B];
+/// Where the payload starts inside a block.
+const PAYLOAD_OFFSET: usize = 32;
+/// One flash page per block, as the boot ROM expects for flash writes.
+const ENCODE_PAYLOAD: usize = 256;
+
+/// Family IDs from the UF2 spec's `uf2families.json` that people are most
+/// likely to download by mistake.
+const KNOWN_FAMILIES: &[(u32, &str)] = &[
+  (RP2040_FAMILY_ID, "RP2040"),
+  (0xE48B_FF57, "RP2350 (absolute)"),
+  (0xE48B_FF58, "RP2350 (data)"),
+  (0xE48B_FF59, "RP2350 (Arm Secure)"),
+  (0xE48B_FF5A, "RP2350 (RISC-V)"),
+  (0xE48B_FF5B, "RP2350 (Arm Non-Secure)"),
+  (0x1C5F_21B0, "ESP32"),
+  (0xBFDD_4EEE, "ESP32-S2"),
+  (0xC47E_5767, "ESP32-S3"),
+  (0xD42B_A06C, "ESP32-C3"),
+  (0x68ED_2B88, "SAMD21"),
+  (0x5511_4460, "SAMD51"),
+  (0xADA5_2840, "nRF52840"),
+  (0x5775_5A57, "STM32F4"),
+];
+
+#[derive(Debug, Clone, Copy)]
+pub struct Uf2Block {
+  pub block_no: u32,
+  pub num_blocks: u32,
+  pub family_id: Option<u32>,
+  pub target_addr: u32,
+  pub payload_size: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AddressRange {
+  pub start: u32,
+  /// Exclusive.
+  pub end: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Uf2Inspection {
+  pub family_id: Option<u32>,
+  pub family_name: Option<String>,
+  pub block_count: u32,
+  pub payload_bytes: u64,
+  /// Contiguous target address ranges, merged and sorted.
+  pub ranges: Vec<AddressRange>,
+  /// Program name and version embedded by the Pico SDK, if any.
+  pub binary_info: BinaryInfo,
+  /// Whether the file would pass the check `flash_uf2` runs.
+  pub compatible: bool,
+  pub problem: Option<String>,
+}
+
+fn read_u32(block: &[u8], offset: usize) -> u32 {
+  u32::from_le_bytes([block[offset], block[offset + 1], block[offset + 2], block[offset + 3]])
+}
+
+fn parse_block(block: &[u8], index: usize) -> Result<Uf2Block, HayboxError> {
+  if read_u32(block, 0) != MAGIC_START0 || read_u32(block, 4) != MAGIC_START1 || read_u32(block, 508) != MAGIC_END {
+    return Err(HayboxError::Other(format!("Block {} is not a valid UF2 block", index)));
+  }
+
+  let flags = read_u32(block, 8);
+  let payload_size = read_u32(block, 16);
+  if payload_size > MAX_PAYLOAD {
+    return Err(HayboxError::Other(format!(
+      "Block {} has an invalid payload size of {}",
+      index, payload_size
+    )));
+  }
+
+  Ok(Uf2Block {
+    block_no: read_u32(block, 20),
+    num_blocks: read_u32(block, 24),
+    family_id: (flags & FLAG_FAMILY_ID_PRESENT != 0).then(|| read_u32(block, 28)),
+    target_addr: read_u32(block, 12),
+    payload_size,
+  })
+}
+
+pub fn parse_blocks(data: &[u8]) -> Result<Vec<Uf2Block>, HayboxError> {
+  if data.is_empty() || !data.len().is_multiple_of(BLOCK_SIZE) {
+    return Err(HayboxError::Other(format!(
+      "File size {} is not a multiple of the {}-byte UF2 block size",
+      data.len(),
+      BLOCK_SIZE
+    )));
+  }
+
+  data
+    .chunks_exact(BLOCK_SIZE)
+    .enumerate()
+    .map(|(index, block)| parse_block(block, index))
+    .collect()
+}
+
+/// Checks that the file is a complete UF2 image for `family_id`. Blocks
+/// without a family ID are accepted, as older tools did not write one.
+pub fn validate(data: &[u8], family_id: u32) -> Result<Vec<Uf2Block>, HayboxError> {
+  validate_for(data, &[family_id])
+}
+
+/// Like `validate`, for chips whose boot ROM accepts several family IDs.
+pub fn validate_for(data: &[u8], family_ids: &[u32]) -> Result<Vec<Uf2Block>, HayboxError> {
+  let blocks = parse_blocks(data)?;
+
+  if let Some(other) = blocks
+    .iter()
+    .filter_map(|block| block.family_id)
+    .find(|id| !family_ids.contains(id))
+  {
+    return Err(HayboxError::Other(format!(
+      "UF2 family ID 0x{:08X} ({}) is not one of {}; this firmware is for a different chip",
+      other,
+      family_name(other).unwrap_or("unknown"),
+      family_ids
+        .iter()
+        .map(|id| format!("0x{:08X}", id))
+        .collect::<Vec<_>>()
+        .join(", ")
+    )));
+  }
+
+  // A file may concatenate several images, each numbered from zero; RP2350
+  // SDK builds do this.
+  let mut start = 0;
+  while start < blocks.len() {
+    let num_blocks = blocks[start].num_blocks;
+    let complete = num_blocks > 0
+      && blocks
+        .get(start..start.saturating_add(num_blocks as usize))
+        .is_some_and(|image| {
+          image
+            .iter()
+            .enumerate()
+            .all(|(index, block)| block.num_blocks == num_blocks && block.block_no == index as u32)
+        });
+    if !complete {
+      return Err(HayboxError::Other(format!(
+        "UF2 file is incomplete or out of order ({} blocks, header at block {} says {})",
+        blocks.len(),
+        start,
+        num_blocks
+      )));
+    }
+    start += num_blocks as usize;
+  }
+
+  Ok(blocks)
+}
+
+/// Wraps a raw image starting at `base_addr` into UF2 blocks of 256-byte
+/// payloads, the layout the boot ROM and picotool write.
+pub fn encode(image: &[u8], base_addr: u32, family_id: u32) -> Vec<u8> {
+  let num_blocks = image.len().div_ceil(ENCODE_PAYLOAD) as u32;
+  let mut out = Vec::with_capacity(num_blocks as usize * BLOCK_SIZE);
+
+  for (index, payload) in image.chunks(ENCODE_PAYLOAD).enumerate() {
+    let mut block = [0u8; BLOCK_SIZE];
+    let header = [
+      MAGIC_START0,
+      MAGIC_START1,
+      FLAG_FAMILY_ID_PRESENT,
+      base_addr + (index * ENCODE_PAYLOAD) as u32,
+      payload.len() as u32,
+      index as u32,
+      num_blocks,
+      family_id,
+    ];
+    for (word, value) in header.iter().enumerate() {
+      block[word * 4..word * 4 + 4].copy_from_slice(&value.to_le_bytes());
+    }
+    block[PAYLOAD_OFFSET..PAYLOAD_OFFSET + payload.len()].copy_from_slice(payload);
+    block[508..].copy_from_slice(&MAGIC_END.to_le_bytes());
+    out.extend_from_slice(&block);
+  }
+  out
+}
+
+pub fn family_name(family_id: u32) -> Option<&'static str> {
+  KNOWN_FAMILIES
+    .iter()
+    .find(|(id, _)| *id == family_id)
+    .map(|(_, name)| *name)
+}
+
+fn merge_ranges(blocks: &[Uf2Block]) -> Vec<AddressRange> {
+  let mut ranges: Vec<AddressRange> = blocks
+    .iter()
+    .map(|block| AddressRange {
+      start: block.target_addr,
+      end: block.target_addr.saturating_add(block.payload_size),
+    })
+    .collect();
+  ranges.sort_by_key(|range| range.start);
+
+  let mut merged: Vec<AddressRange> = Vec::new();
+  for range in ranges {
+    match merged.last_mut() {
+      Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+      _ => merged.push(range),
+    }
+  }
+  merged
+}
+
+/// Reads `len` bytes at `addr` from the blocks' payloads, if they are all
+/// covered.
+fn read_image(data: &[u8], blocks: &[Uf2Block], addr: u32, len: usize) -> Option<Vec<u8>> {
+  let mut out = Vec::with_capacity(len);
+  while out.len() < len {
+    let wanted = addr.checked_add(out.len() as u32)?;
+    let (index, block) = blocks
+      .iter()
+      .enumerate()
+      .find(|(_, block)| wanted >= block.target_addr && wanted - block.target_addr < block.payload_size)?;
+    let start = index * BLOCK_SIZE + PAYLOAD_OFFSET + (wanted - block.target_addr) as usize;
+    let available = (block.payload_size - (wanted - block.target_addr)) as usize;
+    let take = available.min(len - out.len());
+    out.extend_from_slice(data.get(start..start + take)?);
+  }
+  Some(out)
+}
+
+/// Describes a UF2 file without flashing it: which chip it targets, where it
+/// writes and, for Pico SDK builds, the program name and version it embeds.
+pub fn inspect(data: &[u8]) -> Result<Uf2Inspection, HayboxError> {
+  let blocks = parse_blocks(data)?;
+  let family_id = blocks.iter().find_map(|block| block.family_id);
+  let problem = validate(data, RP2040_FAMILY_ID).err().map(|e| e.to_string());
+
+  Ok(Uf2Inspection {
+    family_id,
+    family_name: family_id.and_then(family_name).map(str::to_string),
+    block_count: blocks.len() as u32,
+    payload_bytes: blocks.iter().map(|block| block.payload_size as u64).sum(),
+    ranges: merge_ranges(&blocks),
+    binary_info: binary_info::parse(|addr, len| read_image(data, &blocks, addr, len)),
+    compatible: problem.is_none(),
+    problem,
+  })
+}
@@ -0,0 +1,247 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::HayboxError;
+use crate::input_monitor::InputState;
+use crate::paths::app_data_dir;
+
+const STATS_FILE: &str = "usage_stats.json";
+/// How often a running session is written out, so a crash loses little.
+const SAVE_INTERVAL_US: u64 = 60_000_000;
+/// Upper bounds of the hold duration buckets; the last bucket is open.
+const HOLD_BUCKETS_MS: [u64; 4] = [50, 150, 500, 2000];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ButtonTotals {
+  pub presses: u64,
+  pub total_hold_us: u64,
+  pub longest_hold_us: u64,
+  /// Presses per hold duration bucket, shortest first.
+  pub hold_histogram: Vec<u64>,
+}
+
+/// Everything recorded for one controller, as stored.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct DeviceUsage {
+  pub product: Option<String>,
+  pub monitored_us: u64,
+  /// Seconds since the epoch.
+  pub first_seen: u64,
+  pub last_seen: u64,
+  pub buttons: BTreeMap<u16, ButtonTotals>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ButtonUsage {
+  pub button: u16,
+  pub presses: u64,
+  /// Share of all presses on the device.
+  pub press_share: f64,
+  pub presses_per_hour: f64,
+  pub avg_hold_us: Option<u64>,
+  pub longest_hold_us: u64,
+  pub hold_histogram: Vec<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UsageStats {
+  /// The controller's USB serial number, or VID:PID when it has none.
+  pub device: String,
+  pub product: Option<String>,
+  pub monitored_us: u64,
+  pub first_seen: u64,
+  pub last_seen: u64,
+  pub total_presses: u64,
+  /// Hold bucket upper bounds in milliseconds, for `hold_histogram`.
+  pub hold_buckets_ms: Vec<u64>,
+  /// Most pressed first.
+  pub buttons: Vec<ButtonUsage>,
+}
+
+struct Session {
+  device: String,
+  usage: DeviceUsage,
+  /// Press start per held button.
+  held: HashMap<u16, u64>,
+  last_us: Option<u64>,
+  last_saved_us: u64,
+}
+
+lazy_static::lazy_static! {
+  static ref SESSION: Mutex<Option<Session>> = Mutex::new(None);
+}
+
+fn stats_path() -> PathBuf {
+  app_data_dir().join(STATS_FILE)
+}
+
+fn now_secs() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_secs())
+    .unwrap_or_default()
+}
+
+fn load_all() -> BTreeMap<String, DeviceUsage> {
+  std::fs::read_to_string(stats_path())
+    .ok()
+    .and_then(|content| serde_json::from_str(&content).ok())
+    .unwrap_or_default()
+}
+
+fn save(session: &Session) -> Result<(), HayboxError> {
+  let mut all = load_all();
+  all.insert(session.device.clone(), session.usage.clone());
+  let path = stats_path();
+  let content = serde_json::to_string_pretty(&all)
+    .map_err(|e| HayboxError::Other(format!("Failed to serialize usage stats: {}", e)))?;
+  std::fs::write(&path, content).map_err(|e| HayboxError::Io(format!("Failed to write {}: {}", path.display(), e)))
+}
+
+fn record_hold(usage: &mut DeviceUsage, button: u16, hold_us: u64) {
+  let totals = usage.buttons.entry(button).or_default();
+  totals.total_hold_us += hold_us;
+  totals.longest_hold_us = totals.longest_hold_us.max(hold_us);
+  totals.hold_histogram.resize(HOLD_BUCKETS_MS.len() + 1, 0);
+  let bucket = HOLD_BUCKETS_MS
+    .iter()
+    .position(|&bound| hold_us < bound * 1000)
+    .unwrap_or(HOLD_BUCKETS_MS.len());
+  totals.hold_histogram[bucket] += 1;
+}
+
+/// Starts accumulating into the stored totals for `device`. The input
+/// monitor calls this when it starts.
+pub fn begin(device: String, product: Option<String>) {
+  finish();
+  let mut usage = load_all().remove(&device).unwrap_or_else(|| DeviceUsage {
+    first_seen: now_secs(),
+    ..Default::default()
+  });
+  usage.product = product.or(usage.product);
+  usage.last_seen = now_secs();
+  *SESSION.lock().unwrap() = Some(Session {
+    device,
+    usage,
+    held: HashMap::new(),
+    last_us: None,
+    last_saved_us: 0,
+  });
+}
+
+/// Called by the input monitor for every report.
+pub fn observe(state: &InputState) {
+  let mut guard = SESSION.lock().unwrap();
+  let Some(session) = guard.as_mut() else {
+    return;
+  };
+  let now = state.timestamp_us;
+  if let Some(last) = session.last_us {
+    session.usage.monitored_us += now.saturating_sub(last);
+  }
+  session.last_us = Some(now);
+
+  let released: Vec<(u16, u64)> = session
+    .held
+    .iter()
+    .filter(|(button, _)| !state.pressed.contains(button))
+    .map(|(&button, &start)| (button, start))
+    .collect();
+  for (button, start) in released {
+    session.held.remove(&button);
+    record_hold(&mut session.usage, button, now.saturating_sub(start));
+  }
+  for &button in &state.pressed {
+    if session.held.contains_key(&button) {
+      continue;
+    }
+    session.held.insert(button, now);
+    session.usage.buttons.entry(button).or_default().presses += 1;
+  }
+
+  if now.saturating_sub(session.last_saved_us) >= SAVE_INTERVAL_US {
+    session.last_saved_us = now;
+    session.usage.last_seen = now_secs();
+    if let Err(e) = save(session) {
+      tracing::warn!("{}", e);
+    }
+  }
+}
+
+/// Counts buttons still held up to the last report and writes the session
+/// out. Called when the input monitor stops.
+pub fn finish() {
+  let Some(mut session) = SESSION.lock().unwrap().take() else {
+    return;
+  };
+  let last = session.last_us.unwrap_or_default();
+  for (button, start) in std::mem::take(&mut session.held) {
+    record_hold(&mut session.usage, button, last.saturating_sub(start));
+  }
+  session.usage.last_seen = now_secs();
+  if let Err(e) = save(&session) {
+    tracing::warn!("{}", e);
+  }
+}
+
+fn summarize(device: String, usage: DeviceUsage) -> UsageStats {
+  let total_presses: u64 = usage.buttons.values().map(|totals| totals.presses).sum();
+  let hours = usage.monitored_us as f64 / 3_600_000_000.0;
+  let mut buttons: Vec<ButtonUsage> = usage
+    .buttons
+    .into_iter()
+    .map(|(button, totals)| {
+      let holds: u64 = totals.hold_histogram.iter().sum();
+      ButtonUsage {
+        button,
+        presses: totals.presses,
+        press_share: totals.presses as f64 / total_presses.max(1) as f64,
+        presses_per_hour: if hours > 0.0 {
+          totals.presses as f64 / hours
+        } else {
+          0.0
+        },
+        avg_hold_us: (holds > 0).then(|| totals.total_hold_us / holds),
+        longest_hold_us: totals.longest_hold_us,
+        hold_histogram: totals.hold_histogram,
+      }
+    })
+    .collect();
+  buttons.sort_by_key(|usage| std::cmp::Reverse(usage.presses));
+
+  UsageStats {
+    device,
+    product: usage.product,
+    monitored_us: usage.monitored_us,
+    first_seen: usage.first_seen,
+    last_seen: usage.last_seen,
+    total_presses,
+    hold_buckets_ms: HOLD_BUCKETS_MS.to_vec(),
+    buttons,
+  }
+}
+
+/// Press counts and hold durations accumulated across every input monitor
+/// session, for one device or all of them, including the running session.
+pub fn get_usage_stats(device: Option<&str>) -> Result<Vec<UsageStats>, HayboxError> {
+  let mut all = load_all();
+  if let Some(session) = SESSION.lock().unwrap().as_ref() {
+    all.insert(session.device.clone(), session.usage.clone());
+  }
+  if let Some(device) = device {
+    all.retain(|key, _| key == device);
+    if all.is_empty() {
+      return Err(HayboxError::Other(format!("No usage has been recorded for {}", device)));
+    }
+  }
+  Ok(
+    all
+      .into_iter()
+      .map(|(device, usage)| summarize(device, usage))
+      .collect(),
+  )
+}
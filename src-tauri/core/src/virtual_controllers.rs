@@ -0,0 +1,108 @@
+#[cfg(target_os = "windows")]
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::HayboxError;
+#[cfg(target_os = "windows")]
+use crate::integrity::file_version;
+#[cfg(target_os = "windows")]
+use crate::wmi_worker;
+
+/// ViGEmBus releases before this one are known to crash or drop virtual pads
+/// under Dolphin and Parsec.
+#[cfg(target_os = "windows")]
+const MIN_VIGEMBUS_VERSION: [u32; 4] = [1, 17, 333, 0];
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct VirtualDriverInfo {
+  pub installed: bool,
+  pub running: bool,
+  pub version: Option<String>,
+  /// Whether the version is older than the minimum known to work.
+  pub outdated: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct VirtualControllerStack {
+  pub vigembus: VirtualDriverInfo,
+  pub vjoy: VirtualDriverInfo,
+}
+
+#[cfg(target_os = "windows")]
+#[derive(Debug, Deserialize)]
+struct WmiSystemDriver {
+  #[serde(rename = "Name")]
+  name: String,
+  #[serde(rename = "State")]
+  state: Option<String>,
+  #[serde(rename = "PathName")]
+  path_name: Option<String>,
+}
+
+/// Turns a service image path such as `\SystemRoot\System32\drivers\x.sys`
+/// or `\??\C:\...` into a regular file system path.
+#[cfg(target_os = "windows")]
+fn image_path(path_name: &str) -> PathBuf {
+  let path = path_name.trim_start_matches("\\??\\");
+  match path.get(..11) {
+    Some(prefix) if prefix.eq_ignore_ascii_case("\\SystemRoot") => {
+      let system_root = std::env::var("SystemRoot").unwrap_or_else(|_| "C:\\Windows".to_string());
+      PathBuf::from(format!("{}{}", system_root, &path[11..]))
+    }
+    _ => PathBuf::from(path),
+  }
+}
+
+#[cfg(target_os = "windows")]
+fn parse_version(version: &str) -> Vec<u32> {
+  version.split('.').filter_map(|part| part.parse().ok()).collect()
+}
+
+#[cfg(target_os = "windows")]
+fn driver_info(service: Option<&WmiSystemDriver>, min_version: Option<&[u32]>) -> VirtualDriverInfo {
+  let service = match service {
+    Some(service) => service,
+    None => return VirtualDriverInfo::default(),
+  };
+
+  let version = service
+    .path_name
+    .as_deref()
+    .and_then(|path| file_version(&image_path(path)));
+  let outdated = match (&version, min_version) {
+    (Some(version), Some(min_version)) => parse_version(version).as_slice() < min_version,
+    _ => false,
+  };
+
+  VirtualDriverInfo {
+    installed: true,
+    running: service
+      .state
+      .as_deref()
+      .map(|state| state.eq_ignore_ascii_case("Running"))
+      .unwrap_or(false),
+    version,
+    outdated,
+  }
+}
+
+/// ViGEmBus and vJoy are Windows drivers, so neither is ever installed here.
+#[cfg(not(target_os = "windows"))]
+pub fn detect() -> Result<VirtualControllerStack, HayboxError> {
+  Ok(VirtualControllerStack::default())
+}
+
+/// Looks up the ViGEmBus and vJoy kernel drivers and their versions.
+#[cfg(target_os = "windows")]
+pub fn detect() -> Result<VirtualControllerStack, HayboxError> {
+  let services: Vec<WmiSystemDriver> =
+    wmi_worker::query("SELECT Name, State, PathName FROM Win32_SystemDriver WHERE Name = 'ViGEmBus' OR Name = 'vjoy'")?;
+
+  let find = |name: &str| services.iter().find(|service| service.name.eq_ignore_ascii_case(name));
+
+  Ok(VirtualControllerStack {
+    vigembus: driver_info(find("ViGEmBus"), Some(&MIN_VIGEMBUS_VERSION)),
+    vjoy: driver_info(find("vjoy"), None),
+  })
+}
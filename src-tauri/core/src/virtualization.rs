@@ -0,0 +1,167 @@
+use crate::environment::{EnvironmentIssue, EnvironmentWarning};
+use crate::error::HayboxError;
+use crate::{driver, platform, udev, UsbDeviceInfo, DEVICES};
+
+/// Hypervisor vendor strings from CPUID leaf 0x40000000.
+const HYPERVISORS: &[(&str, &str)] = &[
+  ("Microsoft Hv", "Hyper-V"),
+  ("VMwareVMware", "VMware"),
+  ("KVMKVMKVM", "KVM"),
+  ("VBoxVBoxVBox", "VirtualBox"),
+  ("TCGTCGTCGTCG", "QEMU"),
+  ("XenVMMXenVMM", "Xen"),
+  ("prl hyperv", "Parallels"),
+  ("ACRNACRNACRN", "ACRN"),
+];
+
+/// Driver services and providers that forward a USB device somewhere else
+/// instead of letting Windows use it.
+const PASSTHROUGH_DRIVERS: &[(&str, &str)] = &[
+  ("usbipd", "usbipd-win"),
+  ("virtualhere", "VirtualHere"),
+  ("vhusb", "VirtualHere"),
+  ("vboxusb", "VirtualBox"),
+  ("vmusb", "VMware"),
+  ("vmware", "VMware"),
+];
+
+fn supported_devices() -> [&'static UsbDeviceInfo; 5] {
+  [
+    &DEVICES.default_mode,
+    &DEVICES.config_mode,
+    &DEVICES.bootsel_mode,
+    &DEVICES.switch_mode,
+    &DEVICES.gamecube_mode,
+  ]
+}
+
+/// The hypervisor the app runs under, if any. Windows with virtualization
+/// based security runs on Hyper-V even on bare metal; that root partition
+/// can create partitions, which a guest cannot.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+fn hypervisor() -> Option<&'static str> {
+  #[cfg(target_arch = "x86")]
+  use std::arch::x86::__cpuid;
+  #[cfg(target_arch = "x86_64")]
+  use std::arch::x86_64::__cpuid;
+
+  const HYPERVISOR_PRESENT: u32 = 1 << 31;
+  const CREATE_PARTITIONS: u32 = 1;
+
+  if __cpuid(1).ecx & HYPERVISOR_PRESENT == 0 {
+    return None;
+  }
+  let leaf = __cpuid(0x4000_0000);
+  let vendor: Vec<u8> = [leaf.ebx, leaf.ecx, leaf.edx]
+    .iter()
+    .flat_map(|register| register.to_le_bytes())
+    .collect();
+  let vendor = String::from_utf8_lossy(&vendor);
+  let (_, name) = HYPERVISORS
+    .iter()
+    .find(|(signature, _)| vendor.starts_with(signature))
+    .copied()
+    .unwrap_or(("", "an unknown hypervisor"));
+
+  if name == "Hyper-V" && __cpuid(0x4000_0003).ebx & CREATE_PARTITIONS != 0 {
+    return None;
+  }
+  Some(name)
+}
+
+/// ARM hosts have no CPUID to ask.
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+fn hypervisor() -> Option<&'static str> {
+  None
+}
+
+fn is_wsl() -> bool {
+  std::fs::read_to_string("/proc/sys/kernel/osrelease").is_ok_and(|release| {
+    let release = release.to_lowercase();
+    release.contains("microsoft") || release.contains("wsl")
+  })
+}
+
+/// Warns when the app runs in WSL or a virtual machine, where the
+/// controller arrives through USB passthrough and drops off every time it
+/// switches mode.
+pub fn detect_virtual_machine() -> Result<Vec<EnvironmentWarning>, HayboxError> {
+  if cfg!(target_os = "linux") && is_wsl() {
+    return Ok(vec![EnvironmentWarning {
+      issue: EnvironmentIssue::VirtualMachine,
+      message: "Running under WSL: the controller has to be attached with usbipd, and again every time it switches \
+                to config or BOOTSEL mode"
+        .to_string(),
+      detail: None,
+    }]);
+  }
+
+  Ok(
+    hypervisor()
+      .map(|name| EnvironmentWarning {
+        issue: EnvironmentIssue::VirtualMachine,
+        message: format!(
+          "Running in a {} virtual machine: USB passthrough usually has to be set up again each time the controller \
+           switches mode",
+          name
+        ),
+        detail: None,
+      })
+      .into_iter()
+      .collect(),
+  )
+}
+
+/// Names the passthrough tool a controller is forwarded through: the
+/// driver bound to it on Windows, or the USB/IP virtual host controller it
+/// hangs off on Linux.
+fn passthrough_for(device: &UsbDeviceInfo) -> Result<Option<&'static str>, HayboxError> {
+  if cfg!(target_os = "linux") {
+    let over_usbip = udev::sysfs_devices().iter().any(|usb_device| {
+      (usb_device.vendor_id, usb_device.product_id) == (device.vid, device.pid)
+        && std::fs::canonicalize(&usb_device.path).is_ok_and(|path| path.to_string_lossy().contains("vhci_hcd"))
+    });
+    return Ok(over_usbip.then_some("USB/IP"));
+  }
+
+  let Some(binding) = driver::query_binding(device.vid, device.pid, None)? else {
+    return Ok(None);
+  };
+  let names = format!(
+    "{} {}",
+    binding.service.unwrap_or_default(),
+    binding.provider.unwrap_or_default()
+  )
+  .to_lowercase();
+  Ok(
+    PASSTHROUGH_DRIVERS
+      .iter()
+      .find(|(pattern, _)| names.contains(pattern))
+      .map(|(_, tool)| *tool),
+  )
+}
+
+/// Warns for each connected controller that is forwarded over usbipd,
+/// VirtualHere or a VM's USB capture, where config mode is unreliable.
+pub fn detect_usb_passthrough() -> Result<Vec<EnvironmentWarning>, HayboxError> {
+  let connected = platform::current().connected_devices();
+
+  let mut warnings = Vec::new();
+  for device in supported_devices() {
+    if !connected.contains(&(device.vid, device.pid)) {
+      continue;
+    }
+    if let Some(tool) = passthrough_for(device)? {
+      warnings.push(EnvironmentWarning {
+        issue: EnvironmentIssue::UsbPassthrough,
+        message: format!(
+          "{} is forwarded through {}; config mode and flashing re-enumerate the controller, which drops the \
+           connection",
+          device.name, tool
+        ),
+        detail: Some(format!("{:04X}:{:04X}", device.vid, device.pid)),
+      });
+    }
+  }
+  Ok(warnings)
+}
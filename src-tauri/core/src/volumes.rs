@@ -0,0 +1,75 @@
+use std::path::PathBuf;
+
+/// Mount points that may hold a boot ROM volume. Callers still check each
+/// one for INFO_UF2.TXT.
+#[cfg(target_os = "windows")]
+pub fn candidate_roots() -> Vec<PathBuf> {
+  (b'A'..=b'Z')
+    .map(|letter| PathBuf::from(format!("{}:\\", letter as char)))
+    .collect()
+}
+
+/// Everything under /Volumes, where macOS mounts removable drives.
+#[cfg(target_os = "macos")]
+pub fn candidate_roots() -> Vec<PathBuf> {
+  std::fs::read_dir("/Volumes")
+    .map(|entries| entries.flatten().map(|entry| entry.path()).collect())
+    .unwrap_or_default()
+}
+
+/// Mount points from /proc/mounts. Desktops usually automount the drive;
+/// when nothing has, boot volumes found by label are mounted through
+/// udisks, which works without root for removable media.
+#[cfg(target_os = "linux")]
+pub fn candidate_roots() -> Vec<PathBuf> {
+  let mounts = std::fs::read_to_string("/proc/mounts").unwrap_or_default();
+  let mut roots: Vec<(String, PathBuf)> = mounts
+    .lines()
+    .filter_map(|line| {
+      let mut fields = line.split_whitespace();
+      let device = fields.next()?.to_string();
+      // Spaces and tabs in mount points are octal-escaped.
+      let mount_point = fields.next()?.replace("\\040", " ").replace("\\011", "\t");
+      Some((device, PathBuf::from(mount_point)))
+    })
+    .collect();
+
+  for label in crate::flash_target::ALL_TARGETS
+    .iter()
+    .filter_map(|target| target.board_id())
+  {
+    let Ok(device) = std::fs::canonicalize(format!("/dev/disk/by-label/{}", label)) else {
+      continue;
+    };
+    let device = device.display().to_string();
+    if roots.iter().any(|(mounted, _)| *mounted == device) {
+      continue;
+    }
+    if let Some(mount_point) = udisks_mount(&device) {
+      roots.push((device, mount_point));
+    }
+  }
+
+  roots.into_iter().map(|(_, mount_point)| mount_point).collect()
+}
+
+/// Runs `udisksctl mount` and parses "Mounted /dev/sdb1 at /media/user/RPI-RP2".
+#[cfg(target_os = "linux")]
+fn udisks_mount(device: &str) -> Option<PathBuf> {
+  let output = std::process::Command::new("udisksctl")
+    .args(["mount", "--no-user-interaction", "-b", device])
+    .output()
+    .ok()?;
+  if !output.status.success() {
+    tracing::warn!(
+      "udisksctl could not mount {}: {}",
+      device,
+      String::from_utf8_lossy(&output.stderr).trim()
+    );
+    return None;
+  }
+
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  let mount_point = stdout.split(" at ").nth(1)?.trim().trim_end_matches('.');
+  Some(PathBuf::from(mount_point))
+}
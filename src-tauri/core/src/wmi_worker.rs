@@ -0,0 +1,104 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Mutex;
+
+use serde::de::DeserializeOwned;
+use windows::Win32::System::Com::{CoInitializeEx, CoUninitialize, COINIT_APARTMENTTHREADED, COINIT_DISABLE_OLE1DDE};
+use wmi::{COMLibrary, WMIConnection};
+
+use crate::error::HayboxError;
+
+type Job = Box<dyn FnOnce(Result<&WMIConnection, &str>) + Send>;
+
+lazy_static::lazy_static! {
+  static ref WORKER: Mutex<Option<Sender<Job>>> = Mutex::new(None);
+}
+
+/// Runs jobs until every sender is gone. The connection is opened on the
+/// first job and retried on later jobs if that fails.
+fn serve(jobs: Receiver<Job>) {
+  let mut connection: Option<WMIConnection> = None;
+  for job in jobs {
+    if connection.is_none() {
+      // Sound because `run_worker` initialized COM on this thread and keeps
+      // it until this function returns.
+      let com = unsafe { COMLibrary::assume_initialized() };
+      match WMIConnection::new(com) {
+        Ok(new_connection) => connection = Some(new_connection),
+        Err(e) => {
+          job(Err(&format!("Failed to initialize WMI: {}", e)));
+          continue;
+        }
+      }
+    }
+    job(Ok(connection.as_ref().unwrap()));
+  }
+}
+
+/// Owns COM for its whole life, so the connection is only ever used on the
+/// thread that initialized it. If COM cannot be initialized, every job is
+/// answered with that error instead of opening a connection.
+fn run_worker(jobs: Receiver<Job>) {
+  let initialized = unsafe { CoInitializeEx(None, COINIT_APARTMENTTHREADED | COINIT_DISABLE_OLE1DDE) };
+  if let Err(e) = initialized.ok() {
+    tracing::error!("failed to initialize COM for WMI: {}", e);
+    let message = format!("Failed to initialize COM for WMI: {}", e);
+    for job in jobs {
+      job(Err(&message));
+    }
+    return;
+  }
+
+  serve(jobs);
+
+  unsafe { CoUninitialize() };
+}
+
+fn sender() -> Sender<Job> {
+  let mut worker = WORKER.lock().unwrap();
+  if let Some(sender) = worker.as_ref() {
+    return sender.clone();
+  }
+
+  let (sender, receiver) = mpsc::channel();
+  std::thread::Builder::new()
+    .name("wmi".to_string())
+    .spawn(move || run_worker(receiver))
+    .expect("failed to start the WMI worker thread");
+  *worker = Some(sender.clone());
+  sender
+}
+
+/// Runs `f` with the shared WMI connection on the WMI thread and waits for
+/// its result. Safe to call from any thread, including Tauri's command pool.
+pub fn with_connection<R, F>(f: F) -> Result<R, HayboxError>
+where
+  R: Send + 'static,
+  F: FnOnce(&WMIConnection) -> Result<R, HayboxError> + Send + 'static,
+{
+  let (result_sender, result_receiver) = mpsc::channel();
+  let job: Job = Box::new(move |connection| {
+    let _ = result_sender.send(connection.map_err(|e| HayboxError::Wmi(e.to_string())).and_then(f));
+  });
+
+  if let Err(mpsc::SendError(job)) = sender().send(job) {
+    // The worker is gone, most likely because a job panicked. Start a new
+    // one and try once more.
+    WORKER.lock().unwrap().take();
+    sender()
+      .send(job)
+      .map_err(|_| HayboxError::Wmi("The WMI worker thread is not running".to_string()))?;
+  }
+  result_receiver
+    .recv()
+    .map_err(|_| HayboxError::Wmi("The WMI worker thread stopped before answering".to_string()))?
+}
+
+/// Runs a WQL query on the WMI thread.
+pub fn query<T: DeserializeOwned + Send + 'static>(query: &str) -> Result<Vec<T>, HayboxError> {
+  let query = query.to_string();
+  with_connection(move |connection| {
+    connection
+      .raw_query(&query)
+      .map_err(|e| HayboxError::Wmi(format!("Failed to query WMI: {}", e)))
+  })
+}
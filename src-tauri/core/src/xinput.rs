@@ -0,0 +1,455 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+#[cfg(target_os = "windows")]
+use windows::core::{s, HSTRING, PCSTR};
+#[cfg(target_os = "windows")]
+use windows::Win32::Foundation::{FreeLibrary, HMODULE};
+#[cfg(target_os = "windows")]
+use windows::Win32::System::LibraryLoader::{GetProcAddress, LoadLibraryW};
+#[cfg(target_os = "windows")]
+use windows::Win32::UI::Input::XboxController::{XINPUT_STATE, XINPUT_VIBRATION};
+
+use crate::check_admin_rights;
+use crate::error::HayboxError;
+use crate::events::Events;
+use crate::file_access::with_write_access;
+use crate::integrity::{file_version, is_microsoft_signed, sha256_file};
+use crate::paths::app_data_dir;
+use crate::resources::EMBEDDED_RESOURCES;
+
+const XINPUT_DLL: &str = "xinput1_4.dll";
+const MANIFEST_FILE: &str = "xinput_manifest.json";
+/// Optional `<sha256>  <version>` list of known Windows builds compiled in
+/// with the driver resources.
+const KNOWN_GOOD_LIST: &str = "XINPUT_SHA256SUMS";
+/// System file protection and Windows Update replace the DLL within minutes,
+/// so a slow poll is enough to catch it before the next session.
+const RESTORE_POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// Number of controller slots XInput has.
+#[cfg(target_os = "windows")]
+pub const XUSER_MAX_COUNT: u32 = 4;
+#[cfg(target_os = "windows")]
+const ERROR_SUCCESS: u32 = 0;
+
+#[cfg(target_os = "windows")]
+type XInputGetState = unsafe extern "system" fn(u32, *mut XINPUT_STATE) -> u32;
+#[cfg(target_os = "windows")]
+type XInputSetState = unsafe extern "system" fn(u32, *mut XINPUT_VIBRATION) -> u32;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct XInputBackupStatus {
+  pub exists: bool,
+  pub path: String,
+  pub sha256: Option<String>,
+  pub file_version: Option<String>,
+  /// Last modification time of the backup file, in seconds since the epoch.
+  pub modified_at: Option<u64>,
+  /// When the app moved the DLL aside, from the manifest.
+  pub recorded_at: Option<u64>,
+  pub matches_original: Option<bool>,
+}
+
+/// The DLL as it was before the app first removed it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct XInputManifest {
+  pub sha256: String,
+  pub file_version: Option<String>,
+  pub recorded_at: u64,
+  /// Set while the app has the DLL moved aside. If the DLL shows up again in
+  /// this state, Windows put it back.
+  #[serde(default)]
+  pub removed: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct XInputStatus {
+  pub present: bool,
+  pub sha256: Option<String>,
+  pub file_version: Option<String>,
+  /// Signed by Microsoft or listed as a known build.
+  pub recognized: bool,
+  /// Whether the installed DLL is the one recorded before removal, if any was.
+  pub hash_matches_original: Option<bool>,
+  pub backed_up: bool,
+  pub backup_sha256: Option<String>,
+  pub original: Option<XInputManifest>,
+}
+
+pub fn system32_dir() -> PathBuf {
+  std::env::var("SystemRoot")
+    .map(|root| PathBuf::from(root).join("System32"))
+    .unwrap_or_else(|_| PathBuf::from("C:\\Windows\\System32"))
+}
+
+pub fn xinput_path() -> PathBuf {
+  system32_dir().join(XINPUT_DLL)
+}
+
+/// xinput1_4.dll loaded at runtime, so the app still starts when the
+/// removal flow has moved it aside.
+#[cfg(target_os = "windows")]
+pub struct XInputLibrary {
+  module: HMODULE,
+  get_state: XInputGetState,
+  set_state: XInputSetState,
+}
+
+#[cfg(target_os = "windows")]
+impl XInputLibrary {
+  pub fn load() -> Result<Self, HayboxError> {
+    let path = xinput_path();
+    let module = unsafe { LoadLibraryW(&HSTRING::from(path.as_os_str())) }
+      .map_err(|e| HayboxError::Io(format!("Failed to load {}: {}", path.display(), e)))?;
+    let export = |proc: PCSTR, name: &str| {
+      unsafe { GetProcAddress(module, proc) }
+        .ok_or_else(|| HayboxError::Other(format!("{} has no {} export", path.display(), name)))
+    };
+    let (get_state, set_state) = match (
+      export(s!("XInputGetState"), "XInputGetState"),
+      export(s!("XInputSetState"), "XInputSetState"),
+    ) {
+      (Ok(get_state), Ok(set_state)) => (get_state, set_state),
+      (Err(e), _) | (_, Err(e)) => {
+        let _ = unsafe { FreeLibrary(module) };
+        return Err(e);
+      }
+    };
+    Ok(Self {
+      module,
+      get_state: unsafe { std::mem::transmute::<unsafe extern "system" fn() -> isize, XInputGetState>(get_state) },
+      set_state: unsafe { std::mem::transmute::<unsafe extern "system" fn() -> isize, XInputSetState>(set_state) },
+    })
+  }
+
+  pub fn state(&self, slot: u32) -> Option<XINPUT_STATE> {
+    let mut state = XINPUT_STATE::default();
+    (unsafe { (self.get_state)(slot, &mut state) } == ERROR_SUCCESS).then_some(state)
+  }
+
+  /// Sets the speed of a slot's low and high frequency motors. `false` when
+  /// no controller is connected there.
+  pub fn set_vibration(&self, slot: u32, left: u16, right: u16) -> bool {
+    let mut vibration = XINPUT_VIBRATION {
+      wLeftMotorSpeed: left,
+      wRightMotorSpeed: right,
+    };
+    unsafe { (self.set_state)(slot, &mut vibration) == ERROR_SUCCESS }
+  }
+}
+
+#[cfg(target_os = "windows")]
+impl Drop for XInputLibrary {
+  fn drop(&mut self) {
+    let _ = unsafe { FreeLibrary(self.module) };
+  }
+}
+
+pub fn backup_path() -> PathBuf {
+  xinput_path().with_extension("dll.bak")
+}
+
+fn manifest_path() -> PathBuf {
+  app_data_dir().join(MANIFEST_FILE)
+}
+
+pub fn load_manifest() -> Option<XInputManifest> {
+  std::fs::read_to_string(manifest_path())
+    .ok()
+    .and_then(|content| serde_json::from_str(&content).ok())
+}
+
+fn save_manifest(manifest: &XInputManifest) -> Result<(), HayboxError> {
+  let content = serde_json::to_string_pretty(manifest)
+    .map_err(|e| HayboxError::Other(format!("Failed to serialize XInput manifest: {}", e)))?;
+  std::fs::write(manifest_path(), content)
+    .map_err(|e| HayboxError::Io(format!("Failed to write XInput manifest: {}", e)))
+}
+
+fn set_removed(removed: bool) -> Result<(), HayboxError> {
+  match load_manifest() {
+    Some(manifest) => save_manifest(&XInputManifest { removed, ..manifest }),
+    None => Ok(()),
+  }
+}
+
+fn known_good_hashes() -> HashSet<String> {
+  let Some((_, bytes, _, _)) = EMBEDDED_RESOURCES
+    .iter()
+    .find(|(name, _, _, _)| *name == KNOWN_GOOD_LIST)
+  else {
+    return HashSet::new();
+  };
+
+  String::from_utf8_lossy(bytes)
+    .lines()
+    .filter_map(|line| line.split_whitespace().next())
+    .map(|hash| hash.to_lowercase())
+    .collect()
+}
+
+/// Only hashes compiled into the build and Microsoft's own signature count.
+/// The manifest lives in the user-writable app data directory, so it cannot
+/// vouch for a file.
+fn is_recognized(path: &Path, sha256: &str) -> bool {
+  known_good_hashes().contains(sha256) || is_microsoft_signed(path)
+}
+
+fn describe(path: &Path, sha256: &str) -> String {
+  format!(
+    "{} (version {}, SHA-256 {})",
+    path.display(),
+    file_version(path).unwrap_or_else(|| "unknown".to_string()),
+    sha256
+  )
+}
+
+fn now_secs() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_secs())
+    .unwrap_or_default()
+}
+
+pub fn is_installed() -> bool {
+  xinput_path().exists()
+}
+
+pub fn status() -> XInputStatus {
+  let path = xinput_path();
+  let original = load_manifest();
+  let sha256 = sha256_file(&path).ok();
+  let backup_sha256 = sha256_file(&backup_path()).ok();
+
+  XInputStatus {
+    present: path.exists(),
+    file_version: file_version(&path),
+    recognized: sha256
+      .as_deref()
+      .map(|hash| is_recognized(&path, hash))
+      .unwrap_or(false),
+    hash_matches_original: match (&sha256, &original) {
+      (Some(hash), Some(original)) => Some(*hash == original.sha256),
+      _ => None,
+    },
+    backed_up: backup_sha256.is_some(),
+    sha256,
+    backup_sha256,
+    original,
+  }
+}
+
+/// Moves xinput1_4.dll aside after checking it is a genuine Windows build and
+/// recording its hash and version. With `take_ownership`, a DLL owned by
+/// TrustedInstaller is taken over for the rename and its ACL put back after.
+pub fn uninstall(take_ownership: bool) -> Result<(), HayboxError> {
+  if !check_admin_rights() {
+    return Err(HayboxError::elevation_required());
+  }
+
+  let path = xinput_path();
+  if !path.exists() {
+    return Ok(());
+  }
+
+  let sha256 = sha256_file(&path).map_err(|e| HayboxError::Io(format!("Failed to read {}: {}", XINPUT_DLL, e)))?;
+  if !is_recognized(&path, &sha256) {
+    return Err(HayboxError::Other(format!(
+      "{} is not a recognized Windows build; refusing to modify it",
+      describe(&path, &sha256)
+    )));
+  }
+
+  let backup = backup_path();
+  if let Ok(backup_sha256) = sha256_file(&backup) {
+    if backup_sha256 != sha256 {
+      return Err(HayboxError::Other(format!(
+        "A different backup already exists at {}; refusing to overwrite it",
+        backup.display()
+      )));
+    }
+  }
+
+  let version = file_version(&path);
+  with_write_access(&path, Some(&backup), take_ownership, || std::fs::rename(&path, &backup))
+    .map_err(|e| HayboxError::Io(format!("Failed to rename {}: {}", XINPUT_DLL, e)))?;
+
+  // Only recorded once the DLL is really gone, so a failed rename never
+  // leaves the restore watcher thinking it was removed.
+  save_manifest(&XInputManifest {
+    sha256: sha256.clone(),
+    file_version: version,
+    recorded_at: now_secs(),
+    removed: true,
+  })?;
+
+  match sha256_file(&backup) {
+    Ok(hash) if hash == sha256 => Ok(()),
+    _ => Err(HayboxError::Other(format!(
+      "Backup at {} does not match the original file",
+      backup.display()
+    ))),
+  }
+}
+
+/// Copies the bundled xinput1_4.dll back into System32. Both the bundled copy
+/// and any file it would replace must be recognized.
+pub fn reinstall(take_ownership: bool) -> Result<(), HayboxError> {
+  if !check_admin_rights() {
+    return Err(HayboxError::elevation_required());
+  }
+
+  let exe_dir = std::env::current_exe()
+    .map_err(|e| HayboxError::Io(format!("Could not find executable path: {}", e)))?
+    .parent()
+    .ok_or_else(|| HayboxError::Io("Could not find executable parent directory".to_string()))?
+    .to_path_buf();
+
+  let source = exe_dir.join("XInput1_4.dll");
+  let source_sha256 =
+    sha256_file(&source).map_err(|e| HayboxError::Io(format!("Failed to read {}: {}", source.display(), e)))?;
+  if !is_recognized(&source, &source_sha256) {
+    return Err(HayboxError::Other(format!(
+      "{} is not a recognized Windows build; refusing to install it",
+      describe(&source, &source_sha256)
+    )));
+  }
+
+  let target = xinput_path();
+  if let Ok(target_sha256) = sha256_file(&target) {
+    if target_sha256 == source_sha256 {
+      return Ok(());
+    }
+    if !is_recognized(&target, &target_sha256) {
+      return Err(HayboxError::Other(format!(
+        "{} is not a recognized Windows build; refusing to overwrite it",
+        describe(&target, &target_sha256)
+      )));
+    }
+  }
+
+  with_write_access(&target, Some(&target), take_ownership, || {
+    std::fs::copy(&source, &target)
+  })
+  .map_err(|e| HayboxError::Io(format!("Failed to copy {}: {}", XINPUT_DLL, e)))?;
+
+  match sha256_file(&target) {
+    Ok(hash) if hash == source_sha256 => set_removed(false),
+    _ => Err(HayboxError::Other(format!(
+      "{} does not match the bundled copy after copying",
+      target.display()
+    ))),
+  }
+}
+
+pub fn backup_status() -> XInputBackupStatus {
+  let backup = backup_path();
+  let original = load_manifest();
+  let sha256 = sha256_file(&backup).ok();
+
+  XInputBackupStatus {
+    exists: backup.exists(),
+    path: backup.display().to_string(),
+    file_version: file_version(&backup),
+    modified_at: std::fs::metadata(&backup)
+      .and_then(|metadata| metadata.modified())
+      .ok()
+      .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+      .map(|d| d.as_secs()),
+    recorded_at: original.as_ref().map(|m| m.recorded_at),
+    matches_original: match (&sha256, &original) {
+      (Some(hash), Some(original)) => Some(*hash == original.sha256),
+      _ => None,
+    },
+    sha256,
+  }
+}
+
+/// Moves the `.bak` file back into place. The backup must still be a
+/// recognized build and nothing may be installed in its place.
+pub fn restore_from_backup(take_ownership: bool) -> Result<(), HayboxError> {
+  if !check_admin_rights() {
+    return Err(HayboxError::elevation_required());
+  }
+
+  let backup = backup_path();
+  let backup_sha256 =
+    sha256_file(&backup).map_err(|e| HayboxError::Io(format!("Failed to read {}: {}", backup.display(), e)))?;
+  if !is_recognized(&backup, &backup_sha256) {
+    return Err(HayboxError::Other(format!(
+      "{} is not a recognized Windows build; refusing to restore it",
+      describe(&backup, &backup_sha256)
+    )));
+  }
+
+  let target = xinput_path();
+  if target.exists() {
+    return Err(HayboxError::Other(format!(
+      "{} is already present; delete the backup instead if it is no longer needed",
+      target.display()
+    )));
+  }
+
+  with_write_access(&backup, Some(&target), take_ownership, || {
+    std::fs::rename(&backup, &target)
+  })
+  .map_err(|e| HayboxError::Io(format!("Failed to restore {}: {}", XINPUT_DLL, e)))?;
+
+  match sha256_file(&target) {
+    Ok(hash) if hash == backup_sha256 => set_removed(false),
+    _ => Err(HayboxError::Other(format!(
+      "{} does not match the backup after restoring",
+      target.display()
+    ))),
+  }
+}
+
+/// Deletes the `.bak` file. Refused while the DLL itself is missing, since the
+/// backup would then be the only copy.
+pub fn delete_backup(take_ownership: bool) -> Result<(), HayboxError> {
+  if !check_admin_rights() {
+    return Err(HayboxError::elevation_required());
+  }
+
+  let backup = backup_path();
+  if !backup.exists() {
+    return Ok(());
+  }
+
+  if !is_installed() {
+    return Err(HayboxError::Other(format!(
+      "{} is missing; restore the backup before deleting it",
+      xinput_path().display()
+    )));
+  }
+
+  with_write_access(&backup, None, take_ownership, || std::fs::remove_file(&backup))
+    .map_err(|e| HayboxError::Io(format!("Failed to delete {}: {}", backup.display(), e)))
+}
+
+/// Watches for Windows restoring xinput1_4.dll after the app removed it and
+/// emits `xinput_restored` with the current status, once per restoration, so
+/// the frontend can offer to remove it again.
+pub fn start_restore_watcher(events: Events) {
+  std::thread::spawn(move || {
+    let mut notified = false;
+
+    loop {
+      std::thread::sleep(RESTORE_POLL_INTERVAL);
+
+      let removed_by_app = load_manifest().map(|m| m.removed).unwrap_or(false);
+      if !removed_by_app || !is_installed() {
+        notified = false;
+        continue;
+      }
+
+      if !notified {
+        tracing::warn!("{} was restored by Windows", xinput_path().display());
+        events.emit("xinput_restored", status());
+        notified = true;
+      }
+    }
+  });
+}
@@ -0,0 +1,29 @@
+use std::time::Duration;
+
+use haybox_core::error::HayboxError;
+
+/// For commands that query devices, drivers or files. These finish in well
+/// under a second normally, so hitting this means something hung.
+pub const QUERY_TIMEOUT: Duration = Duration::from_secs(30);
+/// For driver installs, flashing, measurements and anything that waits on a
+/// UAC prompt or the user.
+pub const OPERATION_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+
+/// Runs `f` on Tauri's blocking thread pool so a hung USB or pnputil call
+/// cannot stall the IPC layer, and stops waiting after `timeout`. The work
+/// itself cannot be interrupted; it keeps running in the background and its
+/// result is dropped.
+pub async fn run<T, F>(timeout: Duration, f: F) -> Result<T, HayboxError>
+where
+  T: Send + 'static,
+  F: FnOnce() -> T + Send + 'static,
+{
+  match tokio::time::timeout(timeout, tauri::async_runtime::spawn_blocking(f)).await {
+    Ok(Ok(value)) => Ok(value),
+    Ok(Err(e)) => Err(HayboxError::Other(format!("Background task failed: {}", e))),
+    Err(_) => Err(HayboxError::Timeout(format!(
+      "Timed out after {} seconds",
+      timeout.as_secs()
+    ))),
+  }
+}
@@ -0,0 +1,349 @@
+use std::io::Write;
+#[cfg(target_os = "windows")]
+use std::sync::mpsc;
+#[cfg(target_os = "windows")]
+use std::time::Duration;
+
+use haybox_core::driver::DriverKind;
+use haybox_core::error::HayboxError;
+#[cfg(target_os = "windows")]
+use haybox_core::paths;
+#[cfg(target_os = "windows")]
+use haybox_core::privileges;
+use haybox_core::{BatchDevice, DriverOperationResult};
+use serde::{Deserialize, Serialize};
+#[cfg(target_os = "windows")]
+use windows::core::{HSTRING, PCWSTR};
+#[cfg(target_os = "windows")]
+use windows::Win32::Foundation::{CloseHandle, LocalFree, HANDLE, HLOCAL, WAIT_OBJECT_0};
+#[cfg(target_os = "windows")]
+use windows::Win32::Security::Authorization::{ConvertStringSecurityDescriptorToSecurityDescriptorW, SDDL_REVISION_1};
+#[cfg(target_os = "windows")]
+use windows::Win32::Security::{PSECURITY_DESCRIPTOR, SECURITY_ATTRIBUTES};
+#[cfg(target_os = "windows")]
+use windows::Win32::Storage::FileSystem::{ReadFile, FILE_FLAG_FIRST_PIPE_INSTANCE, PIPE_ACCESS_INBOUND};
+#[cfg(target_os = "windows")]
+use windows::Win32::System::Pipes::{
+  ConnectNamedPipe, CreateNamedPipeW, PIPE_READMODE_BYTE, PIPE_TYPE_BYTE, PIPE_WAIT,
+};
+#[cfg(target_os = "windows")]
+use windows::Win32::System::Threading::{GetExitCodeProcess, WaitForSingleObject};
+#[cfg(target_os = "windows")]
+use windows::Win32::UI::Shell::{ShellExecuteExW, SEE_MASK_NOCLOSEPROCESS, SEE_MASK_NO_CONSOLE, SHELLEXECUTEINFOW};
+
+const HELPER_FLAG: &str = "--elevated-helper";
+const PIPE_FLAG: &str = "--pipe";
+
+/// How long the elevated helper may run before we stop waiting for it. Driver
+/// installs can take a while on slow machines, so this is deliberately generous.
+#[cfg(target_os = "windows")]
+const HELPER_TIMEOUT_MS: u32 = 5 * 60 * 1000;
+
+/// A driver action the unelevated app can ask an elevated copy of itself to
+/// perform.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ElevatedOperation {
+  InstallWinusb,
+  InstallDriver {
+    kind: DriverKind,
+    vid: u16,
+    pid: u16,
+    interface: Option<u8>,
+  },
+  InstallWinusbBatch {
+    devices: Vec<BatchDevice>,
+  },
+  RestoreDefaultDriver {
+    vid: u16,
+    pid: u16,
+  },
+  ReplaceDriver {
+    device_instance_id: String,
+    kind: DriverKind,
+  },
+  RemoveStaleDrivers,
+  CleanDriverCache,
+  UninstallXinput {
+    #[serde(default)]
+    take_ownership: bool,
+  },
+  ReinstallXinput {
+    #[serde(default)]
+    take_ownership: bool,
+  },
+  RestoreXinputBackup {
+    #[serde(default)]
+    take_ownership: bool,
+  },
+  DeleteXinputBackup {
+    #[serde(default)]
+    take_ownership: bool,
+  },
+  InstallHidhide,
+  HideController {
+    vid: u16,
+    pid: u16,
+  },
+  UnhideController {
+    vid: u16,
+    pid: u16,
+  },
+  AllowHidhideApp {
+    exe_path: String,
+  },
+  DisallowHidhideApp {
+    exe_path: String,
+  },
+  SetHidhideActive {
+    active: bool,
+  },
+}
+
+fn execute(operation: ElevatedOperation) -> DriverOperationResult {
+  match operation {
+    ElevatedOperation::InstallWinusb => haybox_core::run_install_winusb(),
+    ElevatedOperation::InstallDriver {
+      kind,
+      vid,
+      pid,
+      interface,
+    } => haybox_core::run_install_driver_for(kind, vid, pid, interface),
+    ElevatedOperation::InstallWinusbBatch { devices } => haybox_core::run_install_winusb_batch(devices),
+    ElevatedOperation::RestoreDefaultDriver { vid, pid } => haybox_core::run_restore_default_driver(vid, pid),
+    ElevatedOperation::ReplaceDriver {
+      device_instance_id,
+      kind,
+    } => haybox_core::run_replace_driver(device_instance_id, kind),
+    ElevatedOperation::RemoveStaleDrivers => haybox_core::run_remove_stale_drivers(),
+    ElevatedOperation::CleanDriverCache => haybox_core::run_clean_driver_cache(),
+    ElevatedOperation::UninstallXinput { take_ownership } => haybox_core::run_uninstall_xinput(Some(take_ownership)),
+    ElevatedOperation::ReinstallXinput { take_ownership } => haybox_core::run_reinstall_xinput(Some(take_ownership)),
+    ElevatedOperation::RestoreXinputBackup { take_ownership } => {
+      haybox_core::run_restore_xinput_from_backup(Some(take_ownership))
+    }
+    ElevatedOperation::DeleteXinputBackup { take_ownership } => {
+      haybox_core::run_delete_xinput_backup(Some(take_ownership))
+    }
+    ElevatedOperation::InstallHidhide => haybox_core::run_install_hidhide(),
+    ElevatedOperation::HideController { vid, pid } => haybox_core::run_hide_controller(vid, pid),
+    ElevatedOperation::UnhideController { vid, pid } => haybox_core::run_unhide_controller(vid, pid),
+    ElevatedOperation::AllowHidhideApp { exe_path } => haybox_core::run_allow_hidhide_app(exe_path),
+    ElevatedOperation::DisallowHidhideApp { exe_path } => haybox_core::run_disallow_hidhide_app(exe_path),
+    ElevatedOperation::SetHidhideActive { active } => haybox_core::run_set_hidhide_active(active),
+  }
+}
+
+/// Entry point for the elevated copy of the app. Returns the process exit code
+/// when launched as a helper, or `None` for a normal GUI launch.
+pub fn run_helper_from_args() -> Option<i32> {
+  let args: Vec<String> = std::env::args().collect();
+  let operation_json = args
+    .iter()
+    .position(|arg| arg == HELPER_FLAG)
+    .and_then(|i| args.get(i + 1))?;
+  let pipe_name = args
+    .iter()
+    .position(|arg| arg == PIPE_FLAG)
+    .and_then(|i| args.get(i + 1))?;
+
+  let result = match serde_json::from_str::<ElevatedOperation>(operation_json) {
+    Ok(operation) => execute(operation),
+    Err(e) => DriverOperationResult {
+      success: false,
+      message: format!("Invalid elevated operation: {}", e),
+      ..Default::default()
+    },
+  };
+
+  let payload = serde_json::to_vec(&result).unwrap_or_default();
+  let written = std::fs::OpenOptions::new()
+    .write(true)
+    .open(pipe_name)
+    .and_then(|mut pipe| pipe.write_all(&payload));
+
+  match written {
+    Ok(_) if result.success => Some(0),
+    Ok(_) => Some(1),
+    Err(_) => Some(2),
+  }
+}
+
+/// The driver and HidHide operations the helper runs only exist on Windows.
+#[cfg(not(target_os = "windows"))]
+pub fn elevate_and_run(_operation: &ElevatedOperation) -> Result<DriverOperationResult, HayboxError> {
+  Err(HayboxError::Unsupported(
+    "Running an operation as administrator is only available on Windows".to_string(),
+  ))
+}
+
+/// Relaunches the current executable elevated via the `runas` verb, asks it to
+/// perform `operation`, and reads its result back over a named pipe.
+#[cfg(target_os = "windows")]
+pub fn elevate_and_run(operation: &ElevatedOperation) -> Result<DriverOperationResult, HayboxError> {
+  let exe_path =
+    std::env::current_exe().map_err(|e| HayboxError::Io(format!("Could not find executable path: {}", e)))?;
+  let operation_json =
+    serde_json::to_string(operation).map_err(|e| HayboxError::Other(format!("Failed to encode operation: {}", e)))?;
+  let pipe_name = format!(
+    "\\\\.\\pipe\\haybox-elevate-{}-{}",
+    std::process::id(),
+    std::time::SystemTime::now()
+      .duration_since(std::time::UNIX_EPOCH)
+      .map(|d| d.as_nanos())
+      .unwrap_or_default()
+  );
+
+  let descriptor = pipe_security_descriptor()?;
+  let attributes = SECURITY_ATTRIBUTES {
+    nLength: std::mem::size_of::<SECURITY_ATTRIBUTES>() as u32,
+    lpSecurityDescriptor: descriptor.0,
+    ..Default::default()
+  };
+  let pipe = unsafe {
+    CreateNamedPipeW(
+      &HSTRING::from(pipe_name.as_str()),
+      PIPE_ACCESS_INBOUND | FILE_FLAG_FIRST_PIPE_INSTANCE,
+      PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT,
+      1,
+      0,
+      64 * 1024,
+      0,
+      Some(&attributes as *const _),
+    )
+  };
+  let _ = unsafe { LocalFree(Some(HLOCAL(descriptor.0))) };
+  if pipe.is_invalid() {
+    return Err(HayboxError::Other("Failed to create result pipe".to_string()));
+  }
+
+  // ConnectNamedPipe blocks until the helper opens the pipe, so read on a
+  // separate thread and give up on it if the helper exits without connecting.
+  let (tx, rx) = mpsc::channel();
+  let raw_pipe = pipe.0 as isize;
+  std::thread::spawn(move || {
+    let pipe = HANDLE(raw_pipe as *mut _);
+    // A client that connected before this call makes it fail with
+    // ERROR_PIPE_CONNECTED, which is fine: reading works either way.
+    let _ = unsafe { ConnectNamedPipe(pipe, None) };
+
+    let mut payload = Vec::new();
+    let mut buffer = [0u8; 4096];
+    loop {
+      let mut read = 0u32;
+      if unsafe { ReadFile(pipe, Some(&mut buffer), Some(&mut read), None) }.is_err() || read == 0 {
+        break;
+      }
+      payload.extend_from_slice(&buffer[..read as usize]);
+    }
+    let _ = tx.send(payload);
+  });
+
+  let mut parameters = format!(
+    "{} {} {} {}",
+    HELPER_FLAG,
+    quote_argument(&operation_json),
+    PIPE_FLAG,
+    quote_argument(&pipe_name)
+  );
+  // The helper must write its audit entries and staged files to the same
+  // place, and only the flag file carries over to a new process by itself.
+  if paths::is_portable() {
+    parameters = format!("{} {}", parameters, paths::PORTABLE_SWITCH);
+  }
+  let verb = HSTRING::from("runas");
+  let file = HSTRING::from(exe_path.as_os_str());
+  let params = HSTRING::from(parameters.as_str());
+
+  let mut info = SHELLEXECUTEINFOW {
+    cbSize: std::mem::size_of::<SHELLEXECUTEINFOW>() as u32,
+    fMask: SEE_MASK_NOCLOSEPROCESS | SEE_MASK_NO_CONSOLE,
+    lpVerb: PCWSTR(verb.as_ptr()),
+    lpFile: PCWSTR(file.as_ptr()),
+    lpParameters: PCWSTR(params.as_ptr()),
+    nShow: 0,
+    ..Default::default()
+  };
+
+  if let Err(e) = unsafe { ShellExecuteExW(&mut info) } {
+    release_pipe(&pipe_name, pipe);
+    return Err(HayboxError::ElevationRequired(format!(
+      "Elevation was cancelled or failed: {}",
+      e
+    )));
+  }
+
+  let wait = unsafe { WaitForSingleObject(info.hProcess, HELPER_TIMEOUT_MS) };
+  let mut exit_code = 0u32;
+  let _ = unsafe { GetExitCodeProcess(info.hProcess, &mut exit_code) };
+  let _ = unsafe { CloseHandle(info.hProcess) };
+
+  if wait != WAIT_OBJECT_0 {
+    release_pipe(&pipe_name, pipe);
+    return Err(HayboxError::Timeout(
+      "Timed out waiting for the elevated helper".to_string(),
+    ));
+  }
+
+  let payload = match rx.recv_timeout(Duration::from_secs(2)) {
+    Ok(payload) => payload,
+    Err(_) => {
+      release_pipe(&pipe_name, pipe);
+      return Err(HayboxError::Other(format!(
+        "Elevated helper exited with code {} without reporting a result",
+        exit_code
+      )));
+    }
+  };
+  let _ = unsafe { CloseHandle(pipe) };
+
+  serde_json::from_slice(&payload)
+    .map_err(|e| HayboxError::Other(format!("Invalid result from elevated helper: {}", e)))
+}
+
+/// Lets only the current user open the result pipe, so no other local
+/// process can connect first and report a forged result. Administrators are
+/// allowed too because over-the-shoulder elevation runs the helper as another
+/// account, and an administrator could take the pipe over regardless.
+#[cfg(target_os = "windows")]
+fn pipe_security_descriptor() -> Result<PSECURITY_DESCRIPTOR, HayboxError> {
+  let sid = privileges::current_user_sid()
+    .ok_or_else(|| HayboxError::Other("Failed to read the current user's SID".to_string()))?;
+  let sddl = HSTRING::from(format!("D:P(A;;GA;;;{})(A;;GA;;;BA)", sid));
+
+  let mut descriptor = PSECURITY_DESCRIPTOR::default();
+  unsafe { ConvertStringSecurityDescriptorToSecurityDescriptorW(&sddl, SDDL_REVISION_1, &mut descriptor, None) }
+    .map_err(|e| HayboxError::Other(format!("Failed to build the result pipe's security descriptor: {}", e)))?;
+  Ok(descriptor)
+}
+
+/// Quotes `argument` so CommandLineToArgvW hands it back unchanged:
+/// backslashes are only special before a quote, where they are doubled, and
+/// the quote itself is escaped.
+#[cfg(target_os = "windows")]
+fn quote_argument(argument: &str) -> String {
+  let mut quoted = String::from("\"");
+  let mut backslashes = 0;
+  for c in argument.chars() {
+    if c == '\\' {
+      backslashes += 1;
+      continue;
+    }
+    let escapes = if c == '"' { backslashes * 2 + 1 } else { backslashes };
+    quoted.extend(std::iter::repeat_n('\\', escapes));
+    quoted.push(c);
+    backslashes = 0;
+  }
+  // The closing quote must not be escaped by a trailing backslash.
+  quoted.extend(std::iter::repeat_n('\\', backslashes * 2));
+  quoted.push('"');
+  quoted
+}
+
+/// Unblocks the reader thread (which may still be waiting in ConnectNamedPipe)
+/// by connecting to the pipe ourselves, then closes the server handle.
+#[cfg(target_os = "windows")]
+fn release_pipe(pipe_name: &str, pipe: HANDLE) {
+  drop(std::fs::OpenOptions::new().write(true).open(pipe_name));
+  let _ = unsafe { CloseHandle(pipe) };
+}
@@ -1,647 +1,1542 @@
-use std::path::PathBuf;
+#[cfg(target_os = "windows")]
 use std::process::Command;
 
-use rusb::UsbContext;
-use serde::{Deserialize, Serialize};
+use blocking::{OPERATION_TIMEOUT, QUERY_TIMEOUT};
+use elevation::ElevatedOperation;
+use haybox_core::architecture::ArchitectureInfo;
+use haybox_core::audit::AuditEntry;
+use haybox_core::batch_flash::BatchFlashResult;
+use haybox_core::bootsel::{BootselInfo, BootselResult};
+use haybox_core::build_config::{BuildConfig, BuildConfigFormat};
+use haybox_core::bulk_apply::{DeviceApplyResult, DeviceFilter};
+use haybox_core::button_mapping::ProfileMappings;
+use haybox_core::capabilities::ConfigViolation;
+use haybox_core::config_backup::ConfigBackup;
+use haybox_core::config_diff::{ConfigChange, ConfigSource};
+use haybox_core::config_file::ConfigFile;
+use haybox_core::config_proto::{
+  Button, CommunicationBackendId, Config, ConfigModeConnection, DefaultModeResult, GameModeId,
+};
+use haybox_core::controller_profiles::{ControllerProfile, ProfileSnapshot};
+use haybox_core::coordinate_legality::{CaptureSource, ComplianceReport, Ruleset};
+use haybox_core::crash::CrashReport;
+use haybox_core::device_mode::{DeviceMode, ModeTransition};
+use haybox_core::device_tree::PnpDeviceNode;
+use haybox_core::device_usage::DeviceProcess;
+use haybox_core::diagnostics::DiagnosticsExport;
+use haybox_core::doctor::DoctorReport;
+use haybox_core::driver::{ConfigBuilder, DriverKind};
+use haybox_core::driver_store::DriverStoreEntry;
+use haybox_core::environment::EnvironmentWarning;
+use haybox_core::error::HayboxError;
+use haybox_core::event_log::SystemUsbEvent;
+use haybox_core::events::{EventSink, Events};
+use haybox_core::firmware::{CachedFirmware, DeviceFirmwareVersion, FirmwareRelease};
+use haybox_core::firmware_backup::FirmwareBackup;
+use haybox_core::flash_target::FlashTarget;
+use haybox_core::flashing::{DropFlashStatus, DroppedFileResult, FlashResult};
+use haybox_core::frame_trainer::{TrainerConfig, TrainerReport};
+use haybox_core::game_controllers::ControllerSlot;
+use haybox_core::game_profiles::GameProfile;
+use haybox_core::gamecube_adapter::AdapterTestResult;
+use haybox_core::hidhide::HidHideStatus;
+use haybox_core::hotplug::HotplugKind;
+use haybox_core::inf_template::InfTemplate;
+use haybox_core::input_analysis::{AnalysisOptions, InputFinding};
+use haybox_core::input_comparison::ComparisonReport;
+use haybox_core::input_monitor::{HidDeviceEntry, InputMonitorInfo};
+use haybox_core::input_recording::InputRecording;
+use haybox_core::integrity::ResourceVerification;
+use haybox_core::keyboard_map::{KeyMapping, KeyboardMap};
+use haybox_core::latency_test::LatencyResult;
+use haybox_core::layout_share::{ShareTarget, SharedLayout};
+use haybox_core::lighting::LightingSettings;
+use haybox_core::logging::{LogEntry, LogLevel};
+use haybox_core::operations::{OperationInfo, OperationQueue};
+use haybox_core::paths::DataLocation;
+use haybox_core::pending::{PendingAction, PendingActionVerification};
+use haybox_core::platform::{Elevation, EnumerationBackend, PlatformCapabilities};
+use haybox_core::pnp::ReplaceableDevice;
+use haybox_core::polling_rate::PollingRateResult;
+use haybox_core::presentation_test::PresentationTestResult;
+use haybox_core::privileges::PrivilegeStatus;
+use haybox_core::protocol_trace::TraceEntry;
+use haybox_core::recovery::FactoryResetResult;
+use haybox_core::report_descriptor::ParsedDescriptor;
+use haybox_core::rumble::{RumblePattern, RumbleResult};
+use haybox_core::serial_console::ConsoleLine;
+use haybox_core::serial_ports::DeviceSerialPort;
+use haybox_core::socd_test::{SocdRule, SocdTestStatus};
+use haybox_core::steamos::SteamOsInfo;
+use haybox_core::switch_health::SwitchHealthReport;
+use haybox_core::telemetry::TelemetryStatus;
+use haybox_core::udev::UdevStatus;
+use haybox_core::uf2::Uf2Inspection;
+use haybox_core::usage_stats::UsageStats;
+use haybox_core::virtual_controllers::VirtualControllerStack;
+use haybox_core::xinput::{XInputBackupStatus, XInputStatus};
+use haybox_core::{
+  analog_trace, architecture, audit, batch_flash, bootsel, build_config, bulk_apply, button_mapping, capabilities,
+  check_admin_rights, config_backup, config_diff, config_file, config_proto, config_trial, controller_profiles,
+  coordinate_legality, crash, device_mode, device_tree, device_usage, diagnostics, doctor, driver_cache, driver_store,
+  environment, event_log, firmware, firmware_backup, flash_target, flashing, frame_trainer, game_controllers,
+  game_profiles, gamecube_adapter, get_current_device_status, hidhide, hotplug, inf_template, input_analysis,
+  input_comparison, input_monitor, input_recording, keyboard_map, latency_test, layout_share, lighting, logging, paths,
+  pending, platform, pnp, polling_rate, presentation_test, privileges, protocol_trace, recovery, report_descriptor,
+  resources, rumble, run_allow_hidhide_app, run_clean_driver_cache, run_delete_xinput_backup, run_disallow_hidhide_app,
+  run_hide_controller, run_install_driver_for, run_install_hidhide, run_install_winusb, run_install_winusb_batch,
+  run_reinstall_xinput, run_remove_stale_drivers, run_replace_driver, run_restore_default_driver,
+  run_restore_xinput_from_backup, run_set_hidhide_active, run_unhide_controller, run_uninstall_xinput, serial_console,
+  serial_ports, socd_test, steamos, switch_health, telemetry, udev, uf2, usage_stats, xinput, BatchDevice,
+  DeviceIdentifiers, DeviceStatus, DriverInfo, DriverOperationResult, DEVICES,
+};
+use tauri::{Emitter, Manager};
+
+mod blocking;
+mod elevation;
+
+/// Forwards events from the core library to the webview.
+struct WebviewEvents(tauri::AppHandle);
+
+impl EventSink for WebviewEvents {
+  fn emit(&self, event: &str, payload: serde_json::Value) {
+    if let Err(e) = self.0.emit(event, payload) {
+      tracing::warn!("failed to emit {}: {}", event, e);
+    }
+  }
+}
+
+fn webview_events(app_handle: &tauri::AppHandle) -> Events {
+  Events::new(WebviewEvents(app_handle.clone()))
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn get_device_identifiers() -> DeviceIdentifiers {
+  DEVICES.clone()
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn get_device_status() -> DeviceStatus {
+  // The status error is not Send, and a failed query is reported the same
+  // way as a timeout: as nothing connected.
+  blocking::run(QUERY_TIMEOUT, || get_current_device_status().ok())
+    .await
+    .ok()
+    .flatten()
+    .unwrap_or(DeviceStatus {
+      default_mode_connected: false,
+      config_mode_connected: false,
+      bootsel_mode_connected: false,
+      switch_mode_connected: false,
+      xinput_installed: false,
+      gamecube_adapter_connected: false,
+      winusb_installed: false,
+      virtual_controller_stack: VirtualControllerStack::default(),
+      usb_access_problems: Vec::new(),
+      enumeration_backend: EnumerationBackend::Unavailable,
+    })
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn uninstall_xinput(take_ownership: Option<bool>) -> DriverOperationResult {
+  blocking::run(OPERATION_TIMEOUT, move || run_uninstall_xinput(take_ownership))
+    .await
+    .unwrap_or_else(DriverOperationResult::failed)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn reinstall_xinput(take_ownership: Option<bool>) -> DriverOperationResult {
+  blocking::run(OPERATION_TIMEOUT, move || run_reinstall_xinput(take_ownership))
+    .await
+    .unwrap_or_else(DriverOperationResult::failed)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn get_xinput_status() -> Result<XInputStatus, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, xinput::status).await
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn get_xinput_backup_status() -> Result<XInputBackupStatus, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, xinput::backup_status).await
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn restore_xinput_from_backup(take_ownership: Option<bool>) -> DriverOperationResult {
+  blocking::run(OPERATION_TIMEOUT, move || {
+    run_restore_xinput_from_backup(take_ownership)
+  })
+  .await
+  .unwrap_or_else(DriverOperationResult::failed)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn delete_xinput_backup(take_ownership: Option<bool>) -> DriverOperationResult {
+  blocking::run(OPERATION_TIMEOUT, move || run_delete_xinput_backup(take_ownership))
+    .await
+    .unwrap_or_else(DriverOperationResult::failed)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn run_presentation_test(
+  vid: Option<u16>,
+  pid: Option<u16>,
+  window_ms: Option<u64>,
+) -> Result<PresentationTestResult, HayboxError> {
+  blocking::run(OPERATION_TIMEOUT, move || {
+    presentation_test::run_presentation_test(vid, pid, window_ms.map(std::time::Duration::from_millis))
+  })
+  .await?
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn get_hidhide_status() -> Result<HidHideStatus, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, hidhide::status).await?
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn install_hidhide() -> DriverOperationResult {
+  blocking::run(OPERATION_TIMEOUT, run_install_hidhide)
+    .await
+    .unwrap_or_else(DriverOperationResult::failed)
+}
+
+/// Hides the controller from every application except those allowed in
+/// HidHide, as a non-destructive alternative to removing xinput1_4.dll.
+#[tauri::command(rename_all = "snake_case")]
+async fn hide_controller(vid: u16, pid: u16) -> DriverOperationResult {
+  blocking::run(QUERY_TIMEOUT, move || run_hide_controller(vid, pid))
+    .await
+    .unwrap_or_else(DriverOperationResult::failed)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn unhide_controller(vid: u16, pid: u16) -> DriverOperationResult {
+  blocking::run(QUERY_TIMEOUT, move || run_unhide_controller(vid, pid))
+    .await
+    .unwrap_or_else(DriverOperationResult::failed)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn allow_hidhide_app(exe_path: String) -> DriverOperationResult {
+  blocking::run(QUERY_TIMEOUT, move || run_allow_hidhide_app(exe_path))
+    .await
+    .unwrap_or_else(DriverOperationResult::failed)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn disallow_hidhide_app(exe_path: String) -> DriverOperationResult {
+  blocking::run(QUERY_TIMEOUT, move || run_disallow_hidhide_app(exe_path))
+    .await
+    .unwrap_or_else(DriverOperationResult::failed)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn set_hidhide_active(active: bool) -> DriverOperationResult {
+  blocking::run(QUERY_TIMEOUT, move || run_set_hidhide_active(active))
+    .await
+    .unwrap_or_else(DriverOperationResult::failed)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn get_pnp_device_tree() -> Result<Vec<PnpDeviceNode>, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, device_tree::get_pnp_device_tree).await?
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn find_processes_using_device(vid: u16, pid: u16) -> Result<Vec<DeviceProcess>, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || {
+    device_usage::find_processes_using_device(vid, pid)
+  })
+  .await?
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn enter_bootsel_mode() -> Result<BootselResult, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, bootsel::enter_bootsel_mode).await?
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn get_device_mode() -> Result<DeviceMode, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, DeviceMode::current).await
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn request_mode(
+  queue: tauri::State<'_, OperationQueue>,
+  target: DeviceMode,
+) -> Result<ModeTransition, HayboxError> {
+  let queue = queue.inner().clone();
+  blocking::run(OPERATION_TIMEOUT, move || {
+    queue.run("Switching controller mode", || device_mode::request_mode(target))
+  })
+  .await?
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn list_firmware_releases(repo: Option<String>) -> Result<Vec<FirmwareRelease>, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || {
+    firmware::list_releases(repo.as_deref().unwrap_or(firmware::DEFAULT_REPO))
+  })
+  .await?
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn download_firmware(
+  repo: Option<String>,
+  tag: String,
+  asset_name: String,
+) -> Result<CachedFirmware, HayboxError> {
+  blocking::run(OPERATION_TIMEOUT, move || {
+    firmware::download(repo.as_deref().unwrap_or(firmware::DEFAULT_REPO), &tag, &asset_name)
+  })
+  .await?
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn get_device_firmware_version(repo: Option<String>) -> Result<DeviceFirmwareVersion, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || {
+    firmware::check_device_version(repo.as_deref().unwrap_or(firmware::DEFAULT_REPO))
+  })
+  .await?
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn inspect_uf2(path: String) -> Result<Uf2Inspection, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || -> Result<Uf2Inspection, HayboxError> {
+    let data = std::fs::read(&path).map_err(|e| HayboxError::Io(format!("Failed to read {}: {}", path, e)))?;
+    uf2::inspect(&data)
+  })
+  .await?
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn get_bootsel_info() -> Result<BootselInfo, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, bootsel::get_bootsel_info).await?
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn export_build_config(
+  path: String,
+  format: BuildConfigFormat,
+  reveal: Option<bool>,
+) -> Result<BuildConfig, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || -> Result<BuildConfig, HayboxError> {
+    let config = build_config::export_build_config(std::path::Path::new(&path), format)?;
+    if reveal.unwrap_or(true) {
+      if let Err(e) = tauri_plugin_opener::reveal_item_in_dir(&path) {
+        tracing::warn!("failed to reveal {}: {}", path, e);
+      }
+    }
+    Ok(config)
+  })
+  .await?
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn backup_firmware(
+  app_handle: tauri::AppHandle,
+  path: String,
+  flash_size: Option<u32>,
+  verify: Option<bool>,
+) -> Result<FirmwareBackup, HayboxError> {
+  blocking::run(OPERATION_TIMEOUT, move || {
+    firmware_backup::backup_firmware(
+      &webview_events(&app_handle),
+      std::path::Path::new(&path),
+      flash_size,
+      verify.unwrap_or(true),
+    )
+  })
+  .await?
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn flash_uf2(
+  app_handle: tauri::AppHandle,
+  queue: tauri::State<'_, OperationQueue>,
+  path: String,
+) -> Result<FlashResult, HayboxError> {
+  let queue = queue.inner().clone();
+  blocking::run(OPERATION_TIMEOUT, move || {
+    queue.run("Flashing firmware", || {
+      flashing::flash_uf2(&webview_events(&app_handle), std::path::Path::new(&path))
+    })
+  })
+  .await?
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn factory_reset_device(
+  app_handle: tauri::AppHandle,
+  queue: tauri::State<'_, OperationQueue>,
+  firmware_path: String,
+) -> Result<FactoryResetResult, HayboxError> {
+  let queue = queue.inner().clone();
+  blocking::run(OPERATION_TIMEOUT, move || {
+    queue.run("Factory reset", || {
+      recovery::factory_reset_device(&webview_events(&app_handle), std::path::Path::new(&firmware_path))
+    })
+  })
+  .await?
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn batch_flash(
+  app_handle: tauri::AppHandle,
+  queue: tauri::State<'_, OperationQueue>,
+  path: String,
+  parallel: Option<bool>,
+) -> Result<BatchFlashResult, HayboxError> {
+  let queue = queue.inner().clone();
+  blocking::run(OPERATION_TIMEOUT, move || {
+    queue.run("Flashing all connected controllers", || {
+      batch_flash::batch_flash(
+        &webview_events(&app_handle),
+        std::path::Path::new(&path),
+        parallel.unwrap_or(false),
+      )
+    })
+  })
+  .await?
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn detect_flash_target() -> Result<Option<FlashTarget>, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, flash_target::detect).await
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn flash_firmware(
+  app_handle: tauri::AppHandle,
+  queue: tauri::State<'_, OperationQueue>,
+  path: String,
+) -> Result<FlashResult, HayboxError> {
+  let queue = queue.inner().clone();
+  blocking::run(OPERATION_TIMEOUT, move || {
+    queue.run("Flashing firmware", || {
+      flash_target::flash_firmware(&webview_events(&app_handle), std::path::Path::new(&path))
+    })
+  })
+  .await?
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn flash_dropped_file(
+  app_handle: tauri::AppHandle,
+  path: String,
+  reboot_into_bootsel: Option<bool>,
+) -> DroppedFileResult {
+  let dropped = path.clone();
+  blocking::run(OPERATION_TIMEOUT, move || {
+    flashing::flash_dropped_file(
+      &webview_events(&app_handle),
+      std::path::Path::new(&path),
+      reboot_into_bootsel.unwrap_or(false),
+    )
+  })
+  .await
+  .unwrap_or_else(|e| flashing::dropped_result(std::path::Path::new(&dropped), DropFlashStatus::Failed, e.to_string()))
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn connect_config_mode(port: Option<String>) -> Result<ConfigModeConnection, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || {
+    config_proto::connect_config_mode(port.as_deref())
+  })
+  .await?
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn disconnect_config_mode() -> Result<(), HayboxError> {
+  blocking::run(QUERY_TIMEOUT, config_proto::disconnect_config_mode).await
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn get_config() -> Result<Config, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, config_proto::get_config).await?
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn set_config(config: Config) -> Result<(), HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || config_proto::set_config(&config)).await?
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn get_serial_ports_for_device(vid: u16, pid: u16) -> Result<Vec<DeviceSerialPort>, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || {
+    serial_ports::get_serial_ports_for_device(vid, pid)
+  })
+  .await?
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn start_input_monitor(
+  app_handle: tauri::AppHandle,
+  vid: Option<u16>,
+  pid: Option<u16>,
+) -> Result<InputMonitorInfo, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || {
+    input_monitor::start_input_monitor(&webview_events(&app_handle), vid, pid)
+  })
+  .await?
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn stop_input_monitor() -> Result<(), HayboxError> {
+  blocking::run(QUERY_TIMEOUT, input_monitor::stop_input_monitor).await
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn start_recording() -> Result<(), HayboxError> {
+  blocking::run(QUERY_TIMEOUT, input_recording::start_recording).await?
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn stop_recording(path: String) -> Result<InputRecording, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || {
+    input_recording::stop_recording(std::path::Path::new(&path))
+  })
+  .await?
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn load_recording(path: String) -> Result<InputRecording, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || {
+    input_recording::load_recording(std::path::Path::new(&path))
+  })
+  .await?
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn analyze_recording(path: String, options: Option<AnalysisOptions>) -> Result<Vec<InputFinding>, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || {
+    input_analysis::analyze_recording(std::path::Path::new(&path), &options.unwrap_or_default())
+  })
+  .await?
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn analyze_live_input(
+  window_ms: Option<u64>,
+  options: Option<AnalysisOptions>,
+) -> Result<Vec<InputFinding>, HayboxError> {
+  blocking::run(OPERATION_TIMEOUT, move || {
+    input_analysis::analyze_live_input(
+      window_ms.map(std::time::Duration::from_millis),
+      &options.unwrap_or_default(),
+    )
+  })
+  .await?
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn get_switch_health_report(debounce_window_us: Option<u64>) -> SwitchHealthReport {
+  switch_health::get_switch_health_report(debounce_window_us)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn start_frame_trainer(app_handle: tauri::AppHandle, config: TrainerConfig) -> Result<(), HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || {
+    frame_trainer::start_frame_trainer(&webview_events(&app_handle), config)
+  })
+  .await?
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn get_frame_trainer_report() -> Result<TrainerReport, HayboxError> {
+  frame_trainer::get_frame_trainer_report()
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn stop_frame_trainer() -> Result<TrainerReport, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, frame_trainer::stop_frame_trainer).await?
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn get_usage_stats(device: Option<String>) -> Result<Vec<UsageStats>, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || usage_stats::get_usage_stats(device.as_deref())).await?
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn reset_switch_health() {
+  switch_health::reset_switch_health()
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn start_socd_test(rules: Vec<SocdRule>) -> Result<SocdTestStatus, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || socd_test::start_socd_test(rules)).await?
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn check_socd_step() -> Result<SocdTestStatus, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, socd_test::check_socd_step).await?
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn cancel_socd_test() {
+  socd_test::cancel_socd_test()
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn start_analog_trace() {
+  analog_trace::start_analog_trace()
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn stop_analog_trace() {
+  analog_trace::stop_analog_trace()
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn export_trace(csv_path: String) -> Result<usize, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || {
+    analog_trace::export_trace(std::path::Path::new(&csv_path))
+  })
+  .await?
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn get_rulesets() -> Vec<Ruleset> {
+  coordinate_legality::get_rulesets()
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn check_coordinate_legality(source: CaptureSource, ruleset: Ruleset) -> Result<ComplianceReport, HayboxError> {
+  blocking::run(OPERATION_TIMEOUT, move || {
+    coordinate_legality::check_coordinate_legality(&source, &ruleset)
+  })
+  .await?
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn parse_report_descriptor(vid: u16, pid: u16) -> Result<ParsedDescriptor, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || {
+    report_descriptor::read_report_descriptor(vid, pid)
+  })
+  .await?
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn list_hid_devices() -> Result<Vec<HidDeviceEntry>, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, input_monitor::list_hid_devices).await?
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn start_comparison_session(path_a: String, path_b: String) -> Result<(), HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || {
+    input_comparison::start_comparison_session(&path_a, &path_b)
+  })
+  .await?
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn get_comparison_report() -> Result<ComparisonReport, HayboxError> {
+  input_comparison::get_comparison_report()
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn stop_comparison_session() -> Result<ComparisonReport, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, input_comparison::stop_comparison_session).await?
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn measure_polling_rate(window_ms: Option<u64>) -> Result<PollingRateResult, HayboxError> {
+  blocking::run(OPERATION_TIMEOUT, move || {
+    polling_rate::measure_polling_rate(window_ms.map(std::time::Duration::from_millis))
+  })
+  .await?
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn run_latency_test(
+  queue: tauri::State<'_, OperationQueue>,
+  samples: Option<u32>,
+  button: Option<u16>,
+  label: Option<String>,
+) -> Result<LatencyResult, HayboxError> {
+  let queue = queue.inner().clone();
+  blocking::run(OPERATION_TIMEOUT, move || {
+    queue.run("Latency test", || {
+      latency_test::run_latency_test(samples, button, label)
+    })
+  })
+  .await?
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn get_operations(app_handle: tauri::AppHandle) -> Vec<OperationInfo> {
+  app_handle.state::<OperationQueue>().list()
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn cancel_operation(queue: tauri::State<'_, OperationQueue>, id: u64) -> Result<(), HayboxError> {
+  queue.cancel(id)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn get_latency_results() -> Result<Vec<LatencyResult>, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, latency_test::get_latency_results).await
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn open_console(
+  app_handle: tauri::AppHandle,
+  port: Option<String>,
+  baud_rate: Option<u32>,
+) -> Result<String, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || {
+    serial_console::open_console(&webview_events(&app_handle), port.as_deref(), baud_rate)
+  })
+  .await?
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn close_console() -> Result<(), HayboxError> {
+  blocking::run(QUERY_TIMEOUT, serial_console::close_console).await
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn get_console_port() -> Option<String> {
+  serial_console::console_port()
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn set_console_filter(pattern: Option<String>) -> Result<(), HayboxError> {
+  serial_console::set_console_filter(pattern.as_deref())
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn get_console_lines() -> Vec<ConsoleLine> {
+  serial_console::get_console_lines()
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn clear_console() {
+  serial_console::clear_console()
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn save_console_log(path: String) -> Result<usize, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || {
+    serial_console::save_console_log(std::path::Path::new(&path))
+  })
+  .await?
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn set_protocol_trace(enabled: bool) {
+  protocol_trace::set_enabled(enabled)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn get_protocol_trace() -> Vec<TraceEntry> {
+  protocol_trace::get_protocol_trace()
+}
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct UsbDeviceInfo {
-  pub vid: u16,
-  pub pid: u16,
-  pub name: String,
+#[tauri::command(rename_all = "snake_case")]
+async fn clear_protocol_trace() {
+  protocol_trace::clear_protocol_trace()
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct DeviceIdentifiers {
-  pub default_mode: UsbDeviceInfo,
-  pub config_mode: UsbDeviceInfo,
-  pub bootsel_mode: UsbDeviceInfo,
-  pub switch_mode: UsbDeviceInfo,
-  pub gamecube_mode: UsbDeviceInfo,
+#[tauri::command(rename_all = "snake_case")]
+async fn try_config(
+  app_handle: tauri::AppHandle,
+  config: Config,
+  timeout_secs: Option<u64>,
+) -> Result<(), HayboxError> {
+  blocking::run(OPERATION_TIMEOUT, move || {
+    config_trial::try_config(
+      &webview_events(&app_handle),
+      &config,
+      timeout_secs.map(std::time::Duration::from_secs),
+    )
+  })
+  .await?
 }
 
-#[derive(Debug)]
-pub struct Config {
-  pub vendor_id: u16,
-  pub product_id: u16,
-  pub description: String,
-  pub manufacturer: String,
+#[tauri::command(rename_all = "snake_case")]
+async fn confirm_config_trial(app_handle: tauri::AppHandle) -> Result<(), HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || {
+    config_trial::confirm_config_trial(&webview_events(&app_handle))
+  })
+  .await?
 }
 
-impl Config {
-  pub fn prepare_driver(&self) -> Result<(), PrepareDriverError> {
-    if !check_admin_rights() {
-      return Err(PrepareDriverError::PermissionDenied);
-    }
+#[tauri::command(rename_all = "snake_case")]
+async fn cancel_config_trial(app_handle: tauri::AppHandle) -> Result<(), HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || {
+    config_trial::cancel_config_trial(&webview_events(&app_handle))
+  })
+  .await?
+}
 
-    let temp_dir = match std::env::temp_dir().join("haybox_drivers") {
-      path => {
-        if !path.exists() {
-          std::fs::create_dir_all(&path)
-            .map_err(|e| PrepareDriverError::UnknownError(format!("Failed to create temp directory: {}", e)))?;
-        }
-        path
-      }
-    };
+#[tauri::command(rename_all = "snake_case")]
+async fn apply_config_to_all(
+  config: Config,
+  filter: Option<DeviceFilter>,
+) -> Result<Vec<DeviceApplyResult>, HayboxError> {
+  blocking::run(OPERATION_TIMEOUT, move || {
+    bulk_apply::apply_config_to_all(&config, &filter.unwrap_or_default())
+  })
+  .await?
+}
 
-    let exe_dir = std::env::current_exe()
-      .map_err(|e| PrepareDriverError::UnknownError(format!("Could not find executable path: {}", e)))?
-      .parent()
-      .ok_or_else(|| PrepareDriverError::UnknownError("Could not find executable parent directory".to_string()))?
-      .to_path_buf();
+#[tauri::command(rename_all = "snake_case")]
+async fn validate_config_for_device(config: Config) -> Result<Vec<ConfigViolation>, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || capabilities::validate_config_for_device(&config)).await?
+}
 
-    let driver_resource_path = exe_dir.join("driver_resources");
-    if !driver_resource_path.exists() {
-      return Err(PrepareDriverError::DriverNotFound);
-    }
+#[tauri::command(rename_all = "snake_case")]
+async fn export_config(path: String) -> Result<ConfigFile, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || {
+    config_file::export_config(std::path::Path::new(&path))
+  })
+  .await?
+}
 
-    let inf_template_path = driver_resource_path.join("winusb_template.inf");
-    if !inf_template_path.exists() {
-      return Err(PrepareDriverError::DriverNotFound);
-    }
+#[tauri::command(rename_all = "snake_case")]
+async fn import_config(path: String) -> Result<ConfigFile, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || {
+    config_file::import_config(std::path::Path::new(&path))
+  })
+  .await?
+}
 
-    let template_content = std::fs::read_to_string(&inf_template_path)
-      .map_err(|e| PrepareDriverError::UnknownError(format!("Failed to read INF template: {}", e)))?;
-
-    let inf_content = template_content
-      .replace("{{VID}}", &format!("{:04X}", self.vendor_id))
-      .replace("{{PID}}", &format!("{:04X}", self.product_id))
-      .replace("{{DESCRIPTION}}", &self.description)
-      .replace("{{MANUFACTURER}}", &self.manufacturer);
-
-    let inf_path = temp_dir.join("winusb_driver.inf");
-    std::fs::write(&inf_path, inf_content)
-      .map_err(|e| PrepareDriverError::UnknownError(format!("Failed to write INF file: {}", e)))?;
-
-    for file_name in ["WinUSBCoInstaller2.dll", "WdfCoInstaller01011.dll"] {
-      let source_path = driver_resource_path.join(file_name);
-      if source_path.exists() {
-        let target_path = temp_dir.join(file_name);
-        std::fs::copy(&source_path, &target_path)
-          .map_err(|e| PrepareDriverError::UnknownError(format!("Failed to copy {}: {}", file_name, e)))?;
-      } else {
-        return Err(PrepareDriverError::DriverNotFound);
-      }
-    }
+#[tauri::command(rename_all = "snake_case")]
+async fn list_config_backups() -> Result<Vec<ConfigBackup>, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, config_backup::list_config_backups).await?
+}
 
-    Ok(())
-  }
-  
-  pub fn install_driver(&self) -> Result<(), String> {
-    if !check_admin_rights() {
-      return Err("Administrator privileges required".to_string());
-    }
+#[tauri::command(rename_all = "snake_case")]
+async fn restore_config_backup(id: String) -> Result<ConfigFile, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || config_backup::restore_config_backup(&id)).await?
+}
 
-    let temp_dir = std::env::temp_dir().join("haybox_drivers");
-    let inf_path = temp_dir.join("winusb_driver.inf");
-    
-    if !inf_path.exists() {
-      return Err("Driver INF file not found. Did you call prepare_driver first?".to_string());
-    }
+#[tauri::command(rename_all = "snake_case")]
+async fn publish_config(source: ConfigSource, target: ShareTarget) -> Result<SharedLayout, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || layout_share::publish_config(&source, &target)).await?
+}
 
-    let inf_path_str = inf_path.to_string_lossy().to_string();
-    
-    let output = Command::new("pnputil")
-      .args(&["/add-driver", &inf_path_str, "/install"])
-      .output()
-      .map_err(|e| format!("Failed to execute pnputil: {}", e))?;
+#[tauri::command(rename_all = "snake_case")]
+async fn fetch_shared_config(reference: String) -> Result<ConfigFile, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || layout_share::fetch_shared_config(&reference)).await?
+}
 
-    if !output.status.success() {
-      let error_message = String::from_utf8_lossy(&output.stderr);
-      return Err(format!("pnputil failed: {}", error_message));
-    }
+#[tauri::command(rename_all = "snake_case")]
+async fn diff_configs(a: ConfigSource, b: ConfigSource) -> Result<Vec<ConfigChange>, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || config_diff::diff_configs(&a, &b)).await?
+}
 
-    let exe_dir = std::env::current_exe()
-      .map_err(|e| format!("Could not find executable path: {}", e))?
-      .parent()
-      .ok_or_else(|| "Could not find executable parent directory".to_string())?
-      .to_path_buf();
-    
-    let devcon_path = exe_dir.join("driver_resources").join("devcon.exe");
-    
-    if devcon_path.exists() {
-      let hw_id = format!("USB\\VID_{:04X}&PID_{:04X}", self.vendor_id, self.product_id);
-      
-      let devcon_result = Command::new(&devcon_path)
-        .args(&["update", &inf_path_str, &hw_id])
-        .output();
-        
-      if let Err(e) = devcon_result {
-        println!("Warning: devcon failed: {}", e);
-      }
-    }
+#[tauri::command(rename_all = "snake_case")]
+async fn apply_config_patch(changes: Vec<ConfigChange>) -> Result<Config, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || config_diff::apply_config_patch(&changes)).await?
+}
 
-    Ok(())
-  }
+#[tauri::command(rename_all = "snake_case")]
+async fn set_default_mode(
+  mode: GameModeId,
+  backend: Option<CommunicationBackendId>,
+) -> Result<DefaultModeResult, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || config_proto::set_default_mode(mode, backend)).await?
 }
 
-pub struct ConfigBuilder {
-  vendor_id: u16,
-  product_id: u16,
-  description: String,
-  manufacturer: String,
+#[tauri::command(rename_all = "snake_case")]
+async fn list_profiles() -> Result<Vec<ControllerProfile>, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, controller_profiles::list_profiles).await?
 }
 
-impl ConfigBuilder {
-  pub fn new() -> Self {
-    Self {
-      vendor_id: 0,
-      product_id: 0,
-      description: String::new(),
-      manufacturer: String::new(),
-    }
-  }
-  
-  pub fn vendor_id(mut self, vendor_id: u16) -> Self {
-    self.vendor_id = vendor_id;
-    self
-  }
-  
-  pub fn product_id(mut self, product_id: u16) -> Self {
-    self.product_id = product_id;
-    self
-  }
-  
-  pub fn description(mut self, description: &str) -> Self {
-    self.description = description.to_string();
-    self
-  }
-  
-  pub fn manufacturer(mut self, manufacturer: &str) -> Self {
-    self.manufacturer = manufacturer.to_string();
-    self
-  }
-  
-  pub fn build(self) -> Config {
-    Config {
-      vendor_id: self.vendor_id,
-      product_id: self.product_id,
-      description: self.description,
-      manufacturer: self.manufacturer,
-    }
-  }
+#[tauri::command(rename_all = "snake_case")]
+async fn create_profile(
+  name: String,
+  mode: GameModeId,
+  activation_binding: Option<Vec<Button>>,
+) -> Result<Vec<ControllerProfile>, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || {
+    controller_profiles::create_profile(&name, mode, &activation_binding.unwrap_or_default())
+  })
+  .await?
 }
 
-#[derive(Debug)]
-pub enum PrepareDriverError {
-  DriverNotFound,
-  PermissionDenied,
-  UnknownError(String),
+#[tauri::command(rename_all = "snake_case")]
+async fn rename_profile(profile: u32, name: String) -> Result<Vec<ControllerProfile>, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || {
+    controller_profiles::rename_profile(profile, &name)
+  })
+  .await?
 }
 
-impl std::fmt::Display for PrepareDriverError {
-  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-    match self {
-      PrepareDriverError::DriverNotFound => write!(f, "Driver files not found"),
-      PrepareDriverError::PermissionDenied => write!(f, "Permission denied"),
-      PrepareDriverError::UnknownError(e) => write!(f, "Unknown error: {}", e),
-    }
-  }
+#[tauri::command(rename_all = "snake_case")]
+async fn delete_profile(profile: u32) -> Result<Vec<ControllerProfile>, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || controller_profiles::delete_profile(profile)).await?
 }
 
-impl std::error::Error for PrepareDriverError {}
+#[tauri::command(rename_all = "snake_case")]
+async fn set_active_profile(profile: u32) -> Result<Vec<ControllerProfile>, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || controller_profiles::set_active_profile(profile)).await?
+}
 
-lazy_static::lazy_static! {
-  static ref DEVICES: DeviceIdentifiers = DeviceIdentifiers {
-    default_mode: UsbDeviceInfo {
-      vid: 0x0738,
-      pid: 0x4726,
-      name: "Default Mode".to_string(),
-    },
-    config_mode: UsbDeviceInfo {
-      vid: 0x2E8A,
-      pid: 0x000A,
-      name: "Config Mode".to_string(),
-    },
-    bootsel_mode: UsbDeviceInfo {
-      vid: 0x2E8A,
-      pid: 0x0003,
-      name: "BOOTSEL Mode".to_string(),
-    },
-    switch_mode: UsbDeviceInfo {
-      vid: 0x0F0D,
-      pid: 0x0092,
-      name: "Switch Mode".to_string(),
-    },
-    gamecube_mode: UsbDeviceInfo {
-      vid: 0x057E,
-      pid: 0x0337,
-      name: "GameCube Adapter".to_string(),
-    }
-  };
+#[tauri::command(rename_all = "snake_case")]
+async fn get_profile_snapshot() -> Result<Option<ProfileSnapshot>, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, controller_profiles::get_profile_snapshot).await?
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
-pub struct DeviceStatus {
-  default_mode_connected: bool,
-  config_mode_connected: bool,
-  bootsel_mode_connected: bool,
-  switch_mode_connected: bool,
-  xinput_installed: bool,
-  gamecube_adapter_connected: bool,
-  winusb_installed: bool,
+#[tauri::command(rename_all = "snake_case")]
+async fn restore_profile_snapshot() -> Result<Vec<ControllerProfile>, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, controller_profiles::restore_profile_snapshot).await?
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct DriverOperationResult {
-  success: bool,
-  message: String,
+#[tauri::command(rename_all = "snake_case")]
+async fn get_keyboard_map(profile: u32) -> Result<KeyboardMap, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || keyboard_map::get_keyboard_map(profile)).await?
 }
 
 #[tauri::command(rename_all = "snake_case")]
-fn get_device_identifiers() -> DeviceIdentifiers {
-  DEVICES.clone()
+async fn set_keyboard_map(
+  profile: u32,
+  mappings: Vec<KeyMapping>,
+  allow_conflicts: Option<bool>,
+) -> Result<KeyboardMap, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || {
+    keyboard_map::set_keyboard_map(profile, &mappings, allow_conflicts.unwrap_or(false))
+  })
+  .await?
 }
 
-fn is_device_connected_batch(devices_to_check: &[(u16, u16)]) -> Vec<bool> {
-  match rusb::Context::new() {
-    Ok(context) => match context.devices() {
-      Ok(device_list) => {
-        let mut results = vec![false; devices_to_check.len()];
+#[tauri::command(rename_all = "snake_case")]
+async fn get_lighting(profile: u32) -> Result<LightingSettings, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || lighting::get_lighting(profile)).await?
+}
 
-        for device in device_list.iter() {
-          if let Ok(device_desc) = device.device_descriptor() {
-            for (i, &(vendor_id, product_id)) in devices_to_check.iter().enumerate() {
-              if device_desc.vendor_id() == vendor_id && device_desc.product_id() == product_id {
-                results[i] = true;
-              }
-            }
-          }
-        }
-        results
-      }
-      Err(_) => vec![false; devices_to_check.len()],
-    },
-    Err(_) => vec![false; devices_to_check.len()],
-  }
+#[tauri::command(rename_all = "snake_case")]
+async fn set_lighting(profile: u32, lighting: LightingSettings) -> Result<LightingSettings, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || lighting::set_lighting(profile, &lighting)).await?
 }
 
-fn get_current_device_status() -> Result<DeviceStatus, Box<dyn std::error::Error>> {
-  let devices_to_check = [
-    (DEVICES.default_mode.vid, DEVICES.default_mode.pid),
-    (DEVICES.config_mode.vid, DEVICES.config_mode.pid),
-    (DEVICES.bootsel_mode.vid, DEVICES.bootsel_mode.pid),
-    (DEVICES.switch_mode.vid, DEVICES.switch_mode.pid),
-  ];
-
-  let connected = is_device_connected_batch(&devices_to_check);
-  let xinput_installed = is_xinput_installed();
-  let winusb_installed = check_winusb_driver(DEVICES.gamecube_mode.vid, DEVICES.gamecube_mode.pid)?;
-  let gamecube_adapter_connected = match rusb::Context::new() {
-    Ok(context) => match context.devices() {
-      Ok(device_list) => device_list.iter().any(|device| {
-        if let Ok(device_desc) = device.device_descriptor() {
-          device_desc.vendor_id() == DEVICES.gamecube_mode.vid && device_desc.product_id() == DEVICES.gamecube_mode.pid
-        } else {
-          false
-        }
-      }),
-      Err(_) => false,
-    },
-    Err(_) => false,
-  };
+#[tauri::command(rename_all = "snake_case")]
+async fn get_button_mappings(profile: u32) -> Result<ProfileMappings, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || button_mapping::get_button_mappings(profile)).await?
+}
 
-  Ok(DeviceStatus {
-    default_mode_connected: connected[0],
-    config_mode_connected: connected[1],
-    bootsel_mode_connected: connected[2],
-    switch_mode_connected: connected[3],
-    xinput_installed,
-    gamecube_adapter_connected,
-    winusb_installed,
+#[tauri::command(rename_all = "snake_case")]
+async fn set_button_mapping(profile: u32, physical: Button, logical: Button) -> Result<ProfileMappings, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || {
+    button_mapping::set_button_mapping(profile, physical, logical)
   })
+  .await?
 }
 
 #[tauri::command(rename_all = "snake_case")]
-async fn get_device_status() -> DeviceStatus {
-  get_current_device_status().unwrap_or(DeviceStatus {
-    default_mode_connected: false,
-    config_mode_connected: false,
-    bootsel_mode_connected: false,
-    switch_mode_connected: false,
-    xinput_installed: false,
-    gamecube_adapter_connected: false,
-    winusb_installed: false,
+async fn test_gamecube_adapter() -> Result<AdapterTestResult, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, gamecube_adapter::test_adapter).await?
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn start_adapter_monitor(app_handle: tauri::AppHandle) -> Result<(), HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || {
+    gamecube_adapter::start_adapter_monitor(&webview_events(&app_handle))
   })
+  .await?
 }
 
 #[tauri::command(rename_all = "snake_case")]
-fn uninstall_xinput() -> DriverOperationResult {
-  match uninstall_xinput_driver() {
-    Ok(_) => DriverOperationResult {
-      success: true,
-      message: "XInput driver successfully uninstalled".to_string(),
-    },
-    Err(e) => DriverOperationResult {
-      success: false,
-      message: format!("Failed to uninstall XInput driver: {}", e),
-    },
-  }
+async fn stop_adapter_monitor() -> Result<(), HayboxError> {
+  blocking::run(QUERY_TIMEOUT, gamecube_adapter::stop_adapter_monitor).await
 }
 
 #[tauri::command(rename_all = "snake_case")]
-fn reinstall_xinput(_app_handle: tauri::AppHandle) -> DriverOperationResult {
-  match reinstall_xinput_driver() {
-    Ok(_) => DriverOperationResult {
-      success: true,
-      message: "XInput driver successfully reinstalled".to_string(),
-    },
-    Err(e) => DriverOperationResult {
-      success: false,
-      message: format!("Failed to reinstall XInput driver: {}", e),
-    },
-  }
+async fn test_rumble(vid: u16, pid: u16, pattern: Option<RumblePattern>) -> Result<RumbleResult, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || rumble::test_rumble(vid, pid, pattern)).await?
 }
 
 #[tauri::command(rename_all = "snake_case")]
-fn install_winusb() -> DriverOperationResult {
-  if !check_admin_rights() {
-    return DriverOperationResult {
-      success: false,
-      message: "Administrator privileges required".to_string(),
-    };
-  }
+async fn get_game_controller_order() -> Result<Vec<ControllerSlot>, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, game_controllers::list_controller_order).await
+}
 
-  let gamecube_mode = &DEVICES.gamecube_mode;
-  let is_connected = match rusb::Context::new() {
-    Ok(context) => match context.devices() {
-      Ok(device_list) => device_list.iter().any(|device| {
-        if let Ok(device_desc) = device.device_descriptor() {
-          device_desc.vendor_id() == gamecube_mode.vid && device_desc.product_id() == gamecube_mode.pid
-        } else {
-          false
-        }
-      }),
-      Err(_) => false,
-    },
-    Err(_) => false,
-  };
+#[tauri::command(rename_all = "snake_case")]
+async fn set_preferred_game_controller(vid: u16, pid: u16) -> Result<Vec<ControllerSlot>, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || {
+    game_controllers::set_preferred_controller(vid, pid)
+  })
+  .await?
+}
 
-  if !is_connected {
-    return DriverOperationResult {
-      success: false,
-      message: "GameCube adapter not found. Please make sure it is connected and in the correct mode.".to_string(),
-    };
-  }
+#[tauri::command(rename_all = "snake_case")]
+async fn create_game_profile(exe_path: String, hide_xinput: bool) -> Result<GameProfile, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || {
+    game_profiles::create_profile(&exe_path, hide_xinput)
+  })
+  .await?
+}
 
-  let config = ConfigBuilder::new()
-    .vendor_id(gamecube_mode.vid)
-    .product_id(gamecube_mode.pid)
-    .description(&gamecube_mode.name)
-    .manufacturer("Nintendo")
-    .build();
+#[tauri::command(rename_all = "snake_case")]
+async fn list_game_profiles() -> Result<Vec<GameProfile>, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, game_profiles::load_profiles).await
+}
 
-  match install_winusb_driver(&config) {
-    Ok(_) => DriverOperationResult {
-      success: true,
-      message: "WinUSB driver successfully installed for GameCube adapter".to_string(),
-    },
-    Err(e) => DriverOperationResult {
-      success: false,
-      message: format!("Failed to install WinUSB driver: {}", e),
-    },
-  }
+#[tauri::command(rename_all = "snake_case")]
+async fn delete_game_profile(exe_path: String) -> Result<(), HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || game_profiles::delete_profile(&exe_path)).await?
 }
 
-fn install_winusb_driver(config: &Config) -> Result<(), String> {
-  match config.prepare_driver() {
-    Ok(_) => match config.install_driver() {
-      Ok(_) => Ok(()),
-      Err(e) => Err(format!("Failed to install driver: {}", e)),
-    },
-    Err(PrepareDriverError::DriverNotFound) => Err("WinUSB driver files not found".to_string()),
-    Err(e) => Err(format!("Failed to prepare driver: {}", e)),
-  }
+#[tauri::command(rename_all = "snake_case")]
+async fn install_winusb(app_handle: tauri::AppHandle) -> DriverOperationResult {
+  let queue = app_handle.state::<OperationQueue>().inner().clone();
+  blocking::run(OPERATION_TIMEOUT, move || {
+    queue.run("Installing the WinUSB driver for the GameCube adapter", || {
+      run_install_winusb()
+    })
+  })
+  .await
+  .unwrap_or_else(DriverOperationResult::failed)
 }
 
-fn is_xinput_installed() -> bool {
-  let system32_path = std::env::var("SystemRoot")
-    .map(|root| PathBuf::from(root).join("System32"))
-    .unwrap_or_else(|_| PathBuf::from("C:\\Windows\\System32"));
+#[tauri::command(rename_all = "snake_case")]
+async fn install_driver_for(
+  app_handle: tauri::AppHandle,
+  kind: DriverKind,
+  vid: u16,
+  pid: u16,
+  interface: Option<u8>,
+) -> DriverOperationResult {
+  let queue = app_handle.state::<OperationQueue>().inner().clone();
+  blocking::run(OPERATION_TIMEOUT, move || {
+    queue.run(
+      &format!("Installing the {} driver for {:04X}:{:04X}", kind, vid, pid),
+      || run_install_driver_for(kind, vid, pid, interface),
+    )
+  })
+  .await
+  .unwrap_or_else(DriverOperationResult::failed)
+}
 
-  let xinput_path = system32_path.join("xinput1_4.dll");
-  xinput_path.exists()
+/// Installs WinUSB for each device in `devices`, per interface for composite
+/// devices, and reports the result of each device individually. Devices that
+/// are not connected bind when they are next plugged in.
+#[tauri::command(rename_all = "snake_case")]
+async fn install_winusb_batch(app_handle: tauri::AppHandle, devices: Vec<BatchDevice>) -> DriverOperationResult {
+  let queue = app_handle.state::<OperationQueue>().inner().clone();
+  blocking::run(OPERATION_TIMEOUT, move || {
+    queue.run(
+      &format!("Installing the WinUSB driver for {} devices", devices.len()),
+      || run_install_winusb_batch(devices),
+    )
+  })
+  .await
+  .unwrap_or_else(DriverOperationResult::failed)
 }
 
-fn check_admin_rights() -> bool {
-  if let Ok(output) = Command::new("net").args(["session"]).output() {
-    output.status.success()
-  } else {
-    false
-  }
+#[tauri::command(rename_all = "snake_case")]
+async fn restore_default_driver(app_handle: tauri::AppHandle, vid: u16, pid: u16) -> DriverOperationResult {
+  let queue = app_handle.state::<OperationQueue>().inner().clone();
+  blocking::run(OPERATION_TIMEOUT, move || {
+    queue.run(
+      &format!("Restoring the default driver for {:04X}:{:04X}", vid, pid),
+      || run_restore_default_driver(vid, pid),
+    )
+  })
+  .await
+  .unwrap_or_else(DriverOperationResult::failed)
 }
 
-fn uninstall_xinput_driver() -> Result<(), String> {
-  if !check_admin_rights() {
-    return Err("Administrator privileges required".to_string());
-  }
+#[tauri::command(rename_all = "snake_case")]
+async fn list_installed_haybox_drivers() -> Result<Vec<DriverStoreEntry>, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, driver_store::list_haybox_drivers).await?
+}
 
-  let system32_path = std::env::var("SystemRoot")
-    .map(|root| PathBuf::from(root).join("System32"))
-    .unwrap_or_else(|_| PathBuf::from("C:\\Windows\\System32"));
+#[tauri::command(rename_all = "snake_case")]
+async fn remove_stale_drivers() -> DriverOperationResult {
+  blocking::run(OPERATION_TIMEOUT, run_remove_stale_drivers)
+    .await
+    .unwrap_or_else(DriverOperationResult::failed)
+}
 
-  let xinput_path = system32_path.join("xinput1_4.dll");
+#[tauri::command(rename_all = "snake_case")]
+async fn clean_driver_cache() -> DriverOperationResult {
+  blocking::run(OPERATION_TIMEOUT, run_clean_driver_cache)
+    .await
+    .unwrap_or_else(DriverOperationResult::failed)
+}
 
-  if xinput_path.exists() {
-    let backup_path = xinput_path.with_extension("dll.bak");
-    std::fs::rename(&xinput_path, &backup_path).map_err(|e| format!("Failed to rename xinput1_4.dll: {}", e))?;
-  }
+#[tauri::command(rename_all = "snake_case")]
+async fn get_inf_template(kind: DriverKind) -> Result<InfTemplate, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || -> Result<InfTemplate, HayboxError> {
+    inf_template::load_template(kind)?.ok_or_else(|| HayboxError::Driver(format!("No INF template found for {}", kind)))
+  })
+  .await?
+}
 
-  Ok(())
+#[tauri::command(rename_all = "snake_case")]
+async fn set_inf_template(kind: DriverKind, content: String) -> Result<(), HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || inf_template::save_template(kind, &content)).await?
 }
 
-fn reinstall_xinput_driver() -> Result<(), String> {
-  if !check_admin_rights() {
-    return Err("Administrator privileges required".to_string());
-  }
+#[tauri::command(rename_all = "snake_case")]
+async fn reset_inf_template(kind: DriverKind) -> Result<(), HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || inf_template::reset_template(kind)).await?
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn verify_driver_resources(kind: Option<DriverKind>) -> Result<Vec<ResourceVerification>, HayboxError> {
+  blocking::run(
+    QUERY_TIMEOUT,
+    move || -> Result<Vec<ResourceVerification>, HayboxError> {
+      let resource_dir = resources::driver_resource_dir()
+        .ok_or_else(|| HayboxError::Driver("Driver resources not found".to_string()))?;
+
+      let config = ConfigBuilder::new()
+        .kind(kind.unwrap_or(DriverKind::WinUsb))
+        .allow_unverified_resources(true)
+        .build();
+
+      config.verify_resources(&resource_dir).map_err(HayboxError::from)
+    },
+  )
+  .await?
+}
 
-  let system32_path = std::env::var("SystemRoot")
-    .map(|root| PathBuf::from(root).join("System32"))
-    .unwrap_or_else(|_| PathBuf::from("C:\\Windows\\System32"));
+#[tauri::command(rename_all = "snake_case")]
+async fn elevate_and_run(app_handle: tauri::AppHandle, operation: ElevatedOperation) -> DriverOperationResult {
+  blocking::run(OPERATION_TIMEOUT, move || {
+    if check_admin_rights() {
+      return DriverOperationResult {
+        success: false,
+        message: "Already running with administrator privileges; call the operation directly".to_string(),
+        ..Default::default()
+      };
+    }
 
-  let xinput_path = system32_path.join("xinput1_4.dll");
+    // The helper runs in its own process, so cancelling only helps while the
+    // operation is still queued.
+    app_handle
+      .state::<OperationQueue>()
+      .run(
+        "Running an operation as administrator",
+        || match elevation::elevate_and_run(&operation) {
+          Ok(result) => result,
+          Err(e) => DriverOperationResult::failed(e),
+        },
+      )
+  })
+  .await
+  .unwrap_or_else(DriverOperationResult::failed)
+}
 
-  let exe_dir = std::env::current_exe()
-    .map_err(|e| format!("Could not find executable path: {}", e))?
-    .parent()
-    .ok_or_else(|| "Could not find executable parent directory".to_string())?
-    .to_path_buf();
+#[tauri::command(rename_all = "snake_case")]
+async fn list_replaceable_devices() -> Result<Vec<ReplaceableDevice>, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, pnp::list_replaceable_devices).await?
+}
 
-  let source_dll = exe_dir.join("XInput1_4.dll");
+#[tauri::command(rename_all = "snake_case")]
+async fn replace_driver(device_instance_id: String, kind: DriverKind) -> DriverOperationResult {
+  blocking::run(OPERATION_TIMEOUT, move || run_replace_driver(device_instance_id, kind))
+    .await
+    .unwrap_or_else(DriverOperationResult::failed)
+}
 
-  std::fs::copy(&source_dll, &xinput_path).map_err(|e| format!("Failed to copy xinput1_4.dll: {}", e))?;
+#[tauri::command(rename_all = "snake_case")]
+async fn get_driver_audit_log(limit: Option<usize>) -> Result<Vec<AuditEntry>, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || audit::read_audit_log(limit.unwrap_or(200))).await
+}
 
-  Ok(())
+#[tauri::command(rename_all = "snake_case")]
+async fn get_environment_warnings() -> Result<Vec<EnvironmentWarning>, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, environment::collect_environment_warnings).await
 }
 
-#[derive(Debug, Deserialize)]
-struct WmiPnPEntity {
-  #[serde(rename = "DriverProvider")]
-  driver_provider: Option<String>,
+#[tauri::command(rename_all = "snake_case")]
+async fn get_last_crash_report() -> Result<Option<CrashReport>, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, crash::get_last_crash_report).await?
 }
 
-fn check_winusb_driver(vendor_id: u16, product_id: u16) -> Result<bool, Box<dyn std::error::Error>> {
-  let is_connected = match rusb::Context::new() {
-    Ok(context) => match context.devices() {
-      Ok(device_list) => device_list.iter().any(|device| {
-        if let Ok(device_desc) = device.device_descriptor() {
-          device_desc.vendor_id() == vendor_id && device_desc.product_id() == product_id
-        } else {
-          false
-        }
-      }),
-      Err(_) => false,
-    },
-    Err(_) => false,
-  };
+#[tauri::command(rename_all = "snake_case")]
+async fn get_telemetry_status() -> TelemetryStatus {
+  telemetry::status()
+}
 
-  if !is_connected {
-    return Ok(false);
-  }
+#[tauri::command(rename_all = "snake_case")]
+async fn set_telemetry_settings(enabled: bool, endpoint: Option<String>) -> Result<TelemetryStatus, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || telemetry::set_settings(enabled, endpoint)).await?
+}
 
-  let wmi_con = unsafe { wmi::COMLibrary::assume_initialized() };
-  
-  let wmi_connection = wmi::WMIConnection::new(wmi_con)
-    .map_err(|e| format!("Failed to initialize WMI: {}", e))?;
+#[tauri::command(rename_all = "snake_case")]
+async fn run_doctor() -> Result<DoctorReport, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, doctor::run_doctor).await
+}
 
-  let query = format!(
-    "SELECT DeviceID, DriverProvider FROM Win32_PnPEntity WHERE DeviceID LIKE '%VID\\_{0:04X}%' AND DeviceID LIKE '%PID\\_{1:04X}%'",
-    vendor_id, product_id
-  );
+#[tauri::command(rename_all = "snake_case")]
+async fn get_architecture_info() -> Result<ArchitectureInfo, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, architecture::architecture_info).await
+}
 
-  println!("Executing WMI query: {}", query);
-  let devices: Vec<WmiPnPEntity> = wmi_connection.raw_query(&query)?;
+#[tauri::command(rename_all = "snake_case")]
+async fn get_pending_actions() -> Result<Vec<PendingAction>, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, pending::load_pending_actions).await
+}
 
-  for device in devices {
-    if let Some(driver_provider) = device.driver_provider {
-      if driver_provider.contains("WinUSB") {
-        return Ok(true);
-      }
+#[tauri::command(rename_all = "snake_case")]
+async fn reboot_now(app_handle: tauri::AppHandle, confirmed: bool) -> DriverOperationResult {
+  blocking::run(OPERATION_TIMEOUT, move || {
+    if !confirmed {
+      return DriverOperationResult {
+        success: false,
+        message: "Reboot not confirmed".to_string(),
+        ..Default::default()
+      };
     }
+
+    app_handle
+      .state::<OperationQueue>()
+      .run("Scheduling a reboot", schedule_reboot)
+  })
+  .await
+  .unwrap_or_else(DriverOperationResult::failed)
+}
+
+/// Only driver installs ask for a reboot, and those only exist on Windows.
+#[cfg(not(target_os = "windows"))]
+fn schedule_reboot() -> DriverOperationResult {
+  DriverOperationResult::unsupported("Restarting to finish a driver install")
+}
+
+#[cfg(target_os = "windows")]
+fn schedule_reboot() -> DriverOperationResult {
+  match haybox_core::operations::output(Command::new("shutdown").args([
+    "/r",
+    "/t",
+    "5",
+    "/c",
+    "HayBox Debugger is restarting Windows to finish installing drivers",
+  ])) {
+    Ok(output) if output.status.success() => DriverOperationResult {
+      success: true,
+      message: "Windows will restart in a few seconds".to_string(),
+      ..Default::default()
+    },
+    Ok(output) => DriverOperationResult {
+      success: false,
+      message: format!(
+        "Failed to schedule reboot: {}",
+        String::from_utf8_lossy(&output.stderr).trim()
+      ),
+      ..Default::default()
+    },
+    Err(e) => DriverOperationResult {
+      success: false,
+      message: format!("Failed to execute shutdown: {}", e),
+      ..Default::default()
+    },
   }
+}
 
-  Ok(false)
+#[tauri::command(rename_all = "snake_case")]
+async fn get_privilege_status() -> Result<PrivilegeStatus, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, privileges::get_privilege_status).await
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct DriverInfo {
-  device_id: String,
-  device_name: String,
-  driver_provider: Option<String>,
-  driver_version: Option<String>,
-  driver_date: Option<String>,
-  is_winusb: bool,
+#[tauri::command(rename_all = "snake_case")]
+async fn get_platform_capabilities() -> PlatformCapabilities {
+  platform::current().capabilities().with_commands(COMMANDS)
 }
 
 #[tauri::command(rename_all = "snake_case")]
-fn get_driver_info(vendor_id: Option<u16>, product_id: Option<u16>) -> Result<Vec<DriverInfo>, String> {
-  if let (Some(vid), Some(pid)) = (vendor_id, product_id) {
-    let is_connected = match rusb::Context::new() {
-      Ok(context) => match context.devices() {
-        Ok(device_list) => device_list.iter().any(|device| {
-          if let Ok(device_desc) = device.device_descriptor() {
-            device_desc.vendor_id() == vid && device_desc.product_id() == pid
-          } else {
-            false
-          }
-        }),
-        Err(_) => false,
-      },
-      Err(_) => false,
-    };
-
-    if !is_connected {
-      return Ok(vec![]);
-    }
-  }
+async fn get_driver_info(vendor_id: Option<u16>, product_id: Option<u16>) -> Result<Vec<DriverInfo>, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || driver_cache::driver_info(vendor_id, product_id)).await?
+}
 
-  let wmi_con = unsafe { wmi::COMLibrary::assume_initialized() };
-  
-  let wmi_connection = match wmi::WMIConnection::new(wmi_con) {
-    Ok(conn) => conn,
-    Err(e) => return Err(format!("Failed to initialize WMI: {}", e)),
-  };
+/// Re-reads driver details instead of waiting for the cache to expire.
+/// `force` also discards lookups that are still fresh.
+#[tauri::command(rename_all = "snake_case")]
+async fn refresh_driver_info(force: Option<bool>) -> Result<Vec<DriverInfo>, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || driver_cache::refresh(force.unwrap_or(false))).await?
+}
 
-  let query = match (vendor_id, product_id) {
-    (Some(vid), Some(pid)) => format!(
-      "SELECT DeviceID, Name, DriverProvider, DriverVersion, DriverDate FROM Win32_PnPEntity WHERE DeviceID LIKE '%VID_{0:04X}%' AND DeviceID LIKE '%PID_{1:04X}%'",
-      vid, pid
-    ),
-    (Some(vid), None) => format!(
-      "SELECT DeviceID, Name, DriverProvider, DriverVersion, DriverDate FROM Win32_PnPEntity WHERE DeviceID LIKE '%VID_{0:04X}%'",
-      vid
-    ),
-    (None, Some(pid)) => format!(
-      "SELECT DeviceID, Name, DriverProvider, DriverVersion, DriverDate FROM Win32_PnPEntity WHERE DeviceID LIKE '%PID_{0:04X}%'",
-      pid
-    ),
-    (None, None) => {
-      "SELECT DeviceID, Name, DriverProvider, DriverVersion, DriverDate FROM Win32_PnPEntity WHERE DeviceID LIKE '%USB%'".to_string()
-    }
-  };
+#[tauri::command(rename_all = "snake_case")]
+async fn get_udev_status() -> Result<UdevStatus, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, udev::udev_status).await
+}
 
-  #[derive(Debug, Deserialize)]
-  struct WmiDeviceInfo {
-    #[serde(rename = "DeviceID")]
-    device_id: String,
-    #[serde(rename = "Name")]
-    name: Option<String>,
-    #[serde(rename = "DriverProvider")]
-    driver_provider: Option<String>,
-    #[serde(rename = "DriverVersion")]
-    driver_version: Option<String>,
-    #[serde(rename = "DriverDate")]
-    driver_date: Option<String>,
-  }
+#[tauri::command(rename_all = "snake_case")]
+async fn install_udev_rules() -> Result<UdevStatus, HayboxError> {
+  blocking::run(OPERATION_TIMEOUT, move || -> Result<UdevStatus, HayboxError> {
+    let result = udev::install_udev_rules();
+    AuditEntry::new("install_udev_rules").outcome(&result).record();
+    result
+  })
+  .await?
+}
 
-  let devices: Vec<WmiDeviceInfo> = match wmi_connection.raw_query(&query) {
-    Ok(devices) => {
-      println!("WMI query successful. Found {} device(s)", devices.len());
-      devices
-    },
-    Err(e) => {
-      let error_msg = format!("Failed to query WMI: {}", e);
-      println!("ERROR: {}", error_msg);
-      return Err(error_msg);
+#[tauri::command(rename_all = "snake_case")]
+async fn get_steamos_info() -> Result<SteamOsInfo, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, steamos::steamos_info).await
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn get_data_location() -> DataLocation {
+  paths::data_location()
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn get_recent_logs(level: Option<LogLevel>, limit: Option<usize>) -> Vec<LogEntry> {
+  logging::recent_logs(level, limit)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn set_log_level(level: LogLevel) -> Result<(), HayboxError> {
+  logging::set_level(level)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn get_system_usb_events(since: Option<u64>) -> Result<Vec<SystemUsbEvent>, HayboxError> {
+  blocking::run(QUERY_TIMEOUT, move || event_log::get_system_usb_events(since)).await?
+}
+
+#[tauri::command(rename_all = "snake_case")]
+async fn export_diagnostics(app_handle: tauri::AppHandle, path: String) -> Result<DiagnosticsExport, HayboxError> {
+  blocking::run(OPERATION_TIMEOUT, move || {
+    diagnostics::export_diagnostics(
+      std::path::Path::new(&path),
+      &app_handle.package_info().version.to_string(),
+    )
+  })
+  .await?
+}
+
+/// Registers the commands with Tauri and builds the table the frontend reads
+/// elevation requirements from, so the two cannot drift apart.
+macro_rules! commands {
+  ($($command:ident: $elevation:ident),* $(,)?) => {
+    const COMMANDS: &[(&str, Elevation)] = &[$((stringify!($command), Elevation::$elevation)),*];
+
+    fn invoke_handler() -> impl Fn(tauri::ipc::Invoke) -> bool + Send + Sync + 'static {
+      tauri::generate_handler![$($command),*]
     }
   };
+}
 
-  let driver_info: Vec<DriverInfo> = devices
-    .into_iter()
-    .map(|device| {
-      let is_winusb = device.driver_provider
-        .as_ref()
-        .map(|provider| provider.contains("WinUSB"))
-        .unwrap_or(false);
-
-      DriverInfo {
-        device_id: device.device_id,
-        device_name: device.name.unwrap_or_else(|| "Unknown Device".to_string()),
-        driver_provider: device.driver_provider,
-        driver_version: device.driver_version,
-        driver_date: device.driver_date,
-        is_winusb,
-      }
-    })
-    .collect();
-
-  println!("Returning {} driver info records", driver_info.len());
-  Ok(driver_info)
+commands! {
+  get_device_status: Never,
+  get_device_identifiers: Never,
+  uninstall_xinput: Windows,
+  reinstall_xinput: Windows,
+  get_xinput_status: Never,
+  get_xinput_backup_status: Never,
+  restore_xinput_from_backup: Windows,
+  delete_xinput_backup: Windows,
+  run_presentation_test: Never,
+  get_hidhide_status: Never,
+  install_hidhide: Windows,
+  hide_controller: Windows,
+  unhide_controller: Windows,
+  allow_hidhide_app: Windows,
+  disallow_hidhide_app: Windows,
+  set_hidhide_active: Windows,
+  create_game_profile: Never,
+  list_game_profiles: Never,
+  delete_game_profile: Never,
+  get_pnp_device_tree: Never,
+  find_processes_using_device: Never,
+  enter_bootsel_mode: Never,
+  list_firmware_releases: Never,
+  download_firmware: Never,
+  get_device_firmware_version: Never,
+  inspect_uf2: Never,
+  get_bootsel_info: Never,
+  backup_firmware: Never,
+  export_build_config: Never,
+  flash_uf2: Never,
+  flash_dropped_file: Never,
+  detect_flash_target: Never,
+  flash_firmware: Never,
+  batch_flash: Never,
+  factory_reset_device: Never,
+  connect_config_mode: Never,
+  disconnect_config_mode: Never,
+  get_config: Never,
+  set_config: Never,
+  get_serial_ports_for_device: Never,
+  start_input_monitor: Never,
+  stop_input_monitor: Never,
+  start_recording: Never,
+  stop_recording: Never,
+  load_recording: Never,
+  analyze_recording: Never,
+  analyze_live_input: Never,
+  get_switch_health_report: Never,
+  reset_switch_health: Never,
+  start_frame_trainer: Never,
+  get_frame_trainer_report: Never,
+  stop_frame_trainer: Never,
+  get_usage_stats: Never,
+  start_socd_test: Never,
+  check_socd_step: Never,
+  cancel_socd_test: Never,
+  start_analog_trace: Never,
+  stop_analog_trace: Never,
+  export_trace: Never,
+  get_rulesets: Never,
+  check_coordinate_legality: Never,
+  parse_report_descriptor: Never,
+  list_hid_devices: Never,
+  start_comparison_session: Never,
+  get_comparison_report: Never,
+  stop_comparison_session: Never,
+  measure_polling_rate: Never,
+  run_latency_test: Never,
+  get_latency_results: Never,
+  open_console: Never,
+  close_console: Never,
+  get_console_port: Never,
+  set_console_filter: Never,
+  get_console_lines: Never,
+  clear_console: Never,
+  save_console_log: Never,
+  set_protocol_trace: Never,
+  get_protocol_trace: Never,
+  clear_protocol_trace: Never,
+  try_config: Never,
+  confirm_config_trial: Never,
+  cancel_config_trial: Never,
+  apply_config_to_all: Never,
+  validate_config_for_device: Never,
+  export_config: Never,
+  import_config: Never,
+  list_config_backups: Never,
+  restore_config_backup: Never,
+  publish_config: Never,
+  fetch_shared_config: Never,
+  diff_configs: Never,
+  apply_config_patch: Never,
+  set_default_mode: Never,
+  list_profiles: Never,
+  create_profile: Never,
+  rename_profile: Never,
+  delete_profile: Never,
+  set_active_profile: Never,
+  get_profile_snapshot: Never,
+  restore_profile_snapshot: Never,
+  get_button_mappings: Never,
+  set_button_mapping: Never,
+  get_keyboard_map: Never,
+  set_keyboard_map: Never,
+  get_lighting: Never,
+  set_lighting: Never,
+  test_gamecube_adapter: Never,
+  start_adapter_monitor: Never,
+  stop_adapter_monitor: Never,
+  test_rumble: Never,
+  get_game_controller_order: Never,
+  set_preferred_game_controller: Never,
+  install_winusb: Windows,
+  install_driver_for: Windows,
+  install_winusb_batch: Windows,
+  restore_default_driver: Windows,
+  list_installed_haybox_drivers: Never,
+  remove_stale_drivers: Windows,
+  clean_driver_cache: Windows,
+  verify_driver_resources: Never,
+  get_inf_template: Never,
+  set_inf_template: Never,
+  reset_inf_template: Never,
+  elevate_and_run: Never,
+  get_privilege_status: Never,
+  get_environment_warnings: Never,
+  get_architecture_info: Never,
+  get_pending_actions: Never,
+  get_driver_audit_log: Never,
+  list_replaceable_devices: Never,
+  replace_driver: Windows,
+  reboot_now: Never,
+  get_platform_capabilities: Never,
+  get_driver_info: Never,
+  get_udev_status: Never,
+  install_udev_rules: Linux,
+  get_steamos_info: Never,
+  get_data_location: Never,
+  get_recent_logs: Never,
+  set_log_level: Never,
+  export_diagnostics: Never,
+  get_system_usb_events: Never,
+  run_doctor: Never,
+  get_last_crash_report: Never,
+  get_telemetry_status: Never,
+  set_telemetry_settings: Never,
+  refresh_driver_info: Never,
+  get_operations: Never,
+  cancel_operation: Never,
+  get_device_mode: Never,
+  request_mode: Never,
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+  logging::init();
+  crash::install();
+  if let Some(exit_code) = elevation::run_helper_from_args() {
+    std::process::exit(exit_code);
+  }
+  paths::apply_portable_webview_dir();
+
   tauri::Builder::default()
     .plugin(tauri_plugin_dialog::init())
     .plugin(tauri_plugin_opener::init())
-    .invoke_handler(tauri::generate_handler![
-      get_device_status,
-      get_device_identifiers,
-      uninstall_xinput,
-      reinstall_xinput,
-      install_winusb,
-      get_driver_info
-    ])
+    .setup(|app| {
+      app.manage(OperationQueue::new(webview_events(app.handle())));
+      let app_handle = app.handle().clone();
+      std::thread::spawn(move || {
+        let results: Vec<PendingActionVerification> = pending::verify_pending_actions();
+        if !results.is_empty() {
+          if let Err(e) = app_handle.emit("post_reboot_verification", results) {
+            tracing::warn!("failed to emit post_reboot_verification: {}", e);
+          }
+        }
+      });
+
+      hotplug::subscribe(|events, event| {
+        if event.kind != HotplugKind::Arrived {
+          return;
+        }
+        let events = events.clone();
+        let (vendor_id, product_id) = (event.vendor_id, event.product_id);
+        std::thread::spawn(move || {
+          // Give PnP a moment to bind the driver before checking it.
+          std::thread::sleep(std::time::Duration::from_secs(2));
+          let results = pending::verify_on_arrival(vendor_id, product_id);
+          if !results.is_empty() {
+            events.emit("preinstall_verification", results);
+          }
+        });
+      });
+      driver_cache::watch_hotplug();
+      hotplug::start(webview_events(app.handle()));
+      game_profiles::start_watcher(webview_events(app.handle()));
+      xinput::start_restore_watcher(webview_events(app.handle()));
+      telemetry::start_uploader();
+      Ok(())
+    })
+    .on_window_event(|window, event| {
+      let tauri::WindowEvent::DragDrop(tauri::DragDropEvent::Drop { paths, .. }) = event else {
+        return;
+      };
+      let Some(path) = paths
+        .iter()
+        .find(|path| path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("uf2")))
+        .cloned()
+      else {
+        return;
+      };
+      // Only checked here; the UI confirms with the user before calling
+      // `flash_dropped_file`.
+      let app_handle = window.app_handle().clone();
+      std::thread::spawn(move || {
+        let result = flashing::check_dropped_file(&path);
+        if let Err(e) = app_handle.emit("dropped_file_flash", result) {
+          tracing::warn!("failed to emit dropped_file_flash: {}", e);
+        }
+      });
+    })
+    .invoke_handler(invoke_handler())
     .run(tauri::generate_context!())
     .expect("error while running tauri application");
 }